@@ -4,6 +4,54 @@ use std::fs;
 use std::path::Path;
 
 fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    emit_kernel_path_override();
+    emit_trusted_key();
+
+    if env::var_os("CARGO_FEATURE_UEFI").is_some() {
+        emit_uefi_link_args();
+    } else if env::var_os("CARGO_FEATURE_BIOS").is_some() {
+        emit_bios_link_args();
+    }
+}
+
+/// Forward `RUSTYBOOT_KERNEL_PATH` to the crate, where it is searched before
+/// the built-in kernel paths.
+fn emit_kernel_path_override() {
+    println!("cargo:rerun-if-env-changed=RUSTYBOOT_KERNEL_PATH");
+    if let Ok(path) = env::var("RUSTYBOOT_KERNEL_PATH") {
+        if !path.is_empty() {
+            println!("cargo:rustc-env=RUSTYBOOT_KERNEL_PATH={}", path);
+        }
+    }
+}
+
+/// Embed the Ed25519 public key named by `RUSTYBOOT_TRUSTED_KEY` (a raw
+/// 32-byte file). With a key, kernels must come with a valid `kernel.sig`.
+fn emit_trusted_key() {
+    println!("cargo:rerun-if-env-changed=RUSTYBOOT_TRUSTED_KEY");
+    println!("cargo:rustc-check-cfg=cfg(rustyboot_trusted_key)");
+    let path = match env::var("RUSTYBOOT_TRUSTED_KEY") {
+        Ok(p) if !p.is_empty() => p,
+        _ => return,
+    };
+    println!("cargo:rerun-if-changed={}", path);
+    let key = fs::read(&path).unwrap_or_else(|e| panic!("RUSTYBOOT_TRUSTED_KEY {}: {}", path, e));
+    assert!(key.len() == 32, "RUSTYBOOT_TRUSTED_KEY must be a raw 32-byte Ed25519 public key");
+
+    let out_dir = env::var("OUT_DIR").unwrap();
+    fs::write(Path::new(&out_dir).join("trusted_key.bin"), &key).unwrap();
+    println!("cargo:rustc-cfg=rustyboot_trusted_key");
+}
+
+/// PE/COFF EFI application (lld-link flavour).
+fn emit_uefi_link_args() {
+    println!("cargo:rustc-link-arg=/entry:efi_main");
+    println!("cargo:rustc-link-arg=/subsystem:efi_application");
+}
+
+/// Flat ELF image loaded by the real-mode stub at 0x8000.
+fn emit_bios_link_args() {
     let out_dir = env::var("OUT_DIR").unwrap();
     let dest_path = Path::new(&out_dir).join("bootloader.ld");
 
@@ -14,6 +62,11 @@ SECTIONS
 {
     . = 0x8000;
 
+    /* Multiboot2 loaders look for the header in the first 32 KiB */
+    .multiboot2 : {
+        KEEP(*(.multiboot2))
+    }
+
     .text : {
         *(.text)
         *(.text.*)
@@ -39,5 +92,4 @@ SECTIONS
     fs::write(&dest_path, linker_script).unwrap();
     println!("cargo:rustc-link-search=native={}", out_dir);
     println!("cargo:rustc-link-arg=-Tbootloader.ld");
-    println!("cargo:rerun-if-changed=build.rs");
 }
@@ -0,0 +1,201 @@
+//! FADT (`"FACP"`): reset register and PM1 control blocks for reboot and
+//! power-off.
+
+use crate::arch::io::IoPort;
+
+use super::{find_rsdp, find_table, find_table_in, read_u32, read_u64, read_u8};
+
+// ===== FADT field offsets =====
+const FADT_DSDT: usize = 40;
+const FADT_PM1A_CNT_BLK: usize = 64;
+const FADT_PM1B_CNT_BLK: usize = 68;
+const FADT_FLAGS: usize = 112;
+const FADT_RESET_REG: usize = 116; // Generic Address Structure, 12 bytes
+const FADT_RESET_VALUE: usize = 128;
+const FADT_X_DSDT: usize = 140;
+
+const FADT_FLAG_RESET_REG_SUP: u32 = 1 << 10;
+
+// ===== Generic Address Structure address spaces =====
+const GAS_SYSTEM_MEMORY: u8 = 0;
+const GAS_SYSTEM_IO: u8 = 1;
+const GAS_PCI_CONFIG: u8 = 2;
+
+const KBC_STATUS: IoPort<u8> = IoPort::new(0x64);
+const KBC_CMD_RESET: u8 = 0xFE;
+
+const PCI_CONFIG_ADDRESS: IoPort<u32> = IoPort::new(0xCF8);
+const PCI_CONFIG_DATA: IoPort<u8> = IoPort::new(0xCFC);
+
+fn fadt() -> Option<usize> {
+    find_table(b"FACP").map(|p| p as usize)
+}
+
+fn fadt_len(fadt: usize) -> usize {
+    unsafe { read_u32(fadt + 4) as usize }
+}
+
+/// Reset through the FADT reset register, then the keyboard controller.
+/// Halts forever if the machine is still running afterwards.
+pub fn acpi_reset() -> ! {
+    if let Some(fadt) = fadt() {
+        let flags = unsafe { read_u32(fadt + FADT_FLAGS) };
+        if fadt_len(fadt) > FADT_RESET_VALUE && (flags & FADT_FLAG_RESET_REG_SUP) != 0 {
+            unsafe { write_reset_reg(fadt) };
+        }
+    }
+
+    // Wait for the 8042 input buffer to drain, then pulse the reset line
+    for _ in 0..100_000 {
+        if (KBC_STATUS.read() & 0x02) == 0 {
+            break;
+        }
+    }
+    KBC_STATUS.write(KBC_CMD_RESET);
+    halt_forever()
+}
+
+unsafe fn write_reset_reg(fadt: usize) {
+    let space = unsafe { read_u8(fadt + FADT_RESET_REG) };
+    let address = unsafe { read_u64(fadt + FADT_RESET_REG + 4) };
+    let value = unsafe { read_u8(fadt + FADT_RESET_VALUE) };
+    if address == 0 {
+        return;
+    }
+
+    match space {
+        GAS_SYSTEM_MEMORY => unsafe { core::ptr::write_volatile(address as usize as *mut u8, value) },
+        GAS_SYSTEM_IO => IoPort::<u8>::new(address as u16).write(value),
+        GAS_PCI_CONFIG => {
+            // Bus 0; device in bits 47:32, function in 31:16, register in 15:0
+            let dev = ((address >> 32) & 0x1F) as u32;
+            let func = ((address >> 16) & 0x07) as u32;
+            let reg = (address & 0xFF) as u32;
+            PCI_CONFIG_ADDRESS.write(0x8000_0000 | (dev << 11) | (func << 8) | (reg & 0xFC));
+            PCI_CONFIG_DATA.offset((reg & 3) as u16).write(value);
+        }
+        _ => {}
+    }
+}
+
+const FADT_SMI_CMD: usize = 48;
+const FADT_ACPI_ENABLE: usize = 52;
+
+const SLP_EN: u16 = 1 << 13;
+const SCI_EN: u16 = 1 << 0;
+const QEMU_SHUTDOWN_PORT: IoPort<u16> = IoPort::new(0xB004);
+const QEMU_SHUTDOWN_VALUE: u16 = 0x2000;
+
+// AML opcodes needed to read the `_S5_` package
+const AML_NAME_OP: u8 = 0x08;
+const AML_PACKAGE_OP: u8 = 0x12;
+const AML_BYTE_PREFIX: u8 = 0x0A;
+const AML_ROOT_CHAR: u8 = b'\\';
+
+/// Power management fields of the FADT needed for soft-off.
+#[derive(Copy, Clone, Debug)]
+pub struct Fadt {
+    /// Physical address of the table
+    pub address: usize,
+    pub pm1a_cnt_blk: u16,
+    /// 0 when the platform has no PM1b block
+    pub pm1b_cnt_blk: u16,
+    /// `SLP_TYPa`/`SLP_TYPb` for S5 from the DSDT, `None` if not found
+    pub s5_sleep_types: Option<(u8, u8)>,
+}
+
+impl Fadt {
+    /// Follow RSDP -> RSDT/XSDT -> FADT (checksums validated on the way).
+    pub fn from_rsdp(rsdp_phys: u64) -> Option<Fadt> {
+        let address = find_table_in(rsdp_phys as usize, b"FACP")? as usize;
+        let pm1a_cnt_blk = unsafe { read_u32(address + FADT_PM1A_CNT_BLK) } as u16;
+        let pm1b_cnt_blk = unsafe { read_u32(address + FADT_PM1B_CNT_BLK) } as u16;
+        let s5_sleep_types = if pm1a_cnt_blk != 0 { find_s5_sleep_types(address) } else { None };
+        Some(Fadt { address, pm1a_cnt_blk, pm1b_cnt_blk, s5_sleep_types })
+    }
+}
+
+/// Power off via PM1a/PM1b control with the DSDT `_S5` sleep types, then the
+/// QEMU ACPI shutdown port. Halts forever if the machine is still running.
+pub fn power_off() -> ! {
+    if let Some(fadt) = find_rsdp().and_then(Fadt::from_rsdp) {
+        if let Some((slp_typ_a, slp_typ_b)) = fadt.s5_sleep_types {
+            unsafe { enable_acpi_mode(fadt.address, fadt.pm1a_cnt_blk) };
+            IoPort::<u16>::new(fadt.pm1a_cnt_blk).write(((slp_typ_a as u16) << 10) | SLP_EN);
+            if fadt.pm1b_cnt_blk != 0 {
+                IoPort::<u16>::new(fadt.pm1b_cnt_blk).write(((slp_typ_b as u16) << 10) | SLP_EN);
+            }
+        }
+    }
+
+    QEMU_SHUTDOWN_PORT.write(QEMU_SHUTDOWN_VALUE);
+    halt_forever()
+}
+
+/// Switch from legacy to ACPI mode if firmware has not done so (SCI_EN clear).
+unsafe fn enable_acpi_mode(fadt: usize, pm1a: u16) {
+    let pm1a = IoPort::<u16>::new(pm1a);
+    if (pm1a.read() & SCI_EN) != 0 {
+        return;
+    }
+    let smi_cmd = unsafe { read_u32(fadt + FADT_SMI_CMD) } as u16;
+    let enable = unsafe { read_u8(fadt + FADT_ACPI_ENABLE) };
+    if smi_cmd == 0 || enable == 0 {
+        return;
+    }
+    IoPort::<u8>::new(smi_cmd).write(enable);
+    for _ in 0..1_000_000 {
+        if (pm1a.read() & SCI_EN) != 0 {
+            break;
+        }
+    }
+}
+
+/// `SLP_TYPa` and `SLP_TYPb` from the DSDT `_S5_` package:
+/// `NameOp "_S5_" PackageOp PkgLength NumElements [BytePrefix] a [BytePrefix] b`.
+fn find_s5_sleep_types(fadt: usize) -> Option<(u8, u8)> {
+    let x_dsdt = if fadt_len(fadt) >= FADT_X_DSDT + 8 {
+        (unsafe { read_u64(fadt + FADT_X_DSDT) }) as usize
+    } else {
+        0
+    };
+    let dsdt = if x_dsdt != 0 { x_dsdt } else { (unsafe { read_u32(fadt + FADT_DSDT) }) as usize };
+    if dsdt == 0 || unsafe { &*(dsdt as *const [u8; 4]) } != b"DSDT" {
+        return None;
+    }
+
+    let len = unsafe { read_u32(dsdt + 4) } as usize;
+    let aml = unsafe { core::slice::from_raw_parts(dsdt as *const u8, len) };
+    let start = super::SDT_HEADER_LEN;
+    let pos = (start..len.saturating_sub(4)).find(|&i| &aml[i..i + 4] == b"_S5_")?;
+
+    // Must be a named object (optionally in the root scope) holding a package
+    let named = aml[pos - 1] == AML_NAME_OP || (aml[pos - 1] == AML_ROOT_CHAR && aml[pos - 2] == AML_NAME_OP);
+    if !named || *aml.get(pos + 4)? != AML_PACKAGE_OP {
+        return None;
+    }
+
+    // Skip PkgLength (1–4 bytes, count in bits 7:6 of the lead byte) and NumElements
+    let mut i = pos + 5;
+    i += ((*aml.get(i)? & 0xC0) >> 6) as usize + 2;
+
+    let read_elem = |i: &mut usize| -> Option<u8> {
+        if *aml.get(*i)? == AML_BYTE_PREFIX {
+            *i += 1;
+        }
+        let v = *aml.get(*i)?;
+        *i += 1;
+        Some(v)
+    };
+    let a = read_elem(&mut i)?;
+    let b = read_elem(&mut i).unwrap_or(0);
+    Some((a, b))
+}
+
+fn halt_forever() -> ! {
+    loop {
+        unsafe {
+            core::arch::asm!("cli; hlt");
+        }
+    }
+}
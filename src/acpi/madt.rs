@@ -0,0 +1,116 @@
+//! MADT (`"APIC"`): local APICs (one per CPU) and I/O APICs.
+
+use super::{read_u32, read_u64, read_u8, SDT_HEADER_LEN};
+use crate::util::static_vec::StaticVec;
+
+pub const MAX_LOCAL_APICS: usize = 256;
+pub const MAX_IO_APICS: usize = 8;
+
+// Header is followed by the local APIC address and the MADT flags
+const MADT_LOCAL_APIC_ADDRESS: usize = SDT_HEADER_LEN;
+const MADT_FLAGS: usize = SDT_HEADER_LEN + 4;
+const MADT_ENTRIES_OFFSET: usize = SDT_HEADER_LEN + 8;
+
+const MADT_TYPE_LOCAL_APIC: u8 = 0;
+const MADT_TYPE_IO_APIC: u8 = 1;
+const MADT_TYPE_LOCAL_APIC_OVERRIDE: u8 = 5;
+
+/// `LocalApic::flags` bits
+pub const LAPIC_ENABLED: u32 = 1 << 0;
+/// Disabled now but may be brought online later (ACPI 6.3+)
+pub const LAPIC_ONLINE_CAPABLE: u32 = 1 << 1;
+
+/// `MadtInfo::flags`: the machine also has dual 8259 PICs
+pub const MADT_PCAT_COMPAT: u32 = 1 << 0;
+
+#[derive(Copy, Clone, Debug)]
+pub struct LocalApic {
+    /// Processor UID the ACPI namespace refers to the CPU by
+    pub acpi_id: u8,
+    pub apic_id: u8,
+    pub flags: u32,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct IoApic {
+    pub id: u8,
+    pub address: u32,
+    /// First global system interrupt this I/O APIC handles
+    pub gsi_base: u32,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct MadtInfo {
+    /// Physical address of the local APIC registers (after any 64-bit override)
+    pub local_apic_address: u64,
+    pub flags: u32,
+    pub local_apics: StaticVec<LocalApic, MAX_LOCAL_APICS>,
+    pub io_apics: StaticVec<IoApic, MAX_IO_APICS>,
+}
+
+impl MadtInfo {
+    pub const fn empty() -> Self {
+        Self { local_apic_address: 0, flags: 0, local_apics: StaticVec::new(), io_apics: StaticVec::new() }
+    }
+
+    /// CPUs that are running or can be started.
+    pub fn cpu_count(&self) -> usize {
+        self.local_apics.iter().filter(|l| l.flags & (LAPIC_ENABLED | LAPIC_ONLINE_CAPABLE) != 0).count()
+    }
+}
+
+/// Find the MADT through the RSDT/XSDT at `rsdt_phys` and collect its
+/// interrupt controllers. Empty if there is no (valid) MADT; entries beyond
+/// the fixed capacity are dropped.
+pub fn parse(rsdt_phys: u64) -> MadtInfo {
+    match super::find_table_in_root(rsdt_phys as usize, b"APIC") {
+        Some(table) => parse_madt(table),
+        None => MadtInfo::empty(),
+    }
+}
+
+/// Walk the interrupt controller structures of the MADT at `table`.
+pub fn parse_madt(table: *const u8) -> MadtInfo {
+    let mut info = MadtInfo::empty();
+    let base = table as usize;
+    let len = unsafe { read_u32(base + 4) } as usize;
+    if len < MADT_ENTRIES_OFFSET {
+        return info;
+    }
+    info.local_apic_address = unsafe { read_u32(base + MADT_LOCAL_APIC_ADDRESS) } as u64;
+    info.flags = unsafe { read_u32(base + MADT_FLAGS) };
+
+    let mut off = MADT_ENTRIES_OFFSET;
+    while off + 2 <= len {
+        let entry = base + off;
+        let ty = unsafe { read_u8(entry) };
+        let entry_len = unsafe { read_u8(entry + 1) } as usize;
+        if entry_len < 2 || off + entry_len > len {
+            break; // malformed, stop rather than read past the table
+        }
+
+        match ty {
+            MADT_TYPE_LOCAL_APIC if entry_len >= 8 => {
+                let _ = info.local_apics.push(LocalApic {
+                    acpi_id: unsafe { read_u8(entry + 2) },
+                    apic_id: unsafe { read_u8(entry + 3) },
+                    flags: unsafe { read_u32(entry + 4) },
+                });
+            }
+            MADT_TYPE_IO_APIC if entry_len >= 12 => {
+                let _ = info.io_apics.push(IoApic {
+                    id: unsafe { read_u8(entry + 2) },
+                    address: unsafe { read_u32(entry + 4) },
+                    gsi_base: unsafe { read_u32(entry + 8) },
+                });
+            }
+            MADT_TYPE_LOCAL_APIC_OVERRIDE if entry_len >= 12 => {
+                // Reserved u16 at +2, then the 64-bit address
+                info.local_apic_address = unsafe { read_u64(entry + 4) };
+            }
+            _ => {}
+        }
+        off += entry_len;
+    }
+    info
+}
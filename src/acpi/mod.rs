@@ -0,0 +1,162 @@
+//! ACPI table lookup.
+//!
+//! Tables are read in place through their physical addresses; both boot paths
+//! run identity-mapped, so no mapping is needed.
+
+pub mod fadt;
+pub mod madt;
+pub mod srat;
+
+pub use fadt::power_off;
+
+use core::ptr;
+
+const RSDP_SIGNATURE: &[u8; 8] = b"RSD PTR ";
+const BIOS_AREA_START: usize = 0xE0000;
+const BIOS_AREA_END: usize = 0x100000;
+/// BDA word holding the EBDA real-mode segment
+const EBDA_SEGMENT_PTR: usize = 0x40E;
+const EBDA_SEARCH_LEN: usize = 1024;
+/// Where an EBDA can legitimately sit (below the 640 KiB line)
+const EBDA_MIN: usize = 0x80000;
+const EBDA_MAX: usize = 0xA0000;
+/// ACPI 1.0 RSDP size; the 2.0 structure is `length` bytes (36)
+const RSDP_V1_LEN: usize = 20;
+const RSDP_V2_MIN_LEN: usize = 36;
+
+/// Size of the common System Description Table header
+pub const SDT_HEADER_LEN: usize = 36;
+
+#[inline]
+pub(crate) unsafe fn read_u8(addr: usize) -> u8 {
+    unsafe { ptr::read_volatile(addr as *const u8) }
+}
+
+#[inline]
+pub(crate) unsafe fn read_u32(addr: usize) -> u32 {
+    unsafe { ptr::read_unaligned(addr as *const u32) }
+}
+
+#[inline]
+pub(crate) unsafe fn read_u64(addr: usize) -> u64 {
+    unsafe { ptr::read_unaligned(addr as *const u64) }
+}
+
+/// ACPI (and SMBIOS) checksums: all bytes of the structure sum to 0
+/// (mod 256).
+pub(crate) fn checksum_ok(addr: usize, len: usize) -> bool {
+    let mut sum: u8 = 0;
+    for i in 0..len {
+        sum = sum.wrapping_add(unsafe { read_u8(addr + i) });
+    }
+    sum == 0
+}
+
+/// Signature plus the v1 checksum, and for revision >= 2 the extended
+/// checksum over `length` bytes.
+fn rsdp_valid(addr: usize) -> bool {
+    let sig = unsafe { &*(addr as *const [u8; 8]) };
+    if sig != RSDP_SIGNATURE || !checksum_ok(addr, RSDP_V1_LEN) {
+        return false;
+    }
+    let revision = unsafe { read_u8(addr + 15) };
+    if revision >= 2 {
+        let len = unsafe { read_u32(addr + 20) } as usize;
+        return (RSDP_V2_MIN_LEN..=4096).contains(&len) && checksum_ok(addr, len);
+    }
+    true
+}
+
+/// RSDP on a 16-byte boundary in `[start, end)`.
+fn scan_rsdp(start: usize, end: usize) -> Option<usize> {
+    (start..end).step_by(16).find(|&addr| rsdp_valid(addr))
+}
+
+/// RSDP in the first KiB of the EBDA, then in the BIOS read-only area.
+fn find_rsdp_legacy() -> Option<usize> {
+    let ebda = (unsafe { ptr::read_unaligned(EBDA_SEGMENT_PTR as *const u16) } as usize) << 4;
+    if (EBDA_MIN..EBDA_MAX).contains(&ebda) {
+        if let Some(rsdp) = scan_rsdp(ebda, ebda + EBDA_SEARCH_LEN) {
+            return Some(rsdp);
+        }
+    }
+    scan_rsdp(BIOS_AREA_START, BIOS_AREA_END)
+}
+
+/// RSDP from the EFI configuration table, preferring the ACPI 2.0 entry.
+#[cfg(feature = "uefi")]
+fn find_rsdp_uefi() -> Option<usize> {
+    use uefi::table::cfg::{ACPI2_GUID, ACPI_GUID};
+
+    let st = unsafe { uefi_services::system_table().as_ref() };
+    let tables = st.config_table();
+    [ACPI2_GUID, ACPI_GUID].iter().find_map(|guid| {
+        tables
+            .iter()
+            .find(|t| t.guid == *guid)
+            .map(|t| t.address as usize)
+            .filter(|&addr| rsdp_valid(addr))
+    })
+}
+
+/// Physical address of the RSDP. On UEFI the configuration table is asked
+/// first; the EBDA/BIOS area scan is the fallback on both paths.
+pub fn find_rsdp() -> Option<u64> {
+    #[cfg(feature = "uefi")]
+    if let Some(rsdp) = find_rsdp_uefi() {
+        return Some(rsdp as u64);
+    }
+    find_rsdp_legacy().map(|addr| addr as u64)
+}
+
+/// Address of the first table with `signature` (e.g. `b"FACP"`) whose
+/// checksum is valid. Uses the XSDT when the RSDP provides one.
+pub fn find_table(signature: &[u8; 4]) -> Option<*const u8> {
+    find_table_in(find_rsdp()? as usize, signature)
+}
+
+/// `find_table` starting from a known RSDP.
+pub fn find_table_in(rsdp: usize, signature: &[u8; 4]) -> Option<*const u8> {
+    find_table_in_root(root_table(rsdp)?, signature)
+}
+
+/// Physical address of the XSDT when the RSDP provides one, else the RSDT.
+pub fn root_table(rsdp: usize) -> Option<usize> {
+    let revision = unsafe { read_u8(rsdp + 15) };
+    let rsdt = unsafe { read_u32(rsdp + 16) } as usize;
+    let xsdt = if revision >= 2 { (unsafe { read_u64(rsdp + 24) }) as usize } else { 0 };
+    let root = if xsdt != 0 { xsdt } else { rsdt };
+    if root == 0 { None } else { Some(root) }
+}
+
+/// `find_table` in the RSDT or XSDT at `root`, told apart by its signature.
+pub fn find_table_in_root(root: usize, signature: &[u8; 4]) -> Option<*const u8> {
+    let entry_size = match unsafe { &*(root as *const [u8; 4]) } {
+        b"XSDT" => 8,
+        b"RSDT" => 4,
+        _ => return None,
+    };
+    let root_len = unsafe { read_u32(root + 4) } as usize;
+    if root_len < SDT_HEADER_LEN || !checksum_ok(root, root_len) {
+        return None;
+    }
+
+    let count = (root_len - SDT_HEADER_LEN) / entry_size;
+    for i in 0..count {
+        let entry = root + SDT_HEADER_LEN + i * entry_size;
+        let table = if entry_size == 8 {
+            unsafe { read_u64(entry) as usize }
+        } else {
+            unsafe { read_u32(entry) as usize }
+        };
+        if table == 0 {
+            continue;
+        }
+        let sig = unsafe { &*(table as *const [u8; 4]) };
+        let len = unsafe { read_u32(table + 4) } as usize;
+        if sig == signature && len >= SDT_HEADER_LEN && checksum_ok(table, len) {
+            return Some(table as *const u8);
+        }
+    }
+    None
+}
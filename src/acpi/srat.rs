@@ -0,0 +1,101 @@
+//! SRAT (`"SRAT"`): NUMA node of each memory range and CPU.
+
+use super::{read_u32, read_u64, read_u8, SDT_HEADER_LEN};
+use crate::util::static_vec::StaticVec;
+
+// Header is followed by a 4-byte revision and 8 reserved bytes
+const SRAT_ENTRIES_OFFSET: usize = SDT_HEADER_LEN + 12;
+
+const SRAT_TYPE_PROCESSOR: u8 = 0;
+const SRAT_TYPE_MEMORY: u8 = 1;
+const SRAT_TYPE_X2APIC: u8 = 2;
+
+const SRAT_FLAG_ENABLED: u32 = 1 << 0;
+
+#[derive(Copy, Clone, Debug)]
+pub struct MemAffinityEntry {
+    pub base: u64,
+    pub length: u64,
+    pub numa_node: u32,
+    pub enabled: bool,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct CpuAffinityEntry {
+    pub apic_id: u32,
+    pub numa_node: u32,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct SratInfo {
+    pub mem_affinities: StaticVec<MemAffinityEntry, 32>,
+    pub cpu_affinities: StaticVec<CpuAffinityEntry, 64>,
+}
+
+impl SratInfo {
+    pub const fn empty() -> Self {
+        Self {
+            mem_affinities: StaticVec::new(),
+            cpu_affinities: StaticVec::new(),
+        }
+    }
+}
+
+/// Walk the variable-length affinity structures of the SRAT at `table`.
+/// Disabled CPUs are skipped; entries beyond the fixed capacity are dropped.
+pub fn parse_srat(table: *const u8) -> SratInfo {
+    let mut info = SratInfo::empty();
+    let base = table as usize;
+    let len = unsafe { read_u32(base + 4) } as usize;
+
+    let mut off = SRAT_ENTRIES_OFFSET;
+    while off + 2 <= len {
+        let entry = base + off;
+        let ty = unsafe { read_u8(entry) };
+        let entry_len = unsafe { read_u8(entry + 1) } as usize;
+        if entry_len < 2 || off + entry_len > len {
+            break; // malformed, stop rather than read past the table
+        }
+
+        match ty {
+            SRAT_TYPE_PROCESSOR if entry_len >= 16 => {
+                let flags = unsafe { read_u32(entry + 4) };
+                if (flags & SRAT_FLAG_ENABLED) != 0 {
+                    // Proximity domain is split: bits 7:0 at +2, bits 31:8 at +9
+                    let lo = unsafe { read_u8(entry + 2) } as u32;
+                    let hi = unsafe { read_u32(entry + 8) } >> 8;
+                    let _ = info.cpu_affinities.push(CpuAffinityEntry {
+                        apic_id: unsafe { read_u8(entry + 3) } as u32,
+                        numa_node: lo | (hi << 8),
+                    });
+                }
+            }
+            SRAT_TYPE_MEMORY if entry_len >= 40 => {
+                let flags = unsafe { read_u32(entry + 28) };
+                let _ = info.mem_affinities.push(MemAffinityEntry {
+                    base: unsafe { read_u64(entry + 8) },
+                    length: unsafe { read_u64(entry + 16) },
+                    numa_node: unsafe { read_u32(entry + 2) },
+                    enabled: (flags & SRAT_FLAG_ENABLED) != 0,
+                });
+            }
+            SRAT_TYPE_X2APIC if entry_len >= 24 => {
+                let flags = unsafe { read_u32(entry + 12) };
+                if (flags & SRAT_FLAG_ENABLED) != 0 {
+                    let _ = info.cpu_affinities.push(CpuAffinityEntry {
+                        apic_id: unsafe { read_u32(entry + 8) },
+                        numa_node: unsafe { read_u32(entry + 4) },
+                    });
+                }
+            }
+            _ => {}
+        }
+        off += entry_len;
+    }
+    info
+}
+
+/// Locate and parse the SRAT, `None` on non-NUMA machines.
+pub fn find_srat() -> Option<SratInfo> {
+    super::find_table(b"SRAT").map(parse_srat)
+}
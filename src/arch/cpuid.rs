@@ -0,0 +1,117 @@
+//! CPUID helpers.
+
+#[cfg(target_arch = "x86")]
+use core::arch::x86::{__cpuid, __cpuid_count};
+#[cfg(target_arch = "x86_64")]
+use core::arch::x86_64::{__cpuid, __cpuid_count};
+
+const CPUID_HYPERVISOR_LEAF: u32 = 0x4000_0000;
+const CPUID_ECX_HYPERVISOR: u32 = 1 << 31; // leaf 1: running under a hypervisor
+
+// ===== Feature bits =====
+const CPUID_EXT_MAX_LEAF: u32 = 0x8000_0000;
+const CPUID_EXT_FEATURES: u32 = 0x8000_0001;
+const CPUID_ECX_RDRAND: u32 = 1 << 30; // leaf 1
+const CPUID7_EBX_SMEP: u32 = 1 << 7;
+const CPUID7_EBX_AVX512F: u32 = 1 << 16;
+const CPUID7_EBX_RDSEED: u32 = 1 << 18;
+const CPUID7_EBX_SMAP: u32 = 1 << 20;
+const CPUID7_ECX_LA57: u32 = 1 << 16;
+const CPUID_EXT_EDX_NX: u32 = 1 << 20; // leaf 0x80000001
+
+/// CPU vendor and the features the loader checks before using them.
+#[derive(Copy, Clone, Debug)]
+pub struct CpuInfo {
+    /// e.g. `b"GenuineIntel"`, `b"AuthenticAMD"`
+    pub vendor: [u8; 12],
+    pub max_leaf: u32,
+    /// Execute-disable page bit (EFER.NXE)
+    pub has_nx: bool,
+    pub has_rdrand: bool,
+    pub has_rdseed: bool,
+    pub has_smep: bool,
+    pub has_smap: bool,
+    pub has_avx512f: bool,
+    /// Five-level paging
+    pub has_la57: bool,
+}
+
+impl CpuInfo {
+    /// Query leaves 0, 1 and 7 (subleaf 0), plus 0x80000001 for NX, which
+    /// is only reported in the extended range.
+    pub fn read() -> Self {
+        let leaf0 = __cpuid(0);
+        let mut vendor = [0u8; 12];
+        vendor[0..4].copy_from_slice(&leaf0.ebx.to_le_bytes());
+        vendor[4..8].copy_from_slice(&leaf0.edx.to_le_bytes());
+        vendor[8..12].copy_from_slice(&leaf0.ecx.to_le_bytes());
+        let max_leaf = leaf0.eax;
+
+        let leaf1_ecx = if max_leaf >= 1 { __cpuid(1).ecx } else { 0 };
+        let (leaf7_ebx, leaf7_ecx) = if max_leaf >= 7 {
+            let leaf7 = __cpuid_count(7, 0);
+            (leaf7.ebx, leaf7.ecx)
+        } else {
+            (0, 0)
+        };
+        let ext_edx = if __cpuid(CPUID_EXT_MAX_LEAF).eax >= CPUID_EXT_FEATURES {
+            __cpuid(CPUID_EXT_FEATURES).edx
+        } else {
+            0
+        };
+
+        Self {
+            vendor,
+            max_leaf,
+            has_nx: ext_edx & CPUID_EXT_EDX_NX != 0,
+            has_rdrand: leaf1_ecx & CPUID_ECX_RDRAND != 0,
+            has_rdseed: leaf7_ebx & CPUID7_EBX_RDSEED != 0,
+            has_smep: leaf7_ebx & CPUID7_EBX_SMEP != 0,
+            has_smap: leaf7_ebx & CPUID7_EBX_SMAP != 0,
+            has_avx512f: leaf7_ebx & CPUID7_EBX_AVX512F != 0,
+            has_la57: leaf7_ecx & CPUID7_ECX_LA57 != 0,
+        }
+    }
+
+    /// Vendor as text; `"unknown"` if it is not ASCII.
+    pub fn vendor_str(&self) -> &str {
+        core::str::from_utf8(&self.vendor).unwrap_or("unknown")
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum HypervisorKind {
+    HyperV,
+    Kvm,
+    Vmware,
+    Xen,
+}
+
+/// Identify the hypervisor from its CPUID 0x40000000 vendor signature.
+/// Returns `None` on bare metal or for unknown hypervisors.
+pub fn detect_hypervisor() -> Option<HypervisorKind> {
+    let leaf1 = __cpuid(1);
+    if (leaf1.ecx & CPUID_ECX_HYPERVISOR) == 0 {
+        return None;
+    }
+
+    let hv = __cpuid(CPUID_HYPERVISOR_LEAF);
+    let mut sig = [0u8; 12];
+    sig[0..4].copy_from_slice(&hv.ebx.to_le_bytes());
+    sig[4..8].copy_from_slice(&hv.ecx.to_le_bytes());
+    sig[8..12].copy_from_slice(&hv.edx.to_le_bytes());
+
+    match &sig {
+        b"Microsoft Hv" => Some(HypervisorKind::HyperV),
+        b"KVMKVMKVM\0\0\0" => Some(HypervisorKind::Kvm),
+        b"VMwareVMware" => Some(HypervisorKind::Vmware),
+        b"XenVMMXenVMM" => Some(HypervisorKind::Xen),
+        _ => None,
+    }
+}
+
+/// Hyper-V Gen2 guests have no VGA, PS/2 or legacy ATA; touching those
+/// ports hangs the boot, so only firmware protocols may be used.
+pub fn legacy_devices_unavailable() -> bool {
+    detect_hypervisor() == Some(HypervisorKind::HyperV)
+}
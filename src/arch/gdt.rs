@@ -0,0 +1,187 @@
+//! GDT and 64-bit TSS loaded right before the kernel jump.
+//!
+//! The firmware's GDT lives in boot services memory and may be reclaimed once
+//! ExitBootServices has run, so the kernel is entered with this static one.
+//! The BIOS path loads it too, from 32-bit code, before calling an ELF32
+//! kernel or switching to long mode for an ELF64 one:
+//!
+//!   0x00  null
+//!   0x08  code, 64-bit, DPL 0
+//!   0x10  data, DPL 0
+//!   0x18  TSS (16-byte system descriptor, two slots)
+//!   0x28  code, 32-bit, DPL 0 (what the BIOS path runs on until long mode)
+
+use core::mem::size_of;
+
+pub const KERNEL_CODE_SELECTOR: u16 = 0x08;
+pub const KERNEL_DATA_SELECTOR: u16 = 0x10;
+pub const TSS_SELECTOR: u16 = 0x18;
+pub const KERNEL_CODE32_SELECTOR: u16 = 0x28;
+
+// Access byte
+const ACCESS_PRESENT: u8 = 1 << 7;
+const ACCESS_CODE_DATA: u8 = 1 << 4; // S: not a system segment
+const ACCESS_EXECUTABLE: u8 = 1 << 3;
+const ACCESS_RW: u8 = 1 << 1; // readable code / writable data
+const ACCESS_TSS_AVAILABLE: u8 = 0x9;
+
+// Flags nibble
+const FLAG_GRANULARITY_4K: u8 = 1 << 3;
+const FLAG_SIZE_32: u8 = 1 << 2;
+const FLAG_LONG_MODE: u8 = 1 << 1;
+
+/// Legacy 8-byte descriptor.
+const fn descriptor(base: u32, limit: u32, access: u8, flags: u8) -> u64 {
+    (limit as u64 & 0xFFFF)
+        | ((base as u64 & 0xFF_FFFF) << 16)
+        | ((access as u64) << 40)
+        | (((limit as u64 >> 16) & 0xF) << 48)
+        | (((flags & 0xF) as u64) << 52)
+        | (((base as u64 >> 24) & 0xFF) << 56)
+}
+
+/// 64-bit TSS. No interrupt stacks are set up; the kernel installs its own.
+#[repr(C, packed(4))]
+pub struct Tss {
+    reserved0: u32,
+    pub rsp: [u64; 3],
+    reserved1: u64,
+    pub ist: [u64; 7],
+    reserved2: u64,
+    reserved3: u16,
+    pub iomap_base: u16,
+}
+
+impl Tss {
+    pub const fn new() -> Self {
+        Self {
+            reserved0: 0,
+            rsp: [0; 3],
+            reserved1: 0,
+            ist: [0; 7],
+            reserved2: 0,
+            reserved3: 0,
+            // No I/O permission bitmap
+            iomap_base: size_of::<Tss>() as u16,
+        }
+    }
+}
+
+#[repr(C, packed)]
+struct GdtDescriptor {
+    limit: u16,
+    base: usize,
+}
+
+#[repr(C, align(8))]
+pub struct Gdt {
+    entries: [u64; 6],
+}
+
+impl Gdt {
+    pub fn new(tss: &'static Tss) -> Self {
+        let tss_base = tss as *const Tss as u64;
+        let tss_limit = (size_of::<Tss>() - 1) as u32;
+
+        let code = descriptor(
+            0,
+            0xFFFFF,
+            ACCESS_PRESENT | ACCESS_CODE_DATA | ACCESS_EXECUTABLE | ACCESS_RW,
+            FLAG_GRANULARITY_4K | FLAG_LONG_MODE,
+        );
+        let data = descriptor(
+            0,
+            0xFFFFF,
+            ACCESS_PRESENT | ACCESS_CODE_DATA | ACCESS_RW,
+            FLAG_GRANULARITY_4K | FLAG_SIZE_32,
+        );
+        let tss_low = descriptor(tss_base as u32, tss_limit, ACCESS_PRESENT | ACCESS_TSS_AVAILABLE, 0);
+        let tss_high = tss_base >> 32;
+        let code32 = descriptor(
+            0,
+            0xFFFFF,
+            ACCESS_PRESENT | ACCESS_CODE_DATA | ACCESS_EXECUTABLE | ACCESS_RW,
+            FLAG_GRANULARITY_4K | FLAG_SIZE_32,
+        );
+
+        Self { entries: [0, code, data, tss_low, tss_high, code32] }
+    }
+
+    /// `lgdt`, reload CS with a far return, point the data segment registers
+    /// at the data selector and load the task register.
+    ///
+    /// `self` must stay alive (and mapped) as long as these selectors are used.
+    #[cfg(target_arch = "x86_64")]
+    pub unsafe fn load(&'static self) {
+        let desc = GdtDescriptor {
+            limit: (size_of::<Self>() - 1) as u16,
+            base: self as *const Self as usize,
+        };
+        unsafe {
+            core::arch::asm!(
+                "lgdt [{desc}]",
+                "push {code}",
+                "lea {tmp}, [rip + 2f]",
+                "push {tmp}",
+                "retfq",
+                "2:",
+                "mov ds, {data:x}",
+                "mov es, {data:x}",
+                "mov fs, {data:x}",
+                "mov gs, {data:x}",
+                "mov ss, {data:x}",
+                "ltr {tss:x}",
+                desc = in(reg) &desc,
+                code = in(reg) KERNEL_CODE_SELECTOR as u64,
+                data = in(reg) KERNEL_DATA_SELECTOR as u64,
+                tss = in(reg) TSS_SELECTOR as u64,
+                tmp = out(reg) _,
+                options(preserves_flags)
+            );
+        }
+    }
+
+    /// 32-bit variant: `lgdt`, reload CS with the 32-bit code selector and
+    /// the data segment registers. The task register is left alone, since a
+    /// 64-bit TSS is only valid once in long mode; `long_mode` loads it after
+    /// the switch.
+    #[cfg(target_arch = "x86")]
+    pub unsafe fn load(&'static self) {
+        let desc = GdtDescriptor {
+            limit: (size_of::<Self>() - 1) as u16,
+            base: self as *const Self as usize,
+        };
+        unsafe {
+            core::arch::asm!(
+                "lgdt [{desc}]",
+                "push {code}",
+                "lea {tmp}, [2f]",
+                "push {tmp}",
+                "retf",
+                "2:",
+                "mov ds, {data:x}",
+                "mov es, {data:x}",
+                "mov fs, {data:x}",
+                "mov gs, {data:x}",
+                "mov ss, {data:x}",
+                desc = in(reg) &desc,
+                code = in(reg) KERNEL_CODE32_SELECTOR as u32,
+                data = in(reg) KERNEL_DATA_SELECTOR as u32,
+                tmp = out(reg) _,
+                options(preserves_flags)
+            );
+        }
+    }
+}
+
+static TSS: Tss = Tss::new();
+static mut GDT: Gdt = Gdt { entries: [0; 6] };
+
+/// Build the static GDT around the static TSS and load it.
+pub unsafe fn init() {
+    unsafe {
+        let gdt = &mut *core::ptr::addr_of_mut!(GDT);
+        *gdt = Gdt::new(&TSS);
+        (*core::ptr::addr_of!(GDT)).load();
+    }
+}
@@ -0,0 +1,136 @@
+//! HPET main counter as a timebase.
+//!
+//! Unlike the TSC it needs no calibration: `GCAP_ID` states the tick period
+//! in femtoseconds. Only the main counter is used; no comparators are armed.
+//! The registers are read in place through the identity mapping.
+
+use crate::acpi;
+use super::mmio::Mmio;
+
+// ===== HPET ACPI table =====
+/// Offset of the base address GAS; its 64-bit address field is 4 bytes in
+const HPET_TABLE_BASE_GAS: usize = 40;
+const HPET_TABLE_LEN: usize = 56;
+const GAS_SYSTEM_MEMORY: u8 = 0;
+
+// ===== Registers =====
+const GCAP_ID: usize = 0x00;
+const GEN_CONF: usize = 0x10;
+const MAIN_CNT: usize = 0xF0;
+
+const GEN_CONF_ENABLE: u32 = 1 << 0;
+const GCAP_COUNT_SIZE_64: u32 = 1 << 13;
+/// The spec caps the period at 100 ns
+const MAX_PERIOD_FS: u64 = 100_000_000;
+
+const FS_PER_NS: u128 = 1_000_000;
+const FS_PER_MS: u128 = 1_000_000_000_000;
+
+static mut HPET_BASE: usize = 0;
+static mut PERIOD_FS: u64 = 0;
+static mut COUNTER_64: bool = false;
+
+/// Find the HPET through ACPI and start its main counter.
+pub fn init() -> Result<(), &'static str> {
+    if is_initialized() {
+        return Ok(());
+    }
+    let table = acpi::find_table(b"HPET").ok_or("HPET: no ACPI table")? as usize;
+    let len = unsafe { acpi::read_u32(table + 4) } as usize;
+    if len < HPET_TABLE_LEN {
+        return Err("HPET: table too short");
+    }
+    if unsafe { acpi::read_u8(table + HPET_TABLE_BASE_GAS) } != GAS_SYSTEM_MEMORY {
+        return Err("HPET: registers not in memory space");
+    }
+    let base = unsafe { acpi::read_u64(table + HPET_TABLE_BASE_GAS + 4) };
+    if base == 0 || base > usize::MAX as u64 {
+        return Err("HPET: base address not addressable");
+    }
+    let base = base as usize;
+
+    let cap = Mmio::<u32>::new(base + GCAP_ID);
+    let cap_low = cap.read();
+    let period = cap.offset(1).read() as u64;
+    if period == 0 || period > MAX_PERIOD_FS {
+        return Err("HPET: invalid counter period");
+    }
+    let conf = Mmio::<u32>::new(base + GEN_CONF);
+    conf.write(conf.read() | GEN_CONF_ENABLE);
+
+    unsafe {
+        HPET_BASE = base;
+        PERIOD_FS = period;
+        COUNTER_64 = cap_low & GCAP_COUNT_SIZE_64 != 0;
+    }
+    debug_log!("hpet", "period {} fs", unsafe { PERIOD_FS });
+    Ok(())
+}
+
+pub fn is_initialized() -> bool {
+    unsafe { PERIOD_FS != 0 }
+}
+
+/// Main counter value. Read as two halves so it also works on the 32-bit
+/// build; the high half is re-read to catch a carry in between.
+fn counter() -> u64 {
+    let low_half = Mmio::<u32>::new(unsafe { HPET_BASE } + MAIN_CNT);
+    if !unsafe { COUNTER_64 } {
+        return low_half.read() as u64;
+    }
+    let high_half = low_half.offset(1);
+    loop {
+        let high = high_half.read();
+        let low = low_half.read();
+        if high_half.read() == high {
+            return ((high as u64) << 32) | low as u64;
+        }
+    }
+}
+
+/// Spin until `ticks` counter ticks have passed. A 32-bit counter wraps
+/// every few minutes, so elapsed time is taken modulo its width and long
+/// waits are split into half-range pieces.
+fn spin_ticks(mut ticks: u64) {
+    let wide = unsafe { COUNTER_64 };
+    if !wide {
+        const CHUNK: u64 = (u32::MAX / 2) as u64;
+        while ticks > CHUNK {
+            spin_ticks(CHUNK);
+            ticks -= CHUNK;
+        }
+    }
+    let start = counter();
+    loop {
+        let now = counter();
+        let elapsed = if wide { now.wrapping_sub(start) } else { (now as u32).wrapping_sub(start as u32) as u64 };
+        if elapsed >= ticks {
+            return;
+        }
+        core::hint::spin_loop();
+    }
+}
+
+/// Sleep for `ms` milliseconds. Returns at once if `init` has not succeeded.
+pub fn sleep_ms(ms: u64) {
+    if !is_initialized() {
+        return;
+    }
+    spin_ticks((ms as u128 * FS_PER_MS / unsafe { PERIOD_FS } as u128) as u64);
+}
+
+pub fn sleep_us(us: u64) {
+    if !is_initialized() {
+        return;
+    }
+    spin_ticks((us as u128 * FS_PER_NS * 1000 / unsafe { PERIOD_FS } as u128) as u64);
+}
+
+/// Nanoseconds since the counter was started (by us or by firmware), 0
+/// before `init`.
+pub fn now_ns() -> u64 {
+    if !is_initialized() {
+        return 0;
+    }
+    (counter() as u128 * unsafe { PERIOD_FS } as u128 / FS_PER_NS) as u64
+}
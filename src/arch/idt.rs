@@ -0,0 +1,251 @@
+//! Protected-mode IDT with CPU exception handlers for the BIOS path.
+//!
+//! Without it any fault triple-faults and the machine resets with nothing on
+//! screen. Vectors 0-31 get stubs that print the exception, its error code
+//! and the faulting EIP to VGA, then halt. Hardware IRQs stay masked.
+
+use core::arch::global_asm;
+
+use crate::drivers::vga;
+
+const IDT_ENTRIES: usize = 256;
+const EXCEPTION_COUNT: usize = 32;
+
+const GATE_PRESENT: u8 = 1 << 7;
+const GATE_INTERRUPT_32: u8 = 0xE;
+
+const VEC_DOUBLE_FAULT: u32 = 8;
+const VEC_GENERAL_PROTECTION: u32 = 13;
+const VEC_PAGE_FAULT: u32 = 14;
+
+const EXCEPTION_NAMES: [&str; EXCEPTION_COUNT] = [
+    "divide error",
+    "debug",
+    "NMI",
+    "breakpoint",
+    "overflow",
+    "bound range exceeded",
+    "invalid opcode",
+    "device not available",
+    "double fault",
+    "coprocessor segment overrun",
+    "invalid TSS",
+    "segment not present",
+    "stack-segment fault",
+    "general protection fault",
+    "page fault",
+    "reserved",
+    "x87 floating-point error",
+    "alignment check",
+    "machine check",
+    "SIMD floating-point error",
+    "virtualization exception",
+    "control protection",
+    "reserved",
+    "reserved",
+    "reserved",
+    "reserved",
+    "reserved",
+    "reserved",
+    "hypervisor injection",
+    "VMM communication",
+    "security exception",
+    "reserved",
+];
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct IdtEntry {
+    offset_low: u16,
+    selector: u16,
+    zero: u8,
+    type_attr: u8,
+    offset_high: u16,
+}
+
+impl IdtEntry {
+    const fn missing() -> Self {
+        Self { offset_low: 0, selector: 0, zero: 0, type_attr: 0, offset_high: 0 }
+    }
+}
+
+#[repr(C, packed)]
+struct IdtDescriptor {
+    limit: u16,
+    base: u32,
+}
+
+#[repr(C, align(8))]
+pub struct Idt {
+    entries: [IdtEntry; IDT_ENTRIES],
+}
+
+impl Idt {
+    pub const fn new() -> Self {
+        Self { entries: [IdtEntry::missing(); IDT_ENTRIES] }
+    }
+
+    /// Install an interrupt gate for `vec` running `handler` at the current
+    /// code selector. `dpl` is the lowest privilege allowed to `int` it.
+    /// 32-bit gates have no IST field: `ist` keeps the call shape of the
+    /// long-mode layout and must be 0.
+    pub fn set_handler(&mut self, vec: u8, handler: usize, ist: u8, dpl: u8) {
+        debug_assert!(ist == 0, "no IST in protected mode");
+        let cs: u16;
+        unsafe {
+            core::arch::asm!("mov {:x}, cs", out(reg) cs, options(nomem, nostack, preserves_flags));
+        }
+        self.entries[vec as usize] = IdtEntry {
+            offset_low: handler as u16,
+            selector: cs,
+            zero: 0,
+            type_attr: GATE_PRESENT | ((dpl & 3) << 5) | GATE_INTERRUPT_32,
+            offset_high: (handler >> 16) as u16,
+        };
+    }
+
+    /// Point vectors 0-31 at the exception stubs.
+    pub fn install_exception_handlers(&mut self) {
+        let table = unsafe { &*core::ptr::addr_of!(isr_stub_table) };
+        for (vec, &stub) in table.iter().enumerate() {
+            self.set_handler(vec as u8, stub as usize, 0, 0);
+        }
+    }
+
+    pub unsafe fn load(&'static self) {
+        let desc = IdtDescriptor {
+            limit: (core::mem::size_of::<Self>() - 1) as u16,
+            base: self as *const Self as u32,
+        };
+        unsafe {
+            core::arch::asm!("lidt [{}]", in(reg) &desc, options(readonly, nostack, preserves_flags));
+        }
+    }
+}
+
+static mut IDT: Idt = Idt::new();
+
+/// Install the exception handlers and load the IDT.
+pub fn init() {
+    unsafe {
+        let idt = &mut *core::ptr::addr_of_mut!(IDT);
+        idt.install_exception_handlers();
+        (*core::ptr::addr_of!(IDT)).load();
+    }
+}
+
+// ===== Exception stubs =====
+//
+// Each stub pushes a dummy error code where the CPU does not push one, then
+// its vector, so `isr_common` always sees the same frame.
+
+unsafe extern "C" {
+    static isr_stub_table: [u32; EXCEPTION_COUNT];
+}
+
+global_asm!(
+    ".pushsection .text",
+    ".irp vec, 0,1,2,3,4,5,6,7,9,15,16,18,19,20,22,23,24,25,26,27,28,31",
+    "isr_stub_\\vec:",
+    "    push 0",
+    "    push \\vec",
+    "    jmp isr_common",
+    ".endr",
+    ".irp vec, 8,10,11,12,13,14,17,21,29,30",
+    "isr_stub_\\vec:",
+    "    push \\vec",
+    "    jmp isr_common",
+    ".endr",
+    "isr_common:",
+    "    pushad",
+    "    mov eax, cr2",
+    "    push eax",
+    "    push esp",
+    "    call {dispatch}",
+    "2:  cli",
+    "    hlt",
+    "    jmp 2b",
+    ".popsection",
+    ".pushsection .rodata",
+    ".balign 4",
+    ".global isr_stub_table",
+    "isr_stub_table:",
+    ".irp vec, 0,1,2,3,4,5,6,7,8,9,10,11,12,13,14,15,16,17,18,19,20,21,22,23,24,25,26,27,28,29,30,31",
+    "    .long isr_stub_\\vec",
+    ".endr",
+    ".popsection",
+    dispatch = sym exception_dispatch,
+);
+
+/// Stack layout built by `isr_common`, lowest address first.
+#[repr(C)]
+struct ExceptionFrame {
+    cr2: u32,
+    // pushad
+    edi: u32,
+    esi: u32,
+    ebp: u32,
+    esp: u32,
+    ebx: u32,
+    edx: u32,
+    ecx: u32,
+    eax: u32,
+    vector: u32,
+    error_code: u32,
+    // pushed by the CPU
+    eip: u32,
+    cs: u32,
+    eflags: u32,
+}
+
+extern "C" fn exception_dispatch(frame: &ExceptionFrame) -> ! {
+    let vector = frame.vector;
+
+    if vector == VEC_DOUBLE_FAULT {
+        // The stack may be what faulted: print as little as possible
+        vga::print_error("\n[cpu] double fault\n");
+        halt();
+    }
+
+    vga::print_error("\n[cpu] exception ");
+    vga::print_dec_u32(vector);
+    vga::print_error(": ");
+    vga::print_error(EXCEPTION_NAMES.get(vector as usize).copied().unwrap_or("unknown"));
+    vga::print_string("\n eip=");
+    vga::print_hex32(frame.eip);
+    vga::print_string(" cs=");
+    vga::print_hex32(frame.cs);
+    vga::print_string(" eflags=");
+    vga::print_hex32(frame.eflags);
+    vga::print_string(" error=");
+    vga::print_hex32(frame.error_code);
+
+    match vector {
+        VEC_PAGE_FAULT => {
+            vga::print_string("\n fault address=");
+            vga::print_hex32(frame.cr2);
+            // Bit 0: protection violation, 1: write, 2: user mode
+            vga::print_string(if frame.error_code & 1 != 0 { " (protection" } else { " (not present" });
+            vga::print_string(if frame.error_code & 2 != 0 { ", write)" } else { ", read)" });
+        }
+        VEC_GENERAL_PROTECTION if frame.error_code != 0 => {
+            // Segment selector error code: index in bits 15:3, bit 1 IDT, bit 2 LDT
+            vga::print_string("\n selector=");
+            vga::print_hex32(frame.error_code & 0xFFF8);
+            if frame.error_code & 2 != 0 {
+                vga::print_string(" (IDT)");
+            }
+        }
+        _ => {}
+    }
+    vga::print_string("\n Halted\n");
+    halt()
+}
+
+fn halt() -> ! {
+    loop {
+        unsafe {
+            core::arch::asm!("cli", "hlt", options(nomem, nostack));
+        }
+    }
+}
@@ -0,0 +1,130 @@
+//! Typed x86 I/O ports.
+//!
+//! `IoPort<u8>`, `IoPort<u16>` and `IoPort<u32>` issue `in`/`out` of the
+//! matching width, so a port's access size is part of its declaration:
+//!
+//! ```ignore
+//! const KBC_DATA: IoPort<u8> = IoPort::new(0x60);
+//! let scan = KBC_DATA.read();
+//! ```
+//!
+//! Port I/O touches no memory Rust knows about, so `read` and `write` are
+//! safe; what a device does in response is the driver's business, as it
+//! always was.
+
+use core::arch::asm;
+use core::marker::PhantomData;
+
+mod sealed {
+    pub trait Sealed {}
+    impl Sealed for u8 {}
+    impl Sealed for u16 {}
+    impl Sealed for u32 {}
+}
+
+/// A value `in`/`out` can transfer: `u8`, `u16` or `u32`.
+pub trait PortValue: Copy + sealed::Sealed {
+    /// # Safety
+    /// Reading some ports has device side effects the caller must expect.
+    unsafe fn read_from_port(port: u16) -> Self;
+    /// # Safety
+    /// Writing some ports has device side effects the caller must expect.
+    unsafe fn write_to_port(port: u16, value: Self);
+}
+
+impl PortValue for u8 {
+    #[inline(always)]
+    unsafe fn read_from_port(port: u16) -> Self {
+        let val: u8;
+        unsafe {
+            asm!("in al, dx", in("dx") port, out("al") val, options(nomem, nostack, preserves_flags));
+        }
+        val
+    }
+
+    #[inline(always)]
+    unsafe fn write_to_port(port: u16, value: Self) {
+        unsafe {
+            asm!("out dx, al", in("dx") port, in("al") value, options(nomem, nostack, preserves_flags));
+        }
+    }
+}
+
+impl PortValue for u16 {
+    #[inline(always)]
+    unsafe fn read_from_port(port: u16) -> Self {
+        let val: u16;
+        unsafe {
+            asm!("in ax, dx", in("dx") port, out("ax") val, options(nomem, nostack, preserves_flags));
+        }
+        val
+    }
+
+    #[inline(always)]
+    unsafe fn write_to_port(port: u16, value: Self) {
+        unsafe {
+            asm!("out dx, ax", in("dx") port, in("ax") value, options(nomem, nostack, preserves_flags));
+        }
+    }
+}
+
+impl PortValue for u32 {
+    #[inline(always)]
+    unsafe fn read_from_port(port: u16) -> Self {
+        let val: u32;
+        unsafe {
+            asm!("in eax, dx", in("dx") port, out("eax") val, options(nomem, nostack, preserves_flags));
+        }
+        val
+    }
+
+    #[inline(always)]
+    unsafe fn write_to_port(port: u16, value: Self) {
+        unsafe {
+            asm!("out dx, eax", in("dx") port, in("eax") value, options(nomem, nostack, preserves_flags));
+        }
+    }
+}
+
+/// An I/O port accessed `T`-wide.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct IoPort<T: PortValue> {
+    addr: u16,
+    _width: PhantomData<T>,
+}
+
+impl<T: PortValue> IoPort<T> {
+    pub const fn new(addr: u16) -> Self {
+        Self { addr, _width: PhantomData }
+    }
+
+    pub const fn addr(&self) -> u16 {
+        self.addr
+    }
+
+    /// The port `offset` above this one, same width (register blocks like a
+    /// UART's or an ATA channel's).
+    pub const fn offset(&self, offset: u16) -> Self {
+        Self::new(self.addr + offset)
+    }
+
+    #[inline(always)]
+    pub fn read(&self) -> T {
+        unsafe { T::read_from_port(self.addr) }
+    }
+
+    #[inline(always)]
+    pub fn write(&self, value: T) {
+        unsafe { T::write_to_port(self.addr, value) }
+    }
+}
+
+/// POST code port: nothing listens, but a write takes about 1 us on ISA-era
+/// buses and real or emulated chipsets alike.
+const POST_PORT: IoPort<u8> = IoPort::new(0x80);
+
+/// Roughly 1 us pause between accesses to slow legacy devices.
+#[inline(always)]
+pub fn io_wait() {
+    POST_PORT.write(0);
+}
@@ -0,0 +1,116 @@
+//! KVM paravirtual clock (kvmclock).
+//!
+//! With `KVM_FEATURE_CLOCKSOURCE2` the host keeps a `pvclock_vcpu_time_info`
+//! record up to date in a guest page registered through
+//! `MSR_KVM_SYSTEM_TIME_NEW`. Reading it needs no port I/O, which avoids the
+//! PIT emulation latency on overcommitted hosts.
+
+#[cfg(target_arch = "x86")]
+use core::arch::x86::{__cpuid, _rdtsc};
+#[cfg(target_arch = "x86_64")]
+use core::arch::x86_64::{__cpuid, _rdtsc};
+use core::ptr;
+
+use super::cpuid::{self, HypervisorKind};
+use crate::memory;
+
+const CPUID_KVM_FEATURES: u32 = 0x4000_0001;
+const KVM_FEATURE_CLOCKSOURCE2: u32 = 1 << 3;
+const MSR_KVM_SYSTEM_TIME_NEW: u32 = 0x4B56_4D01;
+const KVM_SYSTEM_TIME_ENABLE: u64 = 1;
+
+/// Layout shared with the host (`struct pvclock_vcpu_time_info`).
+#[repr(C)]
+struct KvmClockData {
+    version: u32,
+    pad0: u32,
+    tsc_timestamp: u64,
+    system_time: u64,
+    tsc_to_system_mul: u32,
+    tsc_shift: i8,
+    flags: u8,
+    pad: [u8; 2],
+}
+
+static mut CLOCK_PAGE: *const KvmClockData = ptr::null();
+
+/// Register a clock page with the host. Returns false when not running
+/// under KVM, when the feature is missing, or when no page is available.
+pub fn init() -> bool {
+    if cpuid::detect_hypervisor() != Some(HypervisorKind::Kvm) {
+        return false;
+    }
+    let features = __cpuid(CPUID_KVM_FEATURES);
+    if (features.eax & KVM_FEATURE_CLOCKSOURCE2) == 0 {
+        return false;
+    }
+
+    let page = match memory::allocate_pages(1) {
+        Ok(p) => p,
+        Err(_) => return false,
+    };
+    unsafe {
+        ptr::write_bytes(page, 0, core::mem::size_of::<KvmClockData>());
+        wrmsr(MSR_KVM_SYSTEM_TIME_NEW, page as usize as u64 | KVM_SYSTEM_TIME_ENABLE);
+        CLOCK_PAGE = page as *const KvmClockData;
+    }
+    true
+}
+
+pub fn is_available() -> bool {
+    unsafe { !CLOCK_PAGE.is_null() }
+}
+
+/// Nanoseconds of host system time. Returns 0 if `init` did not succeed.
+pub fn kvm_read_clock_ns() -> u64 {
+    let clock = unsafe { CLOCK_PAGE };
+    if clock.is_null() {
+        return 0;
+    }
+
+    loop {
+        // The host bumps `version` to an odd value while updating the record
+        let version = unsafe { ptr::read_volatile(ptr::addr_of!((*clock).version)) };
+        if version & 1 != 0 {
+            core::hint::spin_loop();
+            continue;
+        }
+        core::sync::atomic::fence(core::sync::atomic::Ordering::Acquire);
+
+        let (tsc_timestamp, system_time, mul, shift) = unsafe {
+            (
+                ptr::read_volatile(ptr::addr_of!((*clock).tsc_timestamp)),
+                ptr::read_volatile(ptr::addr_of!((*clock).system_time)),
+                ptr::read_volatile(ptr::addr_of!((*clock).tsc_to_system_mul)),
+                ptr::read_volatile(ptr::addr_of!((*clock).tsc_shift)),
+            )
+        };
+        let tsc = unsafe { _rdtsc() };
+
+        core::sync::atomic::fence(core::sync::atomic::Ordering::Acquire);
+        if unsafe { ptr::read_volatile(ptr::addr_of!((*clock).version)) } != version {
+            continue;
+        }
+
+        let mut delta = tsc.wrapping_sub(tsc_timestamp);
+        if shift >= 0 {
+            delta <<= shift;
+        } else {
+            delta >>= -shift;
+        }
+        let scaled = ((delta as u128 * mul as u128) >> 32) as u64;
+        return system_time.wrapping_add(scaled);
+    }
+}
+
+unsafe fn wrmsr(msr: u32, value: u64) {
+    unsafe {
+        core::arch::asm!(
+            "wrmsr",
+            in("ecx") msr,
+            in("eax") value as u32,
+            in("edx") (value >> 32) as u32,
+            options(nostack, preserves_flags)
+        );
+    }
+}
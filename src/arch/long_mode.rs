@@ -0,0 +1,86 @@
+//! 32-bit protected mode -> 64-bit long mode switch for the BIOS path.
+//!
+//! The BIOS path reaches Rust in flat 32-bit protected mode (the real-mode
+//! stub has already set CR0.PE and far-jumped into 32-bit code). From there
+//! `enter_long_mode` performs the remaining steps:
+//! 1. load `pml4` into CR3
+//! 2. enable PAE (CR4 bit 5)
+//! 3. set EFER.LME (MSR 0xC0000080 bit 8)
+//! 4. enable paging (CR0 bit 31)
+//! 5. far-jump to the 64-bit code selector, load the TSS and call `entry64`
+//!
+//! The selectors are those of `arch::gdt`, which must already be loaded
+//! (`gdt::init`); the 32-bit code segment it adds is what runs until step 5.
+//!
+//! Calling convention: cdecl `enter_long_mode(pml4, entry64, arg)`.
+//! `entry64` is then called as a SysV64 function with `arg` zero-extended in
+//! RDI, a 16-byte aligned stack and flat data segments. `pml4` and `entry64`
+//! are physical addresses below 4 GiB, and `pml4` must identity-map at least
+//! this code, the stack and `entry64`.
+//!
+//! No `BootInfo` is handed over this way: its pointer and `usize` fields are
+//! 4 bytes wide in this i686 build, so a 64-bit kernel would misread it.
+//! stage2 passes 0.
+
+use core::arch::naked_asm;
+
+use crate::arch::gdt::{KERNEL_CODE_SELECTOR, KERNEL_DATA_SELECTOR, TSS_SELECTOR};
+
+/// Switch to long mode and call `entry64(arg)`. Never returns; if
+/// `entry64` returns, the CPU is halted.
+///
+/// # Safety
+/// Interrupts must be masked (no 64-bit IDT exists yet), `arch::gdt` must be
+/// loaded, `pml4` must point to valid 4-level tables and every address must
+/// be identity-mapped.
+#[unsafe(naked)]
+pub unsafe extern "C" fn enter_long_mode(pml4: u32, entry64: u32, arg: u32) -> ! {
+    naked_asm!(
+        "cli",
+        "mov edi, [esp + 12]", // arg -> RDI for entry64
+        "mov esi, [esp + 8]",  // entry64
+        "mov eax, [esp + 4]",  // pml4
+        "mov cr3, eax",
+        // CR4.PAE
+        "mov eax, cr4",
+        "or eax, 1 << 5",
+        "mov cr4, eax",
+        // EFER.LME
+        "mov ecx, 0xC0000080",
+        "rdmsr",
+        "or eax, 1 << 8",
+        "wrmsr",
+        // CR0.PG (+PE, already set); this activates long mode (compat)
+        "mov eax, cr0",
+        "or eax, 0x80000001",
+        "mov cr0, eax",
+        // Far return into the 64-bit code segment
+        "push {code64}",
+        "lea eax, [2f]",
+        "push eax",
+        "retf",
+        ".code64",
+        "2:",
+        "mov ax, {data}",
+        "mov ds, ax",
+        "mov es, ax",
+        "mov ss, ax",
+        "mov fs, ax",
+        "mov gs, ax",
+        "mov ax, {tss}",
+        "ltr ax",
+        // Upper halves of GPRs are undefined after the switch
+        "mov edi, edi",
+        "mov esi, esi",
+        "mov esp, esp",
+        "and rsp, -16",
+        "call rsi",
+        "3:",
+        "hlt",
+        "jmp 3b",
+        ".code32",
+        code64 = const KERNEL_CODE_SELECTOR,
+        data = const KERNEL_DATA_SELECTOR,
+        tss = const TSS_SELECTOR,
+    )
+}
@@ -0,0 +1,55 @@
+//! Volatile MMIO register access.
+//!
+//! Plain pointer reads/writes to device registers may be merged, reordered
+//! or dropped by the compiler. Every MMIO access in the drivers goes through
+//! `Mmio<T>`, which only ever uses `read_volatile`/`write_volatile`:
+//!
+//! ```ignore
+//! let csts = Mmio::<u32>::new(bar + REG_CSTS);
+//! while csts.read() & CSTS_RDY == 0 {}
+//! ```
+//!
+//! Addresses are physical and must be identity-mapped. On the 32-bit BIOS
+//! build they must also lie below 4 GiB, which is why `new` takes a `usize`:
+//! callers convert (and range-check) firmware's 64-bit addresses once.
+
+use core::ptr::NonNull;
+
+/// A device register (or array of registers) of type `T`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Mmio<T: Copy>(NonNull<T>);
+
+impl<T: Copy> Clone for Mmio<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T: Copy> Copy for Mmio<T> {}
+
+impl<T: Copy> Mmio<T> {
+    /// Register at physical address `phys`, which must be mapped device
+    /// memory aligned for `T`. Panics on a null address.
+    pub fn new(phys: usize) -> Self {
+        Self(NonNull::new(phys as *mut T).expect("MMIO register at address 0"))
+    }
+
+    pub fn addr(&self) -> usize {
+        self.0.as_ptr() as usize
+    }
+
+    #[inline(always)]
+    pub fn read(&self) -> T {
+        unsafe { core::ptr::read_volatile(self.0.as_ptr()) }
+    }
+
+    #[inline(always)]
+    pub fn write(&self, v: T) {
+        unsafe { core::ptr::write_volatile(self.0.as_ptr(), v) }
+    }
+
+    /// The `i`-th `T` after this one, for register arrays such as doorbells.
+    pub fn offset(self, i: usize) -> Self {
+        Self(unsafe { self.0.add(i) })
+    }
+}
@@ -0,0 +1,15 @@
+pub mod cpuid;
+pub mod gdt;
+pub mod hpet;
+#[cfg(feature = "bios")]
+pub mod idt;
+pub mod io;
+#[cfg(feature = "bios")]
+pub mod kvmclock;
+#[cfg(feature = "bios")]
+pub mod long_mode;
+pub mod mmio;
+pub mod time;
+#[cfg(feature = "bios")]
+pub mod timer;
+pub mod x86;
@@ -0,0 +1,95 @@
+//! TSC-based delays calibrated against the PIT (or the HPET without one).
+//!
+//! `io::io_wait` port 0x80 writes only approximate 1 us and measure
+//! nothing; once the TSC is calibrated, `busy_wait_us` spins on RDTSC for a
+//! real interval. Before that it uses the HPET when `hpet::init` succeeded.
+
+use super::hpet;
+use super::io::{self, IoPort};
+use super::x86::rdtsc;
+
+// ===== PIT (8253/8254) =====
+const PIT_CH2_DATA: IoPort<u8> = IoPort::new(0x42);
+const PIT_COMMAND: IoPort<u8> = IoPort::new(0x43);
+const PIT_GATE_PORT: IoPort<u8> = IoPort::new(0x61); // bit 0: ch2 gate, bit 1: speaker, bit 5: ch2 output
+const PIT_HZ: u64 = 1_193_182;
+const CALIBRATION_MS: u64 = 10;
+/// Gate polls before the PIT is assumed missing (no legacy timer)
+const CALIBRATION_POLLS: u32 = 10_000_000;
+
+static mut TICKS_PER_MS: u64 = 0;
+
+/// Measure TSC ticks per millisecond over a 10 ms one-shot on PIT channel 2
+/// (the speaker channel, so the system timer on channel 0 is untouched) and
+/// store the result for `busy_wait_us`. Returns 0, leaving delays on the
+/// port 0x80 fallback, when the PIT never reaches terminal count.
+pub fn calibrate_tsc_from_pit() -> u64 {
+    let count = (PIT_HZ * CALIBRATION_MS / 1000) as u16;
+    let ticks = {
+        // Gate off, speaker off; ch2, lobyte/hibyte, mode 0
+        let gate = PIT_GATE_PORT.read() & !0x03;
+        PIT_GATE_PORT.write(gate);
+        PIT_COMMAND.write(0xB0);
+        PIT_CH2_DATA.write(count as u8);
+        PIT_CH2_DATA.write((count >> 8) as u8);
+
+        // Raising the gate starts the count; OUT goes high at terminal count
+        PIT_GATE_PORT.write(gate | 0x01);
+        let start = rdtsc();
+        let mut polls = 0;
+        while (PIT_GATE_PORT.read() & 0x20) == 0 && polls < CALIBRATION_POLLS {
+            polls += 1;
+            core::hint::spin_loop();
+        }
+        let end = rdtsc();
+        PIT_GATE_PORT.write(gate);
+
+        if polls == CALIBRATION_POLLS { 0 } else { (end - start) / CALIBRATION_MS }
+    };
+    unsafe { TICKS_PER_MS = ticks };
+    ticks
+}
+
+/// Same measurement against the HPET main counter, for machines without a
+/// PIT. Returns 0 if the HPET is not initialised.
+pub fn calibrate_tsc_from_hpet() -> u64 {
+    if !hpet::is_initialized() {
+        return 0;
+    }
+    let start = rdtsc();
+    hpet::sleep_ms(CALIBRATION_MS);
+    let ticks = (rdtsc() - start) / CALIBRATION_MS;
+    unsafe { TICKS_PER_MS = ticks };
+    ticks
+}
+
+/// TSC ticks per millisecond, 0 until calibrated.
+pub fn ticks_per_ms() -> u64 {
+    unsafe { TICKS_PER_MS }
+}
+
+pub fn is_calibrated() -> bool {
+    ticks_per_ms() != 0
+}
+
+/// Spin for `us` microseconds. Before calibration this waits on the HPET,
+/// or failing that does one `io_wait` per microsecond.
+pub fn busy_wait_us(us: u64) {
+    let ticks_per_ms = ticks_per_ms();
+    if ticks_per_ms == 0 {
+        if hpet::is_initialized() {
+            hpet::sleep_us(us);
+            return;
+        }
+        for _ in 0..us {
+            io::io_wait();
+        }
+        return;
+    }
+
+    let start = rdtsc();
+    let deadline = start.saturating_add(us.saturating_mul(ticks_per_ms) / 1000);
+    while rdtsc() < deadline {
+        core::hint::spin_loop();
+    }
+}
@@ -0,0 +1,21 @@
+//! Busy-wait delays.
+
+use super::io;
+use super::kvmclock;
+
+/// Spin for roughly `ms` milliseconds. Uses kvmclock when registered,
+/// otherwise falls back to port 0x80 writes (about 1 us each on real and
+/// emulated ISA buses).
+pub fn sleep_ms(ms: u64) {
+    if kvmclock::is_available() {
+        let deadline = kvmclock::kvm_read_clock_ns().saturating_add(ms * 1_000_000);
+        while kvmclock::kvm_read_clock_ns() < deadline {
+            core::hint::spin_loop();
+        }
+        return;
+    }
+
+    for _ in 0..ms * 1000 {
+        io::io_wait();
+    }
+}
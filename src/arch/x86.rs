@@ -0,0 +1,74 @@
+//! TSC time base for log timestamps, and RDRAND.
+
+#[cfg(target_arch = "x86")]
+use core::arch::x86::_rdtsc;
+#[cfg(target_arch = "x86_64")]
+use core::arch::x86_64::_rdtsc;
+
+/// RDRAND may transiently fail when the DRNG is drained; Intel suggests 10 tries
+const RDRAND_RETRIES: u32 = 10;
+
+static mut BOOT_TSC: u64 = 0;
+static mut TSC_MHZ: u64 = 0;
+
+#[inline(always)]
+pub fn rdtsc() -> u64 {
+    unsafe { _rdtsc() }
+}
+
+/// 32 random bits from RDRAND, `None` if it keeps failing. The caller must
+/// check `CpuInfo::has_rdrand` first; the instruction is #UD without it.
+pub fn rdrand32() -> Option<u32> {
+    for _ in 0..RDRAND_RETRIES {
+        let value: u32;
+        let ok: u8;
+        unsafe {
+            core::arch::asm!(
+                "rdrand {value:e}",
+                "setc {ok}",
+                value = out(reg) value,
+                ok = out(reg_byte) ok,
+                options(nomem, nostack)
+            );
+        }
+        if ok != 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+/// Calibrate the TSC and start the log clock at zero. The HPET is brought
+/// up first and used for calibration if the PIT does not respond.
+pub fn init_tsc_clock() {
+    if let Err(e) = super::hpet::init() {
+        debug_log!("time", "{}", e);
+    }
+    let mut ticks = super::time::calibrate_tsc_from_pit();
+    if ticks == 0 {
+        ticks = super::time::calibrate_tsc_from_hpet();
+    }
+    let mhz = ticks / 1000;
+    unsafe {
+        BOOT_TSC = rdtsc();
+        TSC_MHZ = mhz;
+    }
+}
+
+pub fn tsc_clock_ready() -> bool {
+    unsafe { TSC_MHZ != 0 }
+}
+
+/// Milliseconds since the first call (or `init_tsc_clock`); 0 until calibrated.
+pub fn tsc_ms() -> u64 {
+    let now = rdtsc();
+    unsafe {
+        if BOOT_TSC == 0 {
+            BOOT_TSC = now;
+        }
+        if TSC_MHZ == 0 {
+            return 0;
+        }
+        (now - BOOT_TSC) / (TSC_MHZ * 1000)
+    }
+}
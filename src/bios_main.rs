@@ -0,0 +1,62 @@
+//! BIOS entry point (`feature = "bios"`).
+//!
+//! The real-mode MBR stub loads this image at the linker script address and
+//! jumps to `_start` in 32-bit protected mode.
+
+use core::panic::PanicInfo;
+
+use crate::arch::{cpuid, kvmclock};
+use crate::boot::stage2;
+use crate::{acpi, arch, drivers, memory};
+
+const PANIC_REBOOT_DELAY_MS: u64 = 5000;
+const SERIAL_BAUD: u32 = 115_200;
+
+#[unsafe(no_mangle)]
+pub extern "C" fn _start() -> ! {
+    // First, so headless machines see everything that follows
+    let serial = drivers::serial::init(drivers::serial::COM1, SERIAL_BAUD);
+    // No VGA hardware to program on Hyper-V Gen2
+    if !cpuid::legacy_devices_unavailable() {
+        drivers::vga::init();
+    }
+    if let Err(e) = serial {
+        drivers::vga::print_string(e);
+        drivers::vga::print_string("\n");
+    }
+    // Faults from here on are reported instead of resetting the machine
+    arch::idt::init();
+    memory::init();
+    if kvmclock::init() {
+        drivers::vga::print_string("[timer] using KVM paravirtual clock\n");
+    }
+    stage2::start()
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    drivers::vga::print_error("\n[panic] phase ");
+    drivers::vga::print_error(stage2::current_phase().name());
+    drivers::vga::print_error(": ");
+    drivers::vga::print_error(info.message().as_str().unwrap_or("<no message>"));
+    dump_registers();
+    drivers::vga::print_string("\nRebooting in 5 seconds...\n");
+    // Leave the message on screen for a moment, then reset as the last resort
+    arch::timer::sleep_ms(PANIC_REBOOT_DELAY_MS);
+    acpi::fadt::acpi_reset()
+}
+
+/// Control registers and the stack pointer at the time of the panic.
+fn dump_registers() {
+    let (esp, cr0, cr2, cr3): (u32, u32, u32, u32);
+    unsafe {
+        core::arch::asm!("mov {}, esp", out(reg) esp, options(nomem, nostack, preserves_flags));
+        core::arch::asm!("mov {}, cr0", out(reg) cr0, options(nomem, nostack, preserves_flags));
+        core::arch::asm!("mov {}, cr2", out(reg) cr2, options(nomem, nostack, preserves_flags));
+        core::arch::asm!("mov {}, cr3", out(reg) cr3, options(nomem, nostack, preserves_flags));
+    }
+    for (name, value) in [("\n esp=", esp), (" cr0=", cr0), (" cr2=", cr2), (" cr3=", cr3)] {
+        drivers::vga::print_string(name);
+        drivers::vga::print_hex32(value);
+    }
+}
@@ -0,0 +1,262 @@
+//! Hand-off structure passed to the kernel.
+//!
+//! 64-bit kernels booted through UEFI are entered with the SysV64 ABI (the
+//! BIOS path passes no `BootInfo`, see `arch::long_mode`):
+//!   RDI  `*const BootInfo` (in LOADER_DATA pages, never reclaimed by us)
+//!   R8   boot status word (built by `kernel::loader::boot_status_word`):
+//!        bits  7:0  filesystem the kernel was read from (BOOT_FS_*)
+//!        bits 15:8  disk driver used (BOOT_DISK_*)
+//!        bits 23:16 1 if KASLR relocated the kernel, else 0
+//!   RSP  16-byte aligned, minus the return address of the `call`
+//!
+//! Kernels that ignore RDI/R8 are unaffected.
+
+#[cfg(feature = "uefi")]
+use uefi::table::boot::{AllocateType, BootServices, MemoryDescriptor, MemoryType};
+
+use crate::acpi::madt::MadtInfo;
+use crate::acpi::srat::SratInfo;
+use crate::memory::manager::MemoryRegionType;
+
+/// Max bytes of the boot volume label (UTF-8, truncated)
+pub const VOLUME_LABEL_MAX: usize = 32;
+
+/// Pages allocated for the `BootInfo`
+const BOOT_INFO_PAGES: usize = 2;
+
+/// Layout of `FramebufferDescriptor::format`
+pub const FB_FORMAT_NONE: u32 = 0;
+pub const FB_FORMAT_RGB: u32 = 1;
+pub const FB_FORMAT_BGR: u32 = 2;
+pub const FB_FORMAT_BITMASK: u32 = 3;
+
+/// Firmware-independent memory region type.
+#[repr(u32)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BootMemoryType {
+    Usable = 1,
+    /// Firmware, MMIO and anything not listed below; never touch
+    Reserved = 2,
+    /// ACPI tables; usable once the kernel has parsed them
+    AcpiReclaimable = 3,
+    /// Must be preserved across sleep states
+    AcpiNvs = 4,
+    /// Loader image and firmware boot services memory; free once the kernel
+    /// no longer needs anything the loader handed over
+    BootloaderReclaimable = 5,
+    /// Kernel image, initrd, this `BootInfo` and the memory map itself
+    KernelAndModules = 6,
+    Framebuffer = 7,
+    BadMemory = 8,
+}
+
+/// One entry of `BootInfo::memory_map_base`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct BootMemoryRegion {
+    pub base: u64,
+    pub length: u64,
+    pub ty: BootMemoryType,
+}
+
+/// Everything LOADER_DATA is ours to hand to the kernel; LOADER_CODE is the
+/// loader itself.
+#[cfg(feature = "uefi")]
+pub fn uefi_to_boot_type(ty: MemoryType) -> BootMemoryType {
+    match ty {
+        MemoryType::CONVENTIONAL => BootMemoryType::Usable,
+        MemoryType::LOADER_CODE | MemoryType::BOOT_SERVICES_CODE | MemoryType::BOOT_SERVICES_DATA => {
+            BootMemoryType::BootloaderReclaimable
+        }
+        MemoryType::LOADER_DATA => BootMemoryType::KernelAndModules,
+        MemoryType::ACPI_RECLAIM => BootMemoryType::AcpiReclaimable,
+        MemoryType::ACPI_NON_VOLATILE => BootMemoryType::AcpiNvs,
+        MemoryType::UNUSABLE => BootMemoryType::BadMemory,
+        // RUNTIME_SERVICES_*, MMIO, MMIO_PORT_SPACE, PAL_CODE, PERSISTENT_MEMORY,
+        // RESERVED and OEM/OS-defined types
+        _ => BootMemoryType::Reserved,
+    }
+}
+
+/// Category of a region of the BIOS path's static table.
+pub fn region_to_boot_type(ty: MemoryRegionType) -> BootMemoryType {
+    match ty {
+        MemoryRegionType::Available => BootMemoryType::Usable,
+        MemoryRegionType::Reserved => BootMemoryType::Reserved,
+        MemoryRegionType::AcpiReclaim => BootMemoryType::AcpiReclaimable,
+        MemoryRegionType::AcpiNvs => BootMemoryType::AcpiNvs,
+        MemoryRegionType::BadMemory => BootMemoryType::BadMemory,
+        MemoryRegionType::Bootloader => BootMemoryType::BootloaderReclaimable,
+        MemoryRegionType::Kernel => BootMemoryType::KernelAndModules,
+    }
+}
+
+/// Linear framebuffer left set up by the loader. All zero when there is none.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct FramebufferDescriptor {
+    pub base: u64,
+    pub size: u64,
+    pub width: u32,
+    pub height: u32,
+    /// Pixels (not bytes) per scan line
+    pub stride: u32,
+    /// One of `FB_FORMAT_*`; 32 bits per pixel for every format but NONE
+    pub format: u32,
+    /// Channel masks (also filled in for RGB and BGR)
+    pub red_mask: u32,
+    pub green_mask: u32,
+    pub blue_mask: u32,
+}
+
+impl FramebufferDescriptor {
+    pub const fn empty() -> Self {
+        Self {
+            base: 0,
+            size: 0,
+            width: 0,
+            height: 0,
+            stride: 0,
+            format: FB_FORMAT_NONE,
+            red_mask: 0,
+            green_mask: 0,
+            blue_mask: 0,
+        }
+    }
+}
+
+#[repr(C)]
+pub struct BootInfo {
+    /// Memory map at ExitBootServices, in firmware order
+    pub memory_map_base: *const BootMemoryRegion,
+    pub memory_map_count: usize,
+    pub framebuffer: FramebufferDescriptor,
+    /// Physical address of the RSDP, 0 if not found
+    pub rsdp_address: u64,
+    /// Physical address of the SMBIOS entry point (`_SM_` or `_SM3_`), 0 if none
+    pub smbios_base: u64,
+    /// Kernel command line, UTF-8 without a NUL terminator
+    pub cmdline: *const u8,
+    pub cmdline_len: usize,
+    /// Initial ramdisk, both 0 when none was loaded
+    pub initrd_base: u64,
+    pub initrd_size: u64,
+    /// Physical address of the kernel's lowest PT_LOAD page (randomised with
+    /// `kaslr`), 0 for EFI stub kernels
+    pub kernel_phys_base: u64,
+    /// AP start-up code installed below 1 MiB, 0 if none (SIPI vector is `>> 12`)
+    pub smp_trampoline: u64,
+    /// Label of the volume the kernel was loaded from
+    pub volume_label: [u8; VOLUME_LABEL_MAX],
+    pub volume_label_len: usize,
+    /// NUMA affinities from the SRAT; empty on non-NUMA machines
+    pub srat: SratInfo,
+    /// CPUs and I/O APICs from the MADT; empty if there is none
+    pub madt: MadtInfo,
+}
+
+impl BootInfo {
+    pub const fn empty() -> Self {
+        Self {
+            memory_map_base: core::ptr::null(),
+            memory_map_count: 0,
+            framebuffer: FramebufferDescriptor::empty(),
+            rsdp_address: 0,
+            smbios_base: 0,
+            cmdline: core::ptr::null(),
+            cmdline_len: 0,
+            initrd_base: 0,
+            initrd_size: 0,
+            kernel_phys_base: 0,
+            smp_trampoline: 0,
+            volume_label: [0; VOLUME_LABEL_MAX],
+            volume_label_len: 0,
+            srat: SratInfo::empty(),
+            madt: MadtInfo::empty(),
+        }
+    }
+
+    /// Copy `label`, truncated to `VOLUME_LABEL_MAX` bytes on a char boundary.
+    pub fn set_volume_label(&mut self, label: &str) {
+        let mut len = 0;
+        for ch in label.chars() {
+            if len + ch.len_utf8() > VOLUME_LABEL_MAX {
+                break;
+            }
+            ch.encode_utf8(&mut self.volume_label[len..]);
+            len += ch.len_utf8();
+        }
+        self.volume_label_len = len;
+    }
+
+    /// `cmdline` must stay valid after ExitBootServices (a static or LOADER_DATA).
+    pub fn set_cmdline(&mut self, cmdline: &'static str) {
+        self.cmdline = cmdline.as_ptr();
+        self.cmdline_len = cmdline.len();
+    }
+
+    pub fn cmdline_str(&self) -> &str {
+        if self.cmdline.is_null() {
+            return "";
+        }
+        // Only ever set from a `&'static str` by `set_cmdline`
+        unsafe { core::str::from_utf8_unchecked(core::slice::from_raw_parts(self.cmdline, self.cmdline_len)) }
+    }
+
+    /// Rewrite the firmware map at `map` (`count` descriptors, `desc_size`
+    /// bytes apart) in place as `BootMemoryRegion`s and record it. A region
+    /// is smaller than a descriptor, so entry `i` is always read before
+    /// anything at or past it is written. The framebuffer is tagged as such.
+    #[cfg(feature = "uefi")]
+    pub unsafe fn set_memory_map(&mut self, map: *mut u8, count: usize, desc_size: usize) {
+        const _: () = assert!(core::mem::size_of::<BootMemoryRegion>() <= core::mem::size_of::<MemoryDescriptor>());
+
+        let regions = map as *mut BootMemoryRegion;
+        let fb = self.framebuffer.base;
+        for i in 0..count {
+            let desc = unsafe { (map.add(i * desc_size) as *const MemoryDescriptor).read_unaligned() };
+            let length = desc.page_count * 4096;
+            let contains_fb = fb != 0 && fb >= desc.phys_start && fb < desc.phys_start + length;
+            let ty = if contains_fb { BootMemoryType::Framebuffer } else { uefi_to_boot_type(desc.ty) };
+            unsafe {
+                regions.add(i).write(BootMemoryRegion { base: desc.phys_start, length, ty });
+            }
+        }
+        self.memory_map_base = regions;
+        self.memory_map_count = count;
+    }
+
+    /// Fill in what the loader knows about the platform: ACPI (including the
+    /// CPU list), SMBIOS and the boot volume. The memory map and the SMP
+    /// trampoline are added last, by `jump_to_kernel`.
+    #[cfg(feature = "uefi")]
+    pub fn collect_platform_info(&mut self) {
+        self.rsdp_address = crate::acpi::find_rsdp().unwrap_or(0);
+        self.smbios_base = crate::smbios::find_anchor().unwrap_or(0);
+        self.set_volume_label(crate::uefi_main::volume_label());
+        if let Some(srat) = crate::acpi::srat::find_srat() {
+            self.srat = srat;
+        }
+        if self.rsdp_address != 0 {
+            if let Some(root) = crate::acpi::root_table(self.rsdp_address as usize) {
+                self.madt = crate::acpi::madt::parse(root as u64);
+            }
+        }
+    }
+}
+
+/// Allocate pages for the `BootInfo` and initialise it empty. LOADER_DATA so
+/// they survive ExitBootServices.
+#[cfg(feature = "uefi")]
+pub fn allocate(bs: &BootServices) -> Result<&'static mut BootInfo, &'static str> {
+    const _: () = assert!(core::mem::size_of::<BootInfo>() <= BOOT_INFO_PAGES * 4096, "BootInfo outgrew its pages");
+
+    let page = bs
+        .allocate_pages(AllocateType::AnyPages, MemoryType::LOADER_DATA, BOOT_INFO_PAGES)
+        .map_err(|_| "BootInfo page allocation failed")?;
+    let info = page as *mut BootInfo;
+    unsafe {
+        info.write(BootInfo::empty());
+        Ok(&mut *info)
+    }
+}
@@ -0,0 +1,83 @@
+//! Kernel command line: space-separated tokens, bare (`quiet`) or
+//! `key=value` (`root=/dev/sda2`).
+//!
+//! Sources, first non-empty wins: the `RustyBootCmdLine` EFI variable, the
+//! `[boot]cmdline=` config line, then the empty string.
+
+#[cfg(feature = "uefi")]
+use uefi::prelude::*;
+#[cfg(feature = "uefi")]
+use uefi::table::runtime::VariableVendor;
+
+/// Max bytes of the resolved command line
+pub const CMDLINE_MAX: usize = 1024;
+
+/// Vendor GUID the `RustyBootCmdLine` variable is stored under
+#[cfg(feature = "uefi")]
+pub const RUSTYBOOT_VENDOR: VariableVendor = VariableVendor(uefi::guid!("1f8a3c52-6d0e-4b7a-9c41-52757374794b"));
+
+#[derive(Copy, Clone, Debug)]
+pub struct CmdLine<'a> {
+    raw: &'a str,
+}
+
+impl<'a> CmdLine<'a> {
+    pub const fn new(raw: &'a str) -> Self {
+        Self { raw }
+    }
+
+    pub fn as_str(&self) -> &'a str {
+        self.raw
+    }
+
+    /// `(key, value)` for every token; bare flags have an empty value.
+    pub fn iter(&self) -> impl Iterator<Item = (&'a str, &'a str)> {
+        self.raw
+            .split(' ')
+            .filter(|t| !t.is_empty())
+            .map(|t| t.split_once('=').unwrap_or((t, "")))
+    }
+
+    /// Value of the last `key=` token, `""` for a bare `key`.
+    pub fn get(&self, key: &str) -> Option<&'a str> {
+        self.iter().filter(|(k, _)| *k == key).map(|(_, v)| v).last()
+    }
+
+    pub fn has(&self, key: &str) -> bool {
+        self.iter().any(|(k, _)| k == key)
+    }
+}
+
+static mut CMDLINE_BUF: [u8; CMDLINE_MAX] = [0; CMDLINE_MAX];
+
+/// Copy `bytes` into the static command line buffer, stopping at a NUL and
+/// at `CMDLINE_MAX`. Invalid UTF-8 yields `""`.
+fn store(bytes: &[u8]) -> &'static str {
+    let len = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len()).min(CMDLINE_MAX);
+    let buf = unsafe { &mut *core::ptr::addr_of_mut!(CMDLINE_BUF) };
+    buf[..len].copy_from_slice(&bytes[..len]);
+    core::str::from_utf8(&buf[..len]).map(str::trim).unwrap_or("")
+}
+
+/// Pick the command line for this boot. `from_config` is the config file's
+/// `[boot]cmdline=` value (empty if unset). The result lives in a static
+/// buffer, so it can be handed to the kernel as is.
+#[cfg(feature = "uefi")]
+pub fn resolve(st: &SystemTable<Boot>, from_config: &str) -> &'static str {
+    let mut var_buf = [0u8; CMDLINE_MAX];
+    if let Ok((data, _attrs)) =
+        st.runtime_services().get_variable(uefi::cstr16!("RustyBootCmdLine"), &RUSTYBOOT_VENDOR, &mut var_buf)
+    {
+        let cmdline = store(data);
+        if !cmdline.is_empty() {
+            log_info!("cmdline", "from EFI variable: {}", cmdline);
+            return cmdline;
+        }
+    }
+
+    let cmdline = store(from_config.as_bytes());
+    if !cmdline.is_empty() {
+        log_info!("cmdline", "from config: {}", cmdline);
+    }
+    cmdline
+}
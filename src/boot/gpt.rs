@@ -0,0 +1,291 @@
+//! GUID Partition Table parsing.
+//!
+//! Header and entry-array validation is shared by both boot paths; only the
+//! way sectors are read differs.
+
+#[cfg(feature = "uefi")]
+use uefi::prelude::*;
+#[cfg(feature = "uefi")]
+use uefi::proto::media::block::BlockIO;
+#[cfg(feature = "uefi")]
+use uefi::proto::media::disk::DiskIo;
+#[cfg(feature = "uefi")]
+use uefi::table::boot::BootServices;
+
+#[cfg(feature = "uefi")]
+use crate::uefi::protocol::require_protocol;
+#[cfg(feature = "bios")]
+use crate::drivers::block;
+
+pub const GPT_SIGNATURE: &[u8; 8] = b"EFI PART";
+pub const GPT_MAX_PARTITIONS: usize = 128;
+pub const GPT_HEADER_LBA: u64 = 1;
+/// MBR partition type of the single entry covering a GPT disk
+pub const PROTECTIVE_MBR_TYPE: u8 = 0xEE;
+/// GPT attribute bit 2: legacy BIOS bootable
+pub const GPT_ATTR_LEGACY_BIOS_BOOTABLE: u64 = 1 << 2;
+
+const GPT_HEADER_MIN_SIZE: usize = 92;
+const GPT_HEADER_CRC_OFFSET: usize = 16;
+// Largest entry array we are willing to buffer (128 entries of 128 bytes)
+const GPT_ENTRIES_MAX_BYTES: usize = 16 * 1024;
+
+#[derive(Copy, Clone, Debug)]
+pub struct GptHeader {
+    pub my_lba: u64,
+    pub alternate_lba: u64,
+    pub first_usable_lba: u64,
+    pub last_usable_lba: u64,
+    pub disk_guid: [u8; 16],
+    pub entries_lba: u64,
+    pub num_entries: u32,
+    pub entry_size: u32,
+    pub entries_crc32: u32,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct GptPartition {
+    pub type_guid: [u8; 16],
+    pub unique_guid: [u8; 16],
+    pub first_lba: u64,
+    pub last_lba: u64,
+    pub attributes: u64,
+}
+
+#[derive(Clone, Debug)]
+pub struct GptInfo {
+    pub header: GptHeader,
+    pub partitions: [Option<GptPartition>; GPT_MAX_PARTITIONS],
+    /// True when the primary header was bad and the backup copy was used
+    pub from_backup: bool,
+}
+
+// ===== CRC32 (IEEE, reflected 0xEDB88320) =====
+
+// Table-driven: gzip checks whole kernel images with it too
+const CRC32_TABLE: [u32; 256] = crc32_table();
+
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut c = i as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 { 0xEDB8_8320 ^ (c >> 1) } else { c >> 1 };
+            k += 1;
+        }
+        table[i] = c;
+        i += 1;
+    }
+    table
+}
+
+pub fn crc32(data: &[u8]) -> u32 {
+    !data
+        .iter()
+        .fold(!0u32, |crc, &b| CRC32_TABLE[((crc ^ b as u32) & 0xFF) as usize] ^ (crc >> 8))
+}
+
+fn le_u32(b: &[u8], off: usize) -> u32 {
+    u32::from_le_bytes([b[off], b[off + 1], b[off + 2], b[off + 3]])
+}
+
+fn le_u64(b: &[u8], off: usize) -> u64 {
+    (le_u32(b, off) as u64) | ((le_u32(b, off + 4) as u64) << 32)
+}
+
+/// Parse and validate the header in `sector`, which was read from `lba`.
+pub fn parse_header(sector: &[u8], lba: u64) -> Result<GptHeader, &'static str> {
+    if sector.len() < GPT_HEADER_MIN_SIZE || &sector[0..8] != GPT_SIGNATURE {
+        return Err("GPT: bad header signature");
+    }
+    let header_size = le_u32(sector, 12) as usize;
+    if header_size < GPT_HEADER_MIN_SIZE || header_size > sector.len() {
+        return Err("GPT: bad header size");
+    }
+
+    // The CRC covers the header with its own CRC field zeroed
+    let mut copy = [0u8; 512];
+    copy[..header_size].copy_from_slice(&sector[..header_size]);
+    copy[GPT_HEADER_CRC_OFFSET..GPT_HEADER_CRC_OFFSET + 4].fill(0);
+    if crc32(&copy[..header_size]) != le_u32(sector, GPT_HEADER_CRC_OFFSET) {
+        return Err("GPT: header CRC mismatch");
+    }
+
+    let mut disk_guid = [0u8; 16];
+    disk_guid.copy_from_slice(&sector[56..72]);
+    let header = GptHeader {
+        my_lba: le_u64(sector, 24),
+        alternate_lba: le_u64(sector, 32),
+        first_usable_lba: le_u64(sector, 40),
+        last_usable_lba: le_u64(sector, 48),
+        disk_guid,
+        entries_lba: le_u64(sector, 72),
+        num_entries: le_u32(sector, 80),
+        entry_size: le_u32(sector, 84),
+        entries_crc32: le_u32(sector, 88),
+    };
+    if header.my_lba != lba {
+        return Err("GPT: header LBA mismatch");
+    }
+    if header.entry_size < 128 || (header.entry_size % 8) != 0 {
+        return Err("GPT: bad partition entry size");
+    }
+    Ok(header)
+}
+
+/// Bytes occupied by the partition entry array of `header`.
+pub fn entries_len(header: &GptHeader) -> Result<usize, &'static str> {
+    let len = (header.num_entries as usize)
+        .checked_mul(header.entry_size as usize)
+        .ok_or("GPT: entry array too large")?;
+    if len > GPT_ENTRIES_MAX_BYTES {
+        return Err("GPT: entry array too large");
+    }
+    Ok(len)
+}
+
+/// Validate the entry array CRC and collect the used entries.
+pub fn parse_entries(header: &GptHeader, entries: &[u8]) -> Result<[Option<GptPartition>; GPT_MAX_PARTITIONS], &'static str> {
+    let len = entries_len(header)?;
+    if entries.len() < len {
+        return Err("GPT: entry array truncated");
+    }
+    if crc32(&entries[..len]) != header.entries_crc32 {
+        return Err("GPT: partition entry CRC mismatch");
+    }
+
+    let mut out = [None; GPT_MAX_PARTITIONS];
+    let step = header.entry_size as usize;
+    let count = (header.num_entries as usize).min(GPT_MAX_PARTITIONS);
+    for (i, slot) in out.iter_mut().enumerate().take(count) {
+        let e = &entries[i * step..i * step + 128];
+        let mut type_guid = [0u8; 16];
+        type_guid.copy_from_slice(&e[0..16]);
+        if type_guid == [0u8; 16] {
+            continue; // unused entry
+        }
+        let mut unique_guid = [0u8; 16];
+        unique_guid.copy_from_slice(&e[16..32]);
+        *slot = Some(GptPartition {
+            type_guid,
+            unique_guid,
+            first_lba: le_u64(e, 32),
+            last_lba: le_u64(e, 40),
+            attributes: le_u64(e, 48),
+        });
+    }
+    Ok(out)
+}
+
+// ===== UEFI: read through DiskIo, recover from the backup header =====
+
+#[cfg(feature = "uefi")]
+fn read_gpt_copy(disk: &DiskIo, media_id: u32, block_size: u64, lba: u64) -> Result<GptInfo, &'static str> {
+    let mut sector = [0u8; 512];
+    disk.read_disk(media_id, lba * block_size, &mut sector)
+        .map_err(|_| "GPT: disk read failed")?;
+    let header = parse_header(&sector, lba)?;
+
+    let mut entries = [0u8; GPT_ENTRIES_MAX_BYTES];
+    let len = entries_len(&header)?;
+    disk.read_disk(media_id, header.entries_lba * block_size, &mut entries[..len])
+        .map_err(|_| "GPT: disk read failed")?;
+    let partitions = parse_entries(&header, &entries)?;
+
+    Ok(GptInfo { header, partitions, from_backup: false })
+}
+
+/// Read the GPT of `disk_handle`, falling back to the backup header at the
+/// last LBA (and the entry array in front of it) if the primary is corrupt.
+#[cfg(feature = "uefi")]
+pub fn probe_with_backup_recovery(bs: &BootServices, disk_handle: Handle) -> Result<GptInfo, &'static str> {
+    let block = require_protocol::<BlockIO>(bs, disk_handle).inspect_err(|e| log_info!("gpt", "{}", e))?;
+    let disk = require_protocol::<DiskIo>(bs, disk_handle).inspect_err(|e| log_info!("gpt", "{}", e))?;
+    let media = block.media();
+    let media_id = media.media_id();
+    let block_size = media.block_size() as u64;
+    let last_lba = media.last_block();
+
+    let primary_err = match read_gpt_copy(disk, media_id, block_size, GPT_HEADER_LBA) {
+        Ok(info) => return Ok(info),
+        Err(e) => e,
+    };
+
+    let mut info = read_gpt_copy(disk, media_id, block_size, last_lba)?;
+    info.from_backup = true;
+    log_info!("gpt", "*** WARNING: primary GPT unusable ({}) ***", primary_err);
+    log_info!("gpt", "*** using backup GPT at LBA {}; repair the disk ***", last_lba);
+    Ok(info)
+}
+
+// ===== BIOS: read through ATA PIO =====
+
+/// Last LBA covered by a protective MBR entry, or `None` if `mbr` does not
+/// protect a GPT disk.
+pub fn protective_mbr_last_lba(mbr: &[u8]) -> Option<u64> {
+    if mbr.len() < 512 || mbr[510] != 0x55 || mbr[511] != 0xAA {
+        return None;
+    }
+    (0..4).find_map(|i| {
+        let e = &mbr[446 + i * 16..446 + (i + 1) * 16];
+        if e[4] != PROTECTIVE_MBR_TYPE {
+            return None;
+        }
+        let start = le_u32(e, 8) as u64;
+        let sectors = le_u32(e, 12) as u64;
+        // Disks over 2 TiB store 0xFFFFFFFF here
+        Some((start + sectors).saturating_sub(1).max(start))
+    })
+}
+
+#[cfg(feature = "bios")]
+fn read_gpt_copy_disk(lba: u64) -> Result<GptInfo, &'static str> {
+    let mut sector = [0u8; 512];
+    block::read_sectors(lba, 1, &mut sector)?;
+    let header = parse_header(&sector, lba)?;
+
+    let mut entries = [0u8; GPT_ENTRIES_MAX_BYTES];
+    let len = entries_len(&header)?;
+    let sectors = len.div_ceil(512);
+    block::read_sectors(header.entries_lba, sectors as u32, &mut entries[..sectors * 512])?;
+    let partitions = parse_entries(&header, &entries)?;
+
+    Ok(GptInfo { header, partitions, from_backup: false })
+}
+
+/// Read the GPT of the boot disk. Returns `Ok(None)` when LBA 0 is not a
+/// protective MBR, so the caller can fall back to plain MBR parsing.
+///
+/// The backup header is checked too: if both copies are valid but describe
+/// different partition arrays the disk is rejected rather than guessing.
+#[cfg(feature = "bios")]
+pub fn probe() -> Result<Option<GptInfo>, &'static str> {
+    let mut mbr = [0u8; 512];
+    block::read_sectors(0, 1, &mut mbr)?;
+    let last_lba = match protective_mbr_last_lba(&mbr) {
+        Some(lba) => lba,
+        None => return Ok(None),
+    };
+
+    match read_gpt_copy_disk(GPT_HEADER_LBA) {
+        Ok(primary) => {
+            match read_gpt_copy_disk(primary.header.alternate_lba) {
+                Ok(backup) if backup.header.entries_crc32 != primary.header.entries_crc32 => {
+                    return Err("GPT: primary and backup partition arrays differ");
+                }
+                Ok(_) => {}
+                Err(e) => log_info!("gpt", "backup GPT unusable: {}", e),
+            }
+            Ok(Some(primary))
+        }
+        Err(primary_err) => {
+            let mut info = read_gpt_copy_disk(last_lba)?;
+            info.from_backup = true;
+            log_info!("gpt", "*** WARNING: primary GPT unusable ({}) ***", primary_err);
+            log_info!("gpt", "*** using backup GPT at LBA {}; repair the disk ***", last_lba);
+            Ok(Some(info))
+        }
+    }
+}
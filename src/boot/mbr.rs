@@ -3,7 +3,8 @@
 //! Responsibilities:
 //! - Read LBA 0 (sector 0) from the boot disk
 //! - Verify 0x55AA signature
-//! - Parse four partition entries
+//! - Parse four partition entries, plus the logical drives of an extended
+//!   partition (EBR chain)
 //! - Provide helpers to query the active partition and print info for debugging
 //!
 //! This module is *not* the 512‑byte real-mode MBR. It runs as part of the
@@ -13,12 +14,20 @@
 
 use core::mem::size_of;
 
-use crate::drivers::{disk, vga};
+use crate::drivers::{block, vga};
 
 pub const MBR_BYTES: usize = 512;
 pub const MBR_SIGNATURE: u16 = 0xAA55; // note: little-endian on disk is 55 AA
 pub const PARTITION_TABLE_OFFSET: usize = 446; // 0x1BE
 pub const PARTITION_ENTRY_COUNT: usize = 4;
+pub const DISK_SIGNATURE_OFFSET: usize = 0x1B8; // Windows unique disk ID
+const DISK_SIGNATURE_MARKER_OFFSET: usize = 0x1BC; // 0x0000 when the ID is present
+
+/// Logical drives followed in an extended partition (Linux numbers them 5..64)
+pub const MAX_LOGICAL_PARTITIONS: usize = 60;
+const PART_TYPE_EXTENDED_CHS: u8 = 0x05;
+const PART_TYPE_EXTENDED_LBA: u8 = 0x0F;
+const PART_TYPE_EXTENDED_LINUX: u8 = 0x85;
 
 #[repr(C, packed)]
 #[derive(Copy, Clone, Debug, Default)]
@@ -43,11 +52,18 @@ pub struct PartitionEntry {
 pub struct MbrInfo {
     pub signature_valid: bool,
     pub partitions: [Option<PartitionEntry>; PARTITION_ENTRY_COUNT],
+    /// Logical drives of the first extended partition, LBAs absolute
+    pub logical: [Option<PartitionEntry>; MAX_LOGICAL_PARTITIONS],
+    pub disk_signature: Option<u32>,
+}
+
+pub fn is_extended(partition_type: u8) -> bool {
+    matches!(partition_type, PART_TYPE_EXTENDED_CHS | PART_TYPE_EXTENDED_LBA | PART_TYPE_EXTENDED_LINUX)
 }
 
 /// Read LBA0 into a fixed 512‑byte buffer.
 pub fn read_mbr_sector(buf: &mut [u8; MBR_BYTES]) -> Result<(), &'static str> {
-    disk::read_sectors(0, 1, buf).map_err(|_| "disk read LBA0 failed")
+    block::read_sectors(0, 1, buf).map_err(|_| "disk read LBA0 failed")
 }
 
 /// Validate the 0x55AA signature at the end of the MBR.
@@ -60,6 +76,29 @@ fn has_valid_signature(mbr: &[u8]) -> bool {
     (hi << 8) | lo == MBR_SIGNATURE
 }
 
+/// Windows-style 32-bit disk signature at 0x1B8, present only when the two
+/// bytes after it are zero.
+pub fn disk_signature(mbr: &[u8]) -> Option<u32> {
+    if mbr.len() < DISK_SIGNATURE_MARKER_OFFSET + 2 {
+        return None;
+    }
+    if mbr[DISK_SIGNATURE_MARKER_OFFSET] != 0 || mbr[DISK_SIGNATURE_MARKER_OFFSET + 1] != 0 {
+        return None;
+    }
+    let o = DISK_SIGNATURE_OFFSET;
+    Some(u32::from_le_bytes([mbr[o], mbr[o + 1], mbr[o + 2], mbr[o + 3]]))
+}
+
+/// Format `sig` as 8 uppercase hex digits (e.g. `1A2B3C4D`).
+pub fn disk_signature_str(sig: u32, buf: &mut [u8; 8]) -> &str {
+    const HEX: &[u8; 16] = b"0123456789ABCDEF";
+    for (i, b) in buf.iter_mut().enumerate() {
+        *b = HEX[((sig >> (28 - i * 4)) & 0xF) as usize];
+    }
+    // SAFETY: only ASCII hex digits were written
+    unsafe { core::str::from_utf8_unchecked(buf) }
+}
+
 /// Parse the four partition entries from the 512‑byte buffer.
 pub fn parse_partitions(mbr: &[u8]) -> [Option<PartitionEntry>; PARTITION_ENTRY_COUNT] {
     let mut out: [Option<PartitionEntry>; PARTITION_ENTRY_COUNT] = [None, None, None, None];
@@ -94,6 +133,41 @@ pub fn parse_partitions(mbr: &[u8]) -> [Option<PartitionEntry>; PARTITION_ENTRY_
     out
 }
 
+/// Follow the EBR chain of the extended partition at `base_ebr_lba`. In each
+/// EBR, entry 0 is the logical drive (start relative to that EBR) and entry 1
+/// points at the next EBR (start relative to `base_ebr_lba`); a zero pointer
+/// ends the chain. A bad read or signature ends it too, keeping what was found.
+pub fn enumerate_logical(base_ebr_lba: u32) -> [Option<PartitionEntry>; MAX_LOGICAL_PARTITIONS] {
+    let mut out = [None; MAX_LOGICAL_PARTITIONS];
+    let mut buf = [0u8; MBR_BYTES];
+    let mut ebr_offset = 0u32;
+
+    // Bounded, so a chain that loops back on itself still terminates
+    for slot in out.iter_mut() {
+        let ebr_lba = match base_ebr_lba.checked_add(ebr_offset) {
+            Some(lba) => lba,
+            None => break,
+        };
+        if block::read_sectors(ebr_lba as u64, 1, &mut buf).is_err() || !has_valid_signature(&buf) {
+            break;
+        }
+        let entries = parse_partitions(&buf);
+
+        if let Some(mut logical) = entries[0] {
+            logical.starting_lba = match ebr_lba.checked_add(logical.starting_lba) {
+                Some(lba) => lba,
+                None => break,
+            };
+            *slot = Some(logical);
+        }
+        match entries[1] {
+            Some(next) if is_extended(next.partition_type) && next.starting_lba != 0 => ebr_offset = next.starting_lba,
+            _ => break,
+        }
+    }
+    out
+}
+
 /// Read, verify, and parse the MBR into a high‑level `MbrInfo`.
 pub fn probe() -> Result<MbrInfo, &'static str> {
     let mut buf = [0u8; MBR_BYTES];
@@ -105,32 +179,37 @@ pub fn probe() -> Result<MbrInfo, &'static str> {
     }
 
     let partitions = parse_partitions(&buf);
+    let logical = match partitions.iter().flatten().find(|p| is_extended(p.partition_type)) {
+        Some(ext) => enumerate_logical(ext.starting_lba),
+        None => [None; MAX_LOGICAL_PARTITIONS],
+    };
     Ok(MbrInfo {
         signature_valid,
         partitions,
+        logical,
+        disk_signature: disk_signature(&buf),
     })
 }
 
+/// Primary then logical partitions with their index; logical drives count
+/// from `PARTITION_ENTRY_COUNT` (index 4 is Linux's sda5).
+fn all_partitions(info: &MbrInfo) -> impl Iterator<Item = (usize, PartitionEntry)> + '_ {
+    info.partitions
+        .iter()
+        .chain(info.logical.iter())
+        .enumerate()
+        .filter_map(|(idx, p)| p.map(|pe| (idx, pe)))
+}
+
 /// Return the first `bootable` (active) partition, if any, with its index.
 pub fn find_active_partition(info: &MbrInfo) -> Option<(usize, PartitionEntry)> {
-    for (idx, p) in info.partitions.iter().enumerate() {
-        if let Some(pe) = p {
-            if pe.bootable {
-                return Some((idx, *pe));
-            }
-        }
-    }
-    None
+    all_partitions(info).find(|(_, pe)| pe.bootable)
 }
 
 /// Convenience: return the first non‑empty partition if no active flag is set.
+/// Extended containers are skipped; their logical drives are candidates.
 pub fn first_present_partition(info: &MbrInfo) -> Option<(usize, PartitionEntry)> {
-    for (idx, p) in info.partitions.iter().enumerate() {
-        if let Some(pe) = p {
-            return Some((idx, *pe));
-        }
-    }
-    None
+    all_partitions(info).find(|(_, pe)| !is_extended(pe.partition_type))
 }
 
 /// Pretty‑print parsed MBR information to VGA for debugging during bring‑up.
@@ -142,58 +221,40 @@ pub fn debug_print(info: &MbrInfo) {
     } else {
         vga::print_string("BAD\n");
     }
+    if let Some(sig) = info.disk_signature {
+        let mut buf = [0u8; 8];
+        vga::print_string("disk id: ");
+        vga::print_string(disk_signature_str(sig, &mut buf));
+        vga::print_string("\n");
+    }
 
     for i in 0..PARTITION_ENTRY_COUNT {
         match info.partitions[i] {
             None => {
                 vga::print_string("[ ");
-                print_dec(i as u32);
+                vga::print_dec_u32(i as u32);
                 vga::print_string(" ] <empty>\n");
             }
-            Some(p) => {
-                vga::print_string("[ ");
-                print_dec(i as u32);
-                vga::print_string("] boot=");
-                vga::print_string(if p.bootable { "Y" } else { "N" });
-                vga::print_string(" type=0x");
-                print_hex8(p.partition_type);
-                vga::print_string(" start=");
-                print_dec(p.starting_lba);
-                vga::print_string(" sectors=");
-                print_dec(p.sectors);
-                vga::print_string("\n");
-            }
+            Some(p) => print_entry(i, &p),
         }
     }
-}
-
-// ===== Small local print helpers (avoid depending on other private modules) =====
-fn print_hex8(mut v: u8) {
-    for shift in [4u8, 0u8] {
-        let nibble = ((v >> shift) & 0xF) as u8;
-        let ch = if nibble < 10 {
-            b'0' + nibble
-        } else {
-            b'A' + (nibble - 10)
-        };
-        vga::print_char(ch);
+    for (i, p) in info.logical.iter().enumerate() {
+        if let Some(p) = p {
+            print_entry(PARTITION_ENTRY_COUNT + i, p);
+        }
     }
 }
 
-fn print_dec(mut n: u32) {
-    if n == 0 {
-        vga::print_char(b'0');
-        return;
-    }
-    let mut buf = [0u8; 10];
-    let mut i = 0;
-    while n > 0 && i < buf.len() {
-        buf[i] = (n % 10) as u8 + b'0';
-        n /= 10;
-        i += 1;
-    }
-    while i > 0 {
-        i -= 1;
-        vga::print_char(buf[i]);
-    }
+fn print_entry(index: usize, p: &PartitionEntry) {
+    vga::print_string("[ ");
+    vga::print_dec_u32(index as u32);
+    vga::print_string("] boot=");
+    vga::print_string(if p.bootable { "Y" } else { "N" });
+    vga::print_string(" type=");
+    vga::print_hex8(p.partition_type);
+    vga::print_string(" start=");
+    vga::print_dec_u32(p.starting_lba);
+    vga::print_string(" sectors=");
+    vga::print_dec_u32(p.sectors);
+    vga::print_string("\n");
 }
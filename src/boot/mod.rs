@@ -1,2 +1,12 @@
+pub mod bootinfo;
+pub mod cmdline;
+pub mod gpt;
+#[cfg(feature = "bios")]
 pub mod mbr;
+pub mod multiboot2;
+#[cfg(feature = "bios")]
+pub mod partition;
+#[cfg(feature = "bios")]
 pub mod stage2;
+#[cfg(feature = "uefi")]
+pub mod stivale2;
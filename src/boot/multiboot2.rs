@@ -0,0 +1,376 @@
+//! Multiboot2 header and boot information.
+//!
+//! `MULTIBOOT2_HEADER` lets a Multiboot2 loader start the BIOS build itself.
+//! In the other direction, a kernel with a Multiboot2 header in its first
+//! 32 KiB is entered with `BOOTLOADER_MAGIC` in EAX and the physical address
+//! of a `Multiboot2Info` in EBX, in the i386 machine state: the BIOS path
+//! jumps there from its own protected mode, the UEFI path leaves long mode
+//! first (see `loader::jump_to_kernel`).
+
+use core::mem::size_of;
+
+use crate::boot::bootinfo::{BootInfo, BootMemoryRegion, BootMemoryType, FB_FORMAT_NONE, FramebufferDescriptor};
+
+pub const HEADER_MAGIC: u32 = 0xE852_50D6;
+/// EAX on entry to a Multiboot2 kernel
+pub const BOOTLOADER_MAGIC: u32 = 0x36D7_6289;
+const ARCH_I386: u32 = 0;
+
+/// The header is 8-byte aligned somewhere in the first 32 KiB of the image
+const HEADER_SEARCH_LIMIT: usize = 32 * 1024;
+const HEADER_ALIGN: usize = 8;
+/// The info structure itself only needs 8; 64 keeps it on a cache line
+pub const INFO_ALIGN: usize = 64;
+const TAG_ALIGN: usize = 8;
+
+// ===== Header tags =====
+const HEADER_TAG_END: u16 = 0;
+const HEADER_TAG_INFO_REQUEST: u16 = 1;
+const HEADER_TAG_CONSOLE_FLAGS: u16 = 4;
+const HEADER_TAG_FRAMEBUFFER: u16 = 5;
+/// The loader may ignore the tag if it cannot honour it
+const HEADER_TAG_OPTIONAL: u16 = 1 << 0;
+const CONSOLE_EGA_TEXT_SUPPORTED: u32 = 1 << 1;
+
+// ===== Information tags =====
+const TAG_END: u32 = 0;
+const TAG_CMDLINE: u32 = 1;
+const TAG_BOOTLOADER_NAME: u32 = 2;
+const TAG_BASIC_MEMINFO: u32 = 4;
+const TAG_MMAP: u32 = 6;
+const TAG_FRAMEBUFFER: u32 = 8;
+
+const MMAP_ENTRY_SIZE: usize = 24;
+const MMAP_ENTRY_VERSION: u32 = 0;
+
+// ===== Memory map entry types =====
+const MEMORY_AVAILABLE: u32 = 1;
+const MEMORY_RESERVED: u32 = 2;
+const MEMORY_ACPI_RECLAIMABLE: u32 = 3;
+const MEMORY_NVS: u32 = 4;
+const MEMORY_BADRAM: u32 = 5;
+
+// ===== Framebuffer types =====
+const FRAMEBUFFER_TYPE_RGB: u8 = 1;
+const FRAMEBUFFER_TYPE_EGA_TEXT: u8 = 2;
+/// Common framebuffer tag fields, then the RGB field positions and sizes
+const FRAMEBUFFER_TAG_COMMON: usize = 24;
+const FRAMEBUFFER_TAG_BODY: usize = FRAMEBUFFER_TAG_COMMON + 6;
+
+const EGA_TEXT_BUFFER: u64 = 0xB8000;
+const BOOTLOADER_NAME: &str = "RustyBoot";
+
+/// Upper bound of conventional memory reported in the basic meminfo tag
+const LOWER_MEMORY_LIMIT: u64 = 640 * 1024;
+const UPPER_MEMORY_BASE: u64 = 0x10_0000;
+
+#[repr(C)]
+struct InfoRequestTag {
+    ty: u16,
+    flags: u16,
+    size: u32,
+    requests: [u32; 4],
+}
+
+#[repr(C)]
+struct ConsoleFlagsTag {
+    ty: u16,
+    flags: u16,
+    size: u32,
+    console_flags: u32,
+}
+
+#[repr(C)]
+struct FramebufferTag {
+    ty: u16,
+    flags: u16,
+    size: u32,
+    width: u32,
+    height: u32,
+    depth: u32,
+}
+
+#[repr(C)]
+struct EndTag {
+    ty: u16,
+    flags: u16,
+    size: u32,
+}
+
+/// Header with the magic fields and the tags we ask a loader for: memory
+/// and framebuffer information, and an EGA text console. Every tag starts
+/// 8-byte aligned, hence the padding.
+#[repr(C, align(8))]
+pub struct Multiboot2Header {
+    magic: u32,
+    architecture: u32,
+    header_length: u32,
+    checksum: u32,
+    info_request: InfoRequestTag,
+    console: ConsoleFlagsTag,
+    _pad0: u32,
+    framebuffer: FramebufferTag,
+    _pad1: u32,
+    end: EndTag,
+}
+
+impl Multiboot2Header {
+    pub const fn new() -> Self {
+        let length = size_of::<Self>() as u32;
+        Self {
+            magic: HEADER_MAGIC,
+            architecture: ARCH_I386,
+            header_length: length,
+            checksum: 0u32.wrapping_sub(HEADER_MAGIC.wrapping_add(ARCH_I386).wrapping_add(length)),
+            info_request: InfoRequestTag {
+                ty: HEADER_TAG_INFO_REQUEST,
+                flags: HEADER_TAG_OPTIONAL,
+                size: size_of::<InfoRequestTag>() as u32,
+                requests: [TAG_CMDLINE, TAG_BASIC_MEMINFO, TAG_MMAP, TAG_FRAMEBUFFER],
+            },
+            console: ConsoleFlagsTag {
+                ty: HEADER_TAG_CONSOLE_FLAGS,
+                flags: HEADER_TAG_OPTIONAL,
+                size: size_of::<ConsoleFlagsTag>() as u32,
+                console_flags: CONSOLE_EGA_TEXT_SUPPORTED,
+            },
+            _pad0: 0,
+            // Width, height and depth 0: no preferred mode
+            framebuffer: FramebufferTag {
+                ty: HEADER_TAG_FRAMEBUFFER,
+                flags: HEADER_TAG_OPTIONAL,
+                size: size_of::<FramebufferTag>() as u32,
+                width: 0,
+                height: 0,
+                depth: 0,
+            },
+            _pad1: 0,
+            end: EndTag { ty: HEADER_TAG_END, flags: 0, size: size_of::<EndTag>() as u32 },
+        }
+    }
+}
+
+/// Placed first in the BIOS image by the linker script. PE section names
+/// are limited to 8 bytes, so the UEFI build has none.
+#[cfg(feature = "bios")]
+#[used]
+#[unsafe(link_section = ".multiboot2")]
+pub static MULTIBOOT2_HEADER: Multiboot2Header = Multiboot2Header::new();
+
+fn read_u32(data: &[u8], off: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(data.get(off..off + 4)?.try_into().ok()?))
+}
+
+/// Offset of a valid i386 Multiboot2 header in `image`, if it has one.
+pub fn find_header(image: &[u8]) -> Option<usize> {
+    let limit = image.len().min(HEADER_SEARCH_LIMIT);
+    (0..limit).step_by(HEADER_ALIGN).find(|&off| {
+        let (Some(magic), Some(arch), Some(length), Some(checksum)) =
+            (read_u32(image, off), read_u32(image, off + 4), read_u32(image, off + 8), read_u32(image, off + 12))
+        else {
+            return false;
+        };
+        magic == HEADER_MAGIC
+            && arch == ARCH_I386
+            && magic.wrapping_add(arch).wrapping_add(length).wrapping_add(checksum) == 0
+    })
+}
+
+/// Multiboot2 type for a loader region. There is no "reclaimable" type, so
+/// loader memory stays reserved: the stack the kernel is entered on and the
+/// information structure live there.
+pub fn memory_type(ty: BootMemoryType) -> u32 {
+    match ty {
+        BootMemoryType::Usable => MEMORY_AVAILABLE,
+        BootMemoryType::AcpiReclaimable => MEMORY_ACPI_RECLAIMABLE,
+        BootMemoryType::AcpiNvs => MEMORY_NVS,
+        BootMemoryType::BadMemory => MEMORY_BADRAM,
+        BootMemoryType::Reserved
+        | BootMemoryType::BootloaderReclaimable
+        | BootMemoryType::KernelAndModules
+        | BootMemoryType::Framebuffer => MEMORY_RESERVED,
+    }
+}
+
+/// (lower, upper) KiB for the basic meminfo tag: usable memory from 0, and
+/// from 1 MiB up to the first hole.
+fn basic_meminfo(regions: &[BootMemoryRegion]) -> (u32, u32) {
+    // Firmware maps split usable memory, so follow adjacent regions
+    let usable_end_from = |start: u64| {
+        let mut end = start;
+        while let Some(r) =
+            regions.iter().find(|r| r.ty == BootMemoryType::Usable && r.base <= end && end < r.base + r.length)
+        {
+            end = r.base + r.length;
+        }
+        end
+    };
+    let lower = usable_end_from(0).min(LOWER_MEMORY_LIMIT);
+    let upper = usable_end_from(UPPER_MEMORY_BASE) - UPPER_MEMORY_BASE;
+    ((lower / 1024) as u32, (upper / 1024).min(u32::MAX as u64) as u32)
+}
+
+/// Bytes needed for `Multiboot2Info::from_regions`, a framebuffer tag and
+/// the end tag.
+pub fn info_size(cmdline: &str, region_count: usize) -> usize {
+    let tag = |body: usize| (8 + body).next_multiple_of(TAG_ALIGN);
+    8 + tag(cmdline.len() + 1)
+        + tag(BOOTLOADER_NAME.len() + 1)
+        + tag(8)
+        + tag(8 + region_count * MMAP_ENTRY_SIZE)
+        + tag(FRAMEBUFFER_TAG_BODY)
+        + tag(0)
+}
+
+/// Boot information being written into a caller-provided buffer. Tags are
+/// appended in call order; `finish` adds the end tag and the total size.
+pub struct Multiboot2Info<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl<'a> Multiboot2Info<'a> {
+    /// `buf` must be `INFO_ALIGN`-aligned and stay untouched until the kernel
+    /// has read it.
+    pub fn new(buf: &'a mut [u8]) -> Result<Self, &'static str> {
+        if buf.as_ptr() as usize % INFO_ALIGN != 0 {
+            return Err("multiboot2: info buffer misaligned");
+        }
+        if buf.len() < 8 {
+            return Err("multiboot2: info buffer too small");
+        }
+        buf[..8].fill(0);
+        Ok(Self { buf, len: 8 })
+    }
+
+    fn put_u8(&mut self, off: usize, v: u8) {
+        self.buf[off] = v;
+    }
+
+    fn put_u32(&mut self, off: usize, v: u32) {
+        self.buf[off..off + 4].copy_from_slice(&v.to_le_bytes());
+    }
+
+    fn put_u64(&mut self, off: usize, v: u64) {
+        self.buf[off..off + 8].copy_from_slice(&v.to_le_bytes());
+    }
+
+    /// Reserve a tag with a `body_len`-byte (zeroed) body; returns the offset
+    /// of the body.
+    fn push_tag(&mut self, ty: u32, body_len: usize) -> Result<usize, &'static str> {
+        let start = self.len;
+        let end = start + (8 + body_len).next_multiple_of(TAG_ALIGN);
+        if end > self.buf.len() {
+            return Err("multiboot2: info buffer too small");
+        }
+        self.buf[start..end].fill(0);
+        self.put_u32(start, ty);
+        self.put_u32(start + 4, (8 + body_len) as u32);
+        self.len = end;
+        Ok(start + 8)
+    }
+
+    fn push_string(&mut self, ty: u32, s: &str) -> Result<(), &'static str> {
+        let body = self.push_tag(ty, s.len() + 1)?;
+        self.buf[body..body + s.len()].copy_from_slice(s.as_bytes());
+        Ok(())
+    }
+
+    pub fn add_cmdline(&mut self, cmdline: &str) -> Result<(), &'static str> {
+        self.push_string(TAG_CMDLINE, cmdline)
+    }
+
+    pub fn add_bootloader_name(&mut self) -> Result<(), &'static str> {
+        self.push_string(TAG_BOOTLOADER_NAME, BOOTLOADER_NAME)
+    }
+
+    pub fn add_basic_meminfo(&mut self, lower_kb: u32, upper_kb: u32) -> Result<(), &'static str> {
+        let body = self.push_tag(TAG_BASIC_MEMINFO, 8)?;
+        self.put_u32(body, lower_kb);
+        self.put_u32(body + 4, upper_kb);
+        Ok(())
+    }
+
+    pub fn add_memory_map(&mut self, regions: &[BootMemoryRegion]) -> Result<(), &'static str> {
+        let body = self.push_tag(TAG_MMAP, 8 + regions.len() * MMAP_ENTRY_SIZE)?;
+        self.put_u32(body, MMAP_ENTRY_SIZE as u32);
+        self.put_u32(body + 4, MMAP_ENTRY_VERSION);
+        for (i, r) in regions.iter().enumerate() {
+            let entry = body + 8 + i * MMAP_ENTRY_SIZE;
+            self.put_u64(entry, r.base);
+            self.put_u64(entry + 8, r.length);
+            self.put_u32(entry + 16, memory_type(r.ty));
+        }
+        Ok(())
+    }
+
+    /// Direct-colour framebuffer tag for a GOP mode. Nothing is added for
+    /// `FB_FORMAT_NONE` (BltOnly GOP, or no GOP at all).
+    pub fn add_framebuffer(&mut self, fb: &FramebufferDescriptor) -> Result<(), &'static str> {
+        if fb.base == 0 || fb.format == FB_FORMAT_NONE {
+            return Ok(());
+        }
+        const BYTES_PER_PIXEL: u32 = 4;
+        let body = self.push_tag(TAG_FRAMEBUFFER, FRAMEBUFFER_TAG_BODY)?;
+        self.put_u64(body, fb.base);
+        self.put_u32(body + 8, fb.stride * BYTES_PER_PIXEL);
+        self.put_u32(body + 12, fb.width);
+        self.put_u32(body + 16, fb.height);
+        self.put_u8(body + 20, (BYTES_PER_PIXEL * 8) as u8);
+        self.put_u8(body + 21, FRAMEBUFFER_TYPE_RGB);
+        for (i, mask) in [fb.red_mask, fb.green_mask, fb.blue_mask].into_iter().enumerate() {
+            let position = if mask == 0 { 0 } else { mask.trailing_zeros() as u8 };
+            self.put_u8(body + FRAMEBUFFER_TAG_COMMON + 2 * i, position);
+            self.put_u8(body + FRAMEBUFFER_TAG_COMMON + 1 + 2 * i, mask.count_ones() as u8);
+        }
+        Ok(())
+    }
+
+    /// Framebuffer tag for the 80x25 VGA text buffer the BIOS path prints to.
+    pub fn add_ega_text(&mut self) -> Result<(), &'static str> {
+        const COLUMNS: u32 = 80;
+        const ROWS: u32 = 25;
+        let body = self.push_tag(TAG_FRAMEBUFFER, FRAMEBUFFER_TAG_COMMON)?;
+        self.put_u64(body, EGA_TEXT_BUFFER);
+        self.put_u32(body + 8, COLUMNS * 2);
+        self.put_u32(body + 12, COLUMNS);
+        self.put_u32(body + 16, ROWS);
+        self.put_u8(body + 20, 16);
+        self.put_u8(body + 21, FRAMEBUFFER_TYPE_EGA_TEXT);
+        Ok(())
+    }
+
+    /// Command line, loader name, memory information and the memory map, in
+    /// that order. The framebuffer tag is left to the caller.
+    pub fn from_regions(buf: &'a mut [u8], cmdline: &str, regions: &[BootMemoryRegion]) -> Result<Self, &'static str> {
+        let mut info = Self::new(buf)?;
+        info.add_cmdline(cmdline)?;
+        info.add_bootloader_name()?;
+        let (lower, upper) = basic_meminfo(regions);
+        info.add_basic_meminfo(lower, upper)?;
+        info.add_memory_map(regions)?;
+        Ok(info)
+    }
+
+    /// Everything `boot_info` has for a Multiboot2 kernel. Call once the
+    /// memory map has been recorded.
+    pub fn from_boot_info(buf: &'a mut [u8], boot_info: &BootInfo) -> Result<Self, &'static str> {
+        let regions = if boot_info.memory_map_base.is_null() {
+            &[][..]
+        } else {
+            unsafe { core::slice::from_raw_parts(boot_info.memory_map_base, boot_info.memory_map_count) }
+        };
+        let mut info = Self::from_regions(buf, boot_info.cmdline_str(), regions)?;
+        info.add_framebuffer(&boot_info.framebuffer)?;
+        Ok(info)
+    }
+
+    /// Close the structure; returns its physical address for EBX.
+    pub fn finish(mut self) -> Result<u32, &'static str> {
+        self.push_tag(TAG_END, 0)?;
+        let total = self.len as u32;
+        self.put_u32(0, total);
+        let addr = self.buf.as_ptr() as usize;
+        u32::try_from(addr).map_err(|_| "multiboot2: info above 4 GiB")
+    }
+}
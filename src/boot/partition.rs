@@ -0,0 +1,57 @@
+//! Partition table of the boot disk, GPT or MBR.
+
+use super::gpt::{self, GptInfo, GPT_ATTR_LEGACY_BIOS_BOOTABLE};
+use super::mbr::{self, MbrInfo};
+
+#[derive(Clone, Debug)]
+pub enum PartitionTable {
+    Gpt(GptInfo),
+    Mbr(MbrInfo),
+}
+
+/// A partition independent of the table format.
+#[derive(Copy, Clone, Debug)]
+pub struct BootPartition {
+    pub index: usize,
+    pub start_lba: u64,
+    pub sectors: u64,
+}
+
+/// Probe GPT first, then fall back to MBR if LBA 0 is not a protective MBR.
+pub fn probe() -> Result<PartitionTable, &'static str> {
+    if let Some(info) = gpt::probe()? {
+        return Ok(PartitionTable::Gpt(info));
+    }
+    mbr::probe().map(PartitionTable::Mbr)
+}
+
+/// The active partition: the MBR boot flag, or the GPT legacy-BIOS-bootable
+/// attribute.
+pub fn find_active_partition(table: &PartitionTable) -> Option<BootPartition> {
+    match table {
+        PartitionTable::Mbr(info) => mbr::find_active_partition(info).map(|(i, p)| from_mbr(i, p)),
+        PartitionTable::Gpt(info) => info
+            .partitions
+            .iter()
+            .enumerate()
+            .find_map(|(i, p)| p.filter(|p| (p.attributes & GPT_ATTR_LEGACY_BIOS_BOOTABLE) != 0).map(|p| from_gpt(i, p))),
+    }
+}
+
+/// The first used partition, for disks with no active flag set.
+pub fn first_present_partition(table: &PartitionTable) -> Option<BootPartition> {
+    match table {
+        PartitionTable::Mbr(info) => mbr::first_present_partition(info).map(|(i, p)| from_mbr(i, p)),
+        PartitionTable::Gpt(info) => {
+            info.partitions.iter().enumerate().find_map(|(i, p)| p.map(|p| from_gpt(i, p)))
+        }
+    }
+}
+
+fn from_mbr(index: usize, p: mbr::PartitionEntry) -> BootPartition {
+    BootPartition { index, start_lba: p.starting_lba as u64, sectors: p.sectors as u64 }
+}
+
+fn from_gpt(index: usize, p: gpt::GptPartition) -> BootPartition {
+    BootPartition { index, start_lba: p.first_lba, sectors: p.last_lba.saturating_sub(p.first_lba) + 1 }
+}
@@ -1,49 +1,323 @@
 #[allow(unused)]
 use crate::kernel::loader;
+use crate::arch::cpuid;
+use crate::boot::{multiboot2, partition};
+use crate::config::slots::{self, BootSlot};
+use crate::memory::paging::{LOW_IDENTITY_LIMIT, PageFlags, PageTableSet};
+use crate::ui::boot_menu::{self, BootEntry};
 use crate::{drivers, fs};
 
+use core::sync::atomic::{AtomicU8, Ordering};
+
+/// Coarse boot progress, recorded so a failure can be attributed to a phase.
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BootPhase {
+    Init = 0,
+    DiskDetect,
+    FsMount,
+    KernelFind,
+    KernelLoad,
+    MemSetup,
+    PageTableSetup,
+    KernelJump,
+}
+
+impl BootPhase {
+    pub fn name(self) -> &'static str {
+        match self {
+            BootPhase::Init => "Init",
+            BootPhase::DiskDetect => "DiskDetect",
+            BootPhase::FsMount => "FsMount",
+            BootPhase::KernelFind => "KernelFind",
+            BootPhase::KernelLoad => "KernelLoad",
+            BootPhase::MemSetup => "MemSetup",
+            BootPhase::PageTableSetup => "PageTableSetup",
+            BootPhase::KernelJump => "KernelJump",
+        }
+    }
+
+    fn from_u8(v: u8) -> BootPhase {
+        match v {
+            1 => BootPhase::DiskDetect,
+            2 => BootPhase::FsMount,
+            3 => BootPhase::KernelFind,
+            4 => BootPhase::KernelLoad,
+            5 => BootPhase::MemSetup,
+            6 => BootPhase::PageTableSetup,
+            7 => BootPhase::KernelJump,
+            _ => BootPhase::Init,
+        }
+    }
+}
+
+static CURRENT_PHASE: AtomicU8 = AtomicU8::new(BootPhase::Init as u8);
+
+/// Record and log a phase transition.
+pub fn set_phase(phase: BootPhase) {
+    CURRENT_PHASE.store(phase as u8, Ordering::SeqCst);
+    log_info!("stage2", "Phase: {}", phase.name());
+}
+
+/// Phase the boot was in when this is called (used by panic paths).
+pub fn current_phase() -> BootPhase {
+    BootPhase::from_u8(CURRENT_PHASE.load(Ordering::SeqCst))
+}
+
+/// Boot attempts before giving up; covers drives that spin up slowly.
+const BOOT_ATTEMPTS: u8 = 3;
+const BOOT_RETRY_DELAY_MS: u64 = 1000;
+
+/// Seconds the boot menu waits before booting the first entry
+const BOOT_MENU_TIMEOUT_SECS: u32 = 5;
+const MAX_MENU_ENTRIES: usize = 8;
+
 pub fn start() -> ! {
+    set_phase(BootPhase::Init);
+    crate::arch::x86::init_tsc_clock();
     drivers::vga::print_string("[stage2] Starting...");
 
-    match drivers::disk::init() {
-        Ok(()) => {
-            let _ = drivers::vga::print_string("[stage2] Disk init OK\n");
+    #[cfg(feature = "memtest")]
+    {
+        set_phase(BootPhase::MemSetup);
+        let limit = crate::memory::find_kernel_address(0).unwrap_or(usize::MAX);
+        if !crate::memory::memtest::run_and_report(limit) {
+            panic_msg("[stage2] RAM test failed", "");
         }
-        Err(e) => {
-            panic_msg("[stage2] Disk init failed: {}", e);
+    }
+
+    retry_boot_sequence(BOOT_ATTEMPTS)
+}
+
+/// Run disk init, filesystem mount and kernel load up to `max_attempts` times,
+/// one second apart, then jump to the kernel. Halts with the last error if
+/// every attempt fails.
+fn retry_boot_sequence(max_attempts: u8) -> ! {
+    // Chosen on the first attempt that gets past the mount, then kept
+    let mut chosen = None;
+    let mut last_err = "no boot attempt made";
+    for attempt in 1..=max_attempts {
+        if attempt > 1 {
+            log_info!("stage2", "Retrying boot ({}/{})...", attempt, max_attempts);
+        }
+        match boot_attempt(&mut chosen) {
+            Ok(entry) if loader::multiboot2_requested() => jump_to_multiboot2(entry),
+            Ok(entry) if loader::long_mode_requested() => jump_to_long_mode(entry),
+            Ok(entry) => jump_to_entry(entry),
+            Err(e) => {
+                drivers::vga::print_error(e);
+                drivers::vga::print_string("\n");
+                last_err = e;
+            }
+        }
+        if attempt < max_attempts {
+            crate::arch::timer::sleep_ms(BOOT_RETRY_DELAY_MS);
+        }
+    }
+
+    drivers::vga::print_error("\n========================================\n");
+    drivers::vga::print_error("  BOOT FAILED after all retries\n");
+    drivers::vga::print_error("========================================\n");
+    panic_msg("[stage2] last error: ", last_err)
+}
+
+/// Let the user pick one of the kernel paths, shown with their modification
+/// times on a FAT volume. No menu on Hyper-V Gen2, which has neither VGA nor
+/// a PS/2 keyboard.
+fn choose_kernel() -> Option<&'static str> {
+    if cpuid::legacy_devices_unavailable() {
+        return None;
+    }
+    let mut entries = [BootEntry { title: "", path: "", mtime: "" }; MAX_MENU_ENTRIES];
+    let mut mtimes = [[0u8; 20]; MAX_MENU_ENTRIES];
+    let count = loader::KERNEL_PATHS.len().min(MAX_MENU_ENTRIES);
+    for ((entry, buf), &path) in entries.iter_mut().zip(mtimes.iter_mut()).zip(loader::KERNEL_PATHS) {
+        let mtime = fs::fat::file_mtime(path, buf).unwrap_or("");
+        *entry = BootEntry { title: path, path, mtime };
+    }
+    let choice = boot_menu::show(&entries[..count], BOOT_MENU_TIMEOUT_SECS);
+    Some(loader::KERNEL_PATHS[choice])
+}
+
+fn boot_attempt(chosen: &mut Option<Option<&'static str>>) -> Result<u32, &'static str> {
+    set_phase(BootPhase::DiskDetect);
+    // Disks found by an earlier attempt are kept
+    if drivers::block::count() == 0 {
+        detect_disks();
+    }
+    if drivers::block::count() == 0 {
+        // Gen2 disks sit on VMBus, which no driver here speaks; retrying
+        // cannot help
+        if cpuid::legacy_devices_unavailable() {
+            panic_msg("[stage2] Hyper-V Gen2: no supported boot disk ", "(VMBus storage is not supported)");
         }
+        drivers::vga::print_error("[stage2] Disk init failed: ");
+        return Err("no disk found");
     }
 
-    if let Err(e) = try_mount_filesystems() {
-        drivers::vga::print_string("[stage2] Filesystem init filesystem or skipped: ");
-        drivers::vga::print_string(e);
-        drivers::vga::print_string("\n");
+    set_phase(BootPhase::FsMount);
+    // The first disk holding a filesystem we can mount is the boot disk. A
+    // retry keeps the one an earlier attempt mounted.
+    let mut mounted = fs::ext::is_mounted() || fs::fat::is_mounted();
+    if !mounted {
+        for i in 0..drivers::block::count() {
+            drivers::block::select(i);
+            match try_mount_filesystems() {
+                Ok(()) => {
+                    mounted = true;
+                    break;
+                }
+                Err(e) => log_info!("stage2", "No filesystem on {}: {}", drivers::block::name(i), e),
+            }
+        }
+    }
+    if !mounted {
+        drivers::vga::print_error("[stage2] Filesystem init failed or skipped\n");
     }
-    let entry = match loader::find_and_load_kernel() {
-        Ok(entry) => {
-            drivers::vga::print_string("[stage2] kernel loaded, entry @ 0x");
-            hex_u32(entry);
-            drivers::vga::print_string("\n");
-            entry
+
+    set_phase(BootPhase::KernelFind);
+    // An A/B slot configuration on the boot volume decides the kernel, so the
+    // menu is only shown without one. It comes after the mount so it can show
+    // the kernels' dates.
+    let mut slots_cfg = [0u8; slots::SLOTS_CFG_MAX];
+    let preferred = match boot_slot(&mut slots_cfg) {
+        Some(slot) => {
+            log_info!("stage2", "A/B slot config found, skipping the boot menu");
+            Some(slot.kernel)
         }
-        Err(e) => panic_msg("[stage2] kernel load FAILED: {}", e),
+        None => *chosen.get_or_insert_with(choose_kernel),
+    };
+    let entry = match loader::find_and_load_kernel(preferred) {
+        Ok(entry) => entry,
+        Err(e) => {
+            drivers::vga::print_error("[stage2] kernel load FAILED: ");
+            return Err(e);
+        }
+    };
+    drivers::vga::print_success("[stage2] kernel loaded, entry @ ");
+    drivers::vga::print_hex32(entry);
+    drivers::vga::print_string("\n");
+    Ok(entry)
+}
+
+/// Register the legacy ATA drives, then the disks behind PCI storage
+/// controllers, with `drivers::block`.
+fn detect_disks() {
+    if cpuid::legacy_devices_unavailable() {
+        log_info!("stage2", "Hyper-V Gen2: no legacy IDE, skipping ATA");
+    } else {
+        for d in drivers::disk::probe_all().iter().flatten() {
+            log_info!("stage2", "Disk {}: {} ({} sectors)", d.target.name(), d.model_str(), d.sectors);
+            drivers::block::register(drivers::block::BlockDevice::Ata(d.target));
+        }
+    }
+    drivers::block::probe_pci(&drivers::pci::scan());
+}
+
+/// The slot to boot from `/boot/slots.cfg` on the mounted volume, `None`
+/// without one. Halts when both slots are out of tries.
+fn boot_slot(cfg: &mut [u8; slots::SLOTS_CFG_MAX]) -> Option<BootSlot<'_>> {
+    let mut ext = fs::ext::ExtFs;
+    let mut fat = fs::fat::FatFs;
+    let volume: &mut dyn fs::Filesystem = if fs::ext::is_mounted() {
+        &mut ext
+    } else if fs::fat::is_mounted() {
+        &mut fat
+    } else {
+        return None;
     };
+
+    let mut slots = slots::load_slots(volume, cfg);
+    if slots.iter().all(|s| s.is_none()) {
+        return None;
+    }
+    Some(slots::select_slot(volume, &mut slots))
+}
+
+/// Call an ELF32 kernel in the 32-bit protected mode stage2 runs in.
+fn jump_to_entry(entry: u32) -> ! {
+    set_phase(BootPhase::KernelJump);
     unsafe {
         core::arch::asm!("cli");
+        // The real-mode stub's GDT is in memory the kernel is free to reuse
+        crate::arch::gdt::init();
     }
     unsafe {
-        let entry_fn: extern "C" fn() -> ! = core::mem::transmute(entry as usize);
+        let entry_fn: extern "C" fn() = core::mem::transmute(entry as usize);
         entry_fn();
     }
+
+    // A kernel must never return; power off rather than sit in a dead loop
+    drivers::vga::print_string("[stage2] kernel returned, shutting down\n");
+    crate::acpi::power_off()
+}
+
+/// Enter an ELF64 kernel in long mode: identity-mapped tables for the low
+/// 4 GiB, the static GDT, then `enter_long_mode`, which calls `entry` as a
+/// SysV64 function with RDI = 0; no `BootInfo` is passed on this path (see
+/// `arch::long_mode`).
+fn jump_to_long_mode(entry: u32) -> ! {
+    set_phase(BootPhase::PageTableSetup);
+    let pml4 = match identity_page_tables() {
+        Ok(pml4) => pml4,
+        Err(e) => panic_msg("[stage2] Page tables: ", e),
+    };
+    set_phase(BootPhase::KernelJump);
+    unsafe {
+        core::arch::asm!("cli");
+        crate::arch::gdt::init();
+        crate::arch::long_mode::enter_long_mode(pml4, entry, 0)
+    }
+}
+
+/// Identity-map `[0, LOW_IDENTITY_LIMIT)`; returns the PML4 address.
+fn identity_page_tables() -> Result<u32, &'static str> {
+    let mut tables = PageTableSet::new()?;
+    tables.map_range(0, 0, LOW_IDENTITY_LIMIT, PageFlags::WRITABLE)?;
+    Ok(tables.pml4_address() as u32)
+}
+
+/// Enter a Multiboot2 kernel in the i386 machine state stage2 already runs
+/// in (flat segments, paging off): magic in EAX, information in EBX.
+fn jump_to_multiboot2(entry: u32) -> ! {
+    let info = match loader::build_multiboot2_info() {
+        Ok(info) => info,
+        Err(e) => panic_msg("[stage2] Multiboot2 info: ", e),
+    };
+    set_phase(BootPhase::KernelJump);
+    // EBX is loaded inside the asm, as in `loader::jump_to_kernel`. The
+    // static GDT provides the flat 32-bit segments Multiboot2 asks for.
+    unsafe {
+        core::arch::asm!("cli");
+        crate::arch::gdt::init();
+        core::arch::asm!(
+            "mov ebx, edx",
+            "jmp ecx",
+            in("eax") multiboot2::BOOTLOADER_MAGIC,
+            in("ecx") entry,
+            in("edx") info,
+            options(noreturn)
+        );
+    }
 }
+
 fn try_mount_filesystems() -> Result<(), &'static str> {
-    //Still being worked on.
-    Ok(())
+    let table = partition::probe()?;
+    let part = partition::find_active_partition(&table)
+        .or_else(|| partition::first_present_partition(&table))
+        .ok_or("no usable partition")?;
+
+    match fs::ext::init_with_lba(part.start_lba) {
+        Ok(()) => Ok(()),
+        Err(_) => fs::fat::init_with_lba(part.start_lba),
+    }
 }
 
 fn panic_msg(prefix: &str, msg: &str) -> ! {
-    drivers::vga::print_string(prefix);
-    drivers::vga::print_string(msg);
+    drivers::vga::print_error(prefix);
+    drivers::vga::print_error(msg);
+    drivers::vga::print_string("\n[stage2] Failed in phase: ");
+    drivers::vga::print_string(current_phase().name());
     drivers::vga::print_string("\nHalted\n");
     loop {
         unsafe {
@@ -51,19 +325,3 @@ fn panic_msg(prefix: &str, msg: &str) -> ! {
         }
     }
 }
-
-fn hex_u32(mut v: u32) {
-    const HEX: &[u8; 16] = b"0123456789ABCDEF";
-    drivers::vga::print_string("00000000");
-    // Print into a small buffer then write—simple & no alloc
-    let mut buf = [b'0'; 10];
-    buf[0] = b'0';
-    buf[1] = b'x';
-    for i in 0..8 {
-        let shift = 28 - (i * 4);
-        let nibble = ((v >> shift) & 0xF) as usize;
-        buf[2 + i] = HEX[nibble];
-    }
-    // SAFETY: buf is valid UTF-8 ASCI
-    drivers::vga::print_string(core::str::from_utf8(&buf).unwrap());
-}
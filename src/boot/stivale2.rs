@@ -0,0 +1,245 @@
+//! stivale2 boot protocol.
+//!
+//! A kernel opts in with a `.stivale2hdr` section holding a
+//! `Stivale2Header`. It is entered in long mode with RDI pointing at a
+//! `stivale2_struct` listing the memory map, framebuffer, RSDP and command
+//! line, and RSP on the stack the header names (or on a 16 KiB one the
+//! loader allocates when that is 0). Paging identity-maps all of memory and
+//! maps the low 4 GiB again at `HHDM_BASE`; a kernel linked in the higher
+//! half (usually at 0xffffffff80000000) is mapped at its link address on top
+//! (see `loader::build_page_tables`). There is no terminal.
+
+use crate::boot::bootinfo::{BootInfo, BootMemoryRegion, BootMemoryType, FB_FORMAT_NONE};
+
+pub const HEADER_SECTION: &str = ".stivale2hdr";
+/// Higher-half direct map of the low 4 GiB
+pub const HHDM_BASE: u64 = 0xFFFF_8000_0000_0000;
+pub const HEADER_SIZE: usize = 32;
+/// Stack handed to kernels whose header leaves `stack` at 0
+pub const LOADER_STACK_SIZE: usize = 16 * 1024;
+
+// ===== Header tags =====
+const HEADER_TAG_FRAMEBUFFER: u64 = 0x3ecc_1bc4_3d0f_7971;
+const HEADER_TAG_TERMINAL: u64 = 0xa85d_499b_1823_be72;
+/// Bounds the walk over a (possibly cyclic) header tag chain
+const MAX_HEADER_TAGS: usize = 32;
+
+// ===== Structure tags =====
+const STRUCT_TAG_CMDLINE: u64 = 0xe5e7_6a1b_4597_a781;
+const STRUCT_TAG_MEMMAP: u64 = 0x2187_f79e_8612_de07;
+const STRUCT_TAG_FRAMEBUFFER: u64 = 0x5064_61d2_9504_08fa;
+const STRUCT_TAG_RSDP: u64 = 0x9e17_8693_0a37_5e78;
+
+// ===== Memory map entry types =====
+const MEMMAP_USABLE: u32 = 1;
+const MEMMAP_RESERVED: u32 = 2;
+const MEMMAP_ACPI_RECLAIMABLE: u32 = 3;
+const MEMMAP_ACPI_NVS: u32 = 4;
+const MEMMAP_BAD_MEMORY: u32 = 5;
+const MEMMAP_BOOTLOADER_RECLAIMABLE: u32 = 0x1000;
+const MEMMAP_KERNEL_AND_MODULES: u32 = 0x1001;
+const MEMMAP_FRAMEBUFFER: u32 = 0x1002;
+
+const MEMMAP_ENTRY_SIZE: usize = 24;
+const FRAMEBUFFER_MEMORY_MODEL_RGB: u8 = 1;
+
+// ===== stivale2_struct =====
+const BRAND_LEN: usize = 64;
+const BOOTLOADER_BRAND: &str = "RustyBoot";
+const BOOTLOADER_VERSION: &str = env!("CARGO_PKG_VERSION");
+const STRUCT_TAGS_OFFSET: usize = 2 * BRAND_LEN;
+const STRUCT_SIZE: usize = STRUCT_TAGS_OFFSET + 8;
+/// identifier + next
+const TAG_HEADER_SIZE: usize = 16;
+const TAG_ALIGN: usize = 8;
+
+fn read_u16(data: &[u8], off: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(data.get(off..off + 2)?.try_into().ok()?))
+}
+
+fn read_u64(data: &[u8], off: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(data.get(off..off + 8)?.try_into().ok()?))
+}
+
+/// What a kernel's `.stivale2hdr` asks for.
+#[derive(Copy, Clone, Debug)]
+pub struct Stivale2Header {
+    /// 0: use the ELF entry point
+    pub entry_point: u64,
+    /// Initial RSP; 0 asks the loader for a stack
+    pub stack: u64,
+    pub flags: u64,
+    /// Preferred (width, height, bpp), all 0 for no preference
+    pub framebuffer: Option<(u16, u16, u16)>,
+    pub terminal: bool,
+}
+
+impl Stivale2Header {
+    /// Parse the section contents. `tag_at` maps a tag's link-time address to
+    /// the image bytes from there on.
+    pub fn parse<'a>(section: &[u8], tag_at: impl Fn(u64) -> Option<&'a [u8]>) -> Option<Self> {
+        let mut header = Self {
+            entry_point: read_u64(section, 0)?,
+            stack: read_u64(section, 8)?,
+            flags: read_u64(section, 16)?,
+            framebuffer: None,
+            terminal: false,
+        };
+
+        let mut next = read_u64(section, 24)?;
+        for _ in 0..MAX_HEADER_TAGS {
+            if next == 0 {
+                break;
+            }
+            let tag = tag_at(next)?;
+            match read_u64(tag, 0)? {
+                HEADER_TAG_FRAMEBUFFER => {
+                    header.framebuffer = Some((read_u16(tag, 16)?, read_u16(tag, 18)?, read_u16(tag, 20)?));
+                }
+                HEADER_TAG_TERMINAL => header.terminal = true,
+                // Tags we do not know are optional by definition
+                _ => {}
+            }
+            next = read_u64(tag, 8)?;
+        }
+        Some(header)
+    }
+}
+
+pub fn memmap_type(ty: BootMemoryType) -> u32 {
+    match ty {
+        BootMemoryType::Usable => MEMMAP_USABLE,
+        BootMemoryType::Reserved => MEMMAP_RESERVED,
+        BootMemoryType::AcpiReclaimable => MEMMAP_ACPI_RECLAIMABLE,
+        BootMemoryType::AcpiNvs => MEMMAP_ACPI_NVS,
+        BootMemoryType::BootloaderReclaimable => MEMMAP_BOOTLOADER_RECLAIMABLE,
+        BootMemoryType::KernelAndModules => MEMMAP_KERNEL_AND_MODULES,
+        BootMemoryType::Framebuffer => MEMMAP_FRAMEBUFFER,
+        BootMemoryType::BadMemory => MEMMAP_BAD_MEMORY,
+    }
+}
+
+/// Bytes `build` needs for `cmdline` and a map of `region_count` entries.
+pub fn struct_size(cmdline: &str, region_count: usize) -> usize {
+    let tag = |body: usize| (TAG_HEADER_SIZE + body).next_multiple_of(TAG_ALIGN);
+    STRUCT_SIZE
+        + tag(8 + region_count * MEMMAP_ENTRY_SIZE)
+        + tag(24)
+        + tag(8)
+        + tag(8)
+        + (cmdline.len() + 1).next_multiple_of(TAG_ALIGN)
+}
+
+/// `stivale2_struct` and its tag list being written into a buffer. Tags are
+/// linked by physical address, which the identity mapping makes valid for
+/// the kernel as is.
+struct StructWriter<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+    /// Offset of the `next` field to point at the following tag
+    link: usize,
+}
+
+impl<'a> StructWriter<'a> {
+    fn put_u8(&mut self, off: usize, v: u8) {
+        self.buf[off] = v;
+    }
+
+    fn put_u16(&mut self, off: usize, v: u16) {
+        self.buf[off..off + 2].copy_from_slice(&v.to_le_bytes());
+    }
+
+    fn put_u32(&mut self, off: usize, v: u32) {
+        self.buf[off..off + 4].copy_from_slice(&v.to_le_bytes());
+    }
+
+    fn put_u64(&mut self, off: usize, v: u64) {
+        self.buf[off..off + 8].copy_from_slice(&v.to_le_bytes());
+    }
+
+    fn addr(&self, off: usize) -> u64 {
+        (self.buf.as_ptr() as usize + off) as u64
+    }
+
+    /// Reserve `len` zeroed bytes; returns their offset.
+    fn reserve(&mut self, len: usize) -> Result<usize, &'static str> {
+        let start = self.len;
+        let end = start + len.next_multiple_of(TAG_ALIGN);
+        if end > self.buf.len() {
+            return Err("stivale2: structure buffer too small");
+        }
+        self.buf[start..end].fill(0);
+        self.len = end;
+        Ok(start)
+    }
+
+    /// Append a tag with a `body_len`-byte body and link it in; returns the
+    /// offset of the body.
+    fn push_tag(&mut self, id: u64, body_len: usize) -> Result<usize, &'static str> {
+        let tag = self.reserve(TAG_HEADER_SIZE + body_len)?;
+        self.put_u64(tag, id);
+        let addr = self.addr(tag);
+        self.put_u64(self.link, addr);
+        self.link = tag + 8;
+        Ok(tag + TAG_HEADER_SIZE)
+    }
+}
+
+/// Write the `stivale2_struct` for `boot_info` (memory map already recorded)
+/// into `buf`. Returns its address for RDI.
+pub fn build(buf: &mut [u8], boot_info: &BootInfo) -> Result<u64, &'static str> {
+    if buf.as_ptr() as usize % TAG_ALIGN != 0 {
+        return Err("stivale2: structure buffer misaligned");
+    }
+    let mut w = StructWriter { buf, len: 0, link: STRUCT_TAGS_OFFSET };
+    w.reserve(STRUCT_SIZE)?;
+    w.buf[..BOOTLOADER_BRAND.len()].copy_from_slice(BOOTLOADER_BRAND.as_bytes());
+    let version = &BOOTLOADER_VERSION.as_bytes()[..BOOTLOADER_VERSION.len().min(BRAND_LEN - 1)];
+    w.buf[BRAND_LEN..BRAND_LEN + version.len()].copy_from_slice(version);
+
+    let regions: &[BootMemoryRegion] = if boot_info.memory_map_base.is_null() {
+        &[]
+    } else {
+        unsafe { core::slice::from_raw_parts(boot_info.memory_map_base, boot_info.memory_map_count) }
+    };
+    let body = w.push_tag(STRUCT_TAG_MEMMAP, 8 + regions.len() * MEMMAP_ENTRY_SIZE)?;
+    w.put_u64(body, regions.len() as u64);
+    for (i, r) in regions.iter().enumerate() {
+        let entry = body + 8 + i * MEMMAP_ENTRY_SIZE;
+        w.put_u64(entry, r.base);
+        w.put_u64(entry + 8, r.length);
+        w.put_u32(entry + 16, memmap_type(r.ty));
+    }
+
+    let fb = &boot_info.framebuffer;
+    if fb.base != 0 && fb.format != FB_FORMAT_NONE {
+        const BYTES_PER_PIXEL: u32 = 4;
+        let body = w.push_tag(STRUCT_TAG_FRAMEBUFFER, 24)?;
+        w.put_u64(body, fb.base);
+        w.put_u16(body + 8, fb.width as u16);
+        w.put_u16(body + 10, fb.height as u16);
+        w.put_u16(body + 12, (fb.stride * BYTES_PER_PIXEL) as u16);
+        w.put_u16(body + 14, (BYTES_PER_PIXEL * 8) as u16);
+        w.put_u8(body + 16, FRAMEBUFFER_MEMORY_MODEL_RGB);
+        for (i, mask) in [fb.red_mask, fb.green_mask, fb.blue_mask].into_iter().enumerate() {
+            let shift = if mask == 0 { 0 } else { mask.trailing_zeros() as u8 };
+            w.put_u8(body + 17 + 2 * i, mask.count_ones() as u8);
+            w.put_u8(body + 18 + 2 * i, shift);
+        }
+    }
+
+    if boot_info.rsdp_address != 0 {
+        let body = w.push_tag(STRUCT_TAG_RSDP, 8)?;
+        w.put_u64(body, boot_info.rsdp_address);
+    }
+
+    // The tag points at a NUL-terminated copy kept in the same buffer
+    let cmdline = boot_info.cmdline_str();
+    let body = w.push_tag(STRUCT_TAG_CMDLINE, 8)?;
+    let text = w.reserve(cmdline.len() + 1)?;
+    w.buf[text..text + cmdline.len()].copy_from_slice(cmdline.as_bytes());
+    let text_addr = w.addr(text);
+    w.put_u64(body, text_addr);
+
+    Ok(w.addr(0))
+}
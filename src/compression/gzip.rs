@@ -0,0 +1,334 @@
+//! gzip (RFC 1952) container around a DEFLATE (RFC 1951) decoder.
+//!
+//! Output goes straight into the caller's buffer and back references are
+//! resolved against it, so no separate window is kept. Huffman codes are
+//! decoded canonically one bit at a time (as in zlib's `puff.c`): slower than
+//! a table-driven decoder, but small and fast enough for a kernel image.
+
+use crate::boot::gpt::crc32;
+
+// ===== gzip container =====
+const GZIP_MAGIC: [u8; 2] = [0x1F, 0x8B];
+const GZIP_CM_DEFLATE: u8 = 8;
+const GZIP_HEADER_LEN: usize = 10;
+const GZIP_TRAILER_LEN: usize = 8; // CRC32, ISIZE
+
+const FLAG_HCRC: u8 = 1 << 1;
+const FLAG_EXTRA: u8 = 1 << 2;
+const FLAG_NAME: u8 = 1 << 3;
+const FLAG_COMMENT: u8 = 1 << 4;
+const FLAG_RESERVED: u8 = 0xE0;
+
+// ===== DEFLATE =====
+const MAX_BITS: usize = 15;
+const MAX_LIT_CODES: usize = 286;
+const MAX_DIST_CODES: usize = 30;
+const FIXED_LIT_CODES: usize = 288;
+const CLEN_CODES: usize = 19;
+const END_OF_BLOCK: u16 = 256;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145,
+    8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+/// Order the code length code lengths are sent in
+const CLEN_ORDER: [usize; CLEN_CODES] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+/// `1f 8b` followed by the deflate method byte.
+pub fn is_gzip(data: &[u8]) -> bool {
+    data.len() >= GZIP_HEADER_LEN + GZIP_TRAILER_LEN && data[0..2] == GZIP_MAGIC && data[2] == GZIP_CM_DEFLATE
+}
+
+/// Uncompressed size from the trailer (ISIZE, modulo 2^32), for sizing the
+/// output buffer of `inflate`.
+pub fn uncompressed_size(data: &[u8]) -> Option<usize> {
+    if !is_gzip(data) {
+        return None;
+    }
+    let isize = &data[data.len() - 4..];
+    Some(u32::from_le_bytes(isize.try_into().ok()?) as usize)
+}
+
+/// Decompress the gzip member in `input` into `output` and return the number
+/// of bytes written. The CRC32 and size in the trailer are checked.
+pub fn inflate(input: &[u8], output: &mut [u8]) -> Result<usize, &'static str> {
+    if !is_gzip(input) {
+        return Err("gzip: bad magic or compression method");
+    }
+    let flags = input[3];
+    if flags & FLAG_RESERVED != 0 {
+        return Err("gzip: reserved header flags set");
+    }
+
+    // Skip MTIME, XFL, OS and the optional fields
+    let mut pos = GZIP_HEADER_LEN;
+    if flags & FLAG_EXTRA != 0 {
+        let xlen = u16::from_le_bytes([*input.get(pos).ok_or(TRUNCATED)?, *input.get(pos + 1).ok_or(TRUNCATED)?]);
+        pos += 2 + xlen as usize;
+    }
+    for flag in [FLAG_NAME, FLAG_COMMENT] {
+        if flags & flag != 0 {
+            let nul = input.get(pos..).ok_or(TRUNCATED)?.iter().position(|&b| b == 0).ok_or(TRUNCATED)?;
+            pos += nul + 1;
+        }
+    }
+    if flags & FLAG_HCRC != 0 {
+        pos += 2;
+    }
+    let body = input.get(pos..).ok_or(TRUNCATED)?;
+
+    let (written, consumed) = inflate_raw(body, output)?;
+
+    let trailer = body.get(consumed..consumed + GZIP_TRAILER_LEN).ok_or(TRUNCATED)?;
+    let crc = u32::from_le_bytes(trailer[0..4].try_into().unwrap());
+    let size = u32::from_le_bytes(trailer[4..8].try_into().unwrap());
+    if size != written as u32 {
+        return Err("gzip: size mismatch");
+    }
+    if crc != crc32(&output[..written]) {
+        return Err("gzip: CRC mismatch");
+    }
+    Ok(written)
+}
+
+const TRUNCATED: &str = "gzip: truncated input";
+
+/// Decode a raw DEFLATE stream. Returns `(bytes written, bytes of input
+/// consumed)`; the last partial byte counts as consumed.
+pub fn inflate_raw(input: &[u8], output: &mut [u8]) -> Result<(usize, usize), &'static str> {
+    let mut inflater = Inflater { bits: BitReader::new(input), out: output, out_pos: 0 };
+    loop {
+        let last = inflater.bits.bits(1)? == 1;
+        match inflater.bits.bits(2)? {
+            0 => inflater.stored()?,
+            1 => inflater.fixed()?,
+            2 => inflater.dynamic()?,
+            _ => return Err("gzip: invalid block type"),
+        }
+        if last {
+            break;
+        }
+    }
+    Ok((inflater.out_pos, inflater.bits.pos))
+}
+
+// ===== Bit reader =====
+
+/// LSB-first bit reader; bytes are pulled in only as needed, so after
+/// `align` the next read starts at `pos`.
+struct BitReader<'a> {
+    input: &'a [u8],
+    pos: usize,
+    buf: u32,
+    count: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(input: &'a [u8]) -> Self {
+        Self { input, pos: 0, buf: 0, count: 0 }
+    }
+
+    /// Next `n` (at most 16) bits.
+    fn bits(&mut self, n: u32) -> Result<u32, &'static str> {
+        while self.count < n {
+            let byte = *self.input.get(self.pos).ok_or(TRUNCATED)?;
+            self.pos += 1;
+            self.buf |= (byte as u32) << self.count;
+            self.count += 8;
+        }
+        let value = self.buf & ((1u32 << n) - 1);
+        self.buf >>= n;
+        self.count -= n;
+        Ok(value)
+    }
+
+    /// Drop the rest of the current byte.
+    fn align(&mut self) {
+        self.buf = 0;
+        self.count = 0;
+    }
+}
+
+// ===== Canonical Huffman decoding =====
+
+struct Huffman {
+    /// Number of codes of each length
+    count: [u16; MAX_BITS + 1],
+    /// Symbols ordered by code
+    symbol: [u16; FIXED_LIT_CODES],
+}
+
+impl Huffman {
+    /// Build from per-symbol code lengths (0 = unused). Incomplete codes are
+    /// accepted (a lone distance code is legal); over-subscribed ones are not.
+    fn new(lengths: &[u8]) -> Result<Self, &'static str> {
+        let mut h = Self { count: [0; MAX_BITS + 1], symbol: [0; FIXED_LIT_CODES] };
+        for &len in lengths {
+            h.count[len as usize] += 1;
+        }
+
+        let mut left = 1i32;
+        for len in 1..=MAX_BITS {
+            left = (left << 1) - h.count[len] as i32;
+            if left < 0 {
+                return Err("gzip: over-subscribed Huffman code");
+            }
+        }
+
+        let mut offsets = [0u16; MAX_BITS + 1];
+        for len in 1..MAX_BITS {
+            offsets[len + 1] = offsets[len] + h.count[len];
+        }
+        for (sym, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                h.symbol[offsets[len as usize] as usize] = sym as u16;
+                offsets[len as usize] += 1;
+            }
+        }
+        Ok(h)
+    }
+
+    fn decode(&self, bits: &mut BitReader) -> Result<u16, &'static str> {
+        // Codes of one length are consecutive: `first` is the first code of
+        // this length, `index` the position of its symbol
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for len in 1..=MAX_BITS {
+            code |= bits.bits(1)? as i32;
+            let count = self.count[len] as i32;
+            if code - first < count {
+                return Ok(self.symbol[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err("gzip: invalid Huffman code")
+    }
+}
+
+// ===== Blocks =====
+
+struct Inflater<'a, 'b> {
+    bits: BitReader<'a>,
+    out: &'b mut [u8],
+    out_pos: usize,
+}
+
+impl Inflater<'_, '_> {
+    fn stored(&mut self) -> Result<(), &'static str> {
+        self.bits.align();
+        let len = self.bits.bits(16)? as usize;
+        let nlen = self.bits.bits(16)? as usize;
+        if len != !nlen & 0xFFFF {
+            return Err("gzip: stored block length check failed");
+        }
+        let src = self.bits.input.get(self.bits.pos..self.bits.pos + len).ok_or(TRUNCATED)?;
+        let dst = self.out.get_mut(self.out_pos..self.out_pos + len).ok_or(OUTPUT_FULL)?;
+        dst.copy_from_slice(src);
+        self.bits.pos += len;
+        self.out_pos += len;
+        Ok(())
+    }
+
+    fn fixed(&mut self) -> Result<(), &'static str> {
+        let mut lengths = [0u8; FIXED_LIT_CODES];
+        lengths[..144].fill(8);
+        lengths[144..256].fill(9);
+        lengths[256..280].fill(7);
+        lengths[280..].fill(8);
+        let lit = Huffman::new(&lengths)?;
+        let dist = Huffman::new(&[5u8; MAX_DIST_CODES])?;
+        self.codes(&lit, &dist)
+    }
+
+    fn dynamic(&mut self) -> Result<(), &'static str> {
+        let hlit = self.bits.bits(5)? as usize + 257;
+        let hdist = self.bits.bits(5)? as usize + 1;
+        let hclen = self.bits.bits(4)? as usize + 4;
+        if hlit > MAX_LIT_CODES || hdist > MAX_DIST_CODES {
+            return Err("gzip: too many length or distance codes");
+        }
+
+        let mut clen_lengths = [0u8; CLEN_CODES];
+        for &i in &CLEN_ORDER[..hclen] {
+            clen_lengths[i] = self.bits.bits(3)? as u8;
+        }
+        let clen = Huffman::new(&clen_lengths)?;
+
+        let mut lengths = [0u8; MAX_LIT_CODES + MAX_DIST_CODES];
+        let total = hlit + hdist;
+        let mut i = 0;
+        while i < total {
+            let sym = clen.decode(&mut self.bits)?;
+            if sym < 16 {
+                lengths[i] = sym as u8;
+                i += 1;
+                continue;
+            }
+            let (value, repeat) = match sym {
+                16 if i == 0 => return Err("gzip: repeat with no previous length"),
+                16 => (lengths[i - 1], 3 + self.bits.bits(2)? as usize),
+                17 => (0, 3 + self.bits.bits(3)? as usize),
+                _ => (0, 11 + self.bits.bits(7)? as usize),
+            };
+            if i + repeat > total {
+                return Err("gzip: code lengths overrun");
+            }
+            lengths[i..i + repeat].fill(value);
+            i += repeat;
+        }
+        if lengths[END_OF_BLOCK as usize] == 0 {
+            return Err("gzip: no end-of-block code");
+        }
+
+        let lit = Huffman::new(&lengths[..hlit])?;
+        let dist = Huffman::new(&lengths[hlit..total])?;
+        self.codes(&lit, &dist)
+    }
+
+    /// Literals and length/distance pairs up to the end-of-block code.
+    fn codes(&mut self, lit: &Huffman, dist: &Huffman) -> Result<(), &'static str> {
+        loop {
+            let sym = lit.decode(&mut self.bits)?;
+            if sym < END_OF_BLOCK {
+                *self.out.get_mut(self.out_pos).ok_or(OUTPUT_FULL)? = sym as u8;
+                self.out_pos += 1;
+                continue;
+            }
+            if sym == END_OF_BLOCK {
+                return Ok(());
+            }
+
+            let sym = (sym - 257) as usize;
+            if sym >= LENGTH_BASE.len() {
+                return Err("gzip: invalid length code");
+            }
+            let len = LENGTH_BASE[sym] as usize + self.bits.bits(LENGTH_EXTRA[sym] as u32)? as usize;
+
+            let dsym = dist.decode(&mut self.bits)? as usize;
+            if dsym >= DIST_BASE.len() {
+                return Err("gzip: invalid distance code");
+            }
+            let distance = DIST_BASE[dsym] as usize + self.bits.bits(DIST_EXTRA[dsym] as u32)? as usize;
+            if distance > self.out_pos {
+                return Err("gzip: distance before start of output");
+            }
+            if self.out_pos + len > self.out.len() {
+                return Err(OUTPUT_FULL);
+            }
+
+            // Byte by byte: the source may overlap what is being written
+            for _ in 0..len {
+                self.out[self.out_pos] = self.out[self.out_pos - distance];
+                self.out_pos += 1;
+            }
+        }
+    }
+}
+
+const OUTPUT_FULL: &str = "gzip: output buffer too small";
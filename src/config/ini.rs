@@ -0,0 +1,226 @@
+//! `\EFI\BOOT\rustyboot.cfg`: boot parameters in INI form.
+//!
+//! ```text
+//! # defaults
+//! [boot]
+//! kernel=\EFI\BOOT\kernel.elf
+//! cmdline=quiet
+//! timeout=5
+//! default=1
+//!
+//! ; menu entries, 0-15
+//! [boot.0]
+//! title=Linux
+//! kernel=\vmlinuz
+//! initrd=\initrd.img
+//! cmdline=root=/dev/sda2
+//! ```
+//!
+//! The file is scanned through a 4 KiB stack buffer, so no line may be longer
+//! than that. Unknown sections and keys, and lines without `=`, are skipped;
+//! a value too long for its field is an error rather than being cut short.
+
+use uefi::proto::media::file::{Directory, File, FileAttribute, FileMode, RegularFile};
+
+pub const CONFIG_PATH: &str = "\\EFI\\BOOT\\rustyboot.cfg";
+pub const MAX_ENTRIES: usize = 16;
+
+pub const PATH_MAX: usize = 256;
+pub const CMDLINE_MAX: usize = crate::boot::cmdline::CMDLINE_MAX;
+pub const TITLE_MAX: usize = 64;
+
+const SCAN_BUF_SIZE: usize = 4096;
+
+/// One `[boot.N]` section. Unset fields are all zero.
+#[derive(Copy, Clone)]
+pub struct BootEntry {
+    pub title: [u8; TITLE_MAX],
+    pub kernel: [u8; PATH_MAX],
+    pub initrd: [u8; PATH_MAX],
+    pub cmdline: [u8; CMDLINE_MAX],
+}
+
+impl BootEntry {
+    pub const fn empty() -> Self {
+        Self { title: [0; TITLE_MAX], kernel: [0; PATH_MAX], initrd: [0; PATH_MAX], cmdline: [0; CMDLINE_MAX] }
+    }
+
+    /// Sections without a `kernel=` line are not bootable.
+    pub fn is_present(&self) -> bool {
+        self.kernel[0] != 0
+    }
+
+    /// Title, or the kernel path when no `title=` was given.
+    pub fn title_str(&self) -> &str {
+        if self.title[0] != 0 { field_str(&self.title) } else { field_str(&self.kernel) }
+    }
+
+    pub fn kernel_str(&self) -> &str {
+        field_str(&self.kernel)
+    }
+
+    pub fn initrd_str(&self) -> &str {
+        field_str(&self.initrd)
+    }
+
+    pub fn cmdline_str(&self) -> &str {
+        field_str(&self.cmdline)
+    }
+}
+
+/// Parsed config. String fields are NUL-padded UTF-8.
+pub struct BootConfig {
+    /// `[boot]` values
+    pub kernel: [u8; PATH_MAX],
+    pub initrd: [u8; PATH_MAX],
+    pub cmdline: [u8; CMDLINE_MAX],
+    /// Boot menu timeout in seconds, 0 for no menu
+    pub timeout: u32,
+    /// Index into `entries` booted when the timeout runs out
+    pub default: u32,
+    pub entries: [BootEntry; MAX_ENTRIES],
+}
+
+impl BootConfig {
+    pub const fn empty() -> Self {
+        Self {
+            kernel: [0; PATH_MAX],
+            initrd: [0; PATH_MAX],
+            cmdline: [0; CMDLINE_MAX],
+            timeout: 0,
+            default: 0,
+            entries: [BootEntry::empty(); MAX_ENTRIES],
+        }
+    }
+
+    pub fn kernel_str(&self) -> &str {
+        field_str(&self.kernel)
+    }
+
+    pub fn initrd_str(&self) -> &str {
+        field_str(&self.initrd)
+    }
+
+    pub fn cmdline_str(&self) -> &str {
+        field_str(&self.cmdline)
+    }
+
+    /// `(index, entry)` for every section that names a kernel.
+    pub fn entries(&self) -> impl Iterator<Item = (usize, &BootEntry)> {
+        self.entries.iter().enumerate().filter(|(_, e)| e.is_present())
+    }
+}
+
+/// Bytes up to the first NUL; `""` if that is not UTF-8 (cannot happen for
+/// fields filled by `load`, which only copies `&str`s).
+fn field_str(field: &[u8]) -> &str {
+    let len = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    core::str::from_utf8(&field[..len]).unwrap_or("")
+}
+
+fn set_field(field: &mut [u8], value: &str) -> Result<(), &'static str> {
+    if value.len() > field.len() {
+        return Err("Config value too long");
+    }
+    field.fill(0);
+    field[..value.len()].copy_from_slice(value.as_bytes());
+    Ok(())
+}
+
+/// Which section the following `key=value` lines belong to.
+#[derive(Copy, Clone)]
+enum Section {
+    Boot,
+    Entry(usize),
+    Ignored,
+}
+
+/// Read and parse `CONFIG_PATH` from the boot volume.
+pub fn load(root: &mut Directory) -> Result<BootConfig, &'static str> {
+    let mut file = open_config(root)?;
+    let mut config = BootConfig::empty();
+    let mut section = Section::Ignored;
+
+    let mut buf = [0u8; SCAN_BUF_SIZE];
+    let mut filled = 0usize;
+    loop {
+        let n = file.read(&mut buf[filled..]).map_err(|_| "Failed to read config file")?;
+        filled += n;
+        let eof = n == 0;
+
+        // Parse every complete line, then move the partial tail to the front
+        let mut start = 0;
+        while let Some(nl) = buf[start..filled].iter().position(|&b| b == b'\n') {
+            parse_line(&mut config, &mut section, &buf[start..start + nl])?;
+            start += nl + 1;
+        }
+        if eof {
+            if start < filled {
+                parse_line(&mut config, &mut section, &buf[start..filled])?;
+            }
+            return Ok(config);
+        }
+        if start == 0 && filled == buf.len() {
+            return Err("Config line too long");
+        }
+        buf.copy_within(start..filled, 0);
+        filled -= start;
+    }
+}
+
+fn open_config(root: &mut Directory) -> Result<RegularFile, &'static str> {
+    use uefi::CStr16;
+    let mut buf16 = [0u16; 64];
+    let path = CStr16::from_str_with_buf(CONFIG_PATH, &mut buf16).map_err(|_| "Invalid path")?;
+    let handle = root.open(path, FileMode::Read, FileAttribute::empty()).map_err(|_| "Config file not found")?;
+    match handle.into_type().map_err(|_| "Invalid file type")? {
+        File::Regular(f) => Ok(f),
+        _ => Err("Config path is not a regular file"),
+    }
+}
+
+fn parse_line(config: &mut BootConfig, section: &mut Section, line: &[u8]) -> Result<(), &'static str> {
+    let line = core::str::from_utf8(line).map_err(|_| "Config file is not valid UTF-8")?.trim();
+    if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+        return Ok(());
+    }
+
+    if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+        *section = match name.trim() {
+            "boot" => Section::Boot,
+            name => match name.strip_prefix("boot.").and_then(|n| n.parse::<usize>().ok()) {
+                Some(idx) if idx < MAX_ENTRIES => Section::Entry(idx),
+                _ => Section::Ignored,
+            },
+        };
+        return Ok(());
+    }
+
+    let Some((key, value)) = line.split_once('=') else {
+        return Ok(());
+    };
+    let (key, value) = (key.trim(), value.trim());
+
+    match *section {
+        Section::Boot => match key {
+            "kernel" => set_field(&mut config.kernel, value)?,
+            "initrd" => set_field(&mut config.initrd, value)?,
+            "cmdline" => set_field(&mut config.cmdline, value)?,
+            "timeout" => config.timeout = value.parse().map_err(|_| "Invalid timeout in config")?,
+            "default" => config.default = value.parse().map_err(|_| "Invalid default in config")?,
+            _ => {}
+        },
+        Section::Entry(idx) => {
+            let entry = &mut config.entries[idx];
+            match key {
+                "title" => set_field(&mut entry.title, value)?,
+                "kernel" => set_field(&mut entry.kernel, value)?,
+                "initrd" => set_field(&mut entry.initrd, value)?,
+                "cmdline" => set_field(&mut entry.cmdline, value)?,
+                _ => {}
+            }
+        }
+        Section::Ignored => {}
+    }
+    Ok(())
+}
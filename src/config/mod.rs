@@ -0,0 +1,4 @@
+#[cfg(feature = "uefi")]
+pub mod ini;
+#[cfg(feature = "bios")]
+pub mod slots;
@@ -0,0 +1,249 @@
+//! A/B kernel slots with a persisted try counter.
+//!
+//! `/boot/slots.cfg` describes two slots, `a` and `b`:
+//!
+//! ```text
+//! a.kernel=/boot/kernel-a.elf
+//! a.initrd=/boot/initrd-a.img
+//! a.cmdline=root=/dev/sda2
+//! a.tries=3
+//! b.kernel=/boot/kernel-b.elf
+//! b.tries=0
+//! ```
+//!
+//! Each boot decrements `tries` once and writes the file back. A slot whose
+//! counter has reached zero is considered bad and the other slot is used. The
+//! installed OS is expected to reset the counter once it has booted fine.
+//!
+//! The file is rewritten in place (see `Filesystem::write_file`): a counter
+//! that loses a digit is made up for with a trailing newline.
+
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use crate::drivers::vga;
+use crate::fs::{self, Filesystem};
+
+pub const SLOTS_PATH: &str = "/boot/slots.cfg";
+pub const SLOT_COUNT: usize = 2;
+
+/// Size of the buffer `load_slots` parses into
+pub const SLOTS_CFG_MAX: usize = 2048;
+const SLOT_NAMES: [u8; SLOT_COUNT] = [b'a', b'b'];
+
+/// The slot booted this time plus one, 0 until `select_slot` picked it
+static SELECTED_SLOT: AtomicU8 = AtomicU8::new(0);
+
+#[derive(Copy, Clone, Debug)]
+pub struct BootSlot<'a> {
+    pub kernel: &'a str,
+    pub initrd: &'a str,
+    pub cmdline: &'a str,
+    pub tries_left: u8,
+    pub slot_index: u8,
+}
+
+/// Read `/boot/slots.cfg` into `cfg` and parse it; the string fields point
+/// into `cfg`. Slots without a `kernel=` line are `None`.
+pub fn load_slots<'a>(fs: &mut dyn Filesystem, cfg: &'a mut [u8; SLOTS_CFG_MAX]) -> [Option<BootSlot<'a>>; SLOT_COUNT] {
+    let mut out: [Option<BootSlot>; SLOT_COUNT] = [None, None];
+
+    let len = match fs.read_file(SLOTS_PATH, cfg) {
+        Ok(n) => n,
+        Err(e) => {
+            vga::print_string("[slots] cannot read slots.cfg: ");
+            vga::print_string(e);
+            vga::print_string("\n");
+            return out;
+        }
+    };
+    let cfg: &'a [u8] = cfg;
+    let text = match core::str::from_utf8(&cfg[..len]) {
+        Ok(t) => t,
+        Err(_) => {
+            vga::print_string("[slots] slots.cfg is not valid UTF-8\n");
+            return out;
+        }
+    };
+
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (key, value) = match line.split_once('=') {
+            Some((k, v)) => (k.trim(), v.trim()),
+            None => continue,
+        };
+        let (slot, field) = match key.split_once('.') {
+            Some(kv) => kv,
+            None => continue,
+        };
+        let idx = match SLOT_NAMES.iter().position(|&n| slot.as_bytes() == [n]) {
+            Some(i) => i,
+            None => continue,
+        };
+
+        let entry = out[idx].get_or_insert(BootSlot {
+            kernel: "",
+            initrd: "",
+            cmdline: "",
+            tries_left: 0,
+            slot_index: idx as u8,
+        });
+        match field {
+            "kernel" => entry.kernel = value,
+            "initrd" => entry.initrd = value,
+            "cmdline" => entry.cmdline = value,
+            "tries" => match parse_u8(value) {
+                Some(n) => entry.tries_left = n,
+                None => {
+                    // Keeps tries_left at 0, so the slot will not be booted
+                    vga::print_string("[slots] slots.cfg: slot ");
+                    vga::print_char(SLOT_NAMES[idx]);
+                    vga::print_string(" tries is not a number 0-255: ");
+                    vga::print_string(value);
+                    vga::print_string("\n");
+                }
+            },
+            _ => {}
+        }
+    }
+
+    for slot in out.iter_mut() {
+        if matches!(slot, Some(s) if s.kernel.is_empty()) {
+            *slot = None;
+        }
+    }
+    out
+}
+
+/// Pick the slot to boot, consuming one try and persisting the new counter.
+/// Later calls (boot retries) return the same slot without consuming another.
+/// Halts if neither slot has tries left.
+pub fn select_slot<'a>(fs: &mut dyn Filesystem, slots: &mut [Option<BootSlot<'a>>; SLOT_COUNT]) -> BootSlot<'a> {
+    let selected = SELECTED_SLOT.load(Ordering::Relaxed);
+    if selected != 0 {
+        if let Some(slot) = slots[selected as usize - 1] {
+            return slot;
+        }
+    }
+
+    for idx in 0..SLOT_COUNT {
+        let slot = match slots[idx].as_mut() {
+            Some(s) => s,
+            None => continue,
+        };
+        if slot.tries_left == 0 {
+            vga::print_string("[slots] slot ");
+            vga::print_char(SLOT_NAMES[idx]);
+            vga::print_string(" marked bad, switching\n");
+            continue;
+        }
+
+        slot.tries_left -= 1;
+        let chosen = *slot;
+        SELECTED_SLOT.store(idx as u8 + 1, Ordering::Relaxed);
+        if let Err(e) = save_slots(fs, slots) {
+            vga::print_string("[slots] failed to persist try count: ");
+            vga::print_string(e);
+            vga::print_string("\n");
+        }
+
+        vga::print_string("[slots] booting slot ");
+        vga::print_char(SLOT_NAMES[idx]);
+        vga::print_string("\n");
+        return chosen;
+    }
+
+    vga::print_string("[slots] no bootable slot (both slots bad)\nHalted\n");
+    loop {
+        unsafe {
+            core::arch::asm!("hlt");
+        }
+    }
+}
+
+/// Serialize `slots` back into `/boot/slots.cfg`, padded with newlines to the
+/// file's current size.
+pub fn save_slots(fs: &mut dyn Filesystem, slots: &[Option<BootSlot>; SLOT_COUNT]) -> Result<(), &'static str> {
+    let mut buf = [0u8; SLOTS_CFG_MAX];
+    let file_len = fs.read_file(SLOTS_PATH, &mut buf)?;
+    let mut w = Writer { buf: &mut buf, len: 0 };
+
+    for (idx, slot) in slots.iter().enumerate() {
+        let s = match slot {
+            Some(s) => s,
+            None => continue,
+        };
+        w.field(SLOT_NAMES[idx], "kernel", s.kernel.as_bytes())?;
+        if !s.initrd.is_empty() {
+            w.field(SLOT_NAMES[idx], "initrd", s.initrd.as_bytes())?;
+        }
+        if !s.cmdline.is_empty() {
+            w.field(SLOT_NAMES[idx], "cmdline", s.cmdline.as_bytes())?;
+        }
+        let mut digits = [0u8; 3];
+        w.field(SLOT_NAMES[idx], "tries", format_u8(s.tries_left, &mut digits))?;
+    }
+
+    let len = w.len;
+    if len > file_len {
+        return Err("slots.cfg would grow");
+    }
+    buf[len..file_len].fill(b'\n');
+    fs::write_file(fs, SLOTS_PATH, &buf[..file_len])
+}
+
+// ===== Small helpers =====
+
+struct Writer<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl Writer<'_> {
+    fn push(&mut self, bytes: &[u8]) -> Result<(), &'static str> {
+        let end = self.len + bytes.len();
+        if end > self.buf.len() {
+            return Err("slots.cfg too large");
+        }
+        self.buf[self.len..end].copy_from_slice(bytes);
+        self.len = end;
+        Ok(())
+    }
+
+    fn field(&mut self, slot: u8, key: &str, value: &[u8]) -> Result<(), &'static str> {
+        self.push(&[slot, b'.'])?;
+        self.push(key.as_bytes())?;
+        self.push(b"=")?;
+        self.push(value)?;
+        self.push(b"\n")
+    }
+}
+
+fn parse_u8(s: &str) -> Option<u8> {
+    let mut v: u8 = 0;
+    if s.is_empty() {
+        return None;
+    }
+    for b in s.bytes() {
+        if !b.is_ascii_digit() {
+            return None;
+        }
+        v = v.checked_mul(10)?.checked_add(b - b'0')?;
+    }
+    Some(v)
+}
+
+fn format_u8(mut v: u8, buf: &mut [u8; 3]) -> &[u8] {
+    let mut i = buf.len();
+    loop {
+        i -= 1;
+        buf[i] = b'0' + v % 10;
+        v /= 10;
+        if v == 0 {
+            break;
+        }
+    }
+    &buf[i..]
+}
@@ -0,0 +1,314 @@
+//! Ed25519 signature verification (RFC 8032).
+//!
+//! Field and group arithmetic follow TweetNaCl: field elements are sixteen
+//! 16-bit limbs in `i64`s, points are extended (X:Y:Z:T) coordinates. Nothing
+//! here handles secrets, so constant time is not a concern, but the code is
+//! the constant-time original anyway.
+
+use super::sha512::Sha512;
+
+pub const PUBLIC_KEY_LEN: usize = 32;
+pub const SIGNATURE_LEN: usize = 64;
+
+type Gf = [i64; 16];
+type Point = [Gf; 4];
+
+const GF0: Gf = [0; 16];
+const GF1: Gf = [1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+/// Curve constant d = -121665/121666
+const D: Gf = [
+    0x78a3, 0x1359, 0x4dca, 0x75eb, 0xd8ab, 0x4141, 0x0a4d, 0x0070, 0xe898, 0x7779, 0x4079, 0x8cc7, 0xfe73, 0x2b6f,
+    0x6cee, 0x5203,
+];
+const D2: Gf = [
+    0xf159, 0x26b2, 0x9b94, 0xebd6, 0xb156, 0x8283, 0x149a, 0x00e0, 0xd130, 0xeef3, 0x80f2, 0x198e, 0xfce7, 0x56df,
+    0xd9dc, 0x2406,
+];
+/// Base point B
+const BX: Gf = [
+    0xd51a, 0x8f25, 0x2d60, 0xc956, 0xa7b2, 0x9525, 0xc760, 0x692c, 0xdc5c, 0xfdd6, 0xe231, 0xc0a4, 0x53fe, 0xcd6e,
+    0x36d3, 0x2169,
+];
+const BY: Gf = [
+    0x6658, 0x6666, 0x6666, 0x6666, 0x6666, 0x6666, 0x6666, 0x6666, 0x6666, 0x6666, 0x6666, 0x6666, 0x6666, 0x6666,
+    0x6666, 0x6666,
+];
+/// sqrt(-1)
+const SQRT_M1: Gf = [
+    0xa0b0, 0x4a0e, 0x1b27, 0xc4ee, 0xe478, 0xad2f, 0x1806, 0x2f43, 0xd7a7, 0x3dfb, 0x0099, 0x2b4d, 0xdf0b, 0x4fc1,
+    0x2480, 0x2b83,
+];
+/// Group order L = 2^252 + 27742317777372353535851937790883648493, little endian
+const L: [i64; 32] = [
+    0xed, 0xd3, 0xf5, 0x5c, 0x1a, 0x63, 0x12, 0x58, 0xd6, 0x9c, 0xf7, 0xa2, 0xde, 0xf9, 0xde, 0x14, 0, 0, 0, 0, 0, 0, 0,
+    0, 0, 0, 0, 0, 0, 0, 0, 0x10,
+];
+
+/// Check `signature` over `message` against `public_key`, using the
+/// cofactored equation `[8][S]B == [8]R + [8][k]A` with
+/// `k = SHA-512(R || A || message) mod L`. Non-canonical `S` and keys or `R`
+/// that do not decode to a curve point are rejected.
+pub fn verify(public_key: &[u8; PUBLIC_KEY_LEN], message: &[u8], signature: &[u8; SIGNATURE_LEN]) -> bool {
+    let r_bytes: &[u8; 32] = signature[..32].try_into().unwrap();
+    let s_bytes: &[u8; 32] = signature[32..].try_into().unwrap();
+    if !scalar_is_canonical(s_bytes) {
+        return false;
+    }
+    let (Some(a), Some(r)) = (unpack_point(public_key), unpack_point(r_bytes)) else {
+        return false;
+    };
+
+    let mut h = Sha512::new();
+    h.update(r_bytes);
+    h.update(public_key);
+    h.update(message);
+    let k = reduce(&h.finalize());
+
+    // [S]B
+    let mut lhs = scalar_mult(&[BX, BY, GF1, mul(&BX, &BY)], s_bytes);
+    // R + [k]A
+    let mut rhs = scalar_mult(&a, &k);
+    point_add(&mut rhs, &r);
+
+    for _ in 0..3 {
+        let p = lhs;
+        point_add(&mut lhs, &p);
+        let p = rhs;
+        point_add(&mut rhs, &p);
+    }
+    pack_point(&lhs) == pack_point(&rhs)
+}
+
+// ===== Field arithmetic mod 2^255 - 19 =====
+
+fn carry(o: &mut Gf) {
+    for i in 0..16 {
+        o[i] += 1 << 16;
+        let c = o[i] >> 16;
+        if i < 15 {
+            o[i + 1] += c - 1;
+        } else {
+            o[0] += 38 * (c - 1);
+        }
+        o[i] -= c << 16;
+    }
+}
+
+/// Swap `p` and `q` if `b` is 1.
+fn select(p: &mut Gf, q: &mut Gf, b: i64) {
+    let mask = !(b - 1);
+    for i in 0..16 {
+        let t = mask & (p[i] ^ q[i]);
+        p[i] ^= t;
+        q[i] ^= t;
+    }
+}
+
+/// Fully reduced little-endian encoding.
+fn pack(n: &Gf) -> [u8; 32] {
+    let mut t = *n;
+    carry(&mut t);
+    carry(&mut t);
+    carry(&mut t);
+    let mut m = GF0;
+    for _ in 0..2 {
+        m[0] = t[0] - 0xffed;
+        for i in 1..15 {
+            m[i] = t[i] - 0xffff - ((m[i - 1] >> 16) & 1);
+            m[i - 1] &= 0xffff;
+        }
+        m[15] = t[15] - 0x7fff - ((m[14] >> 16) & 1);
+        let b = (m[15] >> 16) & 1;
+        m[14] &= 0xffff;
+        select(&mut t, &mut m, 1 - b);
+    }
+    let mut o = [0u8; 32];
+    for i in 0..16 {
+        o[2 * i] = t[i] as u8;
+        o[2 * i + 1] = (t[i] >> 8) as u8;
+    }
+    o
+}
+
+fn unpack(n: &[u8; 32]) -> Gf {
+    let mut o = GF0;
+    for i in 0..16 {
+        o[i] = n[2 * i] as i64 + ((n[2 * i + 1] as i64) << 8);
+    }
+    o[15] &= 0x7fff;
+    o
+}
+
+/// Low bit of the reduced value (the "sign" of x in point encodings).
+fn parity(a: &Gf) -> u8 {
+    pack(a)[0] & 1
+}
+
+fn add(a: &Gf, b: &Gf) -> Gf {
+    core::array::from_fn(|i| a[i] + b[i])
+}
+
+fn sub(a: &Gf, b: &Gf) -> Gf {
+    core::array::from_fn(|i| a[i] - b[i])
+}
+
+fn mul(a: &Gf, b: &Gf) -> Gf {
+    let mut t = [0i64; 31];
+    for i in 0..16 {
+        for j in 0..16 {
+            t[i + j] += a[i] * b[j];
+        }
+    }
+    // 2^256 = 38 mod p
+    for i in 0..15 {
+        t[i] += 38 * t[i + 16];
+    }
+    let mut o: Gf = t[..16].try_into().unwrap();
+    carry(&mut o);
+    carry(&mut o);
+    o
+}
+
+fn square(a: &Gf) -> Gf {
+    mul(a, a)
+}
+
+/// a^(2^252 - 3), for the square root in point decoding.
+fn pow2523(a: &Gf) -> Gf {
+    let mut c = *a;
+    for i in (0..=250).rev() {
+        c = square(&c);
+        if i != 1 {
+            c = mul(&c, a);
+        }
+    }
+    c
+}
+
+fn inverse(a: &Gf) -> Gf {
+    let mut c = *a;
+    for i in (0..=253).rev() {
+        c = square(&c);
+        if i != 2 && i != 4 {
+            c = mul(&c, a);
+        }
+    }
+    c
+}
+
+// ===== Group operations =====
+
+fn point_add(p: &mut Point, q: &Point) {
+    let a = mul(&sub(&p[1], &p[0]), &sub(&q[1], &q[0]));
+    let b = mul(&add(&p[0], &p[1]), &add(&q[0], &q[1]));
+    let c = mul(&mul(&p[3], &q[3]), &D2);
+    let d = mul(&p[2], &q[2]);
+    let d = add(&d, &d);
+    let e = sub(&b, &a);
+    let f = sub(&d, &c);
+    let g = add(&d, &c);
+    let h = add(&b, &a);
+    *p = [mul(&e, &f), mul(&h, &g), mul(&g, &f), mul(&e, &h)];
+}
+
+fn point_swap(p: &mut Point, q: &mut Point, b: i64) {
+    for i in 0..4 {
+        select(&mut p[i], &mut q[i], b);
+    }
+}
+
+/// `[s]q` by double-and-add over all 256 bits of the little-endian scalar.
+fn scalar_mult(q: &Point, s: &[u8; 32]) -> Point {
+    let mut p: Point = [GF0, GF1, GF1, GF0];
+    let mut q = *q;
+    for i in (0..256).rev() {
+        let b = ((s[i / 8] >> (i & 7)) & 1) as i64;
+        point_swap(&mut p, &mut q, b);
+        point_add(&mut q, &p);
+        let t = p;
+        point_add(&mut p, &t);
+        point_swap(&mut p, &mut q, b);
+    }
+    p
+}
+
+fn pack_point(p: &Point) -> [u8; 32] {
+    let zi = inverse(&p[2]);
+    let x = mul(&p[0], &zi);
+    let y = mul(&p[1], &zi);
+    let mut r = pack(&y);
+    r[31] ^= parity(&x) << 7;
+    r
+}
+
+/// Decode a compressed point: y from the low 255 bits, x recovered as
+/// sqrt((y^2 - 1) / (d y^2 + 1)) with the sign from the top bit.
+fn unpack_point(bytes: &[u8; 32]) -> Option<Point> {
+    let y = unpack(bytes);
+    let y2 = square(&y);
+    let num = sub(&y2, &GF1);
+    let den = add(&GF1, &mul(&y2, &D));
+
+    // x = num * den^3 * (num * den^7)^((p-5)/8)
+    let den2 = square(&den);
+    let den4 = square(&den2);
+    let den6 = mul(&den4, &den2);
+    let t = mul(&mul(&den6, &num), &den);
+    let t = mul(&mul(&pow2523(&t), &num), &den);
+    let mut x = mul(&mul(&t, &den), &den);
+
+    if pack(&mul(&square(&x), &den)) != pack(&num) {
+        x = mul(&x, &SQRT_M1);
+    }
+    if pack(&mul(&square(&x), &den)) != pack(&num) {
+        return None;
+    }
+    if parity(&x) != bytes[31] >> 7 {
+        x = sub(&GF0, &x);
+    }
+    Some([x, y, GF1, mul(&x, &y)])
+}
+
+// ===== Scalars mod L =====
+
+/// `s < L`, as RFC 8032 requires of the signature's S half.
+fn scalar_is_canonical(s: &[u8; 32]) -> bool {
+    for i in (0..32).rev() {
+        if (s[i] as i64) != L[i] {
+            return (s[i] as i64) < L[i];
+        }
+    }
+    false
+}
+
+/// Reduce a 512-bit little-endian value mod L.
+fn reduce(h: &[u8; 64]) -> [u8; 32] {
+    let mut x: [i64; 64] = core::array::from_fn(|i| h[i] as i64);
+
+    for i in (32..64).rev() {
+        let mut c = 0i64;
+        for j in (i - 32)..(i - 12) {
+            x[j] += c - 16 * x[i] * L[j - (i - 32)];
+            c = (x[j] + 128) >> 8;
+            x[j] -= c << 8;
+        }
+        x[i - 12] += c;
+        x[i] = 0;
+    }
+    let mut c = 0i64;
+    for j in 0..32 {
+        x[j] += c - (x[31] >> 4) * L[j];
+        c = x[j] >> 8;
+        x[j] &= 255;
+    }
+    for j in 0..32 {
+        x[j] -= c * L[j];
+    }
+
+    let mut r = [0u8; 32];
+    for i in 0..32 {
+        x[i + 1] += x[i] >> 8;
+        r[i] = x[i] as u8;
+    }
+    r
+}
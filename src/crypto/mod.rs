@@ -0,0 +1,3 @@
+pub mod ed25519;
+pub mod sha256;
+pub mod sha512;
@@ -0,0 +1,277 @@
+//! AHCI SATA driver (polled, READ DMA EXT only).
+//!
+//! The HBA registers are reached through BAR5 (ABAR), which the BIOS path can
+//! use as is since it runs identity-mapped; firmware must have placed it
+//! below 4 GiB. Each port with a SATA disk attached gets one page holding its
+//! command list, received-FIS area and a single command table, so only
+//! command slot 0 is ever used.
+
+use crate::arch::mmio::Mmio;
+use crate::arch::time::busy_wait_us;
+use crate::drivers::pci::{self, PciDevice};
+
+pub const MAX_PORTS: usize = 32;
+pub const SECTOR_SIZE: usize = 512;
+const PROG_IF_AHCI: u8 = 0x01;
+const ABAR_INDEX: usize = 5;
+
+// ===== HBA (generic host control) registers =====
+const HBA_CAP: usize = 0x00;
+const HBA_GHC: usize = 0x04;
+const HBA_PI: usize = 0x0C;
+const HBA_VS: usize = 0x10;
+const GHC_AHCI_ENABLE: u32 = 1 << 31;
+
+// ===== Port registers, at 0x100 + port * 0x80 =====
+const PORT_BASE: usize = 0x100;
+const PORT_STRIDE: usize = 0x80;
+const PX_CLB: usize = 0x00;
+const PX_CLBU: usize = 0x04;
+const PX_FB: usize = 0x08;
+const PX_FBU: usize = 0x0C;
+const PX_IS: usize = 0x10;
+const PX_IE: usize = 0x14;
+const PX_CMD: usize = 0x18;
+const PX_TFD: usize = 0x20;
+const PX_SIG: usize = 0x24;
+const PX_SSTS: usize = 0x28;
+const PX_SERR: usize = 0x30;
+const PX_CI: usize = 0x38;
+
+const CMD_ST: u32 = 1 << 0;
+const CMD_FRE: u32 = 1 << 4;
+const CMD_FR: u32 = 1 << 14;
+const CMD_CR: u32 = 1 << 15;
+const IS_TFES: u32 = 1 << 30;
+const TFD_ERR: u32 = 1 << 0;
+const TFD_DRQ: u32 = 1 << 3;
+const TFD_BSY: u32 = 1 << 7;
+
+const SSTS_DET_PRESENT: u32 = 3;
+const SSTS_IPM_ACTIVE: u32 = 1;
+/// ATAPI devices report 0xEB140101
+const SIG_SATA_DISK: u32 = 0x0000_0101;
+
+// ===== Command structures =====
+const FIS_TYPE_REG_H2D: u8 = 0x27;
+const FIS_H2D_COMMAND: u8 = 0x80;
+const ATA_CMD_READ_DMA_EXT: u8 = 0x25;
+const ATA_DEVICE_LBA: u8 = 0x40;
+/// Register H2D FIS length in dwords
+const CFIS_DWORDS: u32 = 5;
+
+/// Port page layout: command list (1 KiB aligned), received FIS (256-byte
+/// aligned), then the command table (128-byte aligned)
+const PAGE_SIZE: usize = 4096;
+const CMD_LIST_OFFSET: usize = 0;
+const FIS_OFFSET: usize = 1024;
+const CMD_TABLE_OFFSET: usize = 1280;
+const CMD_TABLE_PRDT: usize = 0x80;
+const PRD_SIZE: usize = 16;
+const PRDT_ENTRIES: usize = (PAGE_SIZE - CMD_TABLE_OFFSET - CMD_TABLE_PRDT) / PRD_SIZE;
+/// One PRD covers at most 4 MiB
+const PRD_MAX_BYTES: usize = 4 * 1024 * 1024;
+
+/// Polls, `POLL_INTERVAL_US` apart, before a command or engine stop times out (~1 s)
+const POLL_LIMIT: u32 = 100_000;
+const POLL_INTERVAL_US: u64 = 10;
+
+#[derive(Copy, Clone)]
+struct AhciPort {
+    /// Page with this port's command list, FIS area and command table
+    mem: *mut u8,
+}
+
+#[derive(Copy, Clone)]
+pub struct AhciController {
+    abar: usize,
+    /// `HBA_PI` bitmap
+    pub ports_implemented: u32,
+    ports: [Option<AhciPort>; MAX_PORTS],
+}
+
+fn hba_reg(abar: usize, reg: usize) -> Mmio<u32> {
+    Mmio::new(abar + reg)
+}
+
+fn port_reg(abar: usize, port: usize, reg: usize) -> Mmio<u32> {
+    Mmio::new(abar + PORT_BASE + port * PORT_STRIDE + reg)
+}
+
+/// Poll until `reg & mask == 0`.
+fn wait_clear(reg: Mmio<u32>, mask: u32) -> Result<(), &'static str> {
+    for _ in 0..POLL_LIMIT {
+        if reg.read() & mask == 0 {
+            return Ok(());
+        }
+        busy_wait_us(POLL_INTERVAL_US);
+    }
+    Err("AHCI: timeout")
+}
+
+/// Bring up the HBA at `dev` and every port with a SATA disk behind it.
+pub fn init(dev: &PciDevice) -> Result<AhciController, &'static str> {
+    if dev.class != pci::CLASS_STORAGE || dev.subclass != pci::SUBCLASS_SATA || dev.prog_if != PROG_IF_AHCI {
+        return Err("AHCI: not an AHCI controller");
+    }
+    let abar = dev.mmio_bar(ABAR_INDEX).ok_or("AHCI: BAR5 is not a memory BAR")?;
+    if abar > u32::MAX as u64 {
+        return Err("AHCI: ABAR above 4 GiB");
+    }
+    let abar = abar as usize;
+    dev.enable_bus_master();
+
+    let ghc = hba_reg(abar, HBA_GHC);
+    let value = ghc.read();
+    if value & GHC_AHCI_ENABLE == 0 {
+        ghc.write(value | GHC_AHCI_ENABLE);
+        if ghc.read() & GHC_AHCI_ENABLE == 0 {
+            return Err("AHCI: cannot enable AHCI mode");
+        }
+    }
+
+    let pi = hba_reg(abar, HBA_PI).read();
+    let mut ctrl = AhciController { abar, ports_implemented: pi, ports: [None; MAX_PORTS] };
+    for port in (0..MAX_PORTS).filter(|p| pi & (1 << p) != 0) {
+        if !disk_present(abar, port) {
+            continue;
+        }
+        match start_port(abar, port) {
+            Ok(p) => {
+                debug_log!("ahci", "SATA disk on port {}", port);
+                ctrl.ports[port] = Some(p);
+            }
+            Err(e) => {
+                debug_log!("ahci", "port {}: {}", port, e);
+            }
+        }
+    }
+    Ok(ctrl)
+}
+
+/// Link up with an active device (DET=3, IPM=1) whose signature is a disk.
+fn disk_present(abar: usize, port: usize) -> bool {
+    let ssts = port_reg(abar, port, PX_SSTS).read();
+    let (det, ipm) = (ssts & 0xF, (ssts >> 8) & 0xF);
+    det == SSTS_DET_PRESENT && ipm == SSTS_IPM_ACTIVE && port_reg(abar, port, PX_SIG).read() == SIG_SATA_DISK
+}
+
+/// Stop the port's engines, point it at a fresh page and restart it.
+fn start_port(abar: usize, port: usize) -> Result<AhciPort, &'static str> {
+    let cmd = port_reg(abar, port, PX_CMD);
+    cmd.write(cmd.read() & !CMD_ST);
+    wait_clear(cmd, CMD_CR)?;
+    cmd.write(cmd.read() & !CMD_FRE);
+    wait_clear(cmd, CMD_FR)?;
+
+    let mem = crate::memory::allocate_pages(1)?;
+    unsafe { core::ptr::write_bytes(mem, 0, PAGE_SIZE) };
+    let base = mem as usize;
+    port_reg(abar, port, PX_CLB).write((base + CMD_LIST_OFFSET) as u32);
+    port_reg(abar, port, PX_CLBU).write(0);
+    port_reg(abar, port, PX_FB).write((base + FIS_OFFSET) as u32);
+    port_reg(abar, port, PX_FBU).write(0);
+
+    // Polled: no interrupts, and clear anything left over (write 1 to clear)
+    port_reg(abar, port, PX_IE).write(0);
+    port_reg(abar, port, PX_SERR).write(u32::MAX);
+    port_reg(abar, port, PX_IS).write(u32::MAX);
+
+    cmd.write(cmd.read() | CMD_FRE);
+    cmd.write(cmd.read() | CMD_ST);
+    Ok(AhciPort { mem })
+}
+
+impl AhciController {
+    /// Ports with a disk attached.
+    pub fn disks(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..MAX_PORTS).filter(|&p| self.ports[p].is_some())
+    }
+
+    /// Read `count` sectors from `lba` on `port` with READ DMA EXT.
+    pub fn read_sectors(&mut self, port: usize, lba: u64, count: u16, buf: &mut [u8]) -> Result<(), &'static str> {
+        if count == 0 {
+            return Ok(());
+        }
+        let p = self.ports.get(port).copied().flatten().ok_or("AHCI: no disk on port")?;
+        let len = count as usize * SECTOR_SIZE;
+        if buf.len() < len {
+            return Err("buffer too small for read_sectors");
+        }
+        if lba + count as u64 > (1u64 << 48) {
+            return Err("LBA beyond 48-bit range");
+        }
+        let prds = len.div_ceil(PRD_MAX_BYTES);
+        if prds > PRDT_ENTRIES {
+            return Err("AHCI: transfer too large");
+        }
+
+        let abar = self.abar;
+        unsafe {
+            wait_clear(port_reg(abar, port, PX_TFD), TFD_BSY | TFD_DRQ)?;
+
+            let table = p.mem.add(CMD_TABLE_OFFSET);
+            core::ptr::write_bytes(table, 0, PAGE_SIZE - CMD_TABLE_OFFSET);
+
+            // Register H2D FIS
+            let fis = table;
+            let fis_bytes = [
+                FIS_TYPE_REG_H2D,
+                FIS_H2D_COMMAND,
+                ATA_CMD_READ_DMA_EXT,
+                0,
+                lba as u8,
+                (lba >> 8) as u8,
+                (lba >> 16) as u8,
+                ATA_DEVICE_LBA,
+                (lba >> 24) as u8,
+                (lba >> 32) as u8,
+                (lba >> 40) as u8,
+                0,
+                count as u8,
+                (count >> 8) as u8,
+            ];
+            core::ptr::copy_nonoverlapping(fis_bytes.as_ptr(), fis, fis_bytes.len());
+
+            // PRDT: DBA, DBAU, reserved, DBC (byte count - 1)
+            let prdt = table.add(CMD_TABLE_PRDT) as *mut u32;
+            let data = buf.as_mut_ptr() as usize as u64;
+            for i in 0..prds {
+                let off = i * PRD_MAX_BYTES;
+                let bytes = (len - off).min(PRD_MAX_BYTES);
+                let entry = prdt.add(i * 4);
+                entry.write_volatile((data + off as u64) as u32);
+                entry.add(1).write_volatile(0);
+                entry.add(2).write_volatile(0);
+                entry.add(3).write_volatile(bytes as u32 - 1);
+            }
+
+            // Command header 0: CFL, PRDTL, PRDBC, CTBA, CTBAU
+            let header = p.mem.add(CMD_LIST_OFFSET) as *mut u32;
+            header.write_volatile(CFIS_DWORDS | ((prds as u32) << 16));
+            header.add(1).write_volatile(0);
+            header.add(2).write_volatile(table as usize as u32);
+            header.add(3).write_volatile(0);
+
+            let (is, ci) = (port_reg(abar, port, PX_IS), port_reg(abar, port, PX_CI));
+            is.write(u32::MAX);
+            ci.write(1);
+
+            let mut polls = 0;
+            while ci.read() & 1 != 0 {
+                if is.read() & IS_TFES != 0 {
+                    return Err("AHCI: task file error");
+                }
+                polls += 1;
+                if polls == POLL_LIMIT {
+                    return Err("AHCI: command timeout");
+                }
+                busy_wait_us(POLL_INTERVAL_US);
+            }
+            if port_reg(abar, port, PX_TFD).read() & TFD_ERR != 0 {
+                return Err("AHCI: device reported an error");
+            }
+        }
+        Ok(())
+    }
+}
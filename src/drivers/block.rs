@@ -0,0 +1,213 @@
+//! Boot disk the partition and filesystem code reads from (BIOS path).
+//!
+//! stage2 registers every disk it finds: legacy ATA drives first, then the
+//! disks behind PCI controllers (`probe_pci`) for machines without legacy
+//! IDE. The one holding the filesystem it mounts is selected, and all sector
+//! I/O in `boot::{gpt, mbr}` and `fs` goes through `read_sectors` and
+//! `write_sectors` here. Sectors are always 512 bytes.
+
+use crate::drivers::ahci::{self, AhciController};
+use crate::drivers::disk::{self, DiskTarget};
+use crate::drivers::nvme::{self, NvmeController};
+use crate::drivers::pci::{self, PciBus};
+use crate::drivers::virtio_blk::{self, VirtioBlk};
+
+pub const SECTOR_SIZE: usize = 512;
+pub const MAX_DEVICES: usize = 8;
+/// NVMe namespace booted from; IDs start at 1
+const NVME_NAMESPACE: u32 = 1;
+
+pub enum BlockDevice {
+    Ata(DiskTarget),
+    VirtioBlk(VirtioBlk),
+    /// SATA disk on `port` of an HBA. Each disk holds a copy of the
+    /// controller handle; ports have their own registers and memory.
+    Ahci { ctrl: AhciController, port: usize },
+    /// Namespace `ns` of an NVMe controller, formatted with 512-byte LBAs
+    Nvme { ctrl: NvmeController, ns: u32 },
+}
+
+/// Bounce buffer for controllers that want dword-aligned data
+#[repr(align(4))]
+struct AlignedSector([u8; SECTOR_SIZE]);
+
+impl BlockDevice {
+    pub fn name(&self) -> &'static str {
+        match self {
+            BlockDevice::Ata(target) => target.name(),
+            BlockDevice::VirtioBlk(_) => "virtio-blk",
+            BlockDevice::Ahci { .. } => "AHCI SATA disk",
+            BlockDevice::Nvme { .. } => "NVMe namespace 1",
+        }
+    }
+}
+
+// Registered disks and the index of the selected one. Only touched through
+// the functions below, none of which hands out a reference.
+static mut DEVICES: [Option<BlockDevice>; MAX_DEVICES] = [const { None }; MAX_DEVICES];
+static mut DEVICE_COUNT: usize = 0;
+static mut SELECTED: usize = 0;
+
+/// Add a disk; `false` when the table is full.
+pub fn register(dev: BlockDevice) -> bool {
+    unsafe {
+        if DEVICE_COUNT == MAX_DEVICES {
+            return false;
+        }
+        DEVICES[DEVICE_COUNT] = Some(dev);
+        DEVICE_COUNT += 1;
+    }
+    true
+}
+
+pub fn count() -> usize {
+    unsafe { DEVICE_COUNT }
+}
+
+/// Name of disk `index`, `""` if there is none.
+pub fn name(index: usize) -> &'static str {
+    unsafe {
+        if index >= DEVICE_COUNT {
+            return "";
+        }
+        match &*core::ptr::addr_of!(DEVICES[index]) {
+            Some(dev) => dev.name(),
+            None => "",
+        }
+    }
+}
+
+/// Send all further I/O to disk `index`.
+pub fn select(index: usize) {
+    unsafe {
+        if index >= DEVICE_COUNT {
+            return;
+        }
+        SELECTED = index;
+        if let Some(BlockDevice::Ata(target)) = &*core::ptr::addr_of!(DEVICES[index]) {
+            disk::set_boot_target(*target);
+        }
+    }
+}
+
+/// Bring up the disk controllers on `bus` and register their disks.
+/// Controllers that fail to initialise are logged and skipped.
+pub fn probe_pci(bus: &PciBus) {
+    for dev in bus.iter() {
+        if dev.vendor_id == virtio_blk::VIRTIO_VENDOR_ID && dev.device_id == virtio_blk::VIRTIO_BLK_DEVICE_ID {
+            match virtio_blk::init(dev) {
+                Ok(blk) => {
+                    log_info!("block", "virtio-blk disk ({} sectors)", blk.capacity);
+                    register(BlockDevice::VirtioBlk(blk));
+                }
+                Err(e) => log_info!("block", "{}", e),
+            }
+        } else if dev.class == pci::CLASS_STORAGE && dev.subclass == pci::SUBCLASS_SATA {
+            probe_ahci(dev);
+        } else if dev.class == pci::CLASS_STORAGE && dev.subclass == pci::SUBCLASS_NVM {
+            probe_nvme(dev);
+        }
+    }
+}
+
+/// Register one device per SATA disk behind the HBA at `dev`.
+fn probe_ahci(dev: &pci::PciDevice) {
+    let ctrl = match ahci::init(dev) {
+        Ok(c) => c,
+        Err(e) => {
+            log_info!("block", "{}", e);
+            return;
+        }
+    };
+    for port in ctrl.disks() {
+        log_info!("block", "AHCI SATA disk on port {}", port);
+        if !register(BlockDevice::Ahci { ctrl, port }) {
+            break;
+        }
+    }
+}
+
+/// Register namespace 1 of the NVMe controller at `dev`.
+fn probe_nvme(dev: &pci::PciDevice) {
+    let mut ctrl = match nvme::init(dev) {
+        Ok(c) => c,
+        Err(e) => {
+            log_info!("block", "{}", e);
+            return;
+        }
+    };
+    match ctrl.namespace_lba_size(NVME_NAMESPACE) {
+        Ok(size) if size as usize == SECTOR_SIZE => {
+            log_info!("block", "NVMe namespace {}", NVME_NAMESPACE);
+            register(BlockDevice::Nvme { ctrl, ns: NVME_NAMESPACE });
+        }
+        Ok(size) => log_info!("block", "NVMe: {}-byte LBAs not supported", size),
+        Err(e) => log_info!("block", "{}", e),
+    }
+}
+
+/// Read through `read` directly when `buf` is dword aligned, as NVMe and
+/// AHCI DMA want, or one sector at a time through an aligned buffer.
+fn read_aligned(
+    lba: u64,
+    count: u32,
+    buf: &mut [u8],
+    mut read: impl FnMut(u64, u32, &mut [u8]) -> Result<(), &'static str>,
+) -> Result<(), &'static str> {
+    if buf.as_ptr() as usize % 4 == 0 {
+        return read(lba, count, buf);
+    }
+    if buf.len() < count as usize * SECTOR_SIZE {
+        return Err("buffer too small for read_sectors");
+    }
+    let mut bounce = AlignedSector([0; SECTOR_SIZE]);
+    for (i, chunk) in buf.chunks_exact_mut(SECTOR_SIZE).take(count as usize).enumerate() {
+        read(lba + i as u64, 1, &mut bounce.0)?;
+        chunk.copy_from_slice(&bounce.0);
+    }
+    Ok(())
+}
+
+/// Read `count` sectors from `lba` of the selected disk into `buf`.
+pub fn read_sectors(lba: u64, count: u32, buf: &mut [u8]) -> Result<(), &'static str> {
+    if unsafe { DEVICE_COUNT } == 0 {
+        return Err("no boot disk");
+    }
+    match unsafe { &mut *core::ptr::addr_of_mut!(DEVICES[SELECTED]) } {
+        Some(BlockDevice::Ata(target)) => {
+            let count = u16::try_from(count).map_err(|_| "ATA: too many sectors in one read")?;
+            match u32::try_from(lba) {
+                Ok(lba) => disk::read_sectors(*target, lba, count, buf),
+                Err(_) => disk::read_sectors_lba48(*target, lba, count as u32, buf),
+            }
+        }
+        Some(BlockDevice::VirtioBlk(blk)) => virtio_blk::read_sectors(blk, lba, count, buf),
+        Some(BlockDevice::Ahci { ctrl, port }) => {
+            if count > u16::MAX as u32 {
+                return Err("AHCI: too many sectors in one read");
+            }
+            read_aligned(lba, count, buf, |lba, n, b| ctrl.read_sectors(*port, lba, n as u16, b))
+        }
+        Some(BlockDevice::Nvme { ctrl, ns }) => read_aligned(lba, count, buf, |lba, n, b| nvme::read_lba(ctrl, *ns, lba, n, b)),
+        None => Err("no boot disk"),
+    }
+}
+
+/// Write `count` sectors from `buf` to `lba` of the selected disk. Only ATA
+/// drives are writable.
+pub fn write_sectors(lba: u64, count: u32, buf: &[u8]) -> Result<(), &'static str> {
+    if unsafe { DEVICE_COUNT } == 0 {
+        return Err("no boot disk");
+    }
+    match unsafe { &*core::ptr::addr_of!(DEVICES[SELECTED]) } {
+        Some(BlockDevice::Ata(target)) => {
+            let count = u16::try_from(count).map_err(|_| "ATA: too many sectors in one write")?;
+            match u32::try_from(lba) {
+                Ok(lba) => disk::write_sectors(*target, lba, count, buf),
+                Err(_) => disk::write_sectors_lba48(*target, lba, count as u32, buf),
+            }
+        }
+        Some(_) => Err("boot disk is read-only"),
+        None => Err("no boot disk"),
+    }
+}
@@ -1,7 +1,8 @@
 //! ATA PIO disk driver (minimal) for RustyBoot
 //!
-//! Implements `init()` and `read_sectors()` using 28‑bit LBA on the
-//! primary channel, master drive. Sufficient for QEMU/Bochs and many
+//! Implements `init()`, `read_sectors()` and `write_sectors()` using 28‑bit
+//! LBA, or 48‑bit
+//! LBA on drives that support it, on either channel, master or slave drive. Sufficient for QEMU/Bochs and many
 //! bare‑metal tests.
 //!
 //! Safety: uses raw port I/O and inline asm; x86 only.
@@ -10,26 +11,149 @@
 
 use core::cmp::min;
 
-use crate::drivers::vga;
+use crate::arch::io::IoPort;
+use crate::arch::time::busy_wait_us;
+use crate::drivers::{pci, vga};
 
-// ===== ATA I/O port layout (Primary channel) =====
-const ATA_PRIMARY_IO: u16 = 0xF10;
+// ===== ATA I/O port layout (legacy compatibility mode) =====
+const ATA_PRIMARY_IO: u16 = 0x1F0; // command block
 const ATA_PRIMARY_CTRL: u16 = 0x3F6; // Device control / alt status
+const ATA_SECONDARY_IO: u16 = 0x170;
+const ATA_SECONDARY_CTRL: u16 = 0x376;
+const _: () = assert!(ATA_PRIMARY_IO == 0x1F0, "ATA primary command block must be 0x1F0");
+
+// Register offsets from the command block base
+const ATA_REG_DATA: u16 = 0; // R/W: data (16‑bit)
+const ATA_REG_ERROR: u16 = 1; // R: error
+const ATA_REG_FEATURES: u16 = 1; // W: features
+const ATA_REG_SECCOUNT0: u16 = 2; // sector count (low)
+const ATA_REG_LBA0: u16 = 3; // LBA[7:0]
+const ATA_REG_LBA1: u16 = 4; // LBA[15:8]
+const ATA_REG_LBA2: u16 = 5; // LBA[23:16]
+const ATA_REG_HDDEVSEL: u16 = 6; // drive/head + LBA bits
+const ATA_REG_COMMAND: u16 = 7; // write: command
+const ATA_REG_STATUS: u16 = 7; // read: status
+
+//control side, offsets from the control block base
+const ATA_REG_DEVCTRL: u16 = 0; // write: nIEN, SRST
+const ATA_REG_ALTSTATUS: u16 = 0; // read: alt status
+
+// ===== Drive selection =====
+
+/// One of the four legacy ATA drive positions.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DiskTarget {
+    PrimaryMaster,
+    PrimarySlave,
+    SecondaryMaster,
+    SecondarySlave,
+}
+
+impl DiskTarget {
+    pub const ALL: [DiskTarget; 4] = [
+        DiskTarget::PrimaryMaster,
+        DiskTarget::PrimarySlave,
+        DiskTarget::SecondaryMaster,
+        DiskTarget::SecondarySlave,
+    ];
+
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            DiskTarget::PrimaryMaster => "primary master",
+            DiskTarget::PrimarySlave => "primary slave",
+            DiskTarget::SecondaryMaster => "secondary master",
+            DiskTarget::SecondarySlave => "secondary slave",
+        }
+    }
+
+    fn channel(self) -> usize {
+        self.index() / 2
+    }
+
+    /// HDDEVSEL value for LBA mode: 0xE0 master, 0xF0 slave.
+    fn drive_select(self) -> u8 {
+        if self.index() % 2 == 0 { 0xE0 } else { 0xF0 }
+    }
+}
+
+/// What IDENTIFY reported about a drive.
+#[derive(Copy, Clone, Debug)]
+pub struct DiskInfo {
+    pub target: DiskTarget,
+    /// Addressable sectors (LBA48 count when supported)
+    pub sectors: u64,
+    pub lba48: bool,
+    /// Model string, space padded
+    pub model: [u8; 40],
+}
+
+impl DiskInfo {
+    pub fn model_str(&self) -> &str {
+        core::str::from_utf8(&self.model).unwrap_or("").trim_end()
+    }
+}
+
+// (command block, control block) per channel; replaced when the controller
+// runs a channel in native PCI mode
+static mut CHANNEL_BASES: [(u16, u16); 2] = [(ATA_PRIMARY_IO, ATA_PRIMARY_CTRL), (ATA_SECONDARY_IO, ATA_SECONDARY_CTRL)];
+static mut CHANNELS_DETECTED: bool = false;
+
+// Bases and drive of the target the next command goes to
+static mut ATA_IO_BASE: u16 = ATA_PRIMARY_IO;
+static mut ATA_CTRL_BASE: u16 = ATA_PRIMARY_CTRL;
+static mut ATA_DRIVE: DiskTarget = DiskTarget::PrimaryMaster;
+
+// LBA48 support per target, filled in by `init`
+static mut LBA48_SUPPORTED: [bool; 4] = [false; 4];
+
+// ATA drive holding the boot volume
+static mut BOOT_TARGET: DiskTarget = DiskTarget::PrimaryMaster;
+
+/// Point the port helpers at `target`'s channel.
+unsafe fn select_target(target: DiskTarget) {
+    unsafe {
+        if !CHANNELS_DETECTED {
+            CHANNEL_BASES = detect_channel_bases();
+            CHANNELS_DETECTED = true;
+        }
+        let (io, ctrl) = CHANNEL_BASES[target.channel()];
+        ATA_IO_BASE = io;
+        ATA_CTRL_BASE = ctrl;
+        ATA_DRIVE = target;
+    }
+}
+
+/// ATA drive holding the boot volume, when that is on ATA (set by
+/// `block::select`).
+pub fn boot_target() -> DiskTarget {
+    unsafe { BOOT_TARGET }
+}
+
+pub fn set_boot_target(target: DiskTarget) {
+    unsafe {
+        BOOT_TARGET = target;
+    }
+}
+
+#[inline(always)]
+fn cmd_port(reg: u16) -> IoPort<u8> {
+    IoPort::new(unsafe { ATA_IO_BASE } + reg)
+}
 
-const ATA_REG_DATA: u16 = ATA_PRIMARY_IO + 0; // R/W: data (16‑bit)
-const ATA_REG_ERROR: u16 = ATA_PRIMARY_IO + 1; // R: error
-const ATA_REG_FEATURES: u16 = ATA_PRIMARY_IO + 1; // W: features
-const ATA_REG_SECCOUNT0: u16 = ATA_PRIMARY_IO + 2; // sector count (low)
-const ATA_REG_LBA0: u16 = ATA_PRIMARY_IO + 3; // LBA[7:0]
-const ATA_REG_LBA1: u16 = ATA_PRIMARY_IO + 4; // LBA[15:8]
-const ATA_REG_LBA2: u16 = ATA_PRIMARY_IO + 5; // LBA[23:16]
-const ATA_REG_HDDEVSEL: u16 = ATA_PRIMARY_IO + 6; // drive/head + LBA bits
-const ATA_REG_COMMAND: u16 = ATA_PRIMARY_IO + 7; // write: command
-const ATA_REG_STATUS: u16 = ATA_PRIMARY_IO + 7; // read: status
-
-//control side
-const ATA_REG_DEVCTRL: u16 = ATA_PRIMARY_CTRL; // write: nIEN, SRST
-const ATA_REG_ALTSTATUS: u16 = ATA_PRIMARY_CTRL; // read: alt status
+#[inline(always)]
+fn ctrl_port(reg: u16) -> IoPort<u8> {
+    IoPort::new(unsafe { ATA_CTRL_BASE } + reg)
+}
+
+/// The data register is the one 16-bit wide port of the command block.
+#[inline(always)]
+fn data_port() -> IoPort<u16> {
+    IoPort::new(unsafe { ATA_IO_BASE } + ATA_REG_DATA)
+}
 
 // ===== Status bits =====
 const ATA_SR_ERR: u8 = 0x01; // Error
@@ -41,128 +165,311 @@ const ATA_SR_BSY: u8 = 0x80; // Busy
 // ===== Commands =====
 const ATA_CMD_IDENTIFY: u8 = 0xEC;
 const ATA_CMD_READ_SECTORS: u8 = 0x20; //  LBA28 PIO
+const ATA_CMD_READ_SECTORS_EXT: u8 = 0x24; // LBA48 PIO
+const ATA_CMD_WRITE_SECTORS: u8 = 0x30; // LBA28 PIO
+const ATA_CMD_WRITE_SECTORS_EXT: u8 = 0x34; // LBA48 PIO
+const ATA_CMD_CACHE_FLUSH: u8 = 0xE7;
+const ATA_CMD_CACHE_FLUSH_EXT: u8 = 0xEA;
 
-// ===== Low‑level port I/O (x86 only) =====
-#[inline(always)]
-unsafe fn outb(port: u16, val: u8) {
-    core::arch::asm!("out dx, al", in("dx") port, in("al") val, options(nomem, nostack, preserves_flags));
-}
+// ===== Device control bits =====
+const ATA_DEVCTRL_NIEN: u8 = 0x02; // mask INTRQ
+const ATA_DEVCTRL_HOB: u8 = 0x80; // high order byte of the LBA48 register pairs
 
-#[inline(always)]
-unsafe fn inb(port: u16) -> u8 {
-    let val: u8;
-    core::arch::asm!("in al, dx", in("dx") port, out("al") val, options(nomem, nostack, preserves_flags));
-    val
+// ===== Poll helpers =====
+const ATA_BSY_RETRIES: u32 = 500_000; // status reads before a poll is considered hung
+const ATA_SOFT_RESETS: u32 = 3;
+const ATA_SPINUP_DELAY_MS: u64 = 5000;
+
+/// Read status until the required bits are set and others cleared.
+/// `Ok(None)` means the drive did not get there within `ATA_BSY_RETRIES` reads.
+unsafe fn poll_status_bounded(mask_set: u8, mask_clear: u8) -> Result<Option<u8>, &'static str> {
+    for _ in 0..ATA_BSY_RETRIES {
+        let s = cmd_port(ATA_REG_STATUS).read();
+        if (s & ATA_SR_BSY) == 0 {
+            if (s & ATA_SR_ERR) != 0 {
+                return Err("ATA: status Err");
+            }
+            if (s & ATA_SR_DF) != 0 {
+                return Err("ATA: device fault");
+            }
+        }
+        if (s & mask_set) == mask_set && (s & mask_clear) == 0 {
+            return Ok(Some(s));
+        }
+    }
+    Ok(None)
 }
 
-#[inline(always)]
-unsafe fn inw(port: u16) -> u16 {
-    let val: u16;
-    core::arch::asm!("in ax, dx", in("dx") port, out("ax") val, options(nomem, nostack, preserves_flags));
-    val
-}
+/// What `poll_status` returns once it had to reset the channel; the command
+/// is gone and has to be issued again (see `reissue`).
+const ATA_ERR_RESET: &str = "ATA: command aborted by soft reset";
 
-#[inline(always)]
-unsafe fn io_wait() {
-    outb(0x80, 0);
+/// Poll with recovery: a drive that does not get there in time gets its
+/// channel soft-reset, and `ATA_SPINUP_DELAY_MS` to spin up if it is still
+/// busy afterwards. The reset aborts the command in flight, so this then fails
+/// with `ATA_ERR_RESET` rather than waiting on a command the drive has dropped.
+unsafe fn poll_status(mask_set: u8, mask_clear: u8) -> Result<u8, &'static str> {
+    if let Some(s) = unsafe { poll_status_bounded(mask_set, mask_clear)? } {
+        return Ok(s);
+    }
+    vga::print_string("[disk] drive not responding, soft reset\n");
+    unsafe { ata_soft_reset() };
+
+    if unsafe { poll_status_bounded(0, ATA_SR_BSY)? }.is_none() {
+        vga::print_string("[disk] still busy, waiting for spin-up\n");
+        crate::arch::timer::sleep_ms(ATA_SPINUP_DELAY_MS);
+        if unsafe { poll_status_bounded(0, ATA_SR_BSY)? }.is_none() {
+            return Err("ATA: drive timeout after reset");
+        }
+    }
+    Err(ATA_ERR_RESET)
 }
 
-// ===== Poll helpers =====
-unsafe fn poll_status(mask_set: u8, mask_clear: u8) -> Result<u8, &'static str> {
-    // Read status until the required bits are set and others cleared, or error.
+/// Run `issue` (program the taskfile, send the command, transfer the data)
+/// again each time a soft reset aborted it, up to `ATA_SOFT_RESETS` times.
+unsafe fn reissue(mut issue: impl FnMut() -> Result<(), &'static str>) -> Result<(), &'static str> {
+    let mut resets = 0;
     loop {
-        let s = inb(ATA_REG_STATUS);
-        if (s & ATA_SR_ERR) != 0 {
-            return Err("ATA: status Err");
-        }
-        if (s & ATA_SR_DF) != 0 {
-            return Err("ATA: device fault");
-        }
-        if (s & mask_set) == mask_set && (s & mask_clear) == 0 {
-            return Ok(s);
+        match issue() {
+            Err(e) if e == ATA_ERR_RESET && resets < ATA_SOFT_RESETS => resets += 1,
+            result => return result,
         }
     }
 }
 
+/// Pulse SRST on the control register. Aborts any command in flight and
+/// resets both drives of the channel, so the current drive is re-selected.
+unsafe fn ata_soft_reset() {
+    ctrl_port(ATA_REG_DEVCTRL).write(0x06); // SRST | nIEN
+    busy_wait_us(5); // SRST must be held for at least 5 us
+    ctrl_port(ATA_REG_DEVCTRL).write(0x02);
+    for _ in 0..4 {
+        let _ = ctrl_port(ATA_REG_ALTSTATUS).read();
+        busy_wait_us(1);
+    }
+    cmd_port(ATA_REG_HDDEVSEL).write(unsafe { ATA_DRIVE.drive_select() });
+    busy_wait_us(1);
+}
+
 unsafe fn wait_bsy_clear() -> Result<(), &'static str> {
     // First a few dummy reads per ATA spec
     for _ in 0..4 {
-        let _ = inb(ATA_REG_ALTSTATUS);
-        io_wait();
+        let _ = ctrl_port(ATA_REG_ALTSTATUS).read();
+        busy_wait_us(1);
     }
 
-    loop {
-        let s = inb(ATA_REG_STATUS);
-        if (s & ATA_SR_BSY) == 0 {
-            return Ok(());
-        }
-        if (s & ATA_SR_ERR) != 0 {
-            return Err("ATA: wait BSY ERR");
-        }
-        if (s & ATA_SR_DF) != 0 {
-            return Err("ATA: wait BSY DF");
-        }
-    }
+    unsafe { poll_status(0, ATA_SR_BSY) }.map(|_| ())
 }
 
 unsafe fn wait_drq_set() -> Result<(), &'static str> {
-    poll_status(ATA_SR_DRQ, ATA_SR_BSY).map(|_| ())
+    unsafe { poll_status(ATA_SR_DRQ, ATA_SR_BSY) }.map(|_| ())
+}
+
+/// Program sector count and LBA for a 48-bit command. Each taskfile register
+/// is a two-deep FIFO: the HOB=1 pass loads count[15:8] and LBA[47:24], the
+/// HOB=0 pass count[7:0] and LBA[23:0]. A count of 0 means 65536 sectors.
+unsafe fn write_lba48_regs(lba: u64, count: u16) {
+    // LBA mode; bit 4 picks the slave
+    cmd_port(ATA_REG_HDDEVSEL).write(0x40 | (unsafe { ATA_DRIVE.drive_select() } & 0x10));
+    busy_wait_us(1);
+
+    ctrl_port(ATA_REG_DEVCTRL).write(ATA_DEVCTRL_HOB | ATA_DEVCTRL_NIEN);
+    cmd_port(ATA_REG_SECCOUNT0).write((count >> 8) as u8);
+    cmd_port(ATA_REG_LBA0).write((lba >> 24) as u8);
+    cmd_port(ATA_REG_LBA1).write((lba >> 32) as u8);
+    cmd_port(ATA_REG_LBA2).write((lba >> 40) as u8);
+
+    ctrl_port(ATA_REG_DEVCTRL).write(ATA_DEVCTRL_NIEN);
+    cmd_port(ATA_REG_SECCOUNT0).write(count as u8);
+    cmd_port(ATA_REG_LBA0).write(lba as u8);
+    cmd_port(ATA_REG_LBA1).write((lba >> 8) as u8);
+    cmd_port(ATA_REG_LBA2).write((lba >> 16) as u8);
+}
+
+// ===== PCI IDE detection =====
+const IDE_PROGIF_PRIMARY_NATIVE: u8 = 0x01;
+const IDE_PROGIF_SECONDARY_NATIVE: u8 = 0x04;
+
+/// Command block base of the primary channel: BAR0 when the IDE controller
+/// runs the primary channel in native PCI mode (prog_if bit 0), else `0x1F0`.
+pub fn detect_ata_primary_base() -> u16 {
+    detect_channel_bases()[0].0
+}
+
+/// Base ports of both channels. A channel in native PCI mode (prog_if bit 0
+/// primary, bit 2 secondary) uses BAR0/BAR1 or BAR2/BAR3, the control block
+/// being the BAR + 2; otherwise the legacy ports.
+fn detect_channel_bases() -> [(u16, u16); 2] {
+    let mut bases = [(ATA_PRIMARY_IO, ATA_PRIMARY_CTRL), (ATA_SECONDARY_IO, ATA_SECONDARY_CTRL)];
+    let bus = pci::scan();
+    if let Some(ide) = bus.find_class(pci::CLASS_STORAGE, pci::SUBCLASS_IDE) {
+        let native = [IDE_PROGIF_PRIMARY_NATIVE, IDE_PROGIF_SECONDARY_NATIVE];
+        for (ch, base) in bases.iter_mut().enumerate() {
+            if (ide.prog_if & native[ch]) == 0 {
+                continue;
+            }
+            if let (Some(io), Some(ctrl)) = (ide.io_bar(ch * 2), ide.io_bar(ch * 2 + 1)) {
+                *base = (io, ctrl + 2);
+                debug_log!("disk", "IDE channel {} in native PCI mode", ch);
+            }
+        }
+    }
+    bases
 }
 
 // ===== Public API =====
 
-/// Probe primary master with IDENTIFY. Not strictly required for PIO reads,
-/// but useful to confirm presence and wake the device up.
-pub fn init() -> Result<(), &'static str> {
+/// Probe `target` with IDENTIFY to confirm presence, wake the device up and
+/// learn its size.
+pub fn init(target: DiskTarget) -> Result<DiskInfo, &'static str> {
     unsafe {
+        select_target(target);
+
         // Disable IRQs from controller (nIEN=1), clear SRST
-        outb(ATA_REG_DEVCTRL, 0x02);
-        io_wait();
+        ctrl_port(ATA_REG_DEVCTRL).write(0x02);
+        busy_wait_us(1);
 
-        // Select master, LBA mode upper nibble zero
-        outb(ATA_REG_HDDEVSEL, 0xE0);
-        io_wait();
+        // Select the drive, LBA mode upper nibble zero
+        cmd_port(ATA_REG_HDDEVSEL).write(target.drive_select());
+        busy_wait_us(1);
+
+        // A floating bus reads 0xFF: no channel at all
+        if cmd_port(ATA_REG_STATUS).read() == 0xFF {
+            return Err("ATA: no device");
+        }
 
         // Zero sector count and LBA regs per IDENTIFY requirements
-        outb(ATA_REG_SECCOUNT0, 0);
-        outb(ATA_REG_LBA0, 0);
-        outb(ATA_REG_LBA1, 0);
-        outb(ATA_REG_LBA2, 0);
+        cmd_port(ATA_REG_SECCOUNT0).write(0);
+        cmd_port(ATA_REG_LBA0).write(0);
+        cmd_port(ATA_REG_LBA1).write(0);
+        cmd_port(ATA_REG_LBA2).write(0);
 
         // Send IDENTIFY
-        outb(ATA_REG_COMMAND, ATA_CMD_IDENTIFY);
-        io_wait();
+        cmd_port(ATA_REG_COMMAND).write(ATA_CMD_IDENTIFY);
+        busy_wait_us(1);
 
         // If status is 0, no device
-        let mut status = inb(ATA_REG_STATUS);
+        let status = cmd_port(ATA_REG_STATUS).read();
         if status == 0 {
-            return Err("ATA: no device on primary master");
+            return Err("ATA: no device");
         }
 
         // Busy wait
         wait_bsy_clear()?;
 
         // Some ATAPI devices set LBA1/LBA2 nonzero; treat as not ATA
-        let lba1 = inb(ATA_REG_LBA1);
-        let lba2 = inb(ATA_REG_LBA2);
+        let lba1 = cmd_port(ATA_REG_LBA1).read();
+        let lba2 = cmd_port(ATA_REG_LBA2).read();
         if lba1 != 0 || lba2 != 0 {
             return Err("ATA: not an ATA disk (ATAPI?)");
         }
 
-        // Wait for DRQ then read 256 words of IDENTIFY data and drop them
+        // Wait for DRQ then read the 256 words of IDENTIFY data
         wait_drq_set()?;
+        let mut id = [0u16; 256];
+        for w in id.iter_mut() {
+            *w = data_port().read();
+        }
+
+        // Word 83 bit 10: LBA48 feature set; sizes in words 60-61 and 100-103
+        let lba48 = (id[83] & (1 << 10)) != 0;
+        let sectors = if lba48 {
+            (id[100] as u64) | ((id[101] as u64) << 16) | ((id[102] as u64) << 32) | ((id[103] as u64) << 48)
+        } else {
+            (id[60] as u64) | ((id[61] as u64) << 16)
+        };
+        // Words 27-46 hold the model, two characters per word, high byte first
+        let mut model = [b' '; 40];
+        for (i, w) in id[27..47].iter().enumerate() {
+            model[i * 2] = (w >> 8) as u8;
+            model[i * 2 + 1] = *w as u8;
+        }
+
+        LBA48_SUPPORTED[target.index()] = lba48;
+
+        debug_log!("disk", "ATA {} identified", target.name());
+        Ok(DiskInfo { target, sectors, lba48, model })
+    }
+}
+
+/// IDENTIFY every drive position; absent drives are `None`.
+pub fn probe_all() -> [Option<DiskInfo>; 4] {
+    let mut out = [None; 4];
+    for target in DiskTarget::ALL {
+        out[target.index()] = init(target).ok();
+    }
+    out
+}
+
+/// Highest sector a 28-bit command can address.
+const LBA28_MAX: u64 = 0x0FFF_FFFF;
+/// Sectors per READ SECTORS EXT command (a count of 0 encodes 65536).
+const LBA48_MAX_CHUNK: u32 = 65536;
+
+/// PIO-read `sectors` sectors of an issued read command into `buffer` at `off`.
+unsafe fn pio_read_sectors(sectors: usize, buffer: &mut [u8], off: &mut usize) -> Result<(), &'static str> {
+    for _ in 0..sectors {
+        unsafe {
+            wait_bsy_clear()?;
+            wait_drq_set()?;
+        }
+
+        // 256 words per sector
         for _ in 0..256 {
-            let _ = inw(ATA_REG_DATA);
+            let w = data_port().read();
+            buffer[*off] = (w & 0xFF) as u8;
+            buffer[*off + 1] = (w >> 8) as u8;
+            *off += 2;
         }
 
-        vga::print_string("[disk] ATA primary master identified\n");
-        Ok(())
+        // optional tiny delay
+        busy_wait_us(1);
+    }
+    Ok(())
+}
+
+/// Read `count` sectors starting at a 48-bit `lba` with READ SECTORS EXT.
+/// Needs a drive whose IDENTIFY data reported the LBA48 feature set.
+pub fn read_sectors_lba48(target: DiskTarget, mut lba: u64, mut count: u32, buffer: &mut [u8]) -> Result<(), &'static str> {
+    if count == 0 {
+        return Ok(());
+    }
+    if buffer.len() < (count as usize) * 512 {
+        return Err("buffer too small for read_sectors_lba48");
+    }
+    if lba + count as u64 > (1u64 << 48) {
+        return Err("LBA beyond 48-bit range");
+    }
+
+    let mut off = 0usize;
+
+    unsafe {
+        select_target(target);
+        while count > 0 {
+            let chunk = min(count, LBA48_MAX_CHUNK);
+
+            let start = off;
+            reissue(|| {
+                off = start;
+                // The truncation to 0 for 65536 sectors is what the drive expects
+                write_lba48_regs(lba, chunk as u16);
+                cmd_port(ATA_REG_COMMAND).write(ATA_CMD_READ_SECTORS_EXT);
+                pio_read_sectors(chunk as usize, buffer, &mut off)
+            })?;
+
+            lba += chunk as u64;
+            count -= chunk;
+        }
     }
+
+    Ok(())
 }
 
 /// Read `count` sectors (512 bytes each) starting at `lba` into `buffer`.
-/// Supports up to 255 sectors per command; larger reads are chunked.
-pub fn read_sectors(mut lba: u32, mut count: u16, buffer: &mut [u8]) -> Result<(), &'static str> {
+/// Supports up to 255 sectors per command; larger reads are chunked, or
+/// handed to `read_sectors_lba48` when the drive supports it. Reads past
+/// the 28-bit limit always go through LBA48.
+pub fn read_sectors(target: DiskTarget, mut lba: u32, mut count: u16, buffer: &mut [u8]) -> Result<(), &'static str> {
     if count == 0 {
         return Ok(());
     }
@@ -171,45 +478,156 @@ pub fn read_sectors(mut lba: u32, mut count: u16, buffer: &mut [u8]) -> Result<(
         return Err("buffer too small for read_sectors");
     }
 
+    let lba48 = unsafe { LBA48_SUPPORTED[target.index()] };
+    let past_lba28 = lba as u64 + count as u64 - 1 > LBA28_MAX;
+    if past_lba28 || (count > 255 && lba48) {
+        if !lba48 {
+            return Err("LBA beyond 28-bit range and drive lacks LBA48");
+        }
+        return read_sectors_lba48(target, lba as u64, count as u32, buffer);
+    }
+
     let mut off = 0usize;
 
     unsafe {
+        select_target(target);
         while count > 0 {
             let chunk: u8 = min(count, 255) as u8; // protocol limit for SECCOUNT0
 
-            // Select drive: master (0xE0) | high 4 bits of LBA
-            outb(ATA_REG_HDDEVSEL, 0xE0 | ((lba >> 24) as u8 & 0x0F));
-            io_wait();
-
-            // Program sector count and LBA registers
-            outb(ATA_REG_SECCOUNT0, chunk);
-            outb(ATA_REG_LBA0, (lba & 0xFF) as u8);
-            outb(ATA_REG_LBA1, ((lba >> 8) & 0xFF) as u8);
-            outb(ATA_REG_LBA2, ((lba >> 16) & 0xFF) as u8);
-
-            // Issue READ SECTORS
-            outb(ATA_REG_COMMAND, ATA_CMD_READ_SECTORS);
-
-            // Read `chunk` sectors
-            for _ in 0..chunk {
-                wait_bsy_clear()?;
-                wait_drq_set()?;
-
-                // 256 words per sector
-                for _ in 0..256 {
-                    let w = inw(ATA_REG_DATA);
-                    buffer[off] = (w & 0xFF) as u8;
-                    buffer[off + 1] = (w >> 8) as u8;
-                    off += 2;
-                }
-
-                // optional tiny delay
-                io_wait();
-            }
+            let start = off;
+            reissue(|| {
+                off = start;
+                // Select drive (0xE0 master / 0xF0 slave) | high 4 bits of LBA
+                cmd_port(ATA_REG_HDDEVSEL).write(target.drive_select() | ((lba >> 24) as u8 & 0x0F));
+                busy_wait_us(1);
+
+                // Program sector count and LBA registers
+                cmd_port(ATA_REG_SECCOUNT0).write(chunk);
+                cmd_port(ATA_REG_LBA0).write((lba & 0xFF) as u8);
+                cmd_port(ATA_REG_LBA1).write(((lba >> 8) & 0xFF) as u8);
+                cmd_port(ATA_REG_LBA2).write(((lba >> 16) & 0xFF) as u8);
+
+                // Issue READ SECTORS and read `chunk` sectors
+                cmd_port(ATA_REG_COMMAND).write(ATA_CMD_READ_SECTORS);
+                pio_read_sectors(chunk as usize, buffer, &mut off)
+            })?;
+
+            lba = lba.wrapping_add(chunk as u32);
+            count -= chunk as u16;
+        }
+    }
+
+    Ok(())
+}
+
+/// PIO-write `sectors` sectors from `buffer` at `off` for an issued write command.
+unsafe fn pio_write_sectors(sectors: usize, buffer: &[u8], off: &mut usize) -> Result<(), &'static str> {
+    for _ in 0..sectors {
+        unsafe {
+            wait_bsy_clear()?;
+            wait_drq_set()?;
+        }
+
+        for _ in 0..256 {
+            let w = (buffer[*off] as u16) | ((buffer[*off + 1] as u16) << 8);
+            data_port().write(w);
+            *off += 2;
+        }
+
+        busy_wait_us(1);
+    }
+
+    // The drive raises BSY while committing the last sector; ERR/DF show up after
+    unsafe { wait_bsy_clear() }
+}
+
+/// Flush the drive's write cache so written sectors survive a power cut.
+unsafe fn flush_cache(lba48: bool) -> Result<(), &'static str> {
+    cmd_port(ATA_REG_COMMAND).write(if lba48 { ATA_CMD_CACHE_FLUSH_EXT } else { ATA_CMD_CACHE_FLUSH });
+    unsafe { wait_bsy_clear() }
+}
+
+/// Write `count` sectors starting at a 48-bit `lba` with WRITE SECTORS EXT.
+pub fn write_sectors_lba48(target: DiskTarget, mut lba: u64, mut count: u32, buffer: &[u8]) -> Result<(), &'static str> {
+    if count == 0 {
+        return Ok(());
+    }
+    if buffer.len() < (count as usize) * 512 {
+        return Err("buffer too small for write_sectors_lba48");
+    }
+    if lba + count as u64 > (1u64 << 48) {
+        return Err("LBA beyond 48-bit range");
+    }
+
+    let mut off = 0usize;
+
+    unsafe {
+        select_target(target);
+        while count > 0 {
+            let chunk = min(count, LBA48_MAX_CHUNK);
+
+            let start = off;
+            reissue(|| {
+                off = start;
+                write_lba48_regs(lba, chunk as u16);
+                cmd_port(ATA_REG_COMMAND).write(ATA_CMD_WRITE_SECTORS_EXT);
+                pio_write_sectors(chunk as usize, buffer, &mut off)
+            })?;
+
+            lba += chunk as u64;
+            count -= chunk;
+        }
+        reissue(|| flush_cache(true))?;
+    }
+
+    Ok(())
+}
+
+/// Write `count` sectors (512 bytes each) from `buffer` starting at `lba`.
+/// Chunked and routed to LBA48 the same way as `read_sectors`.
+pub fn write_sectors(target: DiskTarget, mut lba: u32, mut count: u16, buffer: &[u8]) -> Result<(), &'static str> {
+    if count == 0 {
+        return Ok(());
+    }
+    if buffer.len() < (count as usize) * 512 {
+        return Err("buffer too small for write_sectors");
+    }
+
+    let lba48 = unsafe { LBA48_SUPPORTED[target.index()] };
+    let past_lba28 = lba as u64 + count as u64 - 1 > LBA28_MAX;
+    if past_lba28 || (count > 255 && lba48) {
+        if !lba48 {
+            return Err("LBA beyond 28-bit range and drive lacks LBA48");
+        }
+        return write_sectors_lba48(target, lba as u64, count as u32, buffer);
+    }
+
+    let mut off = 0usize;
+
+    unsafe {
+        select_target(target);
+        while count > 0 {
+            let chunk: u8 = min(count, 255) as u8;
+
+            let start = off;
+            reissue(|| {
+                off = start;
+                cmd_port(ATA_REG_HDDEVSEL).write(target.drive_select() | ((lba >> 24) as u8 & 0x0F));
+                busy_wait_us(1);
+
+                cmd_port(ATA_REG_SECCOUNT0).write(chunk);
+                cmd_port(ATA_REG_LBA0).write((lba & 0xFF) as u8);
+                cmd_port(ATA_REG_LBA1).write(((lba >> 8) & 0xFF) as u8);
+                cmd_port(ATA_REG_LBA2).write(((lba >> 16) & 0xFF) as u8);
+
+                cmd_port(ATA_REG_COMMAND).write(ATA_CMD_WRITE_SECTORS);
+                pio_write_sectors(chunk as usize, buffer, &mut off)
+            })?;
 
             lba = lba.wrapping_add(chunk as u32);
             count -= chunk as u16;
         }
+        reissue(|| flush_cache(false))?;
     }
 
     Ok(())
@@ -0,0 +1,142 @@
+//! Linear framebuffer from the UEFI Graphics Output Protocol.
+//!
+//! Only 32 bits per pixel layouts are supported. `PixelBltOnly` modes have no
+//! framebuffer address and are rejected.
+
+use uefi::proto::console::gop::{GraphicsOutput, ModeInfo, PixelFormat};
+use uefi::table::boot::BootServices;
+
+use crate::boot::bootinfo::{
+    FramebufferDescriptor, FB_FORMAT_BGR, FB_FORMAT_BITMASK, FB_FORMAT_RGB,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GopPixelFormat {
+    /// `PixelRedGreenBlueReserved8BitPerColor`: red in the low byte.
+    Rgb,
+    /// `PixelBlueGreenRedReserved8BitPerColor`: blue in the low byte.
+    Bgr,
+    /// `PixelBitMask`: channel positions given by the masks.
+    BitMask { red: u32, green: u32, blue: u32 },
+}
+
+impl GopPixelFormat {
+    pub fn from_mode_info(info: &ModeInfo) -> Result<Self, &'static str> {
+        match info.pixel_format() {
+            PixelFormat::Rgb => Ok(GopPixelFormat::Rgb),
+            PixelFormat::Bgr => Ok(GopPixelFormat::Bgr),
+            PixelFormat::Bitmask => {
+                let mask = info.pixel_bitmask().ok_or("GOP: bitmask mode without masks")?;
+                Ok(GopPixelFormat::BitMask { red: mask.red, green: mask.green, blue: mask.blue })
+            }
+            PixelFormat::BltOnly => Err("GOP: BltOnly mode has no framebuffer"),
+        }
+    }
+}
+
+/// Place an 8-bit channel value into the bits selected by `mask`, scaling to
+/// the mask width.
+fn pack_channel(value: u8, mask: u32) -> u32 {
+    if mask == 0 {
+        return 0;
+    }
+    let shift = mask.trailing_zeros();
+    let width = (mask >> shift).count_ones();
+    let scaled = if width >= 8 {
+        (value as u32) << (width - 8)
+    } else {
+        (value as u32) >> (8 - width)
+    };
+    (scaled << shift) & mask
+}
+
+/// Encode an RGB colour as one 32-bit pixel in `fmt`.
+pub fn pack_pixel(fmt: GopPixelFormat, r: u8, g: u8, b: u8) -> u32 {
+    match fmt {
+        GopPixelFormat::Rgb => (r as u32) | ((g as u32) << 8) | ((b as u32) << 16),
+        GopPixelFormat::Bgr => (b as u32) | ((g as u32) << 8) | ((r as u32) << 16),
+        GopPixelFormat::BitMask { red, green, blue } => {
+            pack_channel(r, red) | pack_channel(g, green) | pack_channel(b, blue)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Framebuffer {
+    pub base: u64,
+    pub size: usize,
+    pub width: usize,
+    pub height: usize,
+    /// Pixels per scan line (may exceed `width`).
+    pub stride: usize,
+    pub format: GopPixelFormat,
+}
+
+impl Framebuffer {
+    /// Describe the current GOP mode.
+    pub fn from_gop(gop: &mut GraphicsOutput) -> Result<Self, &'static str> {
+        let info = gop.current_mode_info();
+        let format = GopPixelFormat::from_mode_info(&info)?;
+        let (width, height) = info.resolution();
+        let mut fb = gop.frame_buffer();
+        Ok(Framebuffer {
+            base: fb.as_mut_ptr() as u64,
+            size: fb.size(),
+            width,
+            height,
+            stride: info.stride(),
+            format,
+        })
+    }
+
+    /// Locate GOP anywhere in the system and describe its current mode.
+    /// `None` on serial-only firmware or when the mode has no framebuffer.
+    pub fn locate(bs: &BootServices) -> Option<Self> {
+        let cell = bs.locate_protocol::<GraphicsOutput>().ok()?;
+        // SAFETY: the interface stays installed until ExitBootServices
+        let gop = unsafe { &mut *cell.get() };
+        Self::from_gop(gop).ok()
+    }
+
+    /// Kernel-facing description of this framebuffer.
+    pub fn descriptor(&self) -> FramebufferDescriptor {
+        let (format, red_mask, green_mask, blue_mask) = match self.format {
+            GopPixelFormat::Rgb => (FB_FORMAT_RGB, 0x0000_00FF, 0x0000_FF00, 0x00FF_0000),
+            GopPixelFormat::Bgr => (FB_FORMAT_BGR, 0x00FF_0000, 0x0000_FF00, 0x0000_00FF),
+            GopPixelFormat::BitMask { red, green, blue } => (FB_FORMAT_BITMASK, red, green, blue),
+        };
+        FramebufferDescriptor {
+            base: self.base,
+            size: self.size as u64,
+            width: self.width as u32,
+            height: self.height as u32,
+            stride: self.stride as u32,
+            format,
+            red_mask,
+            green_mask,
+            blue_mask,
+        }
+    }
+
+    pub fn put_pixel(&self, x: usize, y: usize, r: u8, g: u8, b: u8) {
+        if x >= self.width || y >= self.height {
+            return;
+        }
+        let offset = (y * self.stride + x) * 4;
+        if offset + 4 > self.size {
+            return;
+        }
+        let pixel = pack_pixel(self.format, r, g, b);
+        unsafe {
+            core::ptr::write_volatile((self.base as usize + offset) as *mut u32, pixel);
+        }
+    }
+
+    pub fn fill(&self, r: u8, g: u8, b: u8) {
+        for y in 0..self.height {
+            for x in 0..self.width {
+                self.put_pixel(x, y, r, g, b);
+            }
+        }
+    }
+}
@@ -0,0 +1,116 @@
+//! PS/2 keyboard (8042 controller), polled, scan code set 1.
+//!
+//! The BIOS leaves the controller translating to set 1, so no controller
+//! programming is needed; IRQ1 stays masked and input is read by polling.
+
+use crate::arch::io::IoPort;
+
+// ===== 8042 ports =====
+const KBC_DATA: IoPort<u8> = IoPort::new(0x60);
+const KBC_STATUS: IoPort<u8> = IoPort::new(0x64);
+const STATUS_OUTPUT_FULL: u8 = 0x01;
+
+/// Bytes drained by `init` before giving up on a controller that keeps
+/// reporting data (or is absent and floats 0xFF)
+const FLUSH_LIMIT: u32 = 256;
+
+const SCAN_EXTENDED_PREFIX: u8 = 0xE0;
+const SCAN_RELEASE_BIT: u8 = 0x80;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Key {
+    /// Digits, lowercase letters and `-` `=` `[` `]` `;` `'` `` ` `` `\` `,` `.` `/`
+    Char(u8),
+    Space,
+    Enter,
+    Escape,
+    Backspace,
+    Tab,
+    Up,
+    Down,
+    Left,
+    Right,
+    F(u8),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub key: Key,
+    /// false for a release (break code)
+    pub pressed: bool,
+}
+
+// An 0xE0 prefix was seen; the next code is from the extended set
+static mut EXTENDED_PENDING: bool = false;
+
+/// Drop stale bytes (keys pressed during POST) from the output buffer.
+pub fn init() {
+    for _ in 0..FLUSH_LIMIT {
+        if KBC_STATUS.read() & STATUS_OUTPUT_FULL == 0 {
+            break;
+        }
+        let _ = KBC_DATA.read();
+    }
+    unsafe { EXTENDED_PENDING = false };
+}
+
+/// Next byte from the keyboard, `None` if the output buffer is empty.
+pub fn read_scan_code() -> Option<u8> {
+    if KBC_STATUS.read() & STATUS_OUTPUT_FULL == 0 {
+        return None;
+    }
+    Some(KBC_DATA.read())
+}
+
+/// Translate one set 1 byte. Prefix bytes and unmapped keys give `None`;
+/// an 0xE0 prefix is remembered for the following byte.
+pub fn scan_to_key(scan: u8) -> Option<KeyEvent> {
+    if scan == SCAN_EXTENDED_PREFIX {
+        unsafe { EXTENDED_PENDING = true };
+        return None;
+    }
+    let extended = unsafe { core::mem::replace(&mut *core::ptr::addr_of_mut!(EXTENDED_PENDING), false) };
+    let pressed = scan & SCAN_RELEASE_BIT == 0;
+    let code = scan & !SCAN_RELEASE_BIT;
+
+    // Arrows share codes with the keypad; both are taken as arrows
+    let key = match code {
+        0x48 => Key::Up,
+        0x50 => Key::Down,
+        0x4B => Key::Left,
+        0x4D => Key::Right,
+        0x1C => Key::Enter, // keypad Enter when extended
+        _ if extended => return None,
+        0x01 => Key::Escape,
+        0x0E => Key::Backspace,
+        0x0F => Key::Tab,
+        0x39 => Key::Space,
+        0x3B..=0x44 => Key::F(code - 0x3B + 1),
+        0x57 => Key::F(11),
+        0x58 => Key::F(12),
+        _ => Key::Char(ascii_for(code)?),
+    };
+    Some(KeyEvent { key, pressed })
+}
+
+/// Poll once and translate.
+pub fn read_key() -> Option<KeyEvent> {
+    read_scan_code().and_then(scan_to_key)
+}
+
+/// Unshifted US layout for the character rows of set 1.
+fn ascii_for(code: u8) -> Option<u8> {
+    const ROW_DIGITS: &[u8] = b"1234567890-="; // 0x02..
+    const ROW_TOP: &[u8] = b"qwertyuiop[]"; // 0x10..
+    const ROW_HOME: &[u8] = b"asdfghjkl;'`"; // 0x1E..
+    const ROW_BOTTOM: &[u8] = b"\\zxcvbnm,./"; // 0x2B..
+
+    let (row, first) = match code {
+        0x02..=0x0D => (ROW_DIGITS, 0x02),
+        0x10..=0x1B => (ROW_TOP, 0x10),
+        0x1E..=0x29 => (ROW_HOME, 0x1E),
+        0x2B..=0x35 => (ROW_BOTTOM, 0x2B),
+        _ => return None,
+    };
+    row.get((code - first) as usize).copied()
+}
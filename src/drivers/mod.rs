@@ -1,2 +1,20 @@
+#[cfg(feature = "bios")]
+pub mod ahci;
+#[cfg(feature = "bios")]
+pub mod block;
+#[cfg(feature = "bios")]
 pub mod disk;
+#[cfg(feature = "uefi")]
+pub mod framebuffer;
+#[cfg(feature = "bios")]
+pub mod kbd;
+#[cfg(feature = "bios")]
+pub mod nvme;
+pub mod pci;
+pub mod serial;
+#[cfg(feature = "uefi")]
+pub mod uefi_disk;
+#[cfg(feature = "bios")]
+pub mod virtio_blk;
+#[cfg(feature = "bios")]
 pub mod vga;
@@ -0,0 +1,351 @@
+//! NVMe driver (polled, one I/O queue pair, reads only).
+//!
+//! BAR0 is used in place through the BIOS path's identity mapping, so the
+//! firmware must have put it below 4 GiB. All queues are `QUEUE_DEPTH`
+//! entries deep, each in its own page, and only one command is ever in
+//! flight, so completion is a matter of watching the phase bit flip.
+
+use core::ptr;
+
+use crate::arch::mmio::Mmio;
+use crate::arch::time::busy_wait_us;
+use crate::drivers::pci::{self, PciDevice};
+
+const PROG_IF_NVME: u8 = 0x02;
+const PAGE_SIZE: usize = 4096;
+const QUEUE_DEPTH: u16 = 4;
+const SQ_ENTRY_SIZE: usize = 64;
+const CQ_ENTRY_SIZE: usize = 16;
+const ADMIN_QUEUE: u16 = 0;
+const IO_QUEUE: u16 = 1;
+
+// ===== Controller registers =====
+const REG_CAP: usize = 0x00;
+const REG_VS: usize = 0x08;
+const REG_CC: usize = 0x14;
+const REG_CSTS: usize = 0x1C;
+const REG_AQA: usize = 0x24;
+const REG_ASQ: usize = 0x28;
+const REG_ACQ: usize = 0x30;
+const DOORBELL_BASE: usize = 0x1000;
+
+const CC_EN: u32 = 1 << 0;
+/// 64-byte submission and 16-byte completion entries (log2), 4 KiB pages
+const CC_IOSQES: u32 = 6 << 16;
+const CC_IOCQES: u32 = 4 << 20;
+const CSTS_RDY: u32 = 1 << 0;
+const CSTS_CFS: u32 = 1 << 1;
+
+// ===== Commands =====
+const ADMIN_CREATE_IO_SQ: u8 = 0x01;
+const ADMIN_CREATE_IO_CQ: u8 = 0x05;
+const ADMIN_IDENTIFY: u8 = 0x06;
+const NVM_READ: u8 = 0x02;
+const IDENTIFY_NAMESPACE: u32 = 0x00;
+const IDENTIFY_CONTROLLER: u32 = 0x01;
+/// Queue creation flag: physically contiguous
+const QUEUE_PC: u32 = 1 << 0;
+
+/// Largest read issued as one command, further limited by the controller's MDTS
+const MAX_TRANSFER: usize = 128 * 1024;
+/// Polls, `POLL_INTERVAL_US` apart, before a command times out (~1 s)
+const POLL_LIMIT: u32 = 100_000;
+const POLL_INTERVAL_US: u64 = 10;
+
+/// A submission/completion queue pair.
+struct QueuePair {
+    id: u16,
+    sq: *mut u8,
+    cq: *mut u8,
+    sq_tail: u16,
+    cq_head: u16,
+    /// Phase tag expected on the next new completion; flips on wrap
+    phase: bool,
+    next_cid: u16,
+}
+
+impl QueuePair {
+    fn new(id: u16) -> Result<Self, &'static str> {
+        Ok(Self { id, sq: zeroed_page()?, cq: zeroed_page()?, sq_tail: 0, cq_head: 0, phase: true, next_cid: 0 })
+    }
+}
+
+pub struct NvmeController {
+    bar: usize,
+    /// Doorbell stride in bytes
+    doorbell_stride: usize,
+    admin: QueuePair,
+    io: QueuePair,
+    /// Identify data and PRP list, one page each
+    scratch: *mut u8,
+    prp_list: *mut u64,
+    max_transfer: usize,
+    /// Namespace whose LBA size is in `lba_size`, 0 for none yet
+    cached_ns: u32,
+    lba_size: u32,
+    pub serial: [u8; 20],
+    pub model: [u8; 40],
+}
+
+fn zeroed_page() -> Result<*mut u8, &'static str> {
+    let page = crate::memory::allocate_pages(1)?;
+    unsafe { ptr::write_bytes(page, 0, PAGE_SIZE) };
+    Ok(page)
+}
+
+fn phys(p: *const u8) -> u64 {
+    p as usize as u64
+}
+
+/// Reset the controller at `dev`, set up the admin and I/O queues and read
+/// its Identify data.
+pub fn init(dev: &PciDevice) -> Result<NvmeController, &'static str> {
+    if dev.class != pci::CLASS_STORAGE || dev.subclass != pci::SUBCLASS_NVM || dev.prog_if != PROG_IF_NVME {
+        return Err("NVMe: not an NVMe controller");
+    }
+    let bar = dev.mmio_bar(0).ok_or("NVMe: BAR0 is not a memory BAR")?;
+    if bar > u32::MAX as u64 {
+        return Err("NVMe: BAR0 above 4 GiB");
+    }
+    let bar = bar as usize;
+    dev.enable_bus_master();
+
+    let cap = Mmio::<u64>::new(bar + REG_CAP).read();
+    let max_entries = (cap & 0xFFFF) as u16 + 1;
+    if max_entries < QUEUE_DEPTH {
+        return Err("NVMe: queues too small");
+    }
+    // CAP.TO is in 500 ms units
+    let ready_timeout_ms = ((cap >> 24) & 0xFF).max(1) * 500;
+
+    let mut ctrl = NvmeController {
+        bar,
+        doorbell_stride: 4 << ((cap >> 32) & 0xF),
+        admin: QueuePair::new(ADMIN_QUEUE)?,
+        io: QueuePair::new(IO_QUEUE)?,
+        scratch: zeroed_page()?,
+        prp_list: zeroed_page()? as *mut u64,
+        max_transfer: MAX_TRANSFER,
+        cached_ns: 0,
+        lba_size: 0,
+        serial: [0; 20],
+        model: [0; 40],
+    };
+
+    let cc = Mmio::<u32>::new(bar + REG_CC);
+    cc.write(cc.read() & !CC_EN);
+    wait_ready(bar, false, ready_timeout_ms)?;
+
+    let depth = (QUEUE_DEPTH - 1) as u32;
+    Mmio::<u32>::new(bar + REG_AQA).write((depth << 16) | depth);
+    Mmio::<u64>::new(bar + REG_ASQ).write(phys(ctrl.admin.sq));
+    Mmio::<u64>::new(bar + REG_ACQ).write(phys(ctrl.admin.cq));
+
+    cc.write(CC_IOCQES | CC_IOSQES | CC_EN);
+    wait_ready(bar, true, ready_timeout_ms)?;
+
+    ctrl.identify_controller()?;
+    ctrl.create_io_queues()?;
+    debug_log!("nvme", "controller ready, max transfer {} bytes", ctrl.max_transfer);
+    Ok(ctrl)
+}
+
+/// Wait for CSTS.RDY to match `ready`.
+fn wait_ready(bar: usize, ready: bool, timeout_ms: u64) -> Result<(), &'static str> {
+    let csts_reg = Mmio::<u32>::new(bar + REG_CSTS);
+    for _ in 0..timeout_ms {
+        let csts = csts_reg.read();
+        if csts & CSTS_CFS != 0 {
+            return Err("NVMe: controller fatal status");
+        }
+        if (csts & CSTS_RDY != 0) == ready {
+            return Ok(());
+        }
+        busy_wait_us(1000);
+    }
+    Err("NVMe: timeout waiting for CSTS.RDY")
+}
+
+impl NvmeController {
+    fn identify_controller(&mut self) -> Result<(), &'static str> {
+        let mut cmd = command(ADMIN_IDENTIFY, 0);
+        set_prp(&mut cmd, phys(self.scratch), 0);
+        cmd[10] = IDENTIFY_CONTROLLER;
+        self.submit_admin(cmd)?;
+
+        let id = unsafe { core::slice::from_raw_parts(self.scratch, PAGE_SIZE) };
+        self.serial.copy_from_slice(&id[4..24]);
+        self.model.copy_from_slice(&id[24..64]);
+        // MDTS: 2^n minimum-size (4 KiB) pages, 0 for no limit
+        let mdts = id[77];
+        if mdts != 0 && mdts < 16 {
+            self.max_transfer = self.max_transfer.min(PAGE_SIZE << mdts);
+        }
+        Ok(())
+    }
+
+    fn create_io_queues(&mut self) -> Result<(), &'static str> {
+        let size_and_id = ((QUEUE_DEPTH as u32 - 1) << 16) | IO_QUEUE as u32;
+
+        let mut cmd = command(ADMIN_CREATE_IO_CQ, 0);
+        set_prp(&mut cmd, phys(self.io.cq), 0);
+        cmd[10] = size_and_id;
+        // Interrupts stay off: IEN clear
+        cmd[11] = QUEUE_PC;
+        self.submit_admin(cmd)?;
+
+        let mut cmd = command(ADMIN_CREATE_IO_SQ, 0);
+        set_prp(&mut cmd, phys(self.io.sq), 0);
+        cmd[10] = size_and_id;
+        cmd[11] = ((IO_QUEUE as u32) << 16) | QUEUE_PC;
+        self.submit_admin(cmd)
+    }
+
+    /// LBA data size of namespace `ns` from its current LBA format.
+    pub fn namespace_lba_size(&mut self, ns: u32) -> Result<u32, &'static str> {
+        if self.cached_ns == ns {
+            return Ok(self.lba_size);
+        }
+        let mut cmd = command(ADMIN_IDENTIFY, ns);
+        set_prp(&mut cmd, phys(self.scratch), 0);
+        cmd[10] = IDENTIFY_NAMESPACE;
+        self.submit_admin(cmd)?;
+
+        let id = unsafe { core::slice::from_raw_parts(self.scratch, PAGE_SIZE) };
+        let nsze = u64::from_le_bytes(id[0..8].try_into().unwrap());
+        if nsze == 0 {
+            return Err("NVMe: namespace not active");
+        }
+        // FLBAS selects one of the LBA format descriptors at 128; LBADS is log2
+        let format = (id[26] & 0xF) as usize;
+        let lbads = id[128 + format * 4 + 2];
+        if !(9..=16).contains(&lbads) {
+            return Err("NVMe: unsupported LBA size");
+        }
+        self.cached_ns = ns;
+        self.lba_size = 1 << lbads;
+        Ok(self.lba_size)
+    }
+
+    fn submit_admin(&mut self, cmd: [u32; 16]) -> Result<(), &'static str> {
+        submit(self.bar, self.doorbell_stride, &mut self.admin, cmd)
+    }
+}
+
+/// Read `count` logical blocks of namespace `ns` starting at `lba`. `buf`
+/// must hold `count` blocks of the namespace's LBA size and be dword aligned.
+pub fn read_lba(ctrl: &mut NvmeController, ns: u32, lba: u64, count: u32, buf: &mut [u8]) -> Result<(), &'static str> {
+    if count == 0 {
+        return Ok(());
+    }
+    let lba_size = ctrl.namespace_lba_size(ns)? as usize;
+    let len = count as usize * lba_size;
+    if buf.len() < len {
+        return Err("buffer too small for read_lba");
+    }
+    if buf.as_ptr() as usize % 4 != 0 {
+        return Err("NVMe: buffer not dword aligned");
+    }
+
+    let blocks_per_cmd = (ctrl.max_transfer / lba_size).min(0x1_0000);
+    let mut done = 0usize;
+    while done < count as usize {
+        let blocks = (count as usize - done).min(blocks_per_cmd);
+        let data = unsafe { buf.as_mut_ptr().add(done * lba_size) };
+        let slba = lba + done as u64;
+
+        let mut cmd = command(NVM_READ, ns);
+        let (prp1, prp2) = build_prps(ctrl.prp_list, phys(data), blocks * lba_size);
+        set_prp(&mut cmd, prp1, prp2);
+        cmd[10] = slba as u32;
+        cmd[11] = (slba >> 32) as u32;
+        // Number of logical blocks, 0's based
+        cmd[12] = (blocks - 1) as u32;
+        submit(ctrl.bar, ctrl.doorbell_stride, &mut ctrl.io, cmd)?;
+        done += blocks;
+    }
+    Ok(())
+}
+
+/// PRP1 is the (possibly unaligned) start; PRP2 is the second page, or a
+/// list of all pages after the first when the transfer spans more than two.
+fn build_prps(prp_list: *mut u64, addr: u64, len: usize) -> (u64, u64) {
+    let first_page_bytes = PAGE_SIZE - (addr as usize % PAGE_SIZE);
+    if len <= first_page_bytes {
+        return (addr, 0);
+    }
+    let second = (addr & !(PAGE_SIZE as u64 - 1)) + PAGE_SIZE as u64;
+    let rest = len - first_page_bytes;
+    if rest <= PAGE_SIZE {
+        return (addr, second);
+    }
+    // MAX_TRANSFER keeps this well under the 512 entries a page holds
+    for i in 0..rest.div_ceil(PAGE_SIZE) {
+        unsafe { prp_list.add(i).write_volatile(second + (i * PAGE_SIZE) as u64) };
+    }
+    (addr, phys(prp_list as *const u8))
+}
+
+/// Submission entry with `opcode` and `nsid`; the command id is filled in by
+/// `submit`.
+fn command(opcode: u8, nsid: u32) -> [u32; 16] {
+    let mut cmd = [0u32; 16];
+    cmd[0] = opcode as u32;
+    cmd[1] = nsid;
+    cmd
+}
+
+fn set_prp(cmd: &mut [u32; 16], prp1: u64, prp2: u64) {
+    cmd[6] = prp1 as u32;
+    cmd[7] = (prp1 >> 32) as u32;
+    cmd[8] = prp2 as u32;
+    cmd[9] = (prp2 >> 32) as u32;
+}
+
+/// Queue `cmd` on `q`, ring its doorbell and poll for the completion.
+fn submit(bar: usize, stride: usize, q: &mut QueuePair, mut cmd: [u32; 16]) -> Result<(), &'static str> {
+    let cid = q.next_cid;
+    q.next_cid = q.next_cid.wrapping_add(1);
+    cmd[0] |= (cid as u32) << 16;
+
+    let sq_doorbell = Mmio::<u32>::new(bar + DOORBELL_BASE + (2 * q.id as usize) * stride);
+    let cq_doorbell = Mmio::<u32>::new(sq_doorbell.addr() + stride);
+    unsafe {
+        let slot = q.sq.add(q.sq_tail as usize * SQ_ENTRY_SIZE) as *mut u32;
+        for (i, &dw) in cmd.iter().enumerate() {
+            slot.add(i).write_volatile(dw);
+        }
+        q.sq_tail = (q.sq_tail + 1) % QUEUE_DEPTH;
+        sq_doorbell.write(q.sq_tail as u32);
+
+        // Completion dword 3: CID in 15:0, phase in bit 16, status above it
+        let entry = q.cq.add(q.cq_head as usize * CQ_ENTRY_SIZE) as *const u32;
+        let mut polls = 0;
+        let dw3 = loop {
+            let dw3 = entry.add(3).read_volatile();
+            if (dw3 & (1 << 16) != 0) == q.phase {
+                break dw3;
+            }
+            polls += 1;
+            if polls == POLL_LIMIT {
+                return Err("NVMe: command timeout");
+            }
+            busy_wait_us(POLL_INTERVAL_US);
+        };
+
+        q.cq_head += 1;
+        if q.cq_head == QUEUE_DEPTH {
+            q.cq_head = 0;
+            q.phase = !q.phase;
+        }
+        cq_doorbell.write(q.cq_head as u32);
+
+        if dw3 as u16 != cid {
+            return Err("NVMe: unexpected completion");
+        }
+        if (dw3 >> 17) & 0x7FFF != 0 {
+            return Err("NVMe: command failed");
+        }
+    }
+    Ok(())
+}
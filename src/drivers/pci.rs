@@ -0,0 +1,189 @@
+//! PCI configuration space access (mechanism #1, ports 0xCF8/0xCFC) and bus
+//! enumeration.
+//!
+//! `scan` walks every bus/device/function once and keeps the first
+//! `MAX_DEVICES` functions found; storage drivers pick their controller out
+//! of the result by class code.
+
+use crate::arch::io::IoPort;
+
+const PCI_CONFIG_ADDRESS: IoPort<u32> = IoPort::new(0xCF8);
+const PCI_CONFIG_DATA: IoPort<u32> = IoPort::new(0xCFC);
+
+pub const MAX_DEVICES: usize = 64;
+
+// ===== Configuration space offsets =====
+pub const REG_VENDOR_DEVICE: u8 = 0x00;
+pub const REG_COMMAND: u8 = 0x04;
+pub const REG_CLASS: u8 = 0x08;
+pub const REG_HEADER: u8 = 0x0C;
+pub const REG_BAR0: u8 = 0x10;
+
+const COMMAND_IO_SPACE: u32 = 1 << 0;
+const COMMAND_MEMORY_SPACE: u32 = 1 << 1;
+const COMMAND_BUS_MASTER: u32 = 1 << 2;
+
+const HEADER_TYPE_MASK: u8 = 0x7F;
+const HEADER_MULTI_FUNCTION: u8 = 0x80;
+/// Only general devices (header type 0) have all six BARs; bridges have two
+const HEADER_TYPE_BRIDGE: u8 = 0x01;
+
+const BAR_IO: u32 = 1 << 0;
+const BAR_MEM_TYPE_MASK: u32 = 0x6;
+const BAR_MEM_TYPE_64: u32 = 0x4;
+
+// ===== Class codes =====
+pub const CLASS_STORAGE: u8 = 0x01;
+pub const SUBCLASS_IDE: u8 = 0x01;
+pub const SUBCLASS_SATA: u8 = 0x06;
+pub const SUBCLASS_NVM: u8 = 0x08;
+
+#[inline(always)]
+fn config_address(bus: u8, dev: u8, func: u8, reg: u8) -> u32 {
+    0x8000_0000 | ((bus as u32) << 16) | ((dev as u32) << 11) | ((func as u32) << 8) | ((reg as u32) & 0xFC)
+}
+
+/// Dword at `reg` (rounded down to a multiple of 4).
+pub fn pci_config_read32(bus: u8, dev: u8, func: u8, reg: u8) -> u32 {
+    PCI_CONFIG_ADDRESS.write(config_address(bus, dev, func, reg));
+    PCI_CONFIG_DATA.read()
+}
+
+pub fn pci_config_write32(bus: u8, dev: u8, func: u8, reg: u8, val: u32) {
+    PCI_CONFIG_ADDRESS.write(config_address(bus, dev, func, reg));
+    PCI_CONFIG_DATA.write(val);
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct PciDevice {
+    pub bus: u8,
+    pub dev: u8,
+    pub func: u8,
+    pub vendor_id: u16,
+    pub device_id: u16,
+    pub class: u8,
+    pub subclass: u8,
+    pub prog_if: u8,
+    /// Layout type with the multi-function bit masked off
+    pub header_type: u8,
+    /// Raw BAR registers; unused ones (BAR2-5 of a bridge) are 0
+    pub bar: [u32; 6],
+}
+
+impl PciDevice {
+    fn read(bus: u8, dev: u8, func: u8) -> Option<Self> {
+        let id = pci_config_read32(bus, dev, func, REG_VENDOR_DEVICE);
+        if id & 0xFFFF == 0xFFFF {
+            return None;
+        }
+        let class = pci_config_read32(bus, dev, func, REG_CLASS);
+        let header_type = (pci_config_read32(bus, dev, func, REG_HEADER) >> 16) as u8 & HEADER_TYPE_MASK;
+        let bar_count = match header_type {
+            0 => 6,
+            HEADER_TYPE_BRIDGE => 2,
+            _ => 0,
+        };
+        let mut bar = [0u32; 6];
+        for (i, b) in bar.iter_mut().enumerate().take(bar_count) {
+            *b = pci_config_read32(bus, dev, func, REG_BAR0 + 4 * i as u8);
+        }
+        Some(Self {
+            bus,
+            dev,
+            func,
+            vendor_id: id as u16,
+            device_id: (id >> 16) as u16,
+            class: (class >> 24) as u8,
+            subclass: (class >> 16) as u8,
+            prog_if: (class >> 8) as u8,
+            header_type,
+            bar,
+        })
+    }
+
+    /// Physical address behind memory BAR `index`, combining the upper half
+    /// of a 64-bit BAR. `None` for I/O and unimplemented BARs.
+    pub fn mmio_bar(&self, index: usize) -> Option<u64> {
+        let low = *self.bar.get(index)?;
+        if low & BAR_IO != 0 {
+            return None;
+        }
+        let mut addr = (low & !0xF) as u64;
+        if low & BAR_MEM_TYPE_MASK == BAR_MEM_TYPE_64 {
+            addr |= (*self.bar.get(index + 1)? as u64) << 32;
+        }
+        if addr == 0 { None } else { Some(addr) }
+    }
+
+    /// Port base behind I/O BAR `index`.
+    pub fn io_bar(&self, index: usize) -> Option<u16> {
+        let bar = *self.bar.get(index)?;
+        if bar & BAR_IO != 0 && bar & 0xFFFC != 0 { Some((bar & 0xFFFC) as u16) } else { None }
+    }
+
+    /// Let the device decode its BARs and master DMA, which firmware does not
+    /// always leave on for devices it did not boot from.
+    pub fn enable_bus_master(&self) {
+        let cmd = pci_config_read32(self.bus, self.dev, self.func, REG_COMMAND);
+        let cmd = (cmd & 0xFFFF) | COMMAND_IO_SPACE | COMMAND_MEMORY_SPACE | COMMAND_BUS_MASTER;
+        pci_config_write32(self.bus, self.dev, self.func, REG_COMMAND, cmd);
+    }
+}
+
+pub struct PciBus {
+    pub devices: [Option<PciDevice>; MAX_DEVICES],
+    pub count: usize,
+}
+
+impl PciBus {
+    pub fn iter(&self) -> impl Iterator<Item = &PciDevice> {
+        self.devices[..self.count].iter().flatten()
+    }
+
+    pub fn find(&self, vendor_id: u16, device_id: u16) -> Option<&PciDevice> {
+        self.iter().find(|d| d.vendor_id == vendor_id && d.device_id == device_id)
+    }
+
+    pub fn find_class(&self, class: u8, subclass: u8) -> Option<&PciDevice> {
+        self.iter().find(|d| d.class == class && d.subclass == subclass)
+    }
+
+    fn push(&mut self, dev: PciDevice) -> bool {
+        if self.count == MAX_DEVICES {
+            return false;
+        }
+        self.devices[self.count] = Some(dev);
+        self.count += 1;
+        true
+    }
+}
+
+/// Enumerate buses 0-255. Functions 1-7 are only probed on multi-function
+/// devices, since some single-function devices answer on all eight.
+pub fn scan() -> PciBus {
+    let mut bus_list = PciBus { devices: [None; MAX_DEVICES], count: 0 };
+    for bus in 0..=255u8 {
+        for dev in 0..32u8 {
+            let Some(first) = PciDevice::read(bus, dev, 0) else {
+                continue;
+            };
+            let header = (pci_config_read32(bus, dev, 0, REG_HEADER) >> 16) as u8;
+            if !bus_list.push(first) {
+                debug_log!("pci", "more than {} functions, rest ignored", MAX_DEVICES);
+                return bus_list;
+            }
+            if header & HEADER_MULTI_FUNCTION == 0 {
+                continue;
+            }
+            for func in 1..8u8 {
+                if let Some(device) = PciDevice::read(bus, dev, func) {
+                    if !bus_list.push(device) {
+                        debug_log!("pci", "more than {} functions, rest ignored", MAX_DEVICES);
+                        return bus_list;
+                    }
+                }
+            }
+        }
+    }
+    bus_list
+}
@@ -0,0 +1,105 @@
+//! 16550A UART driver (polled, no interrupts).
+//!
+//! `init` runs a loopback self-test so a missing UART is reported instead of
+//! silently swallowing output. The log macros mirror to the port passed to
+//! the last successful `init`.
+
+use crate::arch::io::IoPort;
+
+pub const COM1: u16 = 0x3F8;
+pub const COM2: u16 = 0x2F8;
+
+const UART_CLOCK_DIVISOR_BASE: u32 = 115_200;
+
+// ===== Register offsets from the port base =====
+const REG_DATA: u16 = 0; // R: RBR, W: THR; DLL when DLAB=1
+const REG_IER: u16 = 1; // interrupt enable; DLM when DLAB=1
+const REG_FCR: u16 = 2; // W: FIFO control
+const REG_LCR: u16 = 3; // line control
+const REG_MCR: u16 = 4; // modem control
+const REG_LSR: u16 = 5; // line status
+
+const LCR_DLAB: u8 = 0x80;
+const LCR_8N1: u8 = 0x03;
+const FCR_ENABLE_CLEAR_14: u8 = 0xC7; // enable, clear RX/TX, 14-byte trigger
+const MCR_DTR_RTS_OUT2: u8 = 0x0B;
+const MCR_LOOPBACK: u8 = 0x1E; // loopback with OUT1/OUT2/RTS
+const MCR_NORMAL: u8 = 0x0F;
+const LSR_DATA_READY: u8 = 0x01;
+const LSR_THR_EMPTY: u8 = 0x20;
+
+// Give up on a byte after this many LSR reads (cable unplugged, flow stuck)
+const TX_POLL_RETRIES: u32 = 100_000;
+
+// Port the log mirrors to; 0 until `init` succeeds
+static mut CONSOLE_PORT: u16 = 0;
+
+fn reg(port: u16, offset: u16) -> IoPort<u8> {
+    IoPort::new(port + offset)
+}
+
+/// Program `port` for `baud` 8N1 with FIFOs enabled.
+pub fn init(port: u16, baud: u32) -> Result<(), &'static str> {
+    if baud == 0 || UART_CLOCK_DIVISOR_BASE % baud != 0 {
+        return Err("serial: unsupported baud rate");
+    }
+    let divisor = (UART_CLOCK_DIVISOR_BASE / baud) as u16;
+
+    reg(port, REG_IER).write(0x00);
+    reg(port, REG_LCR).write(LCR_DLAB);
+    reg(port, REG_DATA).write(divisor as u8);
+    reg(port, REG_IER).write((divisor >> 8) as u8);
+    reg(port, REG_LCR).write(LCR_8N1);
+    reg(port, REG_FCR).write(FCR_ENABLE_CLEAR_14);
+    reg(port, REG_MCR).write(MCR_DTR_RTS_OUT2);
+
+    // Loopback self-test: a byte written must come straight back
+    reg(port, REG_MCR).write(MCR_LOOPBACK);
+    reg(port, REG_DATA).write(0xAE);
+    if reg(port, REG_DATA).read() != 0xAE {
+        return Err("serial: loopback test failed");
+    }
+    reg(port, REG_MCR).write(MCR_NORMAL);
+
+    unsafe {
+        CONSOLE_PORT = port;
+    }
+    Ok(())
+}
+
+/// Port the log output is mirrored to, if a UART has been initialized.
+pub fn console_port() -> Option<u16> {
+    match unsafe { CONSOLE_PORT } {
+        0 => None,
+        port => Some(port),
+    }
+}
+
+/// Wait for the transmit holding register to drain, then send `b`.
+pub fn write_byte(port: u16, b: u8) {
+    for _ in 0..TX_POLL_RETRIES {
+        if (reg(port, REG_LSR).read() & LSR_THR_EMPTY) != 0 {
+            reg(port, REG_DATA).write(b);
+            return;
+        }
+    }
+}
+
+/// A received byte, or `None` if nothing is waiting.
+pub fn read_byte(port: u16) -> Option<u8> {
+    if (reg(port, REG_LSR).read() & LSR_DATA_READY) != 0 {
+        Some(reg(port, REG_DATA).read())
+    } else {
+        None
+    }
+}
+
+/// Send `s`, expanding `\n` to `\r\n` for terminals.
+pub fn write_str(port: u16, s: &str) {
+    for b in s.bytes() {
+        if b == b'\n' {
+            write_byte(port, b'\r');
+        }
+        write_byte(port, b);
+    }
+}
@@ -0,0 +1,128 @@
+//! Disk access through the firmware's `BlockIo` protocol.
+//!
+//! This is the UEFI path's disk backend: it works for whatever controller the
+//! firmware has a driver for (NVMe, AHCI, virtio, Hyper-V storage), where the
+//! BIOS path's ATA PIO driver only finds legacy IDE. Only usable before
+//! ExitBootServices.
+
+use uefi::Identify;
+use uefi::prelude::*;
+use uefi::proto::device_path::DevicePath;
+use uefi::proto::loaded_image::LoadedImage;
+use uefi::proto::media::block::BlockIO;
+use uefi::table::boot::{BootServices, OpenProtocolAttributes, OpenProtocolParams, ScopedProtocol, SearchType};
+
+use crate::uefi::protocol::require_protocol;
+use crate::util::once::OnceCell;
+
+/// Disk `read_sectors` goes to, chosen once by `set_boot_disk`
+static BOOT_DISK: OnceCell<UefiDisk> = OnceCell::new();
+
+pub struct UefiDisk {
+    pub handle: Handle,
+    block_io: ScopedProtocol<'static, BlockIO>,
+    media_id: u32,
+    /// Bytes per block; `read_sectors` counts in these, not in 512s
+    pub block_size: u32,
+    pub last_block: u64,
+}
+
+impl UefiDisk {
+    /// Open `BlockIo` on `handle` without taking it away from the firmware's
+    /// own users (the filesystem driver sits on top of it).
+    pub fn open(st: &SystemTable<Boot>, handle: Handle) -> Result<Self, &'static str> {
+        // SAFETY: boot services stay valid until ExitBootServices, and the
+        // disk is not used after that
+        let bs: &'static BootServices = unsafe { &*(st.boot_services() as *const BootServices) };
+        let block_io = unsafe {
+            bs.open_protocol::<BlockIO>(
+                OpenProtocolParams { handle, agent: bs.image_handle(), controller: None },
+                OpenProtocolAttributes::GetProtocol,
+            )
+        }
+        .map_err(|_| "BlockIo not available on handle")?;
+
+        let media = block_io.media();
+        if !media.is_media_present() {
+            return Err("BlockIo: no media present");
+        }
+        let (media_id, block_size, last_block) = (media.media_id(), media.block_size(), media.last_block());
+        Ok(Self { handle, block_io, media_id, block_size, last_block })
+    }
+
+    /// The disk the loader image was read from. Firmware binds the image to
+    /// its partition, so LBAs here are relative to the whole disk only if the
+    /// parent disk is found; otherwise the partition itself is returned.
+    pub fn boot_disk(st: &SystemTable<Boot>, image_handle: Handle) -> Result<Self, &'static str> {
+        let bs = st.boot_services();
+        let loaded = require_protocol::<LoadedImage>(bs, image_handle).inspect_err(|e| log_info!("uefi", "{}", e))?;
+        let device = loaded.device().ok_or("Loader image has no device handle")?;
+        match parent_disk(bs, device) {
+            Some(disk) => Self::open(st, disk),
+            None => {
+                log_info!("uefi", "Boot disk: parent of the loader partition not found");
+                Self::open(st, device)
+            }
+        }
+    }
+
+    /// Read `count` blocks starting at `lba`. `buf` must honour the media's
+    /// `IoAlign`; firmware rejects misaligned buffers with INVALID_PARAMETER.
+    pub fn read_sectors(&self, lba: u64, count: u32, buf: &mut [u8]) -> Result<(), &'static str> {
+        if count == 0 {
+            return Ok(());
+        }
+        let len = count as usize * self.block_size as usize;
+        if buf.len() < len {
+            return Err("buffer too small for read_sectors");
+        }
+        if lba.checked_add(count as u64 - 1).is_none_or(|end| end > self.last_block) {
+            return Err("BlockIo: read beyond end of media");
+        }
+        self.block_io.read_blocks(self.media_id, lba, &mut buf[..len]).map_err(|_| "BlockIo: ReadBlocks failed")
+    }
+}
+
+/// Whole-disk `BlockIo` handle whose device path is a prefix of the one of
+/// `partition`. `None` if `partition` already is a whole disk.
+fn parent_disk(bs: &BootServices, partition: Handle) -> Option<Handle> {
+    // Quiet lookups: most handles lack one of the protocols
+    let partition_path = unsafe { &*bs.handle_protocol::<DevicePath>(partition).ok()?.get() };
+    let handles = bs.locate_handle_buffer(SearchType::ByProtocol(&BlockIO::GUID)).ok()?;
+    handles.iter().copied().find(|&handle| {
+        if handle == partition {
+            return false;
+        }
+        let whole_disk = bs
+            .handle_protocol::<BlockIO>(handle)
+            .is_ok_and(|b| unsafe { !(*b.get()).media().is_logical_partition() });
+        whole_disk
+            && bs
+                .handle_protocol::<DevicePath>(handle)
+                .is_ok_and(|p| is_path_prefix(unsafe { &*p.get() }, partition_path))
+    })
+}
+
+fn is_path_prefix(prefix: &DevicePath, path: &DevicePath) -> bool {
+    let mut nodes = path.node_iter();
+    prefix.node_iter().all(|p| {
+        nodes.next().is_some_and(|n| {
+            n.device_type() == p.device_type() && n.sub_type() == p.sub_type() && n.data() == p.data()
+        })
+    })
+}
+
+/// Make `disk` the target of `read_sectors` for the rest of the boot.
+pub fn set_boot_disk(disk: UefiDisk) -> Result<(), &'static str> {
+    BOOT_DISK.set(disk).map_err(|_| "UEFI boot disk already set")
+}
+
+pub fn boot_disk() -> Option<&'static UefiDisk> {
+    BOOT_DISK.get()
+}
+
+/// Read `count` blocks of the boot disk starting at `lba`; the UEFI path's
+/// counterpart of `block::read_sectors` on the BIOS path.
+pub fn read_sectors(lba: u64, count: u32, buf: &mut [u8]) -> Result<(), &'static str> {
+    BOOT_DISK.get().ok_or("No UEFI boot disk")?.read_sectors(lba, count, buf)
+}
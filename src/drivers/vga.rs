@@ -1,52 +1,378 @@
+use crate::arch::io::IoPort;
+
 const VGA_BUFFER: *mut u8 = 0xb8000 as *mut u8;
 static mut CURSOR_POS: usize = 0;
+// Rows `print_char` writes and scrolls; 24 while the progress bar holds the
+// bottom row
+static mut TEXT_ROWS: usize = 25;
+
+// ===== Colors =====
+
+/// The 16 text mode colors (identity palette, see `MODE3_AC`).
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum VgaColor {
+    Black = 0x0,
+    Blue = 0x1,
+    Green = 0x2,
+    Cyan = 0x3,
+    Red = 0x4,
+    Magenta = 0x5,
+    Brown = 0x6,
+    LightGrey = 0x7,
+    DarkGrey = 0x8,
+    LightBlue = 0x9,
+    LightGreen = 0xA,
+    LightCyan = 0xB,
+    LightRed = 0xC,
+    Pink = 0xD,
+    Yellow = 0xE,
+    White = 0xF,
+}
+
+/// Attribute byte for `fg` on `bg`. Bit 7 is the blink bit while blinking is
+/// enabled, so only the 8 dark colors work as background.
+pub const fn attr(fg: VgaColor, bg: VgaColor) -> u8 {
+    ((bg as u8 & 0x7) << 4) | (fg as u8 & 0xF)
+}
+
+const DEFAULT_ATTR: u8 = attr(VgaColor::LightGrey, VgaColor::Black);
+
+// Attribute used by `print_char`
+static mut TEXT_ATTR: u8 = DEFAULT_ATTR;
+
+/// Set the attribute for all following text output.
+pub fn set_default_color(fg: VgaColor, bg: VgaColor) {
+    unsafe {
+        TEXT_ATTR = attr(fg, bg);
+    }
+}
+
+// ===== VGA register ports =====
+const VGA_MISC_WRITE: IoPort<u8> = IoPort::new(0x3C2);
+const VGA_SEQ_INDEX: IoPort<u8> = IoPort::new(0x3C4);
+const VGA_SEQ_DATA: IoPort<u8> = IoPort::new(0x3C5);
+const VGA_GC_INDEX: IoPort<u8> = IoPort::new(0x3CE);
+const VGA_GC_DATA: IoPort<u8> = IoPort::new(0x3CF);
+const VGA_AC_INDEX: IoPort<u8> = IoPort::new(0x3C0); // index and data share the port (flip-flop)
+const VGA_CRTC_INDEX: IoPort<u8> = IoPort::new(0x3D4);
+const VGA_CRTC_DATA: IoPort<u8> = IoPort::new(0x3D5);
+const VGA_INSTAT_READ: IoPort<u8> = IoPort::new(0x3DA); // reading resets the AC flip-flop
+
+// ===== Canonical mode 3 (80x25 text, 16 colors) register set =====
+const MODE3_MISC: u8 = 0x67;
+const MODE3_SEQ: [u8; 5] = [0x03, 0x00, 0x03, 0x00, 0x02];
+const MODE3_CRTC: [u8; 25] = [
+    0x5F, 0x4F, 0x50, 0x82, 0x55, 0x81, 0xBF, 0x1F, 0x00, 0x4F, 0x0D, 0x0E, 0x00, 0x00, 0x00,
+    0x00, 0x9C, 0x8E, 0x8F, 0x28, 0x1F, 0x96, 0xB9, 0xA3, 0xFF,
+];
+const MODE3_GC: [u8; 9] = [0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x0E, 0x00, 0xFF];
+const MODE3_AC: [u8; 21] = [
+    0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x14, 0x07, 0x38, 0x39, 0x3A, 0x3B, 0x3C, 0x3D, 0x3E,
+    0x3F, 0x0C, 0x00, 0x0F, 0x08, 0x00,
+];
 
 pub fn init() {
+    reinit_text_mode_80x25();
     unsafe {
         CURSOR_POS = 0;
         clear_screen();
     }
 }
 
+/// Program the VGA hardware for mode 3 (80x25 text, 16 colors) from scratch.
+///
+/// We cannot rely on the BIOS having left the card in a sane state: a
+/// chainloading real-mode stub, a previous graphics mode, or firmware that
+/// never touched VGA can leave the wrong cursor shape, attribute, or memory
+/// mapping behind. Registers written, in order:
+/// - Miscellaneous Output (0x3C2): color I/O at 0x3Dx, 25 MHz dot clock.
+/// - Sequencer (0x3C4/5, idx 0-4): 9-dot chars, odd/even planes 0+1, no chain-4.
+/// - CRTC (0x3D4/5, idx 0-24): 80x25 timings, cursor scanlines 13-14, start
+///   address and cursor location 0. Index 0x11 bit 7 is cleared first to
+///   unlock idx 0-7.
+/// - Graphics Controller (0x3CE/F, idx 0-8): odd/even, text memory at 0xB8000.
+/// - Attribute Controller (0x3C0, idx 0-20): identity 16-color palette,
+///   blinking enabled; finally 0x20 re-enables video output.
+pub fn reinit_text_mode_80x25() {
+    VGA_MISC_WRITE.write(MODE3_MISC);
+
+    for (i, &v) in MODE3_SEQ.iter().enumerate() {
+        VGA_SEQ_INDEX.write(i as u8);
+        VGA_SEQ_DATA.write(v);
+    }
+
+    // Unlock CRTC registers 0-7 (protect bit lives in 0x11 bit 7)
+    VGA_CRTC_INDEX.write(0x11);
+    let protect = VGA_CRTC_DATA.read();
+    VGA_CRTC_DATA.write(protect & 0x7F);
+    for (i, &v) in MODE3_CRTC.iter().enumerate() {
+        VGA_CRTC_INDEX.write(i as u8);
+        VGA_CRTC_DATA.write(v);
+    }
+
+    for (i, &v) in MODE3_GC.iter().enumerate() {
+        VGA_GC_INDEX.write(i as u8);
+        VGA_GC_DATA.write(v);
+    }
+
+    for (i, &v) in MODE3_AC.iter().enumerate() {
+        let _ = VGA_INSTAT_READ.read(); // reset flip-flop to "index"
+        VGA_AC_INDEX.write(i as u8);
+        VGA_AC_INDEX.write(v);
+    }
+    // Set PAS (bit 5) so the AC feeds the palette to the screen again
+    let _ = VGA_INSTAT_READ.read();
+    VGA_AC_INDEX.write(0x20);
+}
+
+const VGA_BUFFER_SIZE: usize = 80 * 25 * 2;
+// Two cells of ' ' on light grey, little-endian: 0x20 0x07 0x20 0x07
+const BLANK_CELL_PAIR: u32 = (0x07u32 << 24) | (0x20u32 << 16) | (0x07u32 << 8) | 0x20u32;
+
 pub fn clear_screen() {
     unsafe {
-        for i in 0..80 * 25 * 2 {
-            *((VGA_BUFFER as usize + i) as *mut u8) = if i % 2 == 0 { b' ' } else { 0x07 };
-        }
+        fill_blank();
+        debug_assert!(is_blank(), "VGA buffer did not clear");
         CURSOR_POS = 0;
+        TEXT_ROWS = 25;
+    }
+}
+
+/// Blank the whole text buffer with one string store instead of 4000 byte writes.
+#[cfg(target_arch = "x86_64")]
+unsafe fn fill_blank() {
+    let pattern = ((BLANK_CELL_PAIR as u64) << 32) | BLANK_CELL_PAIR as u64;
+    unsafe {
+        core::arch::asm!(
+            "cld",
+            "rep stosq",
+            inout("rdi") VGA_BUFFER => _,
+            inout("rcx") VGA_BUFFER_SIZE / 8 => _,
+            in("rax") pattern,
+            options(nostack)
+        );
+    }
+}
+
+#[cfg(target_arch = "x86")]
+unsafe fn fill_blank() {
+    unsafe {
+        core::arch::asm!(
+            "cld",
+            "rep stosd",
+            inout("edi") VGA_BUFFER => _,
+            inout("ecx") VGA_BUFFER_SIZE / 4 => _,
+            in("eax") BLANK_CELL_PAIR,
+            options(nostack)
+        );
     }
 }
 
+unsafe fn is_blank() -> bool {
+    let cells = VGA_BUFFER as *const u32;
+    (0..VGA_BUFFER_SIZE / 4).all(|i| unsafe { core::ptr::read_volatile(cells.add(i)) } == BLANK_CELL_PAIR)
+}
+
 pub fn print_string(s: &str) {
     for byte in s.bytes() {
         print_char(byte);
     }
 }
 
+/// Print `s` in `fg` on `bg`, then restore the previous color.
+pub fn print_string_colored(s: &str, fg: VgaColor, bg: VgaColor) {
+    unsafe {
+        let saved = TEXT_ATTR;
+        TEXT_ATTR = attr(fg, bg);
+        print_string(s);
+        TEXT_ATTR = saved;
+    }
+}
+
+/// Error text: light red on black.
+pub fn print_error(s: &str) {
+    print_string_colored(s, VgaColor::LightRed, VgaColor::Black);
+}
+
+/// Success text: light green on black.
+pub fn print_success(s: &str) {
+    print_string_colored(s, VgaColor::LightGreen, VgaColor::Black);
+}
+
+/// Write one cell directly; does not move the cursor. Out-of-range cells are ignored.
+pub fn write_char_attr(col: usize, row: usize, ch: u8, attr: u8) {
+    if col >= 80 || row >= 25 {
+        return;
+    }
+    let off = row * 160 + col * 2;
+    unsafe {
+        *((VGA_BUFFER as usize + off) as *mut u8) = ch;
+        *((VGA_BUFFER as usize + off + 1) as *mut u8) = attr;
+    }
+}
+
+/// `0x` and 2 hex digits.
+pub fn print_hex8(v: u8) {
+    print_hex(v as u64, 2);
+}
+
+/// `0x` and 8 hex digits, leading zeros kept.
+pub fn print_hex32(v: u32) {
+    print_hex(v as u64, 8);
+}
+
+/// `0x` and 16 hex digits, leading zeros kept.
+pub fn print_hex64(v: u64) {
+    print_hex(v, 16);
+}
+
+fn print_hex(v: u64, digits: u32) {
+    const HEX: &[u8; 16] = b"0123456789ABCDEF";
+    print_string("0x");
+    for i in (0..digits).rev() {
+        print_char(HEX[((v >> (i * 4)) & 0xF) as usize]);
+    }
+}
+
+pub fn print_dec_u32(v: u32) {
+    print_dec_u64(v as u64);
+}
+
+pub fn print_dec_usize(v: usize) {
+    print_dec_u64(v as u64);
+}
+
+/// Unsigned decimal.
+pub fn print_dec_u64(mut v: u64) {
+    let mut buf = [0u8; 20];
+    let mut i = buf.len();
+    loop {
+        i -= 1;
+        buf[i] = b'0' + (v % 10) as u8;
+        v /= 10;
+        if v == 0 {
+            break;
+        }
+    }
+    for &b in &buf[i..] {
+        print_char(b);
+    }
+}
+
+/// Byte count rounded down to the largest of B, KB or MB.
+pub fn print_size(bytes: usize) {
+    if bytes >= 1024 * 1024 {
+        print_dec_usize(bytes / (1024 * 1024));
+        print_string("MB");
+    } else if bytes >= 1024 {
+        print_dec_usize(bytes / 1024);
+        print_string("KB");
+    } else {
+        print_dec_usize(bytes);
+        print_string("B");
+    }
+}
+
 pub fn print_char(c: u8) {
     unsafe {
         if c == b'\n' {
             CURSOR_POS = ((CURSOR_POS / 160) + 1) * 160;
         } else {
             *((VGA_BUFFER as usize + CURSOR_POS) as *mut u8) = c;
-            *((VGA_BUFFER as usize + CURSOR_POS + 1) as *mut u8) = 0x07;
+            *((VGA_BUFFER as usize + CURSOR_POS + 1) as *mut u8) = TEXT_ATTR;
             CURSOR_POS += 2;
         }
 
-        if CURSOR_POS >= 80 * 25 * 2 {
+        if CURSOR_POS >= TEXT_ROWS * 160 {
             scroll_up();
-            CURSOR_POS = 80 * 24 * 2;
+            CURSOR_POS = (TEXT_ROWS - 1) * 160;
         }
     }
 }
 
+/// Scroll the text rows up by one, leaving a reserved progress row alone.
 fn scroll_up() {
     unsafe {
-        for i in 0..(80 * 24 * 2) {
+        let last = (TEXT_ROWS - 1) * 160;
+        for i in 0..last {
             *((VGA_BUFFER as usize + i) as *mut u8) = *((VGA_BUFFER as usize + i + 160) as *mut u8);
         }
-        for i in (80 * 24 * 2)..(80 * 25 * 2) {
+        for i in last..(last + 160) {
             *((VGA_BUFFER as usize + i) as *mut u8) = if i % 2 == 0 { b' ' } else { 0x07 };
         }
     }
 }
+
+const PROGRESS_BAR_WIDTH: usize = 50;
+const PROGRESS_FILL: u8 = 0xDB; // CP437 full block
+const PROGRESS_ATTR: u8 = attr(VgaColor::LightGreen, VgaColor::Black);
+
+/// Reserved row for progress output (bottom line; log text scrolls above it).
+pub const PROGRESS_ROW: u8 = 24;
+
+/// Draw `[#####>     ] NN%` on `row`, writing straight into the VGA buffer so
+/// the bar neither moves the cursor nor gets scrolled with log output. A bar
+/// on `PROGRESS_ROW` keeps that row until the next `clear_screen`.
+pub fn draw_progress_bar(row: u8, done: u64, total: u64) {
+    if row as usize >= 25 {
+        return;
+    }
+    if row == PROGRESS_ROW {
+        reserve_progress_row();
+    }
+    let (filled, percent) = if total == 0 || done >= total {
+        (PROGRESS_BAR_WIDTH, 100u64)
+    } else {
+        (
+            (done * PROGRESS_BAR_WIDTH as u64 / total) as usize,
+            done * 100 / total,
+        )
+    };
+
+    let mut col = 0usize;
+    let mut put = |ch: u8, attr: u8| {
+        write_char_attr(col, row as usize, ch, attr);
+        col += 1;
+    };
+
+    put(b'[', 0x07);
+    for i in 0..PROGRESS_BAR_WIDTH {
+        if i < filled {
+            put(PROGRESS_FILL, PROGRESS_ATTR);
+        } else if i == filled {
+            put(b'>', PROGRESS_ATTR);
+        } else {
+            put(b' ', 0x07);
+        }
+    }
+    put(b']', 0x07);
+    put(b' ', 0x07);
+
+    // Percentage, right-aligned in three columns
+    let digits = [
+        if percent >= 100 { b'1' } else { b' ' },
+        if percent >= 10 { b'0' + ((percent / 10) % 10) as u8 } else { b' ' },
+        b'0' + (percent % 10) as u8,
+    ];
+    for d in digits {
+        put(d, 0x07);
+    }
+    put(b'%', 0x07);
+}
+
+/// Stop text output at the row above `PROGRESS_ROW`, scrolling the cursor
+/// off it first.
+fn reserve_progress_row() {
+    unsafe {
+        let rows = PROGRESS_ROW as usize;
+        if TEXT_ROWS == rows {
+            return;
+        }
+        if CURSOR_POS >= rows * 160 {
+            scroll_up();
+            CURSOR_POS -= 160;
+        }
+        TEXT_ROWS = rows;
+    }
+}
@@ -0,0 +1,253 @@
+//! VirtIO block driver over the legacy (transitional) PCI interface.
+//!
+//! The device registers sit in I/O BAR0. One virtqueue is set up with
+//! interrupts suppressed; each request is a header/data/status descriptor
+//! chain whose completion is polled from the used ring. The legacy interface
+//! fixes the ring size (QEMU reports 256), so the rings are laid out for
+//! whatever `QUEUE_SIZE` says, but only the first three descriptors are used.
+//!
+//! Addresses handed to the device are physical; this relies on the BIOS path
+//! running identity-mapped below 4 GiB.
+
+use core::ptr;
+use core::sync::atomic::{Ordering, fence};
+
+use crate::arch::io::{IoPort, PortValue};
+use crate::arch::time::busy_wait_us;
+use crate::drivers::pci::PciDevice;
+
+pub const VIRTIO_VENDOR_ID: u16 = 0x1AF4;
+/// Transitional virtio-blk; modern-only devices are 0x1042
+pub const VIRTIO_BLK_DEVICE_ID: u16 = 0x1001;
+
+pub const SECTOR_SIZE: usize = 512;
+
+// ===== Legacy register offsets from BAR0 =====
+const REG_DEVICE_FEATURES: u16 = 0x00;
+const REG_GUEST_FEATURES: u16 = 0x04;
+const REG_QUEUE_PFN: u16 = 0x08;
+const REG_QUEUE_SIZE: u16 = 0x0C;
+const REG_QUEUE_SELECT: u16 = 0x0E;
+const REG_QUEUE_NOTIFY: u16 = 0x10;
+const REG_DEVICE_STATUS: u16 = 0x12;
+// Device config follows the common header when MSI-X is off
+const REG_CFG_CAPACITY: u16 = 0x14;
+const REG_CFG_BLK_SIZE: u16 = 0x28;
+
+const STATUS_ACKNOWLEDGE: u8 = 1;
+const STATUS_DRIVER: u8 = 2;
+const STATUS_DRIVER_OK: u8 = 4;
+const STATUS_FAILED: u8 = 0x80;
+
+const VIRTIO_BLK_F_BLK_SIZE: u32 = 1 << 6;
+
+const VIRTQ_DESC_F_NEXT: u16 = 1;
+const VIRTQ_DESC_F_WRITE: u16 = 2;
+const VIRTQ_AVAIL_F_NO_INTERRUPT: u16 = 1;
+
+const VIRTIO_BLK_T_IN: u32 = 0;
+const VIRTIO_BLK_S_OK: u8 = 0;
+
+/// Legacy queue addresses are page frame numbers of 4 KiB pages
+const QUEUE_ALIGN: usize = 4096;
+const REQUEST_QUEUE: u16 = 0;
+/// Largest data descriptor per request
+const MAX_SECTORS_PER_REQUEST: u32 = 128;
+/// Polls of the used ring, `POLL_INTERVAL_US` apart, before giving up (~1 s)
+const POLL_LIMIT: u32 = 100_000;
+const POLL_INTERVAL_US: u64 = 10;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct VirtqDesc {
+    addr: u64,
+    len: u32,
+    flags: u16,
+    next: u16,
+}
+
+#[repr(C)]
+struct BlkRequestHeader {
+    ty: u32,
+    reserved: u32,
+    sector: u64,
+}
+
+pub struct VirtioBlk {
+    io_base: u16,
+    queue_size: u16,
+    /// Start of the descriptor table; the available ring follows it
+    desc: *mut VirtqDesc,
+    avail: *mut u16,
+    used: *mut u8,
+    /// Header and status byte of the request in flight
+    header: *mut BlkRequestHeader,
+    status: *mut u8,
+    last_used_idx: u16,
+    avail_idx: u16,
+    /// Capacity in 512-byte sectors
+    pub capacity: u64,
+    /// Logical block size, 512 unless the device offers `BLK_SIZE`
+    pub block_size: u32,
+}
+
+/// Register at `offset` in the legacy I/O BAR, accessed `T`-wide.
+fn reg<T: PortValue>(io: u16, offset: u16) -> IoPort<T> {
+    IoPort::new(io + offset)
+}
+
+const fn align_up(v: usize, align: usize) -> usize {
+    (v + align - 1) & !(align - 1)
+}
+
+/// Byte offsets of the used ring and the request area, and the total size,
+/// for a legacy queue of `size` entries.
+const fn queue_layout(size: usize) -> (usize, usize, usize) {
+    let desc_and_avail = 16 * size + 6 + 2 * size;
+    let used = align_up(desc_and_avail, QUEUE_ALIGN);
+    let request = align_up(used + 6 + 8 * size, 16);
+    let total = request + core::mem::size_of::<BlkRequestHeader>() + 1;
+    (used, request, total)
+}
+
+/// Reset and configure the device at `dev`, leaving it ready for requests.
+pub fn init(dev: &PciDevice) -> Result<VirtioBlk, &'static str> {
+    if dev.vendor_id != VIRTIO_VENDOR_ID || dev.device_id != VIRTIO_BLK_DEVICE_ID {
+        return Err("virtio-blk: not a virtio block device");
+    }
+    let io = dev.io_bar(0).ok_or("virtio-blk: BAR0 is not an I/O BAR")?;
+    dev.enable_bus_master();
+
+    unsafe {
+        reg::<u8>(io, REG_DEVICE_STATUS).write(0);
+        reg::<u8>(io, REG_DEVICE_STATUS).write(STATUS_ACKNOWLEDGE);
+        reg::<u8>(io, REG_DEVICE_STATUS).write(STATUS_ACKNOWLEDGE | STATUS_DRIVER);
+
+        let features = reg::<u32>(io, REG_DEVICE_FEATURES).read() & VIRTIO_BLK_F_BLK_SIZE;
+        reg::<u32>(io, REG_GUEST_FEATURES).write(features);
+
+        reg::<u16>(io, REG_QUEUE_SELECT).write(REQUEST_QUEUE);
+        let queue_size = reg::<u16>(io, REG_QUEUE_SIZE).read();
+        if queue_size < 3 {
+            reg::<u8>(io, REG_DEVICE_STATUS).write(STATUS_FAILED);
+            return Err("virtio-blk: request queue unavailable");
+        }
+
+        let (used_off, request_off, total) = queue_layout(queue_size as usize);
+        let pages = total.div_ceil(QUEUE_ALIGN);
+        let base = match crate::memory::allocate_pages(pages) {
+            Ok(p) => p,
+            Err(e) => {
+                reg::<u8>(io, REG_DEVICE_STATUS).write(STATUS_FAILED);
+                return Err(e);
+            }
+        };
+        ptr::write_bytes(base, 0, pages * QUEUE_ALIGN);
+        reg::<u32>(io, REG_QUEUE_PFN).write((base as usize / QUEUE_ALIGN) as u32);
+
+        let avail = base.add(16 * queue_size as usize) as *mut u16;
+        // Polled: ask the device not to raise interrupts at all
+        ptr::write_volatile(avail, VIRTQ_AVAIL_F_NO_INTERRUPT);
+
+        reg::<u8>(io, REG_DEVICE_STATUS).write(STATUS_ACKNOWLEDGE | STATUS_DRIVER | STATUS_DRIVER_OK);
+
+        let capacity_low = reg::<u32>(io, REG_CFG_CAPACITY).read() as u64;
+        let capacity = capacity_low | ((reg::<u32>(io, REG_CFG_CAPACITY + 4).read() as u64) << 32);
+        let block_size = if features & VIRTIO_BLK_F_BLK_SIZE != 0 {
+            reg::<u32>(io, REG_CFG_BLK_SIZE).read()
+        } else {
+            SECTOR_SIZE as u32
+        };
+        debug_log!("virtio-blk", "{} sectors, queue size {}", capacity, queue_size);
+
+        let request = base.add(request_off);
+        Ok(VirtioBlk {
+            io_base: io,
+            queue_size,
+            desc: base as *mut VirtqDesc,
+            avail,
+            used: base.add(used_off),
+            header: request as *mut BlkRequestHeader,
+            status: request.add(core::mem::size_of::<BlkRequestHeader>()),
+            last_used_idx: 0,
+            avail_idx: 0,
+            capacity,
+            block_size,
+        })
+    }
+}
+
+/// Read `count` 512-byte sectors starting at `sector` into `buf`.
+pub fn read_sectors(blk: &mut VirtioBlk, sector: u64, count: u32, buf: &mut [u8]) -> Result<(), &'static str> {
+    if buf.len() < count as usize * SECTOR_SIZE {
+        return Err("virtio-blk: buffer too small");
+    }
+    if sector.checked_add(count as u64).is_none_or(|end| end > blk.capacity) {
+        return Err("virtio-blk: read beyond end of disk");
+    }
+
+    let mut done = 0u32;
+    while done < count {
+        let n = (count - done).min(MAX_SECTORS_PER_REQUEST);
+        let offset = done as usize * SECTOR_SIZE;
+        let chunk = &mut buf[offset..offset + n as usize * SECTOR_SIZE];
+        submit_read(blk, sector + done as u64, chunk)?;
+        done += n;
+    }
+    Ok(())
+}
+
+/// One request: descriptors 0 (header, device reads), 1 (data, device
+/// writes) and 2 (status, device writes).
+fn submit_read(blk: &mut VirtioBlk, sector: u64, data: &mut [u8]) -> Result<(), &'static str> {
+    unsafe {
+        ptr::write_volatile(blk.header, BlkRequestHeader { ty: VIRTIO_BLK_T_IN, reserved: 0, sector });
+        ptr::write_volatile(blk.status, 0xFF);
+
+        let chain = [
+            VirtqDesc {
+                addr: blk.header as usize as u64,
+                len: core::mem::size_of::<BlkRequestHeader>() as u32,
+                flags: VIRTQ_DESC_F_NEXT,
+                next: 1,
+            },
+            VirtqDesc {
+                addr: data.as_mut_ptr() as usize as u64,
+                len: data.len() as u32,
+                flags: VIRTQ_DESC_F_NEXT | VIRTQ_DESC_F_WRITE,
+                next: 2,
+            },
+            VirtqDesc { addr: blk.status as usize as u64, len: 1, flags: VIRTQ_DESC_F_WRITE, next: 0 },
+        ];
+        for (i, desc) in chain.iter().enumerate() {
+            ptr::write_volatile(blk.desc.add(i), *desc);
+        }
+
+        // avail: flags, idx, ring[queue_size]
+        let slot = blk.avail_idx % blk.queue_size;
+        ptr::write_volatile(blk.avail.add(2 + slot as usize), 0);
+        fence(Ordering::SeqCst);
+        blk.avail_idx = blk.avail_idx.wrapping_add(1);
+        ptr::write_volatile(blk.avail.add(1), blk.avail_idx);
+        fence(Ordering::SeqCst);
+        reg::<u16>(blk.io_base, REG_QUEUE_NOTIFY).write(REQUEST_QUEUE);
+
+        // used: flags, idx, ring[queue_size] of (id, len)
+        let used_idx = blk.used.add(2) as *const u16;
+        let mut polls = 0;
+        while ptr::read_volatile(used_idx) == blk.last_used_idx {
+            polls += 1;
+            if polls == POLL_LIMIT {
+                return Err("virtio-blk: request timed out");
+            }
+            busy_wait_us(POLL_INTERVAL_US);
+        }
+        fence(Ordering::SeqCst);
+        blk.last_used_idx = blk.last_used_idx.wrapping_add(1);
+
+        if ptr::read_volatile(blk.status) != VIRTIO_BLK_S_OK {
+            return Err("virtio-blk: device reported an I/O error");
+        }
+        Ok(())
+    }
+}
@@ -1,4 +1,7 @@
+use super::{FileBuffer, MAX_FILE_SIZE};
 use crate::drivers;
+use crate::util::fixed_string::FixedString;
+use crate::util::once::OnceCell;
 
 // ===== On-disk structures (ext2-compatible) =====
 
@@ -38,6 +41,21 @@ pub struct Ext2Superblock {
     feature_compat: u32,    // 0x5C
     feature_incompat: u32,  // 0x60
     feature_ro_compat: u32, // 0x64
+    uuid: [u8; 16],         // 0x68
+    volume_name: [u8; 16],  // 0x78
+    last_mounted: [u8; 64], // 0x88
+    algorithm_usage_bitmap: u32, // 0xC8
+    prealloc_blocks: u8,     // 0xCC
+    prealloc_dir_blocks: u8, // 0xCD
+    reserved_gdt_blocks: u16, // 0xCE
+    journal_uuid: [u8; 16], // 0xD0
+    journal_inum: u32,      // 0xE0
+    journal_dev: u32,       // 0xE4
+    last_orphan: u32,       // 0xE8
+    hash_seed: [u32; 4],    // 0xEC
+    def_hash_version: u8,   // 0xFC
+    jnl_backup_type: u8,    // 0xFD
+    desc_size: u16,         // 0xFE
 }
 
 #[repr(C, packed)]
@@ -49,8 +67,12 @@ pub struct Ext2BlockGroupDescriptor {
     free_blocks_count: u16,
     free_inodes_count: u16,
     used_dirs_count: u16,
-    pad: u16,
-    reserved: [u32; 3],
+    flags: u16,
+    exclude_bitmap_lo: u32,
+    block_bitmap_csum_lo: u16,
+    inode_bitmap_csum_lo: u16,
+    itable_unused_lo: u16,
+    checksum: u16, // crc16 (GDT_CSUM) or low 16 bits of crc32c (METADATA_CSUM)
 }
 
 #[repr(C, packed)]
@@ -93,71 +115,85 @@ const _EXT2_FT_DIR: u8 = 2;
 // Inode modes
 const EXT2_S_IFREG: u16 = 0x8000; // Regular file
 const EXT2_S_IFDIR: u16 = 0x4000; // Directory
+const EXT2_S_IFLNK: u16 = 0xA000; // Symbolic link
+const EXT2_S_IFMT: u16 = 0xF000;
+
+// Feature flags
+const EXT2_FEATURE_RO_COMPAT_SPARSE_SUPER: u32 = 0x0001;
+const EXT2_FEATURE_RO_COMPAT_LARGE_FILE: u32 = 0x0002;
+const EXT4_FEATURE_RO_COMPAT_GDT_CSUM: u32 = 0x0010;
+const EXT4_FEATURE_RO_COMPAT_METADATA_CSUM: u32 = 0x0400;
+const EXT4_FEATURE_INCOMPAT_64BIT: u32 = 0x0080;
+const EXT4_FEATURE_INCOMPAT_CSUM_SEED: u32 = 0x2000;
+
+// Group descriptor layout
+const EXT2_DESC_SIZE: usize = core::mem::size_of::<Ext2BlockGroupDescriptor>();
+const EXT4_MIN_DESC_SIZE_64BIT: usize = 64;
+const BGD_CHECKSUM_OFFSET: usize = 0x1E;
+const BGD_INODE_TABLE_HI_OFFSET: usize = 0x28;
+
+// Offset of s_checksum_seed in the on-disk superblock
+const SB_CHECKSUM_SEED_OFFSET: usize = 0x270;
 
 // ===== Global filesystem state =====
-static mut SUPERBLOCK: Option<Ext2Superblock> = None;
-static mut BLOCK_SIZE: usize = 0;
-static mut SECTORS_PER_BLOCK: usize = 0;
-// Base LBA for the partition (added to all on-disk accesses)
-static mut PARTITION_LBA_BASE: u32 = 0;
+struct ExtState {
+    superblock: Ext2Superblock,
+    block_size: usize,
+    sectors_per_block: usize,
+    // Base LBA for the partition (added to all on-disk accesses)
+    partition_lba_base: u64,
+    // crc32c seed for METADATA_CSUM (crc32c(~0, uuid) or s_checksum_seed)
+    csum_seed: u32,
+    // Bytes per block group descriptor: 32, or s_desc_size with 64BIT
+    desc_size: usize,
+}
 
-const MAX_FILE_SIZE: usize = 1024 * 1024; // 1MB max file size
+// Set once by a successful `init_with_lba`
+static STATE: OnceCell<ExtState> = OnceCell::new();
 
-#[allow(dead_code)]
-pub struct FileBuffer {
-    data: [u8; MAX_FILE_SIZE],
-    size: usize,
+fn superblock() -> Result<&'static Ext2Superblock, &'static str> {
+    STATE.get().map(|s| &s.superblock).ok_or("Filesystem not initialized")
 }
 
-#[allow(dead_code)]
-impl FileBuffer {
-    pub fn new() -> Self {
-        Self {
-            data: [0; MAX_FILE_SIZE],
-            size: 0,
-        }
-    }
-
-    pub fn as_slice(&self) -> &[u8] {
-        &self.data[..self.size]
-    }
+/// Block size of the mounted filesystem, 0 before init.
+fn block_size() -> usize {
+    STATE.get().map_or(0, |s| s.block_size)
 }
 
 // ===== Public init API =====
 
+pub fn is_mounted() -> bool {
+    STATE.get().is_some()
+}
+
 /// Initialize EXT reader assuming the filesystem starts at absolute LBA 0.
 pub fn init() -> Result<(), &'static str> {
     init_with_lba(0)
 }
 
 /// Initialize EXT reader using the given partition LBA base (MBR/GPT starting LBA).
-pub fn init_with_lba(lba_base: u32) -> Result<(), &'static str> {
-    unsafe {
-        PARTITION_LBA_BASE = lba_base;
+pub fn init_with_lba(lba_base: u64) -> Result<(), &'static str> {
+    if STATE.get().is_some() {
+        return Err("EXT filesystem already mounted");
     }
 
     // Read superblock at byte offset 1024 from the start of the filesystem.
     // 512B sectors => LBA offset +2, read 2 sectors (1024 bytes).
     let mut buffer = [0u8; 1024];
-    let lba = unsafe { PARTITION_LBA_BASE }.wrapping_add(2);
-    drivers::disk::read_sectors(lba, 2, &mut buffer)?;
-
-    let superblock: Ext2Superblock = unsafe {
-        // Use unaligned read; on-disk data is not guaranteed aligned.
-        core::ptr::read_unaligned(buffer.as_ptr() as *const Ext2Superblock)
-    };
-
-    // Check magic number (0xEF53 for ext2/3/4)
-    if superblock.magic != 0xEF53 {
-        return Err("Not an EXT filesystem");
-    }
-
-    // Basic feature gating: keep early-boot reader simple (no extents/64bit)
-    // feature_incompat: 0x40 = EXTENTS, 0x80 = 64BIT
-    let extents = (superblock.feature_incompat & 0x40) != 0;
-    let has_64bit = (superblock.feature_incompat & 0x80) != 0;
-    if extents || has_64bit {
-        return Err("EXT filesystem uses unsupported features (extents/64bit)");
+    drivers::block::read_sectors(lba_base + 2, 2, &mut buffer)?;
+
+    let mut superblock = parse_superblock(&buffer);
+
+    if !superblock_sane(&superblock) {
+        // A damaged primary still tells us whether backups are sparse; with no
+        // magic at all assume SPARSE_SUPER, the mke2fs default.
+        let sparse = superblock.magic != EXT2_MAGIC
+            || (superblock.feature_ro_compat & EXT2_FEATURE_RO_COMPAT_SPARSE_SUPER) != 0;
+        let group = try_alternate_superblock(lba_base, sparse, &mut buffer)?;
+        superblock = parse_superblock(&buffer);
+        drivers::vga::print_string("EXT: primary superblock bad, using backup from group ");
+        drivers::vga::print_dec_u32(group);
+        drivers::vga::print_string("\n");
     }
 
     // Calculate block size
@@ -175,22 +211,108 @@ pub fn init_with_lba(lba_base: u32) -> Result<(), &'static str> {
     }
     let sectors_per_block = block_size / 512;
 
-    unsafe {
-        SUPERBLOCK = Some(superblock);
-        BLOCK_SIZE = block_size;
-        SECTORS_PER_BLOCK = sectors_per_block;
-    }
+    // 64BIT filesystems have larger group descriptors holding the high
+    // halves of their block numbers
+    let desc_size = if (superblock.feature_incompat & EXT4_FEATURE_INCOMPAT_64BIT) != 0 {
+        let size = superblock.desc_size as usize;
+        if size < EXT4_MIN_DESC_SIZE_64BIT || !size.is_power_of_two() || size > block_size {
+            return Err("EXT: bad group descriptor size");
+        }
+        size
+    } else {
+        EXT2_DESC_SIZE
+    };
+
+    let csum_seed = if (superblock.feature_incompat & EXT4_FEATURE_INCOMPAT_CSUM_SEED) != 0 {
+        u32::from_le_bytes([
+            buffer[SB_CHECKSUM_SEED_OFFSET],
+            buffer[SB_CHECKSUM_SEED_OFFSET + 1],
+            buffer[SB_CHECKSUM_SEED_OFFSET + 2],
+            buffer[SB_CHECKSUM_SEED_OFFSET + 3],
+        ])
+    } else {
+        crc32c_update(!0, &superblock.uuid)
+    };
+
+    STATE
+        .set(ExtState {
+            superblock,
+            block_size,
+            sectors_per_block,
+            partition_lba_base: lba_base,
+            csum_seed,
+            desc_size,
+        })
+        .map_err(|_| "EXT filesystem already mounted")?;
 
     drivers::vga::print_string("EXT filesystem initialized\n");
     Ok(())
 }
 
+// ===== Superblock recovery =====
+
+const EXT2_MAGIC: u16 = 0xEF53;
+/// Highest group probed for a backup superblock when the primary is bad.
+const ALT_SUPERBLOCK_MAX_GROUP: u32 = 4096;
+
+fn parse_superblock(buffer: &[u8; 1024]) -> Ext2Superblock {
+    // Use unaligned read; on-disk data is not guaranteed aligned.
+    unsafe { core::ptr::read_unaligned(buffer.as_ptr() as *const Ext2Superblock) }
+}
+
+fn superblock_sane(sb: &Ext2Superblock) -> bool {
+    sb.magic == EXT2_MAGIC && sb.log_block_size <= 2 && sb.blocks_per_group != 0 && sb.inodes_per_group != 0
+}
+
+fn is_power_of(mut n: u32, base: u32) -> bool {
+    if n == 0 {
+        return false;
+    }
+    while n % base == 0 {
+        n /= base;
+    }
+    n == 1
+}
+
+/// Groups holding a superblock backup under SPARSE_SUPER: 0, 1 and powers of 3, 5, 7.
+fn is_sparse_superblock_group(group: u32) -> bool {
+    group == 0 || group == 1 || is_power_of(group, 3) || is_power_of(group, 5) || is_power_of(group, 7)
+}
+
+/// Look for a backup superblock, guessing the geometry mke2fs would have used
+/// for each supported block size (`8 * block_size` blocks per group). On
+/// success `buffer` holds the backup and the group number is returned.
+fn try_alternate_superblock(lba_base: u64, sparse: bool, buffer: &mut [u8; 1024]) -> Result<u32, &'static str> {
+    for log_block_size in 0..=2u32 {
+        let block_size = 1024u64 << log_block_size;
+        let blocks_per_group = block_size * 8;
+        let first_data_block = if log_block_size == 0 { 1 } else { 0 };
+
+        for group in 1..ALT_SUPERBLOCK_MAX_GROUP {
+            if sparse && !is_sparse_superblock_group(group) {
+                continue;
+            }
+            let byte = (group as u64 * blocks_per_group + first_data_block) * block_size;
+            // Past the end of the disk
+            if drivers::block::read_sectors(lba_base + byte / 512, 2, buffer).is_err() {
+                break;
+            }
+            let sb = parse_superblock(buffer);
+            if superblock_sane(&sb) && sb.log_block_size == log_block_size && sb.block_group_nr as u32 == group {
+                return Ok(group);
+            }
+        }
+    }
+    Err("Not an EXT filesystem")
+}
+
 // ===== Low-level block helpers =====
 
 fn read_block(block_num: u32, buffer: &mut [u8]) -> Result<(), &'static str> {
-    let block_size = unsafe { BLOCK_SIZE };
-    let sectors_per_block = unsafe { SECTORS_PER_BLOCK };
-    let base = unsafe { PARTITION_LBA_BASE };
+    let (block_size, sectors_per_block, base) = match STATE.get() {
+        Some(s) => (s.block_size, s.sectors_per_block, s.partition_lba_base),
+        None => (0, 0, 0),
+    };
 
     if buffer.len() < block_size {
         return Err("Buffer too small for block");
@@ -199,22 +321,52 @@ fn read_block(block_num: u32, buffer: &mut [u8]) -> Result<(), &'static str> {
         return Err("Filesystem not initialized (sectors_per_block=0)");
     }
 
-    let start_sector = base.wrapping_add((block_num as usize * sectors_per_block) as u32);
-    drivers::disk::read_sectors(
-        start_sector,
-        sectors_per_block as u16,
-        &mut buffer[..block_size],
-    )
+    let start_sector = base + block_num as u64 * sectors_per_block as u64;
+    drivers::block::read_sectors(start_sector, sectors_per_block as u32, &mut buffer[..block_size])
+}
+
+fn write_block(block_num: u32, buffer: &[u8]) -> Result<(), &'static str> {
+    let st = STATE.get().ok_or("Filesystem not initialized")?;
+    if buffer.len() < st.block_size {
+        return Err("Buffer too small for block");
+    }
+
+    let start_sector = st.partition_lba_base + block_num as u64 * st.sectors_per_block as u64;
+    drivers::block::write_sectors(start_sector, st.sectors_per_block as u32, &buffer[..st.block_size])
+}
+
+fn desc_size() -> usize {
+    STATE.get().map_or(EXT2_DESC_SIZE, |s| s.desc_size)
 }
 
 fn descriptors_per_block() -> usize {
-    unsafe { BLOCK_SIZE / core::mem::size_of::<Ext2BlockGroupDescriptor>() }
+    block_size() / desc_size()
 }
 
 // ===== Metadata helpers =====
 
 fn get_inode(inode_num: u32) -> Result<Ext2Inode, &'static str> {
-    let superblock = unsafe { SUPERBLOCK.as_ref().ok_or("Filesystem not initialized")? };
+    let inode = read_inode_raw(inode_num)?;
+
+    // links_count == 0 means the inode was deleted; its blocks may already
+    // belong to another file.
+    if inode.links_count == 0 {
+        return Err("Accessing deleted inode");
+    }
+    Ok(inode)
+}
+
+/// Read an inode even if it has been deleted (`links_count == 0`).
+///
+/// Intended for recovery tools only: the block pointers of a deleted inode
+/// may be stale, and the data they reference may since have been overwritten
+/// by other files. Never use this on the normal boot path.
+pub fn read_deleted_inode(inode_num: u32) -> Result<Ext2Inode, &'static str> {
+    read_inode_raw(inode_num)
+}
+
+fn read_inode_raw(inode_num: u32) -> Result<Ext2Inode, &'static str> {
+    let superblock = superblock()?;
 
     if inode_num == 0 {
         return Err("invalid inode 0");
@@ -234,33 +386,39 @@ fn get_inode(inode_num: u32) -> Result<Ext2Inode, &'static str> {
         return Err("invalid descriptors_per_block");
     }
     let gdt_block = gdt_start + (group as usize / d_per_blk) as u32;
-    let index_in_block =
-        (group as usize % d_per_blk) * core::mem::size_of::<Ext2BlockGroupDescriptor>();
+    let desc_size = desc_size();
+    let index_in_block = (group as usize % d_per_blk) * desc_size;
 
     // Read the GDT block and load the descriptor for `group`
     let mut bgd_buffer = [0u8; 4096];
     read_block(gdt_block, &mut bgd_buffer)?;
 
-    if index_in_block + core::mem::size_of::<Ext2BlockGroupDescriptor>() > unsafe { BLOCK_SIZE } {
+    if index_in_block + desc_size > block_size() {
         return Err("BGD index out of range");
     }
 
+    let raw_bgd = &bgd_buffer[index_in_block..index_in_block + desc_size];
     let bgd: Ext2BlockGroupDescriptor = unsafe {
-        core::ptr::read_unaligned(
-            bgd_buffer.as_ptr().add(index_in_block) as *const Ext2BlockGroupDescriptor
-        )
+        core::ptr::read_unaligned(raw_bgd.as_ptr() as *const Ext2BlockGroupDescriptor)
     };
+    if !verify_bgd_checksum(raw_bgd, group, &superblock.uuid) {
+        return Err("BGD checksum mismatch");
+    }
+    // Block numbers are 32-bit throughout this reader
+    if desc_size > EXT2_DESC_SIZE && block_ptr(raw_bgd, BGD_INODE_TABLE_HI_OFFSET / 4) != 0 {
+        return Err("EXT: inode table beyond 32-bit block range");
+    }
 
     // Read inode from inode table
     let mut inode_size = 128usize;
     if superblock.rev_level >= 1 {
         let sz = superblock.inode_size as usize;
         // Accept sane sizes: >=128, <= block size, 4-byte aligned
-        if sz >= 128 && sz <= unsafe { BLOCK_SIZE } && (sz & 3) == 0 {
+        if sz >= 128 && sz <= block_size() && (sz & 3) == 0 {
             inode_size = sz;
         }
     }
-    let inodes_per_block = unsafe { BLOCK_SIZE } / inode_size;
+    let inodes_per_block = block_size() / inode_size;
     if inodes_per_block == 0 {
         return Err("invalid inodes_per_block");
     }
@@ -272,7 +430,7 @@ fn get_inode(inode_num: u32) -> Result<Ext2Inode, &'static str> {
     let mut inode_buffer = [0u8; 4096];
     read_block(inode_block, &mut inode_buffer)?;
 
-    if inode_offset + inode_size > unsafe { BLOCK_SIZE } {
+    if inode_offset + inode_size > block_size() {
         return Err("inode offset out of range");
     }
 
@@ -283,6 +441,69 @@ fn get_inode(inode_num: u32) -> Result<Ext2Inode, &'static str> {
     Ok(inode)
 }
 
+// ===== Checksums =====
+
+/// CRC-16 (poly 0x8005, reflected), as used by ext4 GDT_CSUM. No final xor.
+fn crc16_update(mut crc: u16, data: &[u8]) -> u16 {
+    for &b in data {
+        crc ^= b as u16;
+        for _ in 0..8 {
+            crc = if (crc & 1) != 0 { (crc >> 1) ^ 0xA001 } else { crc >> 1 };
+        }
+    }
+    crc
+}
+
+/// CRC-32C (Castagnoli, reflected) raw update, matching the kernel's
+/// `crc32c()` helper: caller supplies the seed, no final inversion.
+fn crc32c_update(mut crc: u32, data: &[u8]) -> u32 {
+    for &b in data {
+        crc ^= b as u32;
+        for _ in 0..8 {
+            crc = if (crc & 1) != 0 { (crc >> 1) ^ 0x82F6_3B78 } else { crc >> 1 };
+        }
+    }
+    crc
+}
+
+/// Verify the checksum of block group descriptor `group`, given as its
+/// on-disk bytes (32, or `s_desc_size` on 64BIT filesystems).
+///
+/// METADATA_CSUM: low 16 bits of crc32c(seed, group_le || desc-with-zeroed-csum).
+/// GDT_CSUM: crc16(~0, uuid || group_le || desc without the checksum field).
+/// Returns true when neither feature is enabled (nothing to check).
+pub fn verify_bgd_checksum(raw: &[u8], group: u32, uuid: &[u8; 16]) -> bool {
+    let (ro_compat, csum_seed) = match STATE.get() {
+        Some(s) => (s.superblock.feature_ro_compat, s.csum_seed),
+        None => return false,
+    };
+    if raw.len() < EXT2_DESC_SIZE {
+        return false;
+    }
+
+    let expected = u16::from_le_bytes([raw[BGD_CHECKSUM_OFFSET], raw[BGD_CHECKSUM_OFFSET + 1]]);
+    let (before, after) = (&raw[..BGD_CHECKSUM_OFFSET], &raw[BGD_CHECKSUM_OFFSET + 2..]);
+    let group_le = group.to_le_bytes();
+
+    if (ro_compat & EXT4_FEATURE_RO_COMPAT_METADATA_CSUM) != 0 {
+        let mut crc = crc32c_update(csum_seed, &group_le);
+        crc = crc32c_update(crc, before);
+        crc = crc32c_update(crc, &[0, 0]);
+        crc = crc32c_update(crc, after);
+        return (crc & 0xFFFF) as u16 == expected;
+    }
+
+    if (ro_compat & EXT4_FEATURE_RO_COMPAT_GDT_CSUM) != 0 {
+        let mut crc = crc16_update(!0, uuid);
+        crc = crc16_update(crc, &group_le);
+        crc = crc16_update(crc, before);
+        crc = crc16_update(crc, after);
+        return crc == expected;
+    }
+
+    true
+}
+
 fn read_dir_entry(buf: &[u8], offset: usize) -> Result<Ext2DirEntryView, &'static str> {
     if offset + 8 > buf.len() {
         return Err("dir entry short");
@@ -306,119 +527,616 @@ fn read_dir_entry(buf: &[u8], offset: usize) -> Result<Ext2DirEntryView, &'stati
 
 // ===== Directory and file access =====
 
+/// Scan one directory data block for `filename`, returning its inode number.
+fn scan_dir_block(block: &[u8], filename: &str) -> Result<Option<u32>, &'static str> {
+    let block_size = block.len();
+    let mut offset = 0usize;
+
+    while offset + 8 <= block_size {
+        let entry = read_dir_entry(block, offset)?;
+
+        if entry.rec_len == 0 {
+            break;
+        }
+        let rec_len = entry.rec_len as usize;
+        if rec_len < 8 || offset + rec_len > block_size {
+            // Corrupted dir entry; stop scanning this block
+            break;
+        }
+        if entry.inode == 0 {
+            // Unused slot (deleted entry); keep walking the block
+            offset += rec_len;
+            continue;
+        }
+
+        // Safe bounds for name
+        let name_end = 8 + (entry.name_len as usize);
+        if name_end <= rec_len && offset + name_end <= block_size {
+            let name_slice = &block[offset + 8..offset + name_end];
+
+            // Compare with requested filename
+            if let Ok(name_str) = core::str::from_utf8(name_slice) {
+                if name_str == filename {
+                    return Ok(Some(entry.inode));
+                }
+            }
+        }
+
+        offset += rec_len;
+    }
+
+    Ok(None)
+}
+
 fn find_file_in_directory(dir_inode: &Ext2Inode, filename: &str) -> Result<u32, &'static str> {
-    let block_size = unsafe { BLOCK_SIZE };
+    let block_size = block_size();
     let mut block_buf = [0u8; 4096];
 
-    // Scan direct blocks (0..=11)
-    for &block_num in &dir_inode.block[..12] {
-        if block_num == 0 {
+    // Indexed (HTree) directory: go straight to the leaf holding the hash
+    if (dir_inode.flags & EXT2_INDEX_FL) != 0 {
+        if let Ok(leaf) = htree_find_leaf(dir_inode, filename) {
+            let phys = map_logical_block(dir_inode, leaf)?;
+            if phys != 0 {
+                read_block(phys, &mut block_buf)?;
+                if let Some(ino) = scan_dir_block(&block_buf[..block_size], filename)? {
+                    return Ok(ino);
+                }
+            }
+        }
+        // Fall through: the linear scan below also works on indexed dirs,
+        // since index blocks look like a single empty dirent.
+    }
+
+    // Every data block, whether block-mapped (direct or indirect) or
+    // extent-mapped, like `DirIter`
+    let block_count = (dir_inode.size as usize).div_ceil(block_size) as u32;
+    for lblk in 0..block_count {
+        let phys = map_logical_block(dir_inode, lblk)?;
+        if phys == 0 {
             continue;
         }
 
-        read_block(block_num, &mut block_buf)?;
-        let mut offset = 0usize;
+        read_block(phys, &mut block_buf)?;
+        if let Some(ino) = scan_dir_block(&block_buf[..block_size], filename)? {
+            return Ok(ino);
+        }
+    }
+
+    Err("File not found")
+}
 
-        while offset + 8 <= block_size {
-            let entry = read_dir_entry(&block_buf[..block_size], offset)?;
+// ===== Directory listing =====
 
-            if entry.inode == 0 || entry.rec_len == 0 {
-                break;
+pub const EXT2_NAME_LEN: usize = 255;
+
+#[derive(Copy, Clone)]
+pub struct DirEntry {
+    pub inode: u32,
+    pub name: [u8; EXT2_NAME_LEN],
+    pub name_len: usize,
+    /// `EXT2_FT_*`; 0 (unknown) on filesystems without the FILETYPE feature
+    pub file_type: u8,
+}
+
+impl DirEntry {
+    /// Name as UTF-8, `""` if it is not valid UTF-8.
+    pub fn name_str(&self) -> &str {
+        core::str::from_utf8(&self.name[..self.name_len]).unwrap_or("")
+    }
+}
+
+/// Entries of one directory, block by block. A read error ends the listing.
+pub struct DirIter<'a> {
+    path: &'a str,
+    inode: Ext2Inode,
+    block_count: u32,
+    next_block: u32,
+    block_buf: [u8; 4096],
+    /// Byte offset in `block_buf`; `None` before the first block is read
+    offset: Option<usize>,
+}
+
+/// List the directory at `path` (symlinks followed). Empty (`inode == 0`)
+/// slots are skipped; `.` and `..` are included.
+pub fn read_dir(path: &str) -> Result<DirIter<'_>, &'static str> {
+    let inode = get_inode(resolve_path(path, true)?)?;
+    if (inode.mode & EXT2_S_IFMT) != EXT2_S_IFDIR {
+        return Err("Not a directory");
+    }
+    let block_count = (inode.size as usize).div_ceil(block_size()) as u32;
+    Ok(DirIter { path, inode, block_count, next_block: 0, block_buf: [0u8; 4096], offset: None })
+}
+
+impl DirIter<'_> {
+    pub fn path(&self) -> &str {
+        self.path
+    }
+
+    /// Load the next non-hole directory block. `false` at the end.
+    fn advance_block(&mut self) -> bool {
+        while self.next_block < self.block_count {
+            let lblk = self.next_block;
+            self.next_block += 1;
+            let phys = match map_logical_block(&self.inode, lblk) {
+                Ok(0) => continue,
+                Ok(p) => p,
+                Err(_) => return false,
+            };
+            if read_block(phys, &mut self.block_buf).is_err() {
+                return false;
             }
+            self.offset = Some(0);
+            return true;
+        }
+        false
+    }
+}
+
+impl Iterator for DirIter<'_> {
+    type Item = DirEntry;
+
+    fn next(&mut self) -> Option<DirEntry> {
+        let block_size = block_size();
+        loop {
+            let offset = match self.offset {
+                Some(off) if off + 8 <= block_size => off,
+                _ => {
+                    if !self.advance_block() {
+                        return None;
+                    }
+                    continue;
+                }
+            };
+
+            let entry = read_dir_entry(&self.block_buf[..block_size], offset).ok()?;
             let rec_len = entry.rec_len as usize;
             if rec_len < 8 || offset + rec_len > block_size {
-                // Corrupted dir entry; stop scanning this block
-                break;
+                // Corrupted dir entry; skip the rest of this block
+                self.offset = None;
+                continue;
             }
+            self.offset = Some(offset + rec_len);
 
-            // Safe bounds for name
-            let name_end = 8 + (entry.name_len as usize);
-            if name_end <= rec_len && offset + name_end <= block_size {
-                let name_slice = &block_buf[offset + 8..offset + name_end];
-
-                // Compare with requested filename
-                if let Ok(name_str) = core::str::from_utf8(name_slice) {
-                    if name_str == filename {
-                        return Ok(entry.inode);
-                    }
-                }
+            let name_len = entry.name_len as usize;
+            if entry.inode == 0 || 8 + name_len > rec_len {
+                continue;
             }
+            let mut out = DirEntry { inode: entry.inode, name: [0; EXT2_NAME_LEN], name_len, file_type: entry.file_type };
+            out.name[..name_len].copy_from_slice(&self.block_buf[offset + 8..offset + 8 + name_len]);
+            return Some(out);
+        }
+    }
+}
 
-            offset += rec_len;
+/// Read the `index`-th little-endian u32 from a block of pointers.
+fn block_ptr(buf: &[u8], index: usize) -> u32 {
+    let p = index * 4;
+    u32::from_le_bytes([buf[p], buf[p + 1], buf[p + 2], buf[p + 3]])
+}
+
+/// Translate a file-relative (logical) block number into a physical block.
+/// Returns 0 for holes.
+fn map_logical_block(inode: &Ext2Inode, lblk: u32) -> Result<u32, &'static str> {
+    if uses_extents(inode) {
+        return extent_map_block(inode, lblk);
+    }
+    let ptrs_per_block = (block_size() / 4) as u32;
+    let mut ind_block = [0u8; 4096];
+    let mut lblk = lblk;
+
+    if lblk < 12 {
+        return Ok(inode.block[lblk as usize]);
+    }
+    lblk -= 12;
+
+    if lblk < ptrs_per_block {
+        if inode.block[12] == 0 {
+            return Ok(0);
         }
+        read_block(inode.block[12], &mut ind_block)?;
+        return Ok(block_ptr(&ind_block, lblk as usize));
     }
+    lblk -= ptrs_per_block;
 
-    // Scan single-indirect directory data if present
-    if dir_inode.block[12] != 0 {
-        let mut ind_block = [0u8; 4096];
-        read_block(dir_inode.block[12], &mut ind_block)?;
+    if lblk < ptrs_per_block * ptrs_per_block {
+        if inode.block[13] == 0 {
+            return Ok(0);
+        }
+        read_block(inode.block[13], &mut ind_block)?;
+        let ind = block_ptr(&ind_block, (lblk / ptrs_per_block) as usize);
+        if ind == 0 {
+            return Ok(0);
+        }
+        read_block(ind, &mut ind_block)?;
+        return Ok(block_ptr(&ind_block, (lblk % ptrs_per_block) as usize));
+    }
+    lblk -= ptrs_per_block * ptrs_per_block;
 
-        let ptrs_per_block = block_size / 4;
-        let mut pi = 0usize;
+    let per_double = ptrs_per_block * ptrs_per_block;
+    if (lblk / per_double) < ptrs_per_block {
+        if inode.block[14] == 0 {
+            return Ok(0);
+        }
+        read_block(inode.block[14], &mut ind_block)?;
+        let ind2 = block_ptr(&ind_block, (lblk / per_double) as usize);
+        if ind2 == 0 {
+            return Ok(0);
+        }
+        read_block(ind2, &mut ind_block)?;
+        let ind = block_ptr(&ind_block, ((lblk % per_double) / ptrs_per_block) as usize);
+        if ind == 0 {
+            return Ok(0);
+        }
+        read_block(ind, &mut ind_block)?;
+        return Ok(block_ptr(&ind_block, (lblk % ptrs_per_block) as usize));
+    }
 
-        while pi < ptrs_per_block {
-            let p = pi * 4;
-            let ptr = u32::from_le_bytes([
-                ind_block[p],
-                ind_block[p + 1],
-                ind_block[p + 2],
-                ind_block[p + 3],
-            ]);
-            if ptr == 0 {
-                break;
-            }
+    Err("logical block beyond triple-indirect range")
+}
 
-            read_block(ptr, &mut block_buf)?;
-            let mut offset = 0usize;
+// ===== Extent trees (ext4) =====
+
+const EXT4_EXTENTS_FL: u32 = 0x80000;
+const EXT4_EXT_MAGIC: u16 = 0xF30A;
+const EXT4_EXT_MAX_DEPTH: u16 = 5;
+const EXT4_EXT_ENTRY_SIZE: usize = 12; // header, index and leaf entries alike
+/// ee_len above this marks an uninitialized (reads as zero) extent
+const EXT4_EXT_INIT_MAX_LEN: u16 = 32768;
+
+/// One leaf extent: `len` blocks from `logical` stored at `physical`.
+#[derive(Copy, Clone, Debug)]
+struct Extent {
+    logical: u32,
+    len: u32,
+    physical: u32,
+    uninit: bool,
+}
 
-            while offset + 8 <= block_size {
-                let entry = read_dir_entry(&block_buf[..block_size], offset)?;
+fn uses_extents(inode: &Ext2Inode) -> bool {
+    (inode.flags & EXT4_EXTENTS_FL) != 0
+}
 
-                if entry.inode == 0 || entry.rec_len == 0 {
-                    break;
-                }
-                let rec_len = entry.rec_len as usize;
-                if rec_len < 8 || offset + rec_len > block_size {
-                    // Corrupted dir entry; stop scanning this block
-                    break;
-                }
+fn le_u16_at(buf: &[u8], off: usize) -> u16 {
+    u16::from_le_bytes([buf[off], buf[off + 1]])
+}
 
-                // Safe bounds for name
-                let name_end = 8 + (entry.name_len as usize);
-                if name_end <= rec_len && offset + name_end <= block_size {
-                    let name_slice = &block_buf[offset + 8..offset + name_end];
+/// The 60-byte `i_block` area, where an extent inode keeps its tree root.
+fn inode_block_bytes(inode: &Ext2Inode) -> [u8; 60] {
+    let mut out = [0u8; 60];
+    let ptrs = inode.block;
+    for (chunk, ptr) in out.chunks_exact_mut(4).zip(ptrs.iter()) {
+        chunk.copy_from_slice(&ptr.to_le_bytes());
+    }
+    out
+}
 
-                    // Compare with requested filename
-                    if let Ok(name_str) = core::str::from_utf8(name_slice) {
-                        if name_str == filename {
-                            return Ok(entry.inode);
-                        }
-                    }
-                }
+/// Visit leaf extents in logical order. `f` returns `false` to stop; the walk
+/// then returns `Ok(false)` as well.
+fn walk_extents(
+    node: &[u8],
+    expected_depth: Option<u16>,
+    f: &mut dyn FnMut(Extent) -> Result<bool, &'static str>,
+) -> Result<bool, &'static str> {
+    if node.len() < EXT4_EXT_ENTRY_SIZE || le_u16_at(node, 0) != EXT4_EXT_MAGIC {
+        return Err("EXT4: bad extent header");
+    }
+    let entries = le_u16_at(node, 2) as usize;
+    let depth = le_u16_at(node, 6);
+    if depth > EXT4_EXT_MAX_DEPTH || expected_depth.is_some_and(|d| d != depth) {
+        return Err("EXT4: bad extent tree depth");
+    }
+    if EXT4_EXT_ENTRY_SIZE * (entries + 1) > node.len() {
+        return Err("EXT4: extent entries overflow node");
+    }
 
-                offset += rec_len;
+    for i in 0..entries {
+        let e = &node[EXT4_EXT_ENTRY_SIZE * (i + 1)..EXT4_EXT_ENTRY_SIZE * (i + 2)];
+        if depth == 0 {
+            let raw_len = le_u16_at(e, 4);
+            if le_u16_at(e, 6) != 0 {
+                return Err("EXT4: extent beyond 32-bit block range");
+            }
+            let uninit = raw_len > EXT4_EXT_INIT_MAX_LEN;
+            let extent = Extent {
+                logical: block_ptr(e, 0),
+                len: (if uninit { raw_len - EXT4_EXT_INIT_MAX_LEN } else { raw_len }) as u32,
+                physical: block_ptr(e, 2),
+                uninit,
+            };
+            if !f(extent)? {
+                return Ok(false);
+            }
+        } else {
+            if le_u16_at(e, 8) != 0 {
+                return Err("EXT4: extent index beyond 32-bit block range");
             }
+            let mut child = [0u8; 4096];
+            read_block(block_ptr(e, 1), &mut child)?;
+            if !walk_extents(&child[..block_size()], Some(depth - 1), f)? {
+                return Ok(false);
+            }
+        }
+    }
+    Ok(true)
+}
 
-            pi += 1;
+/// Physical block of logical block `lblk` of an extent inode, 0 for holes.
+fn extent_map_block(inode: &Ext2Inode, lblk: u32) -> Result<u32, &'static str> {
+    let root = inode_block_bytes(inode);
+    let mut phys = 0u32;
+    walk_extents(&root, None, &mut |e| {
+        if lblk >= e.logical && lblk - e.logical < e.len {
+            if !e.uninit {
+                phys = e.physical + (lblk - e.logical);
+            }
+            return Ok(false);
+        }
+        Ok(e.logical <= lblk)
+    })?;
+    Ok(phys)
+}
+
+/// Read an extent inode's data. Holes and uninitialized extents stay zero.
+fn read_extent_data(
+    inode: &Ext2Inode,
+    buffer: &mut FileBuffer,
+    file_size: usize,
+    progress: Option<u8>,
+) -> Result<(), &'static str> {
+    let block_size = block_size();
+    let root = inode_block_bytes(inode);
+    let mut data_block = [0u8; 4096];
+
+    walk_extents(&root, None, &mut |e| {
+        for i in 0..e.len {
+            let off = (e.logical as usize + i as usize) * block_size;
+            if off >= file_size {
+                return Ok(false);
+            }
+            let to_copy = core::cmp::min(block_size, file_size - off);
+            if e.uninit {
+                buffer.data[off..off + to_copy].fill(0);
+            } else {
+                read_block(e.physical + i, &mut data_block)?;
+                buffer.data[off..off + to_copy].copy_from_slice(&data_block[..to_copy]);
+            }
+            report_progress(progress, off + to_copy, file_size);
         }
+        Ok(true)
+    })?;
+
+    buffer.size = file_size;
+    Ok(())
+}
+
+// ===== HTree (dir_index) =====
+
+const EXT2_INDEX_FL: u32 = 0x1000;
+const DX_HASH_HALF_MD4: u8 = 1;
+const DX_HASH_HALF_MD4_UNSIGNED: u8 = 4;
+const DX_MAX_LEVELS: u8 = 3;
+
+/// Half-MD4 transform from the ext4 dir hash code.
+fn half_md4_transform(buf: &mut [u32; 4], input: &[u32; 8]) {
+    const K1: u32 = 0;
+    const K2: u32 = 0x5A82_7999;
+    const K3: u32 = 0x6ED9_EBA1;
+
+    let f = |x: u32, y: u32, z: u32| z ^ (x & (y ^ z));
+    let g = |x: u32, y: u32, z: u32| (x & y).wrapping_add((x ^ y) & z);
+    let h = |x: u32, y: u32, z: u32| x ^ y ^ z;
+
+    let [mut a, mut b, mut c, mut d] = *buf;
+
+    macro_rules! round {
+        ($f:ident, $a:ident, $b:ident, $c:ident, $d:ident, $x:expr, $s:expr) => {
+            $a = $a.wrapping_add($f($b, $c, $d)).wrapping_add($x);
+            $a = $a.rotate_left($s);
+        };
     }
 
-    Err("File not found")
+    round!(f, a, b, c, d, input[0].wrapping_add(K1), 3);
+    round!(f, d, a, b, c, input[1].wrapping_add(K1), 7);
+    round!(f, c, d, a, b, input[2].wrapping_add(K1), 11);
+    round!(f, b, c, d, a, input[3].wrapping_add(K1), 19);
+    round!(f, a, b, c, d, input[4].wrapping_add(K1), 3);
+    round!(f, d, a, b, c, input[5].wrapping_add(K1), 7);
+    round!(f, c, d, a, b, input[6].wrapping_add(K1), 11);
+    round!(f, b, c, d, a, input[7].wrapping_add(K1), 19);
+
+    round!(g, a, b, c, d, input[1].wrapping_add(K2), 3);
+    round!(g, d, a, b, c, input[3].wrapping_add(K2), 5);
+    round!(g, c, d, a, b, input[5].wrapping_add(K2), 9);
+    round!(g, b, c, d, a, input[7].wrapping_add(K2), 13);
+    round!(g, a, b, c, d, input[0].wrapping_add(K2), 3);
+    round!(g, d, a, b, c, input[2].wrapping_add(K2), 5);
+    round!(g, c, d, a, b, input[4].wrapping_add(K2), 9);
+    round!(g, b, c, d, a, input[6].wrapping_add(K2), 13);
+
+    round!(h, a, b, c, d, input[3].wrapping_add(K3), 3);
+    round!(h, d, a, b, c, input[7].wrapping_add(K3), 9);
+    round!(h, c, d, a, b, input[2].wrapping_add(K3), 11);
+    round!(h, b, c, d, a, input[6].wrapping_add(K3), 15);
+    round!(h, a, b, c, d, input[1].wrapping_add(K3), 3);
+    round!(h, d, a, b, c, input[5].wrapping_add(K3), 9);
+    round!(h, c, d, a, b, input[0].wrapping_add(K3), 11);
+    round!(h, b, c, d, a, input[4].wrapping_add(K3), 15);
+
+    buf[0] = buf[0].wrapping_add(a);
+    buf[1] = buf[1].wrapping_add(b);
+    buf[2] = buf[2].wrapping_add(c);
+    buf[3] = buf[3].wrapping_add(d);
+}
+
+/// Pack up to `num * 4` name bytes into hash input words (ext4 `str2hashbuf`).
+fn str2hashbuf(msg: &[u8], len: usize, out: &mut [u32; 8], unsigned: bool) {
+    let mut pad = (len as u32) | ((len as u32) << 8);
+    pad |= pad << 16;
+
+    let mut val = pad;
+    let mut num = out.len() as isize;
+    let take = core::cmp::min(len, out.len() * 4);
+    let mut w = 0usize;
+    for (i, &b) in msg[..take].iter().enumerate() {
+        let c = if unsigned { b as u32 } else { b as i8 as i32 as u32 };
+        val = c.wrapping_add(val << 8);
+        if i % 4 == 3 {
+            out[w] = val;
+            w += 1;
+            val = pad;
+            num -= 1;
+        }
+    }
+    num -= 1;
+    if num >= 0 {
+        out[w] = val;
+        w += 1;
+    }
+    while w < out.len() {
+        out[w] = pad;
+        w += 1;
+    }
+}
+
+/// ext4 half-MD4 directory hash of `name` (major hash, low bit cleared).
+fn dx_hash_half_md4(name: &[u8], seed: [u32; 4], unsigned: bool) -> u32 {
+    let mut buf = if seed.iter().any(|&v| v != 0) {
+        seed
+    } else {
+        [0x6745_2301, 0xEFCD_AB89, 0x98BA_DCFE, 0x1032_5476]
+    };
+
+    let mut input = [0u32; 8];
+    let mut p = 0usize;
+    let mut len = name.len() as isize;
+    while len > 0 {
+        str2hashbuf(&name[p..], len as usize, &mut input, unsigned);
+        half_md4_transform(&mut buf, &input);
+        len -= 32;
+        p += 32;
+    }
+
+    let mut hash = buf[1] & !1;
+    if hash == (0x7FFF_FFFF << 1) {
+        hash = (0x7FFF_FFFF - 1) << 1;
+    }
+    hash
+}
+
+/// In an index block, return the child block of the last entry whose hash
+/// is <= `hash`. `entries` is the byte offset of the count/limit header.
+fn dx_pick_child(block: &[u8], entries: usize, hash: u32) -> Result<u32, &'static str> {
+    if entries + 8 > block.len() {
+        return Err("dx: entries out of range");
+    }
+    let limit = u16::from_le_bytes([block[entries], block[entries + 1]]) as usize;
+    let count = u16::from_le_bytes([block[entries + 2], block[entries + 3]]) as usize;
+    if count == 0 || count > limit || entries + count * 8 > block.len() {
+        return Err("dx: bad count/limit");
+    }
+
+    // Entry 0 has an implicit hash of 0; its hash slot holds count/limit.
+    let mut child = block_ptr(&block[entries + 4..], 0);
+    for i in 1..count {
+        let e = entries + i * 8;
+        let e_hash = block_ptr(&block[e..], 0);
+        if e_hash > hash {
+            break;
+        }
+        child = block_ptr(&block[e + 4..], 0);
+    }
+    Ok(child)
+}
+
+/// Walk the HTree of `dir_inode` and return the logical leaf block that
+/// should contain `filename`.
+fn htree_find_leaf(dir_inode: &Ext2Inode, filename: &str) -> Result<u32, &'static str> {
+    let block_size = block_size();
+    let sb = superblock()?;
+    let mut block_buf = [0u8; 4096];
+
+    let root = map_logical_block(dir_inode, 0)?;
+    if root == 0 {
+        return Err("dx: no root block");
+    }
+    read_block(root, &mut block_buf)?;
+    let block = &block_buf[..block_size];
+
+    // dx_root: "." (12 bytes) + ".." (header 12 bytes) + dx_root_info (8 bytes)
+    let reserved_zero = block_ptr(&block[24..], 0);
+    let hash_version = block[28];
+    let info_length = block[29] as usize;
+    let levels = block[30];
+    if reserved_zero != 0 || info_length != 8 || levels >= DX_MAX_LEVELS {
+        return Err("dx: bad root header");
+    }
+    if hash_version != DX_HASH_HALF_MD4 && hash_version != DX_HASH_HALF_MD4_UNSIGNED {
+        return Err("dx: unsupported hash version");
+    }
+
+    // Names are hashed as signed chars unless the unsigned variant is used;
+    // the two only differ for non-ASCII names.
+    let unsigned = hash_version == DX_HASH_HALF_MD4_UNSIGNED;
+    let hash = dx_hash_half_md4(filename.as_bytes(), sb.hash_seed, unsigned);
+
+    let mut child = dx_pick_child(block, 24 + info_length, hash)?;
+    for _ in 0..levels {
+        let phys = map_logical_block(dir_inode, child)?;
+        if phys == 0 {
+            return Err("dx: hole in index");
+        }
+        read_block(phys, &mut block_buf)?;
+        // dx_node: one fake empty dirent (8 bytes) then count/limit
+        child = dx_pick_child(&block_buf[..block_size], 8, hash)?;
+    }
+    Ok(child)
+}
+
+/// Full 64-bit size of `inode`. With LARGE_FILE on a rev >= 1 filesystem,
+/// regular files keep the upper 32 bits in `dir_acl` (i_size_high).
+pub fn inode_file_size(inode: &Ext2Inode, sb: &Ext2Superblock) -> u64 {
+    let low = inode.size as u64;
+    let large_file = sb.rev_level >= 1
+        && (sb.feature_ro_compat & EXT2_FEATURE_RO_COMPAT_LARGE_FILE) != 0;
+    if large_file && (inode.mode & 0xF000) == EXT2_S_IFREG {
+        low | ((inode.dir_acl as u64) << 32)
+    } else {
+        low
+    }
 }
 
-fn read_inode_data(inode: &Ext2Inode, buffer: &mut FileBuffer) -> Result<(), &'static str> {
-    let block_size = unsafe { BLOCK_SIZE };
-    let file_size = inode.size as usize;
+/// Update the progress bar on `row` (if any) after a block has been copied.
+fn report_progress(progress: Option<u8>, done: usize, total: usize) {
+    if let Some(row) = progress {
+        drivers::vga::draw_progress_bar(row, done as u64, total as u64);
+    }
+}
 
-    if file_size > MAX_FILE_SIZE {
+fn read_inode_data(
+    inode: &Ext2Inode,
+    buffer: &mut FileBuffer,
+    progress: Option<u8>,
+) -> Result<(), &'static str> {
+    let block_size = block_size();
+    let superblock = superblock()?;
+    let full_size = inode_file_size(inode, superblock);
+
+    if full_size > MAX_FILE_SIZE as u64 {
         return Err("File too large");
     }
+    let file_size = full_size as usize;
+
+    // Extent-mapped inodes (ext4) have no indirect blocks
+    if uses_extents(inode) {
+        return read_extent_data(inode, buffer, file_size, progress);
+    }
 
     let mut bytes_read = 0usize;
     let mut data_block = [0u8; 4096];
 
-    // Read direct blocks (0..=11)
-    for &block_num in &inode.block[..12] {
+    // Read direct blocks (0..=11); copied out of the packed inode first
+    let direct = inode.block;
+    for &block_num in &direct[..12] {
         if block_num == 0 || bytes_read >= file_size {
             break;
         }
@@ -428,6 +1146,7 @@ fn read_inode_data(inode: &Ext2Inode, buffer: &mut FileBuffer) -> Result<(), &'s
         let to_copy = core::cmp::min(block_size, file_size - bytes_read);
         buffer.data[bytes_read..bytes_read + to_copy].copy_from_slice(&data_block[..to_copy]);
         bytes_read += to_copy;
+        report_progress(progress, bytes_read, file_size);
     }
 
     if bytes_read >= file_size {
@@ -460,6 +1179,7 @@ fn read_inode_data(inode: &Ext2Inode, buffer: &mut FileBuffer) -> Result<(), &'s
             let to_copy = core::cmp::min(block_size, file_size - bytes_read);
             buffer.data[bytes_read..bytes_read + to_copy].copy_from_slice(&data_block[..to_copy]);
             bytes_read += to_copy;
+            report_progress(progress, bytes_read, file_size);
 
             pi += 1;
         }
@@ -508,6 +1228,61 @@ fn read_inode_data(inode: &Ext2Inode, buffer: &mut FileBuffer) -> Result<(), &'s
                 buffer.data[bytes_read..bytes_read + to_copy]
                     .copy_from_slice(&data_block[..to_copy]);
                 bytes_read += to_copy;
+                report_progress(progress, bytes_read, file_size);
+
+                j += 1;
+            }
+
+            i += 1;
+        }
+    }
+
+    // Triple-indirect (block[14])
+    if bytes_read < file_size && inode.block[14] != 0 {
+        let mut ind3_block = [0u8; 4096];
+        read_block(inode.block[14], &mut ind3_block)?;
+
+        let ptrs_per_block = block_size / 4;
+        let mut i = 0usize;
+
+        while i < ptrs_per_block && bytes_read < file_size {
+            let ptr1 = block_ptr(&ind3_block, i);
+            if ptr1 == 0 {
+                break;
+            }
+
+            // Double-indirect block pointed by ptr1
+            let mut ind2_block = [0u8; 4096];
+            read_block(ptr1, &mut ind2_block)?;
+
+            let mut j = 0usize;
+            while j < ptrs_per_block && bytes_read < file_size {
+                let ptr2 = block_ptr(&ind2_block, j);
+                if ptr2 == 0 {
+                    break;
+                }
+
+                // Single-indirect block pointed by ptr2
+                let mut ind_block = [0u8; 4096];
+                read_block(ptr2, &mut ind_block)?;
+
+                let mut k = 0usize;
+                while k < ptrs_per_block && bytes_read < file_size {
+                    let ptr3 = block_ptr(&ind_block, k);
+                    if ptr3 == 0 {
+                        break;
+                    }
+
+                    read_block(ptr3, &mut data_block)?;
+
+                    let to_copy = core::cmp::min(block_size, file_size - bytes_read);
+                    buffer.data[bytes_read..bytes_read + to_copy]
+                        .copy_from_slice(&data_block[..to_copy]);
+                    bytes_read += to_copy;
+                    report_progress(progress, bytes_read, file_size);
+
+                    k += 1;
+                }
 
                 j += 1;
             }
@@ -517,8 +1292,7 @@ fn read_inode_data(inode: &Ext2Inode, buffer: &mut FileBuffer) -> Result<(), &'s
     }
 
     if bytes_read < file_size {
-        // Triple-indirect not implemented
-        return Err("Large files not fully supported (needs triple indirect)");
+        return Err("File data ends before its size (sparse or truncated block map)");
     }
 
     buffer.size = bytes_read;
@@ -527,52 +1301,179 @@ fn read_inode_data(inode: &Ext2Inode, buffer: &mut FileBuffer) -> Result<(), &'s
 
 /// Read a file by absolute POSIX-like path (e.g., "/boot/vmlinuz") from the EXT filesystem.
 pub fn read_file(path: &str) -> Result<FileBuffer, &'static str> {
-    if !path.starts_with('/') {
-        return Err("Path must be absolute");
+    read_file_impl(path, None)
+}
+
+/// Same as `read_file`, but draws a progress bar on VGA row `row` while the
+/// file data is being read. Useful for large kernels over ATA PIO.
+pub fn read_file_with_progress(path: &str, row: u8) -> Result<FileBuffer, &'static str> {
+    read_file_impl(path, Some(row))
+}
+
+fn read_file_impl(path: &str, progress: Option<u8>) -> Result<FileBuffer, &'static str> {
+    let file_inode = get_inode(resolve_path(path, true)?)?;
+
+    // Ensure it's a regular file
+    if (file_inode.mode & EXT2_S_IFMT) != EXT2_S_IFREG {
+        return Err("Not a regular file");
     }
 
-    let mut current_inode_num = 2; // Root directory is always inode 2
+    let mut file_buffer = FileBuffer::new();
+    read_inode_data(&file_inode, &mut file_buffer, progress)?;
 
-    // Split path and traverse directories
-    let mut start = 1; // skip leading '/'
-    let bytes = path.as_bytes();
-    let mut i = 1;
-    let len = bytes.len();
+    Ok(file_buffer)
+}
 
-    while i <= len {
-        if i == len || bytes[i] == b'/' {
-            if i > start {
-                // component = &path[start..i]
-                let component = match core::str::from_utf8(&bytes[start..i]) {
-                    Ok(s) => s,
-                    Err(_) => return Err("Invalid path encoding"),
-                };
+// ===== Path resolution and symlinks =====
 
-                // Get current inode and ensure directory except for last check is skipped;
-                let inode = get_inode(current_inode_num)?;
+const PATH_MAX: usize = 4096;
+const MAX_SYMLINK_HOPS: u32 = 8;
+/// Targets shorter than this live in `inode.block` itself (fast symlinks).
+const FAST_SYMLINK_MAX: usize = 60;
 
-                // If there are more components after this one, require directory
-                if i < len && (inode.mode & EXT2_S_IFDIR) == 0 {
-                    return Err("Not a directory");
-                }
+/// Walk an absolute path to its inode number. Symlinks in directory
+/// components are always followed; the last component only if `follow_last`.
+fn resolve_path(path: &str, follow_last: bool) -> Result<u32, &'static str> {
+    if !path.starts_with('/') {
+        return Err("Path must be absolute");
+    }
+
+    let mut work: FixedString<PATH_MAX> = FixedString::new();
+    work.push_str(path)?;
+    let mut hops = 0;
+
+    'restart: loop {
+        let mut current_inode_num = 2; // Root directory is always inode 2
+        let bytes = work.as_bytes();
+        let len = bytes.len();
+        let mut start = 1; // skip leading '/'
+        let mut i = 1;
+
+        while i <= len {
+            if i == len || bytes[i] == b'/' {
+                if i > start {
+                    let component = match core::str::from_utf8(&bytes[start..i]) {
+                        Ok(s) => s,
+                        Err(_) => return Err("Invalid path encoding"),
+                    };
+
+                    let inode = get_inode(current_inode_num)?;
+                    if (inode.mode & EXT2_S_IFMT) != EXT2_S_IFDIR {
+                        return Err("Not a directory");
+                    }
+                    let next = find_file_in_directory(&inode, component)?;
+
+                    let is_last = bytes[i..].iter().all(|&b| b == b'/');
+                    let next_inode = get_inode(next)?;
+                    if (next_inode.mode & EXT2_S_IFMT) == EXT2_S_IFLNK && (!is_last || follow_last) {
+                        hops += 1;
+                        if hops > MAX_SYMLINK_HOPS {
+                            return Err("Too many levels of symbolic links");
+                        }
+                        let target = read_link_target(&next_inode)?;
 
-                current_inode_num = find_file_in_directory(&inode, component)?;
+                        // Splice the target in place of the link component
+                        let mut spliced: FixedString<PATH_MAX> = FixedString::new();
+                        if !target.as_str().starts_with('/') {
+                            spliced.push_bytes(&bytes[..start])?;
+                        }
+                        spliced.push_str(target.as_str())?;
+                        spliced.push_bytes(&bytes[i..])?;
+                        work = spliced;
+                        continue 'restart;
+                    }
+                    current_inode_num = next;
+                }
+                start = i + 1;
             }
-            start = i + 1;
+            i += 1;
         }
-        i += 1;
+
+        return Ok(current_inode_num);
     }
+}
 
-    // Read final inode
-    let file_inode = get_inode(current_inode_num)?;
+/// Read the target of a symlink inode, inline or from its first data block.
+fn read_link_target(inode: &Ext2Inode) -> Result<FixedString<PATH_MAX>, &'static str> {
+    let size = inode.size as usize;
+    if size == 0 || size >= PATH_MAX {
+        return Err("Invalid symlink length");
+    }
 
-    // Ensure it's a regular file
-    if (file_inode.mode & EXT2_S_IFREG) == 0 {
-        return Err("Not a regular file");
+    let mut target = FixedString::new();
+    if inode.blocks == 0 {
+        if size >= FAST_SYMLINK_MAX {
+            return Err("Invalid fast symlink length");
+        }
+        let mut inline = [0u8; FAST_SYMLINK_MAX];
+        let ptrs = inode.block;
+        for (chunk, ptr) in inline.chunks_exact_mut(4).zip(ptrs.iter()) {
+            chunk.copy_from_slice(&ptr.to_le_bytes());
+        }
+        target.push_bytes(&inline[..size]).map_err(|_| "Symlink target is not UTF-8")?;
+    } else {
+        if size > block_size() {
+            return Err("Symlink target larger than a block");
+        }
+        let phys = map_logical_block(inode, 0)?;
+        if phys == 0 {
+            return Err("Symlink has no data block");
+        }
+        let mut block_buf = [0u8; 4096];
+        read_block(phys, &mut block_buf)?;
+        target.push_bytes(&block_buf[..size]).map_err(|_| "Symlink target is not UTF-8")?;
     }
+    Ok(target)
+}
 
-    let mut file_buffer = FileBuffer::new();
-    read_inode_data(&file_inode, &mut file_buffer)?;
+/// Return the target of the symlink at `path` (the link itself is not followed).
+pub fn readlink(path: &str) -> Result<FixedString<4096>, &'static str> {
+    let inode = get_inode(resolve_path(path, false)?)?;
+    if (inode.mode & EXT2_S_IFMT) != EXT2_S_IFLNK {
+        return Err("Not a symbolic link");
+    }
+    read_link_target(&inode)
+}
 
-    Ok(file_buffer)
+// ===== Filesystem trait =====
+
+/// Handle for the mounted EXT filesystem (state lives in the module statics).
+pub struct ExtFs;
+
+impl super::Filesystem for ExtFs {
+    fn read_file(&mut self, path: &str, buf: &mut [u8]) -> Result<usize, &'static str> {
+        let file = read_file(path)?;
+        let data = file.as_slice();
+        if data.len() > buf.len() {
+            return Err("Buffer too small for file");
+        }
+        buf[..data.len()].copy_from_slice(data);
+        Ok(data.len())
+    }
+
+    fn write_file(&mut self, path: &str, data: &[u8]) -> Result<(), &'static str> {
+        let inode = get_inode(resolve_path(path, true)?)?;
+        if (inode.mode & EXT2_S_IFMT) != EXT2_S_IFREG {
+            return Err("Not a regular file");
+        }
+        if inode_file_size(&inode, superblock()?) != data.len() as u64 {
+            return Err("EXT: in-place write must keep the file size");
+        }
+
+        let block_size = block_size();
+        let mut block_buf = [0u8; 4096];
+        for (lblk, chunk) in data.chunks(block_size).enumerate() {
+            let phys = map_logical_block(&inode, lblk as u32)?;
+            if phys == 0 {
+                return Err("EXT: cannot write into a hole");
+            }
+            // Keep whatever follows the end of the file in its last block
+            if chunk.len() < block_size {
+                read_block(phys, &mut block_buf)?;
+            }
+            block_buf[..chunk.len()].copy_from_slice(chunk);
+            write_block(phys, &block_buf)?;
+        }
+        Ok(())
+    }
 }
@@ -1,4 +1,510 @@
+//! FAT32 driver: reads files, and rewrites existing ones in place.
+//!
+//! Only 512-byte sectors are handled; names are matched against VFAT long
+//! names and 8.3 short names. Sectors are read one at a time from the boot
+//! disk (`drivers::block`).
+
+use super::FileBuffer;
+use crate::drivers;
+use crate::util::once::OnceCell;
+
+// ===== On-disk structures =====
+
+/// 32-byte short-name directory entry.
+#[repr(C, packed)]
+#[derive(Copy, Clone)]
+pub struct FatDirEntry {
+    pub name: [u8; 11],
+    pub attr: u8,
+    pub nt_res: u8,
+    pub crt_time_tenth: u8,
+    pub crt_time: u16,
+    pub crt_date: u16,
+    pub lst_acc_date: u16,
+    pub fst_clus_hi: u16,
+    pub wrt_time: u16,
+    pub wrt_date: u16,
+    pub fst_clus_lo: u16,
+    pub file_size: u32,
+}
+
+impl FatDirEntry {
+    /// Last modification time as `YYYY-MM-DD HH:MM:SS`.
+    pub fn mtime_str<'a>(&self, buf: &'a mut [u8; 20]) -> &'a str {
+        let (year, month, day) = parse_fat_date(self.wrt_date);
+        let (hour, minute, second) = parse_fat_time(self.wrt_time);
+
+        let mut put = |at: usize, v: u16, digits: usize| {
+            let mut v = v;
+            for i in (0..digits).rev() {
+                buf[at + i] = b'0' + (v % 10) as u8;
+                v /= 10;
+            }
+        };
+        put(0, year, 4);
+        put(5, month as u16, 2);
+        put(8, day as u16, 2);
+        put(11, hour as u16, 2);
+        put(14, minute as u16, 2);
+        put(17, second as u16, 2);
+        buf[4] = b'-';
+        buf[7] = b'-';
+        buf[10] = b' ';
+        buf[13] = b':';
+        buf[16] = b':';
+
+        // Only ASCII digits and separators were written
+        core::str::from_utf8(&buf[..19]).unwrap_or("")
+    }
+}
+
+// ===== Date/time fields =====
+
+/// Decode a FAT date into `(year, month, day)`.
+pub fn parse_fat_date(date: u16) -> (u16, u8, u8) {
+    let year = 1980 + (date >> 9);
+    let month = ((date >> 5) & 0x0F) as u8;
+    let day = (date & 0x1F) as u8;
+    (year, month, day)
+}
+
+/// Decode a FAT time into `(hour, minute, second)`; seconds have 2 s resolution.
+pub fn parse_fat_time(time: u16) -> (u8, u8, u8) {
+    let hour = (time >> 11) as u8;
+    let minute = ((time >> 5) & 0x3F) as u8;
+    let second = ((time & 0x1F) * 2) as u8;
+    (hour, minute, second)
+}
+
+// ===== Global filesystem state =====
+
+const SECTOR_SIZE: usize = 512;
+const DIR_ENTRY_SIZE: usize = 32;
+const FAT32_EOC: u32 = 0x0FFF_FFF8;
+const FAT32_BAD: u32 = 0x0FFF_FFF7;
+const FAT32_MASK: u32 = 0x0FFF_FFFF;
+
+const ATTR_VOLUME_ID: u8 = 0x08;
+const ATTR_DIRECTORY: u8 = 0x10;
+const ATTR_LONG_NAME: u8 = 0x0F;
+const ENTRY_FREE: u8 = 0xE5;
+const ENTRY_END: u8 = 0x00;
+
+struct FatState {
+    sectors_per_cluster: u32,
+    fat_start_lba: u64,
+    data_start_lba: u64,
+    root_cluster: u32,
+    cluster_count: u32,
+}
+
+static STATE: OnceCell<FatState> = OnceCell::new();
+
+fn state() -> Result<&'static FatState, &'static str> {
+    STATE.get().ok_or("FAT filesystem not initialized")
+}
+
+fn le_u16(b: &[u8], off: usize) -> u16 {
+    u16::from_le_bytes([b[off], b[off + 1]])
+}
+
+fn le_u32(b: &[u8], off: usize) -> u32 {
+    u32::from_le_bytes([b[off], b[off + 1], b[off + 2], b[off + 3]])
+}
+
+// ===== Public init API =====
+
+pub fn is_mounted() -> bool {
+    STATE.get().is_some()
+}
+
+/// Initialize the FAT reader assuming the filesystem starts at absolute LBA 0.
 pub fn init() -> Result<(), &'static str> {
-    // FAT filesystem initialization
-    Err("FAT not implemented yet")
+    init_with_lba(0)
+}
+
+/// Mount the FAT32 volume whose boot sector is at `lba_base`.
+pub fn init_with_lba(lba_base: u64) -> Result<(), &'static str> {
+    if STATE.get().is_some() {
+        return Err("FAT filesystem already mounted");
+    }
+
+    let mut bpb = [0u8; SECTOR_SIZE];
+    drivers::block::read_sectors(lba_base, 1, &mut bpb)?;
+    if bpb[510] != 0x55 || bpb[511] != 0xAA {
+        return Err("FAT: missing 55 AA boot sector signature");
+    }
+
+    let bytes_per_sector = le_u16(&bpb, 11) as usize;
+    let sectors_per_cluster = bpb[13] as u32;
+    let reserved_sectors = le_u16(&bpb, 14) as u32;
+    let num_fats = bpb[16] as u32;
+    let fat_size16 = le_u16(&bpb, 22);
+    let total_sectors16 = le_u16(&bpb, 19) as u32;
+    let total_sectors32 = le_u32(&bpb, 32);
+    let fat_size = le_u32(&bpb, 36);
+    let root_cluster = le_u32(&bpb, 44);
+
+    if bytes_per_sector != SECTOR_SIZE {
+        return Err("FAT: only 512-byte sectors are supported");
+    }
+    if sectors_per_cluster == 0 || !sectors_per_cluster.is_power_of_two() {
+        return Err("FAT: bad sectors per cluster");
+    }
+    if num_fats == 0 || reserved_sectors == 0 {
+        return Err("FAT: bad BPB");
+    }
+    if fat_size16 != 0 || fat_size == 0 {
+        return Err("FAT: not a FAT32 volume");
+    }
+    if root_cluster < 2 {
+        return Err("FAT: bad root cluster");
+    }
+
+    let total_sectors = if total_sectors16 != 0 { total_sectors16 } else { total_sectors32 };
+    let fat_start_lba = lba_base + reserved_sectors as u64;
+    let data_offset = reserved_sectors + num_fats * fat_size;
+    let data_start_lba = lba_base + data_offset as u64;
+    let cluster_count = total_sectors.saturating_sub(data_offset) / sectors_per_cluster;
+
+    STATE
+        .set(FatState {
+            sectors_per_cluster,
+            fat_start_lba,
+            data_start_lba,
+            root_cluster,
+            cluster_count,
+        })
+        .map_err(|_| "FAT filesystem already mounted")?;
+
+    drivers::vga::print_string("FAT32 filesystem initialized\n");
+    Ok(())
+}
+
+// ===== Cluster chains =====
+
+/// Remembers the last FAT sector read while walking a chain.
+struct FatCache {
+    lba: u64,
+    sector: [u8; SECTOR_SIZE],
+}
+
+impl FatCache {
+    fn new() -> Self {
+        Self { lba: u64::MAX, sector: [0; SECTOR_SIZE] }
+    }
+}
+
+/// Next cluster after `cluster`, or `None` at the end of the chain.
+fn next_cluster(cache: &mut FatCache, cluster: u32) -> Result<Option<u32>, &'static str> {
+    let st = state()?;
+    let offset = cluster as usize * 4;
+    let lba = st.fat_start_lba + (offset / SECTOR_SIZE) as u64;
+    if cache.lba != lba {
+        drivers::block::read_sectors(lba, 1, &mut cache.sector)?;
+        cache.lba = lba;
+    }
+    let next = le_u32(&cache.sector, offset % SECTOR_SIZE) & FAT32_MASK;
+    match next {
+        n if n >= FAT32_EOC => Ok(None),
+        FAT32_BAD => Err("FAT: bad cluster in chain"),
+        n if n < 2 || n - 2 >= st.cluster_count => Err("FAT: cluster out of range"),
+        n => Ok(Some(n)),
+    }
+}
+
+fn cluster_lba(st: &FatState, cluster: u32) -> u64 {
+    st.data_start_lba + (cluster - 2) as u64 * st.sectors_per_cluster as u64
+}
+
+/// Call `f` with the LBA and contents of every sector of the chain starting
+/// at `first`; stop early when it returns `false`.
+fn for_each_sector(first: u32, mut f: impl FnMut(u64, &[u8; SECTOR_SIZE]) -> Result<bool, &'static str>) -> Result<(), &'static str> {
+    let st = state()?;
+    let mut cache = FatCache::new();
+    let mut sector = [0u8; SECTOR_SIZE];
+    let mut cluster = first;
+    // A well-formed chain cannot be longer than the volume
+    for _ in 0..=st.cluster_count {
+        if cluster < 2 || cluster - 2 >= st.cluster_count {
+            return Err("FAT: cluster out of range");
+        }
+        let lba = cluster_lba(st, cluster);
+        for i in 0..st.sectors_per_cluster as u64 {
+            drivers::block::read_sectors(lba + i, 1, &mut sector)?;
+            if !f(lba + i, &sector)? {
+                return Ok(());
+            }
+        }
+        match next_cluster(&mut cache, cluster)? {
+            Some(n) => cluster = n,
+            None => return Ok(()),
+        }
+    }
+    Err("FAT: cluster chain loops")
+}
+
+// ===== Directory and file access =====
+
+/// Convert a path component to the space-padded, upper-case 8.3 form.
+fn to_short_name(component: &str) -> Option<[u8; 11]> {
+    if component == "." || component == ".." {
+        let mut name = [b' '; 11];
+        name[..component.len()].copy_from_slice(component.as_bytes());
+        return Some(name);
+    }
+    let (base, ext) = match component.rfind('.') {
+        Some(0) | None => (component, ""),
+        Some(dot) => (&component[..dot], &component[dot + 1..]),
+    };
+    if base.is_empty() || base.len() > 8 || ext.len() > 3 {
+        return None;
+    }
+    let mut name = [b' '; 11];
+    for (dst, b) in name[..8].iter_mut().zip(base.bytes()) {
+        *dst = b.to_ascii_uppercase();
+    }
+    for (dst, b) in name[8..].iter_mut().zip(ext.bytes()) {
+        *dst = b.to_ascii_uppercase();
+    }
+    Some(name)
+}
+
+// ===== VFAT long names =====
+
+/// UTF-16 code units of one LFN entry, at byte offsets 1, 14 and 28
+const LFN_UNITS_PER_ENTRY: usize = 13;
+const LFN_MAX_ENTRIES: usize = 20;
+const LFN_SEQ_MASK: u8 = 0x1F;
+const LFN_LAST_ENTRY: u8 = 0x40;
+const LFN_UNIT_OFFSETS: [usize; LFN_UNITS_PER_ENTRY] = [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30];
+/// Longest long name, in UTF-8 bytes (255 UTF-16 units, up to 3 bytes each)
+pub const LFN_UTF8_MAX: usize = 255 * 3;
+
+/// Checksum of the 11-byte short name that each LFN entry must carry.
+fn lfn_checksum(short: &[u8]) -> u8 {
+    short[..11].iter().fold(0u8, |sum, &c| ((sum & 1) << 7).wrapping_add(sum >> 1).wrapping_add(c))
+}
+
+/// Collects the LFN entries that precede a short entry. They are stored
+/// last part first, with sequence numbers counting down to 1.
+struct LfnCollector {
+    units: [u16; LFN_MAX_ENTRIES * LFN_UNITS_PER_ENTRY],
+    /// Sequence number the next entry must have; 0 once the run is complete
+    expected: u8,
+    checksum: u8,
+    active: bool,
+}
+
+impl LfnCollector {
+    fn new() -> Self {
+        Self { units: [0xFFFF; LFN_MAX_ENTRIES * LFN_UNITS_PER_ENTRY], expected: 0, checksum: 0, active: false }
+    }
+
+    fn reset(&mut self) {
+        self.active = false;
+        self.expected = 0;
+    }
+
+    fn push(&mut self, raw: &[u8]) {
+        let seq = raw[0] & LFN_SEQ_MASK;
+        let checksum = raw[13];
+        // Sequence numbers start at 1; a 0 would also match `expected` once a
+        // run is complete
+        if seq == 0 {
+            self.reset();
+            return;
+        }
+        if (raw[0] & LFN_LAST_ENTRY) != 0 {
+            if seq as usize > LFN_MAX_ENTRIES {
+                self.reset();
+                return;
+            }
+            self.units = [0xFFFF; LFN_MAX_ENTRIES * LFN_UNITS_PER_ENTRY];
+            self.active = true;
+            self.checksum = checksum;
+        } else if !self.active || seq != self.expected || checksum != self.checksum {
+            // Out of order or from another name: drop the whole run
+            self.reset();
+            return;
+        }
+
+        let base = (seq as usize - 1) * LFN_UNITS_PER_ENTRY;
+        for (i, &off) in LFN_UNIT_OFFSETS.iter().enumerate() {
+            self.units[base + i] = u16::from_le_bytes([raw[off], raw[off + 1]]);
+        }
+        self.expected = seq - 1;
+    }
+
+    /// The long name for the short entry `short`, as UTF-8 in `out`. `None`
+    /// if no complete run was collected or its checksum does not match.
+    fn finish<'a>(&mut self, short: &[u8], out: &'a mut [u8; LFN_UTF8_MAX]) -> Option<&'a str> {
+        let complete = self.active && self.expected == 0 && self.checksum == lfn_checksum(short);
+        self.reset();
+        if !complete {
+            return None;
+        }
+
+        let mut len = 0usize;
+        for &unit in self.units.iter().take_while(|&&u| u != 0x0000 && u != 0xFFFF) {
+            // No surrogate pairs: anything outside the BMP becomes '?'
+            let ch = char::from_u32(unit as u32).unwrap_or('?');
+            if len + ch.len_utf8() > out.len() {
+                break;
+            }
+            ch.encode_utf8(&mut out[len..]);
+            len += ch.len_utf8();
+        }
+        core::str::from_utf8(&out[..len]).ok()
+    }
+}
+
+fn entry_cluster(e: &FatDirEntry) -> u32 {
+    ((e.fst_clus_hi as u32) << 16) | e.fst_clus_lo as u32
+}
+
+/// Look up `component` in the directory starting at cluster `dir`. Long
+/// names compare ASCII case-insensitively, like the short names do.
+fn find_in_directory(dir: u32, component: &str) -> Result<FatDirEntry, &'static str> {
+    let wanted = to_short_name(component);
+    let mut found: Option<FatDirEntry> = None;
+    let mut lfn = LfnCollector::new();
+    let mut long_name = [0u8; LFN_UTF8_MAX];
+
+    for_each_sector(dir, |_, sector| {
+        for raw in sector.chunks_exact(DIR_ENTRY_SIZE) {
+            match raw[0] {
+                ENTRY_END => return Ok(false),
+                ENTRY_FREE => {
+                    lfn.reset();
+                    continue;
+                }
+                _ => {}
+            }
+            let attr = raw[11];
+            if attr == ATTR_LONG_NAME {
+                lfn.push(raw);
+                continue;
+            }
+            if (attr & ATTR_VOLUME_ID) != 0 {
+                lfn.reset();
+                continue;
+            }
+            let long_match = lfn
+                .finish(raw, &mut long_name)
+                .is_some_and(|name| name.eq_ignore_ascii_case(component));
+            if long_match || wanted.is_some_and(|w| raw[..11] == w) {
+                // SAFETY: `raw` is exactly one 32-byte packed entry
+                found = Some(unsafe { core::ptr::read_unaligned(raw.as_ptr() as *const FatDirEntry) });
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    })?;
+
+    found.ok_or("File not found")
+}
+
+/// Directory entry of the regular file at absolute `path`.
+fn lookup_file(path: &str) -> Result<FatDirEntry, &'static str> {
+    if !path.starts_with('/') {
+        return Err("Path must be absolute");
+    }
+    let st = state()?;
+
+    let mut cluster = st.root_cluster;
+    let mut entry: Option<FatDirEntry> = None;
+    for component in path.split('/').filter(|c| !c.is_empty()) {
+        if let Some(e) = entry {
+            if (e.attr & ATTR_DIRECTORY) == 0 {
+                return Err("Not a directory");
+            }
+        }
+        let e = find_in_directory(cluster, component)?;
+        cluster = entry_cluster(&e);
+        // ".." pointing at the root is stored as cluster 0
+        if cluster == 0 {
+            cluster = st.root_cluster;
+        }
+        entry = Some(e);
+    }
+
+    let entry = entry.ok_or("Not a regular file")?;
+    if (entry.attr & ATTR_DIRECTORY) != 0 {
+        return Err("Not a regular file");
+    }
+    Ok(entry)
+}
+
+/// Modification time of the file at absolute `path`, as `mtime_str` formats it.
+pub fn file_mtime<'a>(path: &str, buf: &'a mut [u8; 20]) -> Result<&'a str, &'static str> {
+    Ok(lookup_file(path)?.mtime_str(buf))
+}
+
+/// Read a file by absolute path (e.g. "/boot/kernel.elf") from the FAT32 volume.
+pub fn read_file(path: &str) -> Result<FileBuffer, &'static str> {
+    let entry = lookup_file(path)?;
+    let file_size = entry.file_size as usize;
+    if file_size > super::MAX_FILE_SIZE {
+        return Err("File too large");
+    }
+
+    let mut buffer = FileBuffer::new();
+    if file_size > 0 {
+        let mut done = 0usize;
+        for_each_sector(entry_cluster(&entry), |_, sector| {
+            let n = core::cmp::min(SECTOR_SIZE, file_size - done);
+            buffer.data[done..done + n].copy_from_slice(&sector[..n]);
+            done += n;
+            Ok(done < file_size)
+        })?;
+        if done < file_size {
+            return Err("FAT: cluster chain shorter than file");
+        }
+    }
+    buffer.size = file_size;
+    Ok(buffer)
+}
+
+// ===== Filesystem trait =====
+
+/// Handle for the mounted FAT volume (state lives in the module statics).
+pub struct FatFs;
+
+impl super::Filesystem for FatFs {
+    fn read_file(&mut self, path: &str, buf: &mut [u8]) -> Result<usize, &'static str> {
+        let file = read_file(path)?;
+        let data = file.as_slice();
+        if data.len() > buf.len() {
+            return Err("Buffer too small for file");
+        }
+        buf[..data.len()].copy_from_slice(data);
+        Ok(data.len())
+    }
+
+    fn write_file(&mut self, path: &str, data: &[u8]) -> Result<(), &'static str> {
+        let entry = lookup_file(path)?;
+        if entry.file_size as usize != data.len() {
+            return Err("FAT: in-place write must keep the file size");
+        }
+        if data.is_empty() {
+            return Ok(());
+        }
+
+        let mut done = 0usize;
+        let mut out = [0u8; SECTOR_SIZE];
+        for_each_sector(entry_cluster(&entry), |lba, sector| {
+            let n = core::cmp::min(SECTOR_SIZE, data.len() - done);
+            // Keep the slack after the end of the file as it is
+            out.copy_from_slice(sector);
+            out[..n].copy_from_slice(&data[done..done + n]);
+            drivers::block::write_sectors(lba, 1, &out)?;
+            done += n;
+            Ok(done < data.len())
+        })?;
+        if done < data.len() {
+            return Err("FAT: cluster chain shorter than file");
+        }
+        Ok(())
+    }
 }
@@ -1,2 +1,44 @@
+#[cfg(feature = "bios")]
 pub mod ext;
+#[cfg(feature = "bios")]
 pub mod fat;
+
+pub const MAX_FILE_SIZE: usize = 1024 * 1024; // 1MB max file size
+
+/// Whole-file read buffer shared by the EXT and FAT readers.
+#[allow(dead_code)]
+pub struct FileBuffer {
+    data: [u8; MAX_FILE_SIZE],
+    size: usize,
+}
+
+#[allow(dead_code)]
+impl FileBuffer {
+    pub fn new() -> Self {
+        Self {
+            data: [0; MAX_FILE_SIZE],
+            size: 0,
+        }
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        &self.data[..self.size]
+    }
+}
+
+/// Minimal filesystem interface shared by the EXT and FAT readers so callers
+/// (slot config, boot menu) need not care which one is mounted.
+pub trait Filesystem {
+    /// Read the whole file at `path` into `buf`, returning the byte count.
+    fn read_file(&mut self, path: &str, buf: &mut [u8]) -> Result<usize, &'static str>;
+
+    /// Overwrite the file at `path` with `data`, in place: nothing is
+    /// allocated and no metadata is touched, so `data` must be exactly as long
+    /// as the file.
+    fn write_file(&mut self, path: &str, data: &[u8]) -> Result<(), &'static str>;
+}
+
+/// Write `data` to `path` on `fs`.
+pub fn write_file(fs: &mut dyn Filesystem, path: &str, data: &[u8]) -> Result<(), &'static str> {
+    fs.write_file(path, data)
+}
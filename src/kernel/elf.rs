@@ -0,0 +1,104 @@
+//! ELF header and program header parsing shared by the kernel loaders.
+//!
+//! Only `core` is used here so `cargo test -p xtask` can build this file for
+//! the host and run its tests.
+
+pub const ELFCLASS32: u8 = 1;
+pub const ELFCLASS64: u8 = 2;
+pub const PT_LOAD: u32 = 1;
+pub const PT_NOTE: u32 = 4;
+
+pub fn read_u16(data: &[u8], off: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(data.get(off..off + 2)?.try_into().ok()?))
+}
+
+pub fn read_u32(data: &[u8], off: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(data.get(off..off + 4)?.try_into().ok()?))
+}
+
+pub fn read_u64(data: &[u8], off: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(data.get(off..off + 8)?.try_into().ok()?))
+}
+
+/// Program header fields we care about (ELF32 values are widened).
+#[derive(Copy, Clone, Debug)]
+pub struct Phdr {
+    pub p_type: u32,
+    pub p_offset: u64,
+    pub p_vaddr: u64,
+    pub p_filesz: u64,
+    pub p_memsz: u64,
+}
+
+/// Yield every program header of an ELF32 or ELF64 image (silently skipping
+/// truncated ones).
+pub fn program_headers(data: &[u8]) -> impl Iterator<Item = Phdr> + '_ {
+    let is32 = data.get(4) == Some(&ELFCLASS32);
+    let (ph_offset, ph_entry_size, ph_count) = if is32 {
+        (read_u32(data, 28).unwrap_or(0) as usize, read_u16(data, 42), read_u16(data, 44))
+    } else {
+        (read_u64(data, 32).unwrap_or(0) as usize, read_u16(data, 54), read_u16(data, 56))
+    };
+    let ph_entry_size = ph_entry_size.unwrap_or(0) as usize;
+    let ph_count = if ph_entry_size == 0 { 0 } else { ph_count.unwrap_or(0) as usize };
+
+    (0..ph_count).filter_map(move |i| {
+        let base = ph_offset.checked_add(i.checked_mul(ph_entry_size)?)?;
+        if is32 {
+            Some(Phdr {
+                p_type: read_u32(data, base)?,
+                p_offset: read_u32(data, base + 4)? as u64,
+                p_vaddr: read_u32(data, base + 8)? as u64,
+                p_filesz: read_u32(data, base + 16)? as u64,
+                p_memsz: read_u32(data, base + 20)? as u64,
+            })
+        } else {
+            Some(Phdr {
+                p_type: read_u32(data, base)?,
+                p_offset: read_u64(data, base + 8)?,
+                p_vaddr: read_u64(data, base + 16)?,
+                p_filesz: read_u64(data, base + 32)?,
+                p_memsz: read_u64(data, base + 40)?,
+            })
+        }
+    })
+}
+
+/// True if `entry` lies inside the memory image of some PT_LOAD segment.
+pub fn validate_entry_point(data: &[u8], entry: u64) -> bool {
+    program_headers(data)
+        .filter(|ph| ph.p_type == PT_LOAD)
+        .any(|ph| ph.p_vaddr <= entry && entry < ph.p_vaddr.saturating_add(ph.p_memsz))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// ELF64 header plus one PT_LOAD program header covering
+    /// [0x10_0000, 0x10_2000).
+    fn elf64_with_segment() -> [u8; 120] {
+        let mut image = [0u8; 120];
+        image[..4].copy_from_slice(b"\x7fELF");
+        image[4] = ELFCLASS64;
+        image[32..40].copy_from_slice(&64u64.to_le_bytes()); // e_phoff
+        image[54..56].copy_from_slice(&56u16.to_le_bytes()); // e_phentsize
+        image[56..58].copy_from_slice(&1u16.to_le_bytes()); // e_phnum
+
+        let ph = &mut image[64..];
+        ph[..4].copy_from_slice(&PT_LOAD.to_le_bytes());
+        ph[16..24].copy_from_slice(&0x10_0000u64.to_le_bytes()); // p_vaddr
+        ph[40..48].copy_from_slice(&0x2000u64.to_le_bytes()); // p_memsz
+        image
+    }
+
+    #[test]
+    fn entry_in_loaded_segment_is_valid() {
+        assert!(validate_entry_point(&elf64_with_segment(), 0x10_1000));
+    }
+
+    #[test]
+    fn entry_at_unmapped_address_is_rejected() {
+        assert!(!validate_entry_point(&elf64_with_segment(), 0x20_0000));
+    }
+}
@@ -1,52 +1,699 @@
 #[allow(dead_code)]
 
+#[cfg(feature = "uefi")]
 use uefi::prelude::*;
-use uefi::proto::media::file::{Directory, File, FileModule, FileAttribute, FileInfo};
-use uefi::table::boot::{AllocateType, MemoryType};
+#[cfg(feature = "uefi")]
+use uefi::proto::device_path::{DeviceSubType, DeviceType};
+#[cfg(feature = "uefi")]
+use uefi::proto::loaded_image::LoadedImage;
+#[cfg(feature = "uefi")]
+use uefi::proto::media::file::{Directory, File, FileAttribute, FileInfo, FileMode, RegularFile};
+#[cfg(feature = "uefi")]
+use uefi::table::boot::{AllocateType, BootServices, LoadImageSource, MemoryType};
 
-use core::ptr::copy_nonoverlapping;
+#[cfg(feature = "uefi")]
+use crate::arch::cpuid::CpuInfo;
+#[cfg(feature = "uefi")]
+use crate::boot::bootinfo::BootInfo;
+#[cfg(feature = "uefi")]
+use crate::boot::cmdline::CmdLine;
+use crate::boot::multiboot2;
+use crate::kernel::elf::{
+    ELFCLASS32, ELFCLASS64, PT_LOAD, PT_NOTE, program_headers, read_u16, read_u32, read_u64, validate_entry_point,
+};
+#[cfg(feature = "uefi")]
+use crate::boot::stivale2;
+#[cfg(feature = "uefi")]
+use crate::crypto::sha256::{self, Sha256};
+use crate::memory::mem::safe_copy;
+#[cfg(feature = "bios")]
+use crate::util::static_vec::StaticVec;
+#[cfg(feature = "uefi")]
+use crate::memory::paging::{LARGE_PAGE_SIZE, LOW_IDENTITY_LIMIT, PageFlags, PageTableSet};
+#[cfg(feature = "uefi")]
+use crate::uefi::path::{UEFI_PATH_MAX, normalize_uefi_path};
+#[cfg(feature = "uefi")]
+use crate::uefi::pool::UefiBox;
+#[cfg(feature = "uefi")]
+use crate::uefi::protocol::require_protocol;
+
+#[cfg(feature = "bios")]
+use crate::drivers::vga;
 
 /// Predefined kernel paths
-const KERNEL_PATHS: &[&str] = &["/EFI/BOOT/KERNEL.EFI", "/kernel.elf", "/boot/kernel.elf"];
+const DEFAULT_KERNEL_PATHS: [&str; 3] = ["/EFI/BOOT/KERNEL.EFI", "/kernel.elf", "/boot/kernel.elf"];
+
+/// Deployment-specific kernel path set at build time (see build.rs)
+const CUSTOM_PATH: Option<&str> = option_env!("RUSTYBOOT_KERNEL_PATH");
+
+const KERNEL_PATH_COUNT: usize = DEFAULT_KERNEL_PATHS.len() + CUSTOM_PATH.is_some() as usize;
+
+const KERNEL_PATH_TABLE: [&str; KERNEL_PATH_COUNT] = {
+    let mut out = [""; KERNEL_PATH_COUNT];
+    let mut i = 0;
+    if let Some(path) = CUSTOM_PATH {
+        out[0] = path;
+        i = 1;
+    }
+    let mut j = 0;
+    while j < DEFAULT_KERNEL_PATHS.len() {
+        out[i + j] = DEFAULT_KERNEL_PATHS[j];
+        j += 1;
+    }
+    out
+};
+
+/// Kernel search order: the build-time override first, then the defaults
+pub const KERNEL_PATHS: &[&str] = &KERNEL_PATH_TABLE;
+
+/// Max UCS-2 code units (including NUL) of the EFI stub command line
+#[cfg(feature = "uefi")]
+const CMDLINE_UCS2_MAX: usize = 1024;
+
+/// Watchdog timeout restored once the kernel has been loaded
+#[cfg(feature = "uefi")]
+const WATCHDOG_AFTER_LOAD_SECS: usize = 300;
+
+/// Initrd file names looked up next to the kernel, in priority order
+#[cfg(feature = "uefi")]
+const INITRD_NAMES: [&str; 3] = ["initrd.img", "initramfs.cpio.gz", "initrd.gz"];
+
+/// Expected SHA-256 of the kernel, looked up next to it: hex digest, then a
+/// newline (`sha256sum` output is accepted too)
+#[cfg(feature = "uefi")]
+const KERNEL_HASH_NAME: &str = "kernel.sha256";
+#[cfg(feature = "uefi")]
+const KERNEL_HASH_FILE_MAX: usize = 512;
+
+/// Detached Ed25519 signature of the kernel file (64 raw bytes), next to it
+#[cfg(feature = "uefi")]
+const KERNEL_SIG_NAME: &str = "kernel.sig";
+
+/// Public key kernels are checked against, from `RUSTYBOOT_TRUSTED_KEY` at
+/// build time (see build.rs). Builds without one skip the check.
+#[cfg(all(feature = "uefi", rustyboot_trusted_key))]
+const TRUSTED_KEY: [u8; crate::crypto::ed25519::PUBLIC_KEY_LEN] =
+    *include_bytes!(concat!(env!("OUT_DIR"), "/trusted_key.bin"));
+
+/// Main entry: find and load kernel, plus `initrd` or else an initrd from the
+/// same directory if there is one (recorded in `boot_info`). `preferred` (the
+/// boot menu choice) is tried before the built-in paths.
+#[cfg(feature = "uefi")]
+pub fn find_and_load_kernel(
+    st: &SystemTable<Boot>,
+    image_handle: Handle,
+    root: &mut Directory,
+    preferred: Option<&str>,
+    initrd: Option<&str>,
+    boot_info: &mut BootInfo,
+) -> Result<usize, &'static str> {
+    // The firmware's default 5 minute watchdog can fire while a large image
+    // is still being read from slow media
+    disable_uefi_watchdog(st.boot_services());
+    let result = search_and_load_kernel(st, image_handle, root, preferred, initrd, boot_info);
+    enable_uefi_watchdog(st.boot_services(), WATCHDOG_AFTER_LOAD_SECS);
+    result
+}
+
+#[cfg(feature = "uefi")]
+pub fn disable_uefi_watchdog(bs: &BootServices) {
+    let _ = bs.set_watchdog_timer(0, 0, None);
+}
+
+#[cfg(feature = "uefi")]
+pub fn enable_uefi_watchdog(bs: &BootServices, seconds: usize) {
+    // Codes 0x0000-0xFFFF are reserved for the firmware
+    let _ = bs.set_watchdog_timer(seconds, 0x10000, None);
+}
+
+#[cfg(feature = "uefi")]
+fn search_and_load_kernel(
+    st: &SystemTable<Boot>,
+    image_handle: Handle,
+    root: &mut Directory,
+    preferred: Option<&str>,
+    initrd: Option<&str>,
+    boot_info: &mut BootInfo,
+) -> Result<usize, &'static str> {
+    if let Some(entry) = preferred.and_then(|path| try_kernel_path(st, image_handle, root, path, initrd, boot_info)) {
+        return Ok(entry);
+    }
+
+    // Look next to the bootloader first, then fall back to the fixed paths
+    if let Some(dir) = get_loader_directory(st.boot_services(), image_handle) {
+        for &path in KERNEL_PATHS {
+            let mut buf = [0u8; LOADER_PATH_MAX];
+            let candidate = match join_loader_path(dir, path, &mut buf) {
+                Some(c) if c != path => c,
+                _ => continue,
+            };
+            if let Some(entry) = try_kernel_path(st, image_handle, root, candidate, initrd, boot_info) {
+                return Ok(entry);
+            }
+        }
+    }
 
-/// Main entry: find and load kernel
-pub fn find_and_load_kernel(st: &SystemTable<Boot>, root: &mut Directory) -> Result<usize, &'static str> {
     for &path in KERNEL_PATHS {
-        writeln!(st.stdout(), "Trying: {}", path).ok();
-        if let Ok(entry) = load_kernel_from_path(st, root, path) {
-            writeln!(st.stdout(), "Loaded kernel at 0x{:X}", entry).ok();
+        if let Some(entry) = try_kernel_path(st, image_handle, root, path, initrd, boot_info) {
             return Ok(entry);
         }
     }
     Err("No kernel found")
 }
 
-/// Load kernel from a given path
-fn load_kernel_from_path(st: &SystemTable<Boot>, root: &mut Directory, path: &str) -> Result<usize, &'static str> {
-    let kernel_buf = read_file_uefi(root, path)?;
+/// Load the kernel at `path` and its initrd (see `load_initrd`); `None` if
+/// the kernel fails to load.
+#[cfg(feature = "uefi")]
+fn try_kernel_path(
+    st: &SystemTable<Boot>,
+    image_handle: Handle,
+    root: &mut Directory,
+    path: &str,
+    initrd: Option<&str>,
+    boot_info: &mut BootInfo,
+) -> Option<usize> {
+    writeln!(st.stdout(), "Trying: {}", path).ok();
+    let entry = load_kernel_from_path(st, image_handle, root, path, boot_info).ok()?;
+    writeln!(st.stdout(), "Loaded kernel at 0x{:X}", entry).ok();
+    load_initrd(st, root, path, initrd, boot_info);
+    Some(entry)
+}
+
+/// Max bytes of the loader directory and of a joined kernel path
+#[cfg(feature = "uefi")]
+const LOADER_PATH_MAX: usize = 260;
+
+#[cfg(feature = "uefi")]
+static mut LOADER_DIR: [u8; LOADER_PATH_MAX] = [0; LOADER_PATH_MAX];
+
+/// Directory the bootloader image was loaded from (e.g. `/EFI/BOOT/`), taken
+/// from the `FilePath` nodes of its `LoadedImage` device path. Separators are
+/// converted to `/` to match `KERNEL_PATHS`.
+#[cfg(feature = "uefi")]
+pub fn get_loader_directory(bs: &BootServices, image_handle: Handle) -> Option<&'static str> {
+    // Not finding it only loses the loader-relative search, so fall back quietly
+    let loaded = require_protocol::<LoadedImage>(bs, image_handle).ok()?;
+    let file_path = loaded.file_path()?;
+
+    let dir = unsafe { &mut *core::ptr::addr_of_mut!(LOADER_DIR) };
+    let mut len = 0usize;
+    for node in file_path.node_iter() {
+        if node.device_type() != DeviceType::MEDIA || node.sub_type() != DeviceSubType::MEDIA_FILE_PATH {
+            continue;
+        }
+        // Node payload is a NUL-terminated UCS-2 string; firmware may split
+        // one path over several nodes
+        for unit in node.data().chunks_exact(2) {
+            let c = u16::from_le_bytes([unit[0], unit[1]]);
+            if c == 0 {
+                break;
+            }
+            if c >= 0x80 || len >= LOADER_PATH_MAX {
+                return None;
+            }
+            dir[len] = if c == b'\\' as u16 { b'/' } else { c as u8 };
+            len += 1;
+        }
+    }
+
+    // Keep everything up to and including the last separator
+    let end = dir[..len].iter().rposition(|&b| b == b'/')? + 1;
+    core::str::from_utf8(&dir[..end]).ok()
+}
+
+/// `dir` + the file name of `path`, e.g. `/EFI/BOOT/` + `kernel.elf`.
+#[cfg(feature = "uefi")]
+fn join_loader_path<'a>(dir: &str, path: &str, buf: &'a mut [u8; LOADER_PATH_MAX]) -> Option<&'a str> {
+    let name = path.rsplit('/').next()?;
+    let total = dir.len() + name.len();
+    if name.is_empty() || total > buf.len() {
+        return None;
+    }
+    buf[..dir.len()].copy_from_slice(dir.as_bytes());
+    buf[dir.len()..total].copy_from_slice(name.as_bytes());
+    core::str::from_utf8(&buf[..total]).ok()
+}
+
+/// Read `initrd`, or else the first of `INITRD_NAMES` in the directory of
+/// `kernel_path`, into LOADER_DATA pages. Having none is fine: `boot_info`
+/// then keeps zeroes.
+#[cfg(feature = "uefi")]
+fn load_initrd(
+    st: &SystemTable<Boot>,
+    root: &mut Directory,
+    kernel_path: &str,
+    initrd: Option<&str>,
+    boot_info: &mut BootInfo,
+) {
+    if let Some(path) = initrd {
+        if !load_initrd_file(st, root, path, boot_info) {
+            writeln!(st.stdout(), "[loader] Initrd {} not found", path).ok();
+        }
+        return;
+    }
+
+    let dir_end = kernel_path.rfind(['/', '\\']).map_or(0, |i| i + 1);
+    let dir = &kernel_path[..dir_end];
+    for &name in INITRD_NAMES.iter() {
+        let mut buf = [0u8; LOADER_PATH_MAX];
+        let path = match join_loader_path(dir, name, &mut buf) {
+            Some(p) => p,
+            None => continue,
+        };
+        if load_initrd_file(st, root, path, boot_info) {
+            return;
+        }
+    }
+}
+
+/// Read the initrd at `path`. False if there is no such (non-empty) file;
+/// once it exists the search is over, even if reading it failed.
+#[cfg(feature = "uefi")]
+fn load_initrd_file(st: &SystemTable<Boot>, root: &mut Directory, path: &str, boot_info: &mut BootInfo) -> bool {
+    let (mut file, size) = match open_regular_file(root, path) {
+        Ok(f) => f,
+        Err(_) => return false,
+    };
+    if size == 0 {
+        return false;
+    }
+
+    let bs = st.boot_services();
+    let pages = size.div_ceil(PAGE_SIZE as usize);
+    let base = match bs.allocate_pages(AllocateType::AnyPages, MemoryType::LOADER_DATA, pages) {
+        Ok(base) => base,
+        Err(_) => {
+            writeln!(st.stdout(), "[loader] No memory for initrd {}", path).ok();
+            return true;
+        }
+    };
+    let data = unsafe { core::slice::from_raw_parts_mut(base as *mut u8, size) };
+    if let Err(e) = read_file_with_progress(st, &mut file, data) {
+        writeln!(st.stdout(), "[loader] Failed to read initrd {}: {}", path, e).ok();
+        let _ = bs.free_pages(base, pages);
+        return true;
+    }
+
+    writeln!(st.stdout(), "[loader] Initrd: {} ({} bytes at 0x{:X})", path, size, base).ok();
+    boot_info.initrd_base = base;
+    boot_info.initrd_size = size as u64;
+    true
+}
+
+/// Read the small file `name` from the directory of `kernel_path` into
+/// `buf`. `Ok(None)` if there is no such file.
+#[cfg(feature = "uefi")]
+fn read_kernel_sidecar(
+    root: &mut Directory,
+    kernel_path: &str,
+    name: &str,
+    buf: &mut [u8],
+) -> Result<Option<usize>, &'static str> {
+    let dir_end = kernel_path.rfind(['/', '\\']).map_or(0, |i| i + 1);
+    let mut path_buf = [0u8; LOADER_PATH_MAX];
+    let path = join_loader_path(&kernel_path[..dir_end], name, &mut path_buf).ok_or("Kernel sidecar path too long")?;
+    let (mut file, size) = match open_regular_file(root, path) {
+        Ok(f) => f,
+        Err(_) => return Ok(None),
+    };
+    let len = size.min(buf.len());
+    let read = file.read(&mut buf[..len]).map_err(|_| "Failed to read file")?;
+    Ok(Some(read))
+}
+
+/// Check `data` against `kernel.sha256` in the directory of `kernel_path`.
+/// Without that file the kernel is accepted unchecked.
+#[cfg(feature = "uefi")]
+fn verify_kernel_hash(
+    st: &SystemTable<Boot>,
+    root: &mut Directory,
+    kernel_path: &str,
+    data: &[u8],
+) -> Result<(), &'static str> {
+    let mut text = [0u8; KERNEL_HASH_FILE_MAX];
+    let len = match read_kernel_sidecar(root, kernel_path, KERNEL_HASH_NAME, &mut text)? {
+        Some(len) => len,
+        None => return Ok(()),
+    };
+    let expected = core::str::from_utf8(&text[..len])
+        .ok()
+        .and_then(|t| t.split_whitespace().next())
+        .and_then(sha256::parse_hex_digest)
+        .ok_or("Malformed kernel hash file")?;
+
+    if Sha256::digest(data) != expected {
+        writeln!(st.stdout(), "[loader] {} does not match {}", kernel_path, KERNEL_HASH_NAME).ok();
+        return Err("Hash mismatch");
+    }
+    writeln!(st.stdout(), "[loader] SHA-256 verified").ok();
+    Ok(())
+}
+
+/// With a `TRUSTED_KEY` built in, require a valid `kernel.sig` over the
+/// raw kernel file; otherwise accept every kernel.
+#[cfg(feature = "uefi")]
+fn verify_kernel_signature(
+    st: &SystemTable<Boot>,
+    root: &mut Directory,
+    kernel_path: &str,
+    data: &[u8],
+) -> Result<(), &'static str> {
+    #[cfg(rustyboot_trusted_key)]
+    {
+        use crate::crypto::ed25519;
+
+        let mut sig = [0u8; ed25519::SIGNATURE_LEN];
+        match read_kernel_sidecar(root, kernel_path, KERNEL_SIG_NAME, &mut sig)? {
+            Some(ed25519::SIGNATURE_LEN) => {}
+            Some(_) => return Err("Malformed kernel signature"),
+            None => return Err("Kernel signature missing"),
+        }
+        if !ed25519::verify(&TRUSTED_KEY, data, &sig) {
+            writeln!(st.stdout(), "[loader] Bad signature on {}", kernel_path).ok();
+            return Err("Kernel signature invalid");
+        }
+        writeln!(st.stdout(), "[loader] Ed25519 signature verified").ok();
+    }
+    #[cfg(not(rustyboot_trusted_key))]
+    let _ = (st, root, kernel_path, data);
+    Ok(())
+}
+
+/// Load kernel from a given path. `kaslr` on the command line randomises the
+/// load address of relocatable kernels. Gzip-wrapped images are decompressed
+/// after the hash and signature checks, which cover the file as stored.
+#[cfg(feature = "uefi")]
+fn load_kernel_from_path(
+    st: &SystemTable<Boot>,
+    image_handle: Handle,
+    root: &mut Directory,
+    path: &str,
+    boot_info: &mut BootInfo,
+) -> Result<usize, &'static str> {
+    let mut kernel_buf = read_file_uefi(st, root, path)?;
+    verify_kernel_hash(st, root, path, kernel_buf.as_slice())?;
+    verify_kernel_signature(st, root, path, kernel_buf.as_slice())?;
+    if crate::compression::gzip::is_gzip(kernel_buf.as_slice()) {
+        writeln!(st.stdout(), "[loader] Decompressing gzip kernel").ok();
+        kernel_buf = inflate_kernel_uefi(st, kernel_buf.as_slice())?;
+    }
+
+    // PE/COFF (EFI stub) kernels are started by the firmware itself
+    if is_efi_stub(kernel_buf.as_slice()) {
+        writeln!(st.stdout(), "[loader] EFI stub kernel detected").ok();
+        return start_efi_stub(st, image_handle, kernel_buf.as_slice(), boot_info.cmdline_str());
+    }
+
+    check_elf_segments_no_overlap(kernel_buf.as_slice())?;
+    note_multiboot2(kernel_buf.as_slice());
+    // The hand-off jumps in 64-bit mode except for Multiboot2 kernels, so
+    // other ELF32 kernels cannot be entered from here
+    if kernel_buf.as_slice().get(4) == Some(&ELFCLASS32) && !multiboot2_requested() {
+        return Err("ELF32 kernel without a Multiboot2 header");
+    }
     writeln!(st.stdout(), "Kernel size: {} bytes", kernel_buf.len()).ok();
+    if let Some(version) = extract_kernel_version(kernel_buf.as_slice()) {
+        writeln!(st.stdout(), "[loader] Kernel: {}", version).ok();
+    }
 
-    // Allocate pages for the kernel
-    let kernel_pages = (kernel_buf.len() + 0xFFF) / 0x1000; // round up
-    let kernel_addr = st.boot_services().allocate_pages(
-        AllocateType::AnyPages,
-        MemoryType::LOADER_DATA,
-        kernel_pages
-    ).map_err(|_| "Failed to allocate pages")?;
+    // Claim the segments' physical pages before copying anything there.
+    // Higher-half kernels keep running at their link address: only the copy
+    // is biased, and `build_page_tables` maps the image back up there.
+    unsafe { HIGHER_HALF_KERNEL = None };
+    let higher_half = lowest_load_page(kernel_buf.as_slice()) >= HIGHER_HALF_START;
+    let (load_bias, run_bias) = if higher_half {
+        let (bias, span) = allocate_higher_half_kernel(st.boot_services(), kernel_buf.as_slice())?;
+        let virt = lowest_load_page(kernel_buf.as_slice());
+        writeln!(st.stdout(), "[loader] Higher-half kernel at 0x{:X}", virt.wrapping_add(bias as u64)).ok();
+        unsafe { HIGHER_HALF_KERNEL = Some((virt, virt.wrapping_add(bias as u64), span)) };
+        (bias, 0)
+    } else {
+        let kaslr = CmdLine::new(boot_info.cmdline_str()).has("kaslr");
+        let (bias, randomised) = allocate_kernel_segments(st.boot_services(), kernel_buf.as_slice(), kaslr)?;
+        if randomised {
+            writeln!(st.stdout(), "[loader] KASLR: relocated by 0x{:X}", bias).ok();
+        } else if bias != 0 {
+            writeln!(st.stdout(), "[loader] Preferred address in use, relocated by 0x{:X}", bias).ok();
+        }
+        (bias, bias)
+    };
 
-    // Parse ELF64 and load segments
-    parse_and_load_elf64(kernel_buf.as_slice(), kernel_addr)?;
+    let entry = load_elf(kernel_buf.as_slice(), load_bias)?.wrapping_sub(load_bias).wrapping_add(run_bias);
+    if load_bias != 0 {
+        let count = apply_relative_relocations(kernel_buf.as_slice(), load_bias as u64, run_bias as u64)?;
+        writeln!(st.stdout(), "[loader] Applied {} relocations", count).ok();
+    }
+    let entry = note_stivale2(st.boot_services(), kernel_buf.as_slice(), run_bias, entry)?;
+    // Multiboot2 kernels are i386 code, entered with paging off (see
+    // `enter_multiboot2_i386`)
+    if multiboot2_requested() {
+        if entry as u64 >= LOW_IDENTITY_LIMIT {
+            return Err("Multiboot2 kernel entry above 4 GiB");
+        }
+        // The switch code and the GDT are part of this image
+        if enter_multiboot2_i386 as usize as u64 >= LOW_IDENTITY_LIMIT {
+            return Err("Loader above 4 GiB cannot enter a Multiboot2 kernel");
+        }
+    }
+    boot_info.kernel_phys_base = lowest_load_page(kernel_buf.as_slice()).wrapping_add(load_bias as u64);
+    Ok(entry)
+}
 
-    Ok(kernel_addr)
+#[cfg(feature = "uefi")]
+const PAGE_SIZE: u64 = 0x1000;
+
+/// ELF type of position-independent (relocatable) kernels
+#[cfg(feature = "uefi")]
+const ET_DYN: u16 = 3;
+
+// ===== KASLR =====
+
+/// Window the randomised kernel base is picked from
+#[cfg(feature = "uefi")]
+const KASLR_MIN: u64 = 0x0200_0000;
+#[cfg(feature = "uefi")]
+const KASLR_MAX: u64 = 0x4000_0000;
+/// Keeps 2 MiB kernel mappings possible
+#[cfg(feature = "uefi")]
+const KASLR_ALIGN: u64 = 0x20_0000;
+/// Random bases tried before falling back to the link address
+#[cfg(feature = "uefi")]
+const KASLR_ATTEMPTS: u32 = 8;
+
+/// Claim `pages` at a random 2 MiB aligned base in `[KASLR_MIN, KASLR_MAX)`.
+/// `None` without RDRAND, or if every candidate is occupied.
+#[cfg(feature = "uefi")]
+fn allocate_kaslr_base(bs: &BootServices, pages: usize) -> Option<u64> {
+    if !CpuInfo::read().has_rdrand {
+        return None;
+    }
+    let span = pages as u64 * PAGE_SIZE;
+    let slots = KASLR_MAX.checked_sub(KASLR_MIN + span)? / KASLR_ALIGN + 1;
+    for _ in 0..KASLR_ATTEMPTS {
+        let random = crate::arch::x86::rdrand32()? as u64;
+        let base = KASLR_MIN + (random % slots) * KASLR_ALIGN;
+        if bs
+            .allocate_pages(AllocateType::Address(base), MemoryType::LOADER_DATA, pages)
+            .is_ok()
+        {
+            return Some(base);
+        }
+    }
+    None
 }
 
-/// Read a file from UEFI SimpleFileSystem
-fn read_file_uefi(root: &mut Directory, path: &str) -> Result<Vec<u8>, &'static str> {
-    use uefi::CStr16;
-    let mut buf16 = [0u16; 260];
-    let cpath = CStr16::from_str_with_buf(path, &mut buf16).map_err(|_| "Invalid path")?;
+/// Allocate every PT_LOAD range at its own physical address so the copy
+/// cannot land on firmware data. If one is occupied, a position-independent
+/// kernel gets one block anywhere and the returned load bias moves it there;
+/// other kernels fail to load.
+///
+/// With `kaslr`, a position-independent kernel is placed at a random base
+/// first (see `allocate_kaslr_base`). Returns the load bias and whether it
+/// was randomised.
+#[cfg(feature = "uefi")]
+fn allocate_kernel_segments(bs: &BootServices, data: &[u8], kaslr: bool) -> Result<(usize, bool), &'static str> {
+    // Page-aligned ranges, sorted and merged (segments may share a page)
+    let mut ranges = [(0u64, 0u64); MAX_LOAD_SEGMENTS];
+    let mut count = 0usize;
+    for ph in program_headers(data).filter(|ph| ph.p_type == PT_LOAD && ph.p_memsz != 0) {
+        let start = ph.p_vaddr & !(PAGE_SIZE - 1);
+        let end = ph
+            .p_vaddr
+            .checked_add(ph.p_memsz)
+            .and_then(|e| e.checked_add(PAGE_SIZE - 1))
+            .ok_or("ELF segment address overflow")?
+            & !(PAGE_SIZE - 1);
+        if count == MAX_LOAD_SEGMENTS {
+            return Err("Too many ELF PT_LOAD segments");
+        }
+        let mut i = count;
+        while i > 0 && ranges[i - 1].0 > start {
+            ranges[i] = ranges[i - 1];
+            i -= 1;
+        }
+        ranges[i] = (start, end);
+        count += 1;
+    }
+    let mut merged = 0usize;
+    for i in 0..count {
+        if merged > 0 && ranges[i].0 <= ranges[merged - 1].1 {
+            ranges[merged - 1].1 = ranges[merged - 1].1.max(ranges[i].1);
+        } else {
+            ranges[merged] = ranges[i];
+            merged += 1;
+        }
+    }
+    if merged == 0 {
+        return Err("ELF has no loadable segments");
+    }
+    let span_start = ranges[0].0;
+    let span_pages = ((ranges[merged - 1].1 - span_start) / PAGE_SIZE) as usize;
+    let relocatable = read_u16(data, 16) == Some(ET_DYN);
+
+    if kaslr && relocatable {
+        if let Some(base) = allocate_kaslr_base(bs, span_pages) {
+            return Ok((base.wrapping_sub(span_start) as usize, true));
+        }
+    }
+
+    let mut allocated = 0usize;
+    while allocated < merged {
+        let (start, end) = ranges[allocated];
+        let pages = ((end - start) / PAGE_SIZE) as usize;
+        if bs
+            .allocate_pages(AllocateType::Address(start), MemoryType::LOADER_DATA, pages)
+            .is_err()
+        {
+            break;
+        }
+        allocated += 1;
+    }
+    if allocated == merged {
+        return Ok((0, false));
+    }
+
+    // Give back what we got and place the whole image elsewhere
+    for &(start, end) in &ranges[..allocated] {
+        let _ = bs.free_pages(start, ((end - start) / PAGE_SIZE) as usize);
+    }
+    if !relocatable {
+        return Err("Kernel load address is occupied and the kernel is not relocatable");
+    }
+    let base = bs
+        .allocate_pages(AllocateType::AnyPages, MemoryType::LOADER_DATA, span_pages)
+        .map_err(|_| "Failed to allocate pages")?;
+    Ok((base.wrapping_sub(span_start) as usize, false))
+}
+
+/// Lowest canonical upper-half address; kernels linked from here on are
+/// higher-half kernels
+#[cfg(feature = "uefi")]
+const HIGHER_HALF_START: u64 = 0xFFFF_8000_0000_0000;
+
+/// Link address, physical address and size of the image if the kernel
+/// loaded last is a higher-half one
+#[cfg(feature = "uefi")]
+static mut HIGHER_HALF_KERNEL: Option<(u64, u64, u64)> = None;
+
+/// Place a higher-half kernel in one block anywhere in physical memory.
+/// Returns the bias from link to physical address and the image size.
+#[cfg(feature = "uefi")]
+fn allocate_higher_half_kernel(bs: &BootServices, data: &[u8]) -> Result<(usize, u64), &'static str> {
+    let start = lowest_load_page(data);
+    let end = program_headers(data)
+        .filter(|ph| ph.p_type == PT_LOAD && ph.p_memsz != 0)
+        .map(|ph| ph.p_vaddr.saturating_add(ph.p_memsz))
+        .max()
+        .unwrap_or(start);
+    let span = (end - start).next_multiple_of(PAGE_SIZE);
+    if span == 0 {
+        return Err("ELF has no loadable segments");
+    }
+    let base = bs
+        .allocate_pages(AllocateType::AnyPages, MemoryType::LOADER_DATA, (span / PAGE_SIZE) as usize)
+        .map_err(|_| "Failed to allocate pages")?;
+    Ok((base.wrapping_sub(start) as usize, span))
+}
+
+/// Page holding the lowest PT_LOAD address (the image base before biasing).
+#[cfg(feature = "uefi")]
+fn lowest_load_page(data: &[u8]) -> u64 {
+    program_headers(data)
+        .filter(|ph| ph.p_type == PT_LOAD && ph.p_memsz != 0)
+        .map(|ph| ph.p_vaddr & !(PAGE_SIZE - 1))
+        .min()
+        .unwrap_or(0)
+}
+
+/// True for PE/COFF images: `MZ` at 0 and `PE\0\0` at the offset stored at 60
+/// (e_lfanew). Linux kernels built with CONFIG_EFI_STUB look like this.
+pub fn is_efi_stub(data: &[u8]) -> bool {
+    if data.len() < 64 || &data[0..2] != b"MZ" {
+        return false;
+    }
+    let pe_offset = match read_u32(data, 60) {
+        Some(off) => off as usize,
+        None => return false,
+    };
+    pe_offset
+        .checked_add(4)
+        .and_then(|end| data.get(pe_offset..end))
+        == Some(&b"PE\0\0"[..])
+}
+
+/// Hand a PE/COFF kernel to the firmware via LoadImage/StartImage, with
+/// `cmdline` in its `LoadedImage.load_options`. Only returns if the kernel
+/// could not be started or exited back to us.
+#[cfg(feature = "uefi")]
+fn start_efi_stub(
+    st: &SystemTable<Boot>,
+    image_handle: Handle,
+    data: &[u8],
+    cmdline: &str,
+) -> Result<usize, &'static str> {
+    let bs = st.boot_services();
+    let kernel_handle = bs
+        .load_image(
+            image_handle,
+            LoadImageSource::FromBuffer {
+                buffer: data,
+                file_path: None,
+            },
+        )
+        .map_err(|_| "LoadImage failed")?;
+
+    // The EFI stub reads its command line as UCS-2 from load_options. The
+    // buffer must stay alive until start_image returns.
+    let mut options = [0u16; CMDLINE_UCS2_MAX];
+    let mut len = 0usize;
+    for c in cmdline.encode_utf16() {
+        if len + 1 >= CMDLINE_UCS2_MAX {
+            return Err("Kernel command line too long");
+        }
+        options[len] = c;
+        len += 1;
+    }
+    if len > 0 {
+        // Scope the exclusive open so the kernel can open LoadedImage itself
+        let mut loaded = bs
+            .open_protocol_exclusive::<LoadedImage>(kernel_handle)
+            .map_err(|_| "Failed to open LoadedImage for kernel")?;
+        unsafe {
+            loaded.set_load_options(options.as_ptr() as *const u8, ((len + 1) * 2) as u32);
+        }
+    }
+
+    bs.start_image(kernel_handle).map_err(|_| "StartImage failed")?;
+    Err("EFI stub kernel returned")
+}
+
+/// Open `path` for reading; returns the file and its size.
+#[cfg(feature = "uefi")]
+fn open_regular_file(root: &mut Directory, path: &str) -> Result<(RegularFile, usize), &'static str> {
+    let mut buf16 = [0u16; UEFI_PATH_MAX];
+    let cpath = normalize_uefi_path(path, &mut buf16).map_err(|_| "Invalid path")?;
     let file_handle = root.open(cpath, FileMode::Read, FileAttribute::empty()).map_err(|_| "Failed to open file")?;
-    
+
     let mut file = match file_handle.into_type().map_err(|_| "Invalid file type")? {
         File::Regular(f) => f,
         _ => return Err("Not a regular file"),
@@ -54,13 +701,280 @@ fn read_file_uefi(root: &mut Directory, path: &str) -> Result<Vec<u8>, &'static
 
     let info = file.get_info::<FileInfo>().map_err(|_| "Failed to get file info")?;
     let size = info.file_size() as usize;
-    let mut buf = vec![0u8; size];
-    file.read(&mut buf).map_err(|_| "Failed to read file")?;
+    Ok((file, size))
+}
+
+/// Read a file from UEFI SimpleFileSystem
+#[cfg(feature = "uefi")]
+fn read_file_uefi(st: &SystemTable<Boot>, root: &mut Directory, path: &str) -> Result<UefiBox<u8>, &'static str> {
+    let (mut file, size) = open_regular_file(root, path)?;
+    // SAFETY: boot services stay valid until ExitBootServices, and the buffer
+    // is dropped before the kernel is started
+    let bs: &'static BootServices = unsafe { &*(st.boot_services() as *const BootServices) };
+    let mut buf = UefiBox::new_filled(bs, size, 0u8)?;
+    read_file_with_progress(st, &mut file, buf.as_mut_slice())?;
     Ok(buf)
 }
 
-/// Parse ELF64 and load PT_LOAD segments
-fn parse_and_load_elf64(data: &[u8], load_addr: usize) -> Result<usize, &'static str> {
+/// Decompress a gzip kernel image into a new pool buffer.
+#[cfg(feature = "uefi")]
+fn inflate_kernel_uefi(st: &SystemTable<Boot>, data: &[u8]) -> Result<UefiBox<u8>, &'static str> {
+    use crate::compression::gzip;
+
+    let size = gzip::uncompressed_size(data).ok_or("gzip: truncated input")?;
+    if size == 0 || size > MAX_INFLATED_KERNEL {
+        return Err("gzip kernel: bad uncompressed size");
+    }
+    // SAFETY: as in `read_file_uefi`
+    let bs: &'static BootServices = unsafe { &*(st.boot_services() as *const BootServices) };
+    let mut out = UefiBox::new_filled(bs, size, 0u8)?;
+    // The trailer's size is checked by `inflate`, so the buffer is filled
+    gzip::inflate(data, out.as_mut_slice())?;
+    Ok(out)
+}
+
+/// Read chunk size; small enough for frequent progress updates on slow media.
+#[cfg(feature = "uefi")]
+const READ_CHUNK: usize = 64 * 1024;
+
+/// Fill `buf` from `file` in `READ_CHUNK` pieces, reporting progress on the
+/// UEFI console (there is no VGA text buffer to draw into here).
+#[cfg(feature = "uefi")]
+fn read_file_with_progress(
+    st: &SystemTable<Boot>,
+    file: &mut RegularFile,
+    buf: &mut [u8],
+) -> Result<(), &'static str> {
+    let total = buf.len();
+    let mut done = 0usize;
+    while done < total {
+        let end = core::cmp::min(done + READ_CHUNK, total);
+        let n = file.read(&mut buf[done..end]).map_err(|_| "Failed to read file")?;
+        if n == 0 {
+            return Err("Unexpected end of file");
+        }
+        done += n;
+        write!(st.stdout(), "\r[loader] {}%", done * 100 / total).ok();
+    }
+    writeln!(st.stdout()).ok();
+    Ok(())
+}
+
+/// Largest decompressed kernel accepted from a gzip image
+const MAX_INFLATED_KERNEL: usize = 64 * 1024 * 1024;
+
+/// BIOS path: read `path` from whichever filesystem stage2 mounted.
+#[cfg(feature = "bios")]
+fn read_kernel_file(path: &str) -> Result<crate::fs::FileBuffer, &'static str> {
+    use crate::fs::{ext, fat};
+
+    if ext::is_mounted() {
+        ext::read_file_with_progress(path, vga::PROGRESS_ROW)
+    } else if fat::is_mounted() {
+        fat::read_file(path)
+    } else {
+        Err("No filesystem mounted")
+    }
+}
+
+/// BIOS path: read the kernel from the mounted EXT or FAT filesystem and load
+/// it, trying `preferred` (the boot menu choice) before the built-in paths.
+/// Gzip-wrapped images are decompressed into pages from the memory manager
+/// first (see `load_gzip_image`).
+#[cfg(feature = "bios")]
+pub fn find_and_load_kernel(preferred: Option<&str>) -> Result<u32, &'static str> {
+    use crate::compression::gzip;
+
+    for path in preferred.into_iter().chain(KERNEL_PATHS.iter().copied()) {
+        let file = match read_kernel_file(path) {
+            Ok(f) => f,
+            Err(_) => continue,
+        };
+        // Found: what fails from here on is the image, not the search
+        crate::boot::stage2::set_phase(crate::boot::stage2::BootPhase::KernelLoad);
+        let data = file.as_slice();
+        if !gzip::is_gzip(data) {
+            return load_elf_image(data);
+        }
+
+        vga::print_string("[loader] Decompressing gzip kernel\n");
+        let size = gzip::uncompressed_size(data).ok_or("gzip: truncated input")?;
+        if size == 0 || size > MAX_INFLATED_KERNEL {
+            return Err("gzip kernel: bad uncompressed size");
+        }
+        return load_gzip_image(data, size.div_ceil(4096));
+    }
+    Err("No kernel found")
+}
+
+/// Inflate the gzip kernel `data` into `pages` heap pages and load it. The
+/// heap starts at 1 MiB, where kernels are usually linked, so a buffer that
+/// lands on a segment is given up: the segments are reserved and the image is
+/// inflated again into memory clear of them.
+#[cfg(feature = "bios")]
+fn load_gzip_image(data: &[u8], pages: usize) -> Result<u32, &'static str> {
+    use crate::memory::manager::global_free_pages;
+
+    let (buf, len) = inflate_to_pages(data, pages)?;
+    let image = unsafe { core::slice::from_raw_parts(buf, len) };
+    if let Err(e) = check_elf_image(image) {
+        global_free_pages(buf, pages);
+        return Err(e);
+    }
+    // Copied out of the image, which freeing the buffer overwrites
+    let ranges = load_ranges(image);
+
+    let (buf_start, buf_end) = (buf as usize, buf as usize + pages * 4096);
+    let (buf, len) = if ranges.iter().any(|&(start, size)| start < buf_end && buf_start < start + size) {
+        global_free_pages(buf, pages);
+        reserve_segments(&ranges)?;
+        inflate_to_pages(data, pages)?
+    } else {
+        if let Err(e) = reserve_segments(&ranges) {
+            global_free_pages(buf, pages);
+            return Err(e);
+        }
+        (buf, len)
+    };
+
+    let result = copy_elf_image(unsafe { core::slice::from_raw_parts(buf, len) });
+    global_free_pages(buf, pages);
+    result
+}
+
+/// Decompress `data` into `pages` fresh heap pages; returns them and the
+/// inflated length.
+#[cfg(feature = "bios")]
+fn inflate_to_pages(data: &[u8], pages: usize) -> Result<(*mut u8, usize), &'static str> {
+    let buf = crate::memory::allocate_pages(pages)?;
+    let out = unsafe { core::slice::from_raw_parts_mut(buf, pages * 4096) };
+    match crate::compression::gzip::inflate(data, out) {
+        Ok(len) => Ok((buf, len)),
+        Err(e) => {
+            crate::memory::manager::global_free_pages(buf, pages);
+            Err(e)
+        }
+    }
+}
+
+/// Set when the kernel loaded last is ELF64, which stage2 enters in long mode
+#[cfg(feature = "bios")]
+static mut ELF64_KERNEL: bool = false;
+
+#[cfg(feature = "bios")]
+fn load_elf_image(data: &[u8]) -> Result<u32, &'static str> {
+    check_elf_image(data)?;
+    reserve_segments(&load_ranges(data))?;
+    copy_elf_image(data)
+}
+
+/// Reject images this path cannot place: overlapping segments, or an ELF64
+/// kernel linked above the low 4 GiB. Segments are copied to their link
+/// address from 32-bit code, and stage2 only identity-maps the low 4 GiB for
+/// the long mode switch.
+#[cfg(feature = "bios")]
+fn check_elf_image(data: &[u8]) -> Result<(), &'static str> {
+    use crate::memory::paging::LOW_IDENTITY_LIMIT;
+
+    check_elf_segments_no_overlap(data)?;
+    let elf64 = data.get(4) == Some(&ELFCLASS64);
+    let above_4gib = program_headers(data)
+        .any(|ph| ph.p_type == PT_LOAD && ph.p_vaddr.saturating_add(ph.p_memsz) > LOW_IDENTITY_LIMIT);
+    if elf64 && above_4gib {
+        return Err("ELF64 kernel linked above 4 GiB");
+    }
+    Ok(())
+}
+
+/// `(address, size)` of every non-empty PT_LOAD segment of an image that
+/// passed `check_elf_image`.
+#[cfg(feature = "bios")]
+fn load_ranges(data: &[u8]) -> StaticVec<(usize, usize), MAX_LOAD_SEGMENTS> {
+    let mut ranges = StaticVec::new();
+    for ph in program_headers(data).filter(|ph| ph.p_type == PT_LOAD && ph.p_memsz != 0) {
+        // check_elf_image bounded the count
+        let _ = ranges.push((ph.p_vaddr as usize, ph.p_memsz as usize));
+    }
+    ranges
+}
+
+/// Take the kernel's memory out of the page allocator, whose heap starts at
+/// 1 MiB where kernels are usually linked, so the page tables and boot
+/// information built after the copy cannot land on it.
+#[cfg(feature = "bios")]
+fn reserve_segments(ranges: &StaticVec<(usize, usize), MAX_LOAD_SEGMENTS>) -> Result<(), &'static str> {
+    for &(start, size) in ranges.iter() {
+        crate::memory::reserve_for_kernel(start, size)?;
+    }
+    Ok(())
+}
+
+/// Copy a checked image to its link address and note how to enter it.
+#[cfg(feature = "bios")]
+fn copy_elf_image(data: &[u8]) -> Result<u32, &'static str> {
+    note_multiboot2(data);
+    let entry = load_elf(data, 0)?;
+    unsafe { ELF64_KERNEL = data.get(4) == Some(&ELFCLASS64) };
+    Ok(entry as u32)
+}
+
+/// Whether the loaded kernel is ELF64 and has to be entered in long mode
+/// (Multiboot2 kernels are entered in 32-bit mode whatever their class).
+#[cfg(feature = "bios")]
+pub fn long_mode_requested() -> bool {
+    unsafe { ELF64_KERNEL }
+}
+
+/// Load an ELF image of either class, chosen by EI_CLASS (`data[4]`).
+fn load_elf(data: &[u8], load_bias: usize) -> Result<usize, &'static str> {
+    match data.get(4) {
+        Some(&ELFCLASS32) => parse_and_load_elf32(data, load_bias),
+        Some(&ELFCLASS64) => parse_and_load_elf64(data, load_bias),
+        _ => Err("Unknown ELF class"),
+    }
+}
+
+/// Parse ELF32 and load PT_LOAD segments. `load_addr` is added to every
+/// `p_vaddr` like the bias of `parse_and_load_elf64` (0 loads at the link
+/// address); returns the entry point with the same offset.
+fn parse_and_load_elf32(data: &[u8], load_addr: usize) -> Result<usize, &'static str> {
+    if data.len() < 52 { return Err("ELF too small"); }
+    if &data[0..4] != b"\x7fELF" { return Err("Not ELF"); }
+    if data[4] != ELFCLASS32 { return Err("Not 32-bit ELF"); } // EI_CLASS
+    if data[5] != 1 { return Err("Not little-endian"); } // EI_DATA
+
+    // Entry point offset 24, 4 bytes in ELF32
+    let entry = read_u32(data, 24).ok_or("ELF too small")? as usize;
+    if !validate_entry_point(data, entry as u64) {
+        return Err("ELF entry point not in any loaded segment");
+    }
+
+    for ph in program_headers(data).filter(|ph| ph.p_type == PT_LOAD) {
+        let file_offset = ph.p_offset as usize;
+        let file_size = ph.p_filesz as usize;
+        let mem_size = ph.p_memsz as usize;
+        let virt_addr = (ph.p_vaddr as usize).wrapping_add(load_addr);
+
+        validate_bss_range(virt_addr as u64, ph.p_filesz, ph.p_memsz)?;
+        let src = data
+            .get(file_offset..file_offset.checked_add(file_size).ok_or("ELF segment offset overflow")?)
+            .ok_or("ELF segment outside file")?;
+
+        safe_copy(virt_addr as *mut u8, mem_size, src.as_ptr(), file_size)?;
+        unsafe {
+            // Zero BSS
+            if mem_size > file_size {
+                core::ptr::write_bytes((virt_addr + file_size) as *mut u8, 0, mem_size - file_size);
+            }
+        }
+    }
+
+    Ok(entry.wrapping_add(load_addr))
+}
+
+/// Parse ELF64 and load PT_LOAD segments: copy them to `p_vaddr + load_bias`
+/// and return the (biased) entry point.
+fn parse_and_load_elf64(data: &[u8], load_bias: usize) -> Result<usize, &'static str> {
     if data.len() < 64 { return Err("ELF too small"); }
     if &data[0..4] != b"\x7fELF" { return Err("Not ELF"); }
     if data[4] != 2 { return Err("Not 64-bit ELF"); } // EI_CLASS
@@ -68,6 +982,9 @@ fn parse_and_load_elf64(data: &[u8], load_addr: usize) -> Result<usize, &'static
 
     // Entry point offset 24
     let entry = u64::from_le_bytes(data[24..32].try_into().unwrap()) as usize;
+    if !validate_entry_point(data, entry as u64) {
+        return Err("ELF entry point not in any loaded segment");
+    }
 
     // Program header table
     let ph_offset = u64::from_le_bytes(data[32..40].try_into().unwrap()) as usize;
@@ -82,13 +999,18 @@ fn parse_and_load_elf64(data: &[u8], load_addr: usize) -> Result<usize, &'static
         if ph_type != 1 { continue; } // PT_LOAD
 
         let file_offset = u64::from_le_bytes(data[ph_base+8..ph_base+16].try_into().unwrap()) as usize;
-        let virt_addr = u64::from_le_bytes(data[ph_base+16..ph_base+24].try_into().unwrap()) as usize;
+        let virt_addr = (u64::from_le_bytes(data[ph_base+16..ph_base+24].try_into().unwrap()) as usize).wrapping_add(load_bias);
         let file_size = u64::from_le_bytes(data[ph_base+32..ph_base+40].try_into().unwrap()) as usize;
         let mem_size = u64::from_le_bytes(data[ph_base+40..ph_base+48].try_into().unwrap()) as usize;
 
+        validate_bss_range(virt_addr as u64, file_size as u64, mem_size as u64)?;
+        let src = data
+            .get(file_offset..file_offset.checked_add(file_size).ok_or("ELF segment offset overflow")?)
+            .ok_or("ELF segment outside file")?;
+
+        // Copy segment; the destination holds `mem_size` bytes
+        safe_copy(virt_addr as *mut u8, mem_size, src.as_ptr(), file_size)?;
         unsafe {
-            // Copy segment
-            copy_nonoverlapping(data[file_offset..file_offset+file_size].as_ptr(), virt_addr as *mut u8, file_size);
             // Zero BSS
             if mem_size > file_size {
                 core::ptr::write_bytes((virt_addr + file_size) as *mut u8, 0, mem_size - file_size);
@@ -96,18 +1018,609 @@ fn parse_and_load_elf64(data: &[u8], load_addr: usize) -> Result<usize, &'static
         }
     }
 
+    Ok(entry.wrapping_add(load_bias))
+}
+
+// ===== Boot status word =====
+//
+// Passed to 64-bit kernels in R8; the bit layout is documented with the entry
+// register convention in `boot::bootinfo`.
+
+pub const BOOT_FS_EXT2: u8 = 0;
+pub const BOOT_FS_FAT32: u8 = 1;
+
+pub const BOOT_DISK_ATA: u8 = 0;
+pub const BOOT_DISK_AHCI: u8 = 1;
+pub const BOOT_DISK_UEFI_BLOCK_IO: u8 = 2;
+
+pub fn boot_status_word(fs: u8, disk: u8, kaslr: bool) -> u64 {
+    (fs as u64) | ((disk as u64) << 8) | ((kaslr as u64) << 16)
+}
+
+// ===== Multiboot2 =====
+
+/// Set when the kernel loaded last carries a Multiboot2 header
+static mut MULTIBOOT2_KERNEL: bool = false;
+
+fn note_multiboot2(image: &[u8]) {
+    let found = multiboot2::find_header(image).is_some();
+    if found {
+        log_info!("loader", "Multiboot2 header found");
+    }
+    unsafe { MULTIBOOT2_KERNEL = found };
+}
+
+/// Whether the loaded kernel wants `multiboot2::BOOTLOADER_MAGIC` in EAX and
+/// a `Multiboot2Info` in EBX.
+pub fn multiboot2_requested() -> bool {
+    unsafe { MULTIBOOT2_KERNEL }
+}
+
+/// BIOS path: Multiboot2 information from the memory manager's region table
+/// and the VGA text console. No command line is passed on this path, so the
+/// tag is empty. Returns the physical address for EBX. The buffer comes from
+/// the heap the kernel was copied into, which is safe only because
+/// `load_elf_image` reserved the kernel's segments first.
+#[cfg(feature = "bios")]
+pub fn build_multiboot2_info() -> Result<u32, &'static str> {
+    use crate::boot::bootinfo::{BootMemoryRegion, BootMemoryType, region_to_boot_type};
+    use crate::memory::manager::MemoryRegionType;
+
+    const MAX_REGIONS: usize = 32;
+    let manager = crate::memory::manager::get_global_manager().ok_or("Memory manager not initialized")?;
+    let mut regions = [BootMemoryRegion { base: 0, length: 0, ty: BootMemoryType::Reserved }; MAX_REGIONS];
+    let mut count = 0;
+    for r in manager.get_regions().iter().flatten().take(MAX_REGIONS) {
+        let ty = region_to_boot_type(r.region_type);
+        regions[count] = BootMemoryRegion { base: r.start as u64, length: r.size as u64, ty };
+        count += 1;
+    }
+
+    let pages = multiboot2::info_size("", count).div_ceil(4096);
+    let buf = crate::memory::allocate_pages(pages)?;
+    let (start, end) = (buf as usize, buf as usize + pages * 4096);
+    let on_kernel = manager
+        .get_regions()
+        .iter()
+        .flatten()
+        .any(|r| r.region_type == MemoryRegionType::Kernel && r.start < end && start < r.start + r.size);
+    if on_kernel {
+        return Err("Multiboot2 info buffer overlaps the kernel");
+    }
+    let buf = unsafe { core::slice::from_raw_parts_mut(buf, pages * 4096) };
+    let mut info = multiboot2::Multiboot2Info::from_regions(buf, "", &regions[..count])?;
+    info.add_ega_text()?;
+    info.finish()
+}
+
+// ===== stivale2 =====
+
+/// Initial RSP for the kernel loaded last if it carries a stivale2 header,
+/// 0 otherwise
+#[cfg(feature = "uefi")]
+static mut STIVALE2_STACK: u64 = 0;
+
+/// Look for a `.stivale2hdr` in the image just loaded to run `load_bias`
+/// above its link address and set up what it asks for: a stack (16 KiB of LOADER_DATA
+/// when the header leaves it 0) and possibly a different entry point.
+/// Returns the entry point to jump to.
+#[cfg(feature = "uefi")]
+fn note_stivale2(bs: &BootServices, image: &[u8], load_bias: usize, entry: usize) -> Result<usize, &'static str> {
+    unsafe { STIVALE2_STACK = 0 };
+    let Some((offset, size)) = find_section(image, stivale2::HEADER_SECTION) else {
+        return Ok(entry);
+    };
+    let section = offset
+        .checked_add(size)
+        .and_then(|end| image.get(offset..end))
+        .filter(|s| s.len() >= stivale2::HEADER_SIZE)
+        .ok_or("stivale2 header truncated")?;
+    let tag_at = |vaddr: u64| vaddr_to_file_offset(image, vaddr).and_then(|off| image.get(off..));
+    let header = stivale2::Stivale2Header::parse(section, tag_at).ok_or("stivale2 header tag out of the image")?;
+    log_info!("loader", "stivale2 header found");
+    if header.terminal {
+        debug_log!("loader", "stivale2 terminal requested but not provided");
+    }
+
+    let stack = if header.stack != 0 {
+        header.stack.wrapping_add(load_bias as u64)
+    } else {
+        let pages = stivale2::LOADER_STACK_SIZE / PAGE_SIZE as usize;
+        let base = bs
+            .allocate_pages(AllocateType::AnyPages, MemoryType::LOADER_DATA, pages)
+            .map_err(|_| "Failed to allocate stivale2 stack")?;
+        base + stivale2::LOADER_STACK_SIZE as u64
+    };
+    unsafe { STIVALE2_STACK = stack };
+
+    if header.entry_point != 0 {
+        return Ok((header.entry_point as usize).wrapping_add(load_bias));
+    }
     Ok(entry)
 }
 
-/// Jump to kernel after exiting boot services
-pub fn jump_to_kernel(st: &SystemTable<Boot>, image_handle: Handle, entry_point: usize) -> ! {
-    let map_size = 4096 * 4;
-    let mut mem_map_buf = [0u8; 4096*4];
-    let (_key, _desc_iter) = st.boot_services().memory_map(&mut mem_map_buf)
-        .expect("Failed to get memory map");
+/// Whether the loaded kernel is entered the stivale2 way (see
+/// `jump_to_kernel`).
+#[cfg(feature = "uefi")]
+pub fn stivale2_requested() -> bool {
+    unsafe { STIVALE2_STACK != 0 }
+}
+
+/// Spare descriptors allocated on top of the reported map size: allocating
+/// the map buffer itself can split a region, and the map may grow between
+/// ExitBootServices attempts
+#[cfg(feature = "uefi")]
+const MEMORY_MAP_SLACK_ENTRIES: usize = 8;
+
+/// ExitBootServices calls before giving up on a map key that keeps going stale
+#[cfg(feature = "uefi")]
+const EXIT_BOOT_SERVICES_ATTEMPTS: u32 = 3;
+
+/// Jump to kernel after exiting boot services, with `boot_info` in RDI and
+/// `status` (see `boot_status_word`) in R8. Multiboot2 kernels are i386 code
+/// and get the magic in EAX and a `Multiboot2Info` in EBX in 32-bit
+/// protected mode instead; stivale2 kernels get only a `stivale2_struct` in
+/// RDI, on the stack their header asked for.
+///
+/// The memory map is read into LOADER_DATA pages right before
+/// ExitBootServices and recorded in `boot_info` as `BootMemoryRegion`s.
+/// The kernel runs on the loader's own page tables (`build_page_tables`)
+/// rather than the firmware's, which live in boot services memory. The SMP
+/// trampoline is installed on them for the kernel's APs.
+#[cfg(feature = "uefi")]
+pub fn jump_to_kernel(
+    st: &SystemTable<Boot>,
+    image_handle: Handle,
+    entry_point: usize,
+    status: u64,
+    boot_info: &'static mut BootInfo,
+) -> ! {
+    let bs = st.boot_services();
+    boot_info.collect_platform_info();
+
+    let sizes = bs.memory_map_size();
+    let map_size = sizes.map_size + MEMORY_MAP_SLACK_ENTRIES * sizes.entry_size;
+    let map_pages = map_size.div_ceil(4096);
+    let map_base = bs
+        .allocate_pages(AllocateType::AnyPages, MemoryType::LOADER_DATA, map_pages)
+        .expect("Failed to allocate memory map buffer");
+    let map_buf = unsafe { core::slice::from_raw_parts_mut(map_base as *mut u8, map_pages * 4096) };
+
+    // Sized and allocated now, filled in once the final map is known. EBX
+    // only holds 32 bits, so it has to sit below 4 GiB.
+    let regions = sizes.map_size / sizes.entry_size + MEMORY_MAP_SLACK_ENTRIES;
+    let stivale2_buf = if stivale2_requested() {
+        let pages = stivale2::struct_size(boot_info.cmdline_str(), regions).div_ceil(4096);
+        let base = bs
+            .allocate_pages(AllocateType::AnyPages, MemoryType::LOADER_DATA, pages)
+            .expect("Failed to allocate stivale2 structure");
+        Some(unsafe { core::slice::from_raw_parts_mut(base as *mut u8, pages * 4096) })
+    } else {
+        None
+    };
+    let multiboot2_buf = if multiboot2_requested() && stivale2_buf.is_none() {
+        let pages = multiboot2::info_size(boot_info.cmdline_str(), regions).div_ceil(4096);
+        let base = bs
+            .allocate_pages(AllocateType::MaxAddress(u32::MAX as u64), MemoryType::LOADER_DATA, pages)
+            .expect("Failed to allocate Multiboot2 information");
+        Some(unsafe { core::slice::from_raw_parts_mut(base as *mut u8, pages * 4096) })
+    } else {
+        None
+    };
 
-    st.exit_boot_services(image_handle, _key).expect("ExitBootServices failed");
+    // Built while boot services can still hand out pages, loaded after the
+    // exit: the firmware's tables are in boot services memory too
+    let tables = build_page_tables(boot_info).expect("Failed to build kernel page tables");
 
-    let kernel: extern "sysv64" fn() -> ! = unsafe { core::mem::transmute(entry_point) };
-    kernel();
+    // Page for the AP start-up code of `BootInfo` kernels, filled in once the
+    // tables above are live: their CR3 is the one the APs load. Without a
+    // free page below 1 MiB, `smp_trampoline` stays 0.
+    let trampoline_page = if stivale2_buf.is_none() && multiboot2_buf.is_none() {
+        let max = crate::smp::trampoline::REAL_MODE_LIMIT as u64 - 1;
+        bs.allocate_pages(AllocateType::MaxAddress(max), MemoryType::LOADER_DATA, 1).ok()
+    } else {
+        None
+    };
+
+    // Firmware events can change the map between GetMemoryMap and
+    // ExitBootServices, which then fails with INVALID_PARAMETER. Only
+    // GetMemoryMap may be called after a failed attempt (no allocations), so
+    // the buffer above is reused: `exit_boot_services` reads the map into it
+    // and exits with that key.
+    let mut attempt = 1;
+    let count = loop {
+        // SAFETY: boot services are not used through `st` once this succeeds
+        match unsafe { st.unsafe_clone() }.exit_boot_services(image_handle, &mut *map_buf) {
+            Ok((_runtime, desc_iter)) => break desc_iter.len(),
+            Err(e) if e.status() == Status::INVALID_PARAMETER && attempt < EXIT_BOOT_SERVICES_ATTEMPTS => attempt += 1,
+            Err(_) => panic!("ExitBootServices failed"),
+        }
+    };
+
+    // Converted only now: the map must be the one ExitBootServices accepted
+    unsafe { boot_info.set_memory_map(map_base as *mut u8, count, sizes.entry_size) };
+
+    let multiboot2_info = multiboot2_buf.map(|buf| {
+        multiboot2::Multiboot2Info::from_boot_info(buf, boot_info)
+            .and_then(|info| info.finish())
+            .expect("Failed to build Multiboot2 information")
+    });
+
+    // The firmware GDT is in boot services memory, which the kernel may reuse
+    unsafe {
+        crate::arch::gdt::init();
+        tables.load();
+    }
+
+    if let Some(buf) = stivale2_buf {
+        let info = stivale2::build(buf, boot_info).expect("Failed to build stivale2 structure");
+        enter_stivale2(entry_point, unsafe { STIVALE2_STACK }, info);
+    }
+    if let Some(info) = multiboot2_info {
+        enter_multiboot2_i386(entry_point as u32, info);
+    }
+    if let Some(page) = trampoline_page {
+        crate::smp::trampoline::install_trampoline(page as usize, entry_point as u64);
+        boot_info.smp_trampoline = page;
+    }
+
+    // Realign the stack so the kernel sees the usual SysV64 entry state
+    let info: *const BootInfo = boot_info;
+    unsafe {
+        core::arch::asm!(
+            "and rsp, -16",
+            "call {entry}",
+            "2: hlt",
+            "jmp 2b",
+            entry = in(reg) entry_point,
+            in("rdi") info,
+            in("r8") status,
+            options(noreturn)
+        );
+    }
+}
+
+/// Leave long mode and jump to an i386 Multiboot2 kernel: far return into the
+/// 32-bit code segment of `gdt`, paging and EFER.LME off, flat data segments,
+/// the magic in EAX and `info` in EBX. The loader's tables identity-map this
+/// code, so it keeps running once paging is off; `entry` and `info` are
+/// physical addresses below 4 GiB (checked at load time and allocated there).
+/// ESP is left undefined, as the specification allows.
+#[cfg(feature = "uefi")]
+fn enter_multiboot2_i386(entry: u32, info: u32) -> ! {
+    use crate::arch::gdt::{KERNEL_CODE32_SELECTOR, KERNEL_DATA_SELECTOR};
+
+    // RBX cannot be an asm operand, so the info goes via EDI
+    unsafe {
+        core::arch::asm!(
+            "cli",
+            "push {code32}",
+            "lea rax, [rip + 2f]",
+            "push rax",
+            "retfq",
+            ".code32",
+            "2:",
+            "mov eax, cr0",
+            "and eax, 0x7FFFFFFF",
+            "mov cr0, eax",
+            "mov ecx, 0xC0000080",
+            "rdmsr",
+            "and eax, 0xFFFFFEFF",
+            "wrmsr",
+            "mov eax, cr4",
+            "and eax, 0xFFFFFFDF",
+            "mov cr4, eax",
+            "mov ax, {data}",
+            "mov ds, ax",
+            "mov es, ax",
+            "mov fs, ax",
+            "mov gs, ax",
+            "mov ss, ax",
+            "mov ebx, edi",
+            "mov eax, {magic}",
+            "jmp esi",
+            ".code64",
+            code32 = const KERNEL_CODE32_SELECTOR,
+            data = const KERNEL_DATA_SELECTOR,
+            magic = const multiboot2::BOOTLOADER_MAGIC,
+            in("esi") entry,
+            in("edi") info,
+            options(noreturn)
+        );
+    }
+}
+
+/// Tables the kernel is entered with: everything up to the top of RAM, the
+/// framebuffer and `LOW_IDENTITY_LIMIT` mapped 1:1, read/write/execute. A
+/// higher-half kernel is also mapped at its link address, and stivale2
+/// kernels get the low 4 GiB again at `stivale2::HHDM_BASE`.
+#[cfg(feature = "uefi")]
+fn build_page_tables(boot_info: &BootInfo) -> Result<PageTableSet, &'static str> {
+    let ram_top = crate::memory::manager::get_global_manager().map_or(0, |m| {
+        m.get_regions().iter().flatten().map(|r| (r.start + r.size) as u64).max().unwrap_or(0)
+    });
+    let fb = &boot_info.framebuffer;
+    let limit = ram_top
+        .max(fb.base + fb.size)
+        .max(LOW_IDENTITY_LIMIT)
+        .next_multiple_of(LARGE_PAGE_SIZE);
+
+    let mut tables = PageTableSet::new()?;
+    tables.map_range(0, 0, limit, PageFlags::WRITABLE)?;
+    if let Some((virt, phys, size)) = unsafe { HIGHER_HALF_KERNEL } {
+        tables.map_kernel(virt, phys as usize, size as usize)?;
+    }
+    if stivale2_requested() {
+        tables.map_range(stivale2::HHDM_BASE, 0, LOW_IDENTITY_LIMIT, PageFlags::WRITABLE)?;
+    }
+    Ok(tables)
+}
+
+/// Switch to the kernel's stack and jump with the structure in RDI. The zero
+/// pushed first stands in for a return address, as if `entry` was called.
+#[cfg(feature = "uefi")]
+fn enter_stivale2(entry_point: usize, stack: u64, info: u64) -> ! {
+    unsafe {
+        core::arch::asm!(
+            "mov rsp, {stack}",
+            "and rsp, -16",
+            "push 0",
+            "jmp {entry}",
+            stack = in(reg) stack,
+            entry = in(reg) entry_point,
+            in("rdi") info,
+            options(noreturn)
+        );
+    }
+}
+
+// ===== ELF inspection helpers =====
+
+const SHT_SYMTAB: u32 = 2;
+const R_X86_64_NONE: u32 = 0;
+const R_X86_64_RELATIVE: u32 = 8;
+const ELF64_RELA_SIZE: usize = 24;
+const NT_GNU_BUILD_ID: u32 = 3;
+
+const KERNEL_VERSION_MAX: usize = 256;
+const LINUX_BANNER_PREFIX: &[u8] = b"Linux version ";
+
+/// Map a virtual address to its offset in the file via the PT_LOAD segments.
+fn vaddr_to_file_offset(data: &[u8], vaddr: u64) -> Option<usize> {
+    program_headers(data)
+        .filter(|ph| ph.p_type == PT_LOAD)
+        .find(|ph| vaddr >= ph.p_vaddr && vaddr < ph.p_vaddr.saturating_add(ph.p_filesz))
+        .map(|ph| (ph.p_offset + (vaddr - ph.p_vaddr)) as usize)
+}
+
+/// Find a note with `name` and `ty` in the PT_NOTE segments; returns its descriptor.
+fn find_elf_note<'a>(data: &'a [u8], name: &[u8], ty: u32) -> Option<&'a [u8]> {
+    let align4 = |v: usize| (v + 3) & !3;
+
+    for ph in program_headers(data).filter(|ph| ph.p_type == PT_NOTE) {
+        let start = ph.p_offset as usize;
+        let end = start.checked_add(ph.p_filesz as usize)?.min(data.len());
+        let mut off = start;
+        while off + 12 <= end {
+            let namesz = read_u32(data, off)? as usize;
+            let descsz = read_u32(data, off + 4)? as usize;
+            let n_type = read_u32(data, off + 8)?;
+            let name_off = off + 12;
+            let desc_off = name_off + align4(namesz);
+            let next = desc_off + align4(descsz);
+            if next > end {
+                break;
+            }
+            if n_type == ty && &data[name_off..name_off + namesz] == name {
+                return Some(&data[desc_off..desc_off + descsz]);
+            }
+            off = next;
+        }
+    }
+    None
+}
+
+/// GNU build-id (`NT_GNU_BUILD_ID` note), if the kernel carries one.
+pub fn extract_build_id(data: &[u8]) -> Option<&[u8]> {
+    find_elf_note(data, b"GNU\0", NT_GNU_BUILD_ID)
+}
+
+/// Look up `name` in the ELF64 `.symtab` and return its `st_value`.
+pub fn elf_symbol_lookup(data: &[u8], name: &str) -> Option<u64> {
+    let sh_offset = read_u64(data, 40)? as usize;
+    let sh_entry_size = read_u16(data, 58)? as usize;
+    let sh_count = read_u16(data, 60)? as usize;
+    if sh_entry_size < 64 {
+        return None;
+    }
+
+    let section = |i: usize| sh_offset.checked_add(i.checked_mul(sh_entry_size)?);
+
+    for i in 0..sh_count {
+        let sh = section(i)?;
+        if read_u32(data, sh + 4)? != SHT_SYMTAB {
+            continue;
+        }
+        let sym_off = read_u64(data, sh + 24)? as usize;
+        let sym_size = read_u64(data, sh + 32)? as usize;
+        let sym_ent = (read_u64(data, sh + 56)? as usize).max(24);
+
+        // sh_link points at the string table holding the symbol names
+        let strtab = section(read_u32(data, sh + 40)? as usize)?;
+        let str_off = read_u64(data, strtab + 24)? as usize;
+        let str_size = read_u64(data, strtab + 32)? as usize;
+        let strings = data.get(str_off..str_off.checked_add(str_size)?)?;
+
+        for s in 0..sym_size / sym_ent {
+            let sym = sym_off + s * sym_ent;
+            let st_name = read_u32(data, sym)? as usize;
+            let sym_name = match strings.get(st_name..) {
+                Some(rest) => &rest[..rest.iter().position(|&b| b == 0).unwrap_or(rest.len())],
+                None => continue,
+            };
+            if sym_name == name.as_bytes() {
+                return read_u64(data, sym + 8);
+            }
+        }
+    }
+    None
+}
+
+/// `(sh_offset, sh_size)` of the ELF64 section named `name`, resolved through
+/// the section header string table (`e_shstrndx`).
+fn find_section(data: &[u8], name: &str) -> Option<(usize, usize)> {
+    let sh_offset = read_u64(data, 40)? as usize;
+    let sh_entry_size = read_u16(data, 58)? as usize;
+    let sh_count = read_u16(data, 60)? as usize;
+    let shstrndx = read_u16(data, 62)? as usize;
+    if sh_entry_size < 64 {
+        return None;
+    }
+
+    let section = |i: usize| sh_offset.checked_add(i.checked_mul(sh_entry_size)?);
+    let strtab = section(shstrndx)?;
+    let str_off = read_u64(data, strtab + 24)? as usize;
+    let str_size = read_u64(data, strtab + 32)? as usize;
+    let strings = data.get(str_off..str_off.checked_add(str_size)?)?;
+
+    (0..sh_count).find_map(|i| {
+        let sh = section(i)?;
+        let rest = strings.get(read_u32(data, sh)? as usize..)?;
+        let sh_name = &rest[..rest.iter().position(|&b| b == 0).unwrap_or(rest.len())];
+        if sh_name != name.as_bytes() {
+            return None;
+        }
+        Some((read_u64(data, sh + 24)? as usize, read_u64(data, sh + 32)? as usize))
+    })
+}
+
+/// Apply the `.rela.dyn` `R_X86_64_RELATIVE` relocations of an image copied
+/// `copy_bias` bytes above its link address that will run `bias` bytes above
+/// it: each target becomes `bias + r_addend`. The two only differ for
+/// higher-half kernels, which run at their link address. Returns how many
+/// were applied; an image without `.rela.dyn` needs none.
+fn apply_relative_relocations(data: &[u8], copy_bias: u64, bias: u64) -> Result<usize, &'static str> {
+    let (offset, size) = match find_section(data, ".rela.dyn") {
+        Some(s) => s,
+        None => return Ok(0),
+    };
+    let relocs = data
+        .get(offset..offset.checked_add(size).ok_or("ELF .rela.dyn overflow")?)
+        .ok_or("ELF .rela.dyn outside file")?;
+
+    let mut applied = 0usize;
+    for rela in relocs.chunks_exact(ELF64_RELA_SIZE) {
+        let r_offset = read_u64(rela, 0).ok_or("ELF relocation truncated")?;
+        let r_info = read_u64(rela, 8).ok_or("ELF relocation truncated")?;
+        let r_addend = read_u64(rela, 16).ok_or("ELF relocation truncated")?;
+        match r_info as u32 {
+            R_X86_64_NONE => continue,
+            R_X86_64_RELATIVE => {}
+            _ => return Err("Unsupported relocation type in kernel"),
+        }
+
+        // The target must lie inside a loaded segment, with room for 8 bytes
+        let in_image = program_headers(data).any(|ph| {
+            ph.p_type == PT_LOAD && r_offset >= ph.p_vaddr && r_offset.saturating_add(8) <= ph.p_vaddr + ph.p_memsz
+        });
+        if !in_image {
+            return Err("ELF relocation outside loaded segments");
+        }
+        unsafe {
+            (r_offset.wrapping_add(copy_bias) as usize as *mut u64).write_unaligned(bias.wrapping_add(r_addend));
+        }
+        applied += 1;
+    }
+    Ok(applied)
+}
+
+/// Printable prefix of `bytes`, stopping at NUL/newline or `KERNEL_VERSION_MAX`.
+fn banner_str(bytes: &[u8]) -> Option<&str> {
+    let bytes = &bytes[..bytes.len().min(KERNEL_VERSION_MAX)];
+    let len = bytes
+        .iter()
+        .position(|&b| b == 0 || b == b'\n' || !(0x20..0x7F).contains(&b))
+        .unwrap_or(bytes.len());
+    if len == 0 {
+        return None;
+    }
+    core::str::from_utf8(&bytes[..len]).ok()
+}
+
+/// Best-effort kernel version string, as a slice into `data`.
+///
+/// A GNU note of type 3 is only a build-id (see `extract_build_id`), so it is
+/// not used here. Instead: resolve the `linux_banner` symbol and read the
+/// string it points to, or fall back to scanning the first PT_LOAD segment
+/// for `"Linux version "`.
+pub fn extract_kernel_version(data: &[u8]) -> Option<&str> {
+    if let Some(addr) = elf_symbol_lookup(data, "linux_banner") {
+        if let Some(off) = vaddr_to_file_offset(data, addr) {
+            if let Some(s) = data.get(off..).and_then(banner_str) {
+                return Some(s);
+            }
+        }
+    }
+
+    let seg = program_headers(data).find(|ph| ph.p_type == PT_LOAD)?;
+    let start = seg.p_offset as usize;
+    let end = start.checked_add(seg.p_filesz as usize)?.min(data.len());
+    let seg_data = data.get(start..end)?;
+    let pos = seg_data
+        .windows(LINUX_BANNER_PREFIX.len())
+        .position(|w| w == LINUX_BANNER_PREFIX)?;
+    banner_str(&seg_data[pos..])
+}
+
+const MAX_LOAD_SEGMENTS: usize = 32;
+
+/// Largest zero-filled tail (`mem_size - file_size`) accepted for one segment
+const MAX_BSS_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Reject segments whose zero-filled part would underflow, wrap the address
+/// space or be implausibly large, before anything is written.
+fn validate_bss_range(virt_addr: u64, file_size: u64, mem_size: u64) -> Result<(), &'static str> {
+    if mem_size < file_size {
+        return Err("ELF segment file size exceeds memory size");
+    }
+    if virt_addr.checked_add(mem_size).is_none() {
+        return Err("ELF segment wraps the address space");
+    }
+    if mem_size - file_size > MAX_BSS_SIZE {
+        return Err("ELF segment .bss too large");
+    }
+    Ok(())
+}
+
+/// Reject ELF images whose PT_LOAD segments overlap in virtual address space
+/// (a later copy would clobber an earlier one) or have `p_filesz > p_memsz`.
+pub fn check_elf_segments_no_overlap(data: &[u8]) -> Result<(), &'static str> {
+    let mut ranges = [(0u64, 0u64); MAX_LOAD_SEGMENTS];
+    let mut count = 0usize;
+
+    for ph in program_headers(data).filter(|ph| ph.p_type == PT_LOAD) {
+        if ph.p_filesz > ph.p_memsz {
+            return Err("ELF segment file_size > mem_size");
+        }
+        if ph.p_memsz == 0 {
+            continue;
+        }
+        let end = ph.p_vaddr.checked_add(ph.p_memsz).ok_or("ELF segment address overflow")?;
+        if count == MAX_LOAD_SEGMENTS {
+            return Err("Too many ELF PT_LOAD segments");
+        }
+        ranges[count] = (ph.p_vaddr, end);
+        count += 1;
+    }
+
+    for i in 0..count {
+        for j in (i + 1)..count {
+            let (a_start, a_end) = ranges[i];
+            let (b_start, b_end) = ranges[j];
+            if a_start < b_end && b_start < a_end {
+                return Err("ELF segments overlap");
+            }
+        }
+    }
+    Ok(())
 }
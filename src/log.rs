@@ -0,0 +1,62 @@
+//! `[module] message` logging to whichever console the build has: the VGA
+//! text buffer on BIOS, the firmware console on UEFI. Every line is mirrored
+//! to the serial port once `drivers::serial::init` has succeeded.
+//!
+//! `log_info!` is always on and meant for messages a user needs (kernel found,
+//! errors). `debug_log!` is dead code without `debug_assertions`, so it can
+//! be used anywhere an expression can while release images drop its format
+//! strings.
+
+use core::fmt;
+
+/// Always-on log line: `log_info!("disk", "read {} sectors", n)`.
+#[macro_export]
+macro_rules! log_info {
+    ($module:expr, $($arg:tt)*) => {
+        $crate::log::write_line($module, format_args!($($arg)*))
+    };
+}
+
+/// Like `log_info!`, but only in debug builds.
+#[macro_export]
+macro_rules! debug_log {
+    ($module:expr, $($arg:tt)*) => {
+        if cfg!(debug_assertions) {
+            $crate::log_info!($module, $($arg)*)
+        }
+    };
+}
+
+/// The build's screen console plus the serial port, if any.
+struct Console;
+
+impl fmt::Write for Console {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        #[cfg(feature = "bios")]
+        crate::drivers::vga::print_string(s);
+        #[cfg(feature = "uefi")]
+        {
+            // Set by `uefi_services::init` before anything is logged
+            let st = unsafe { uefi_services::system_table().as_mut() };
+            let _ = fmt::Write::write_str(st.stdout(), s);
+        }
+        if let Some(port) = crate::drivers::serial::console_port() {
+            crate::drivers::serial::write_str(port, s);
+        }
+        Ok(())
+    }
+}
+
+/// `[<ms>ms] ` once the TSC clock has been calibrated, nothing before.
+fn write_prefix(out: &mut dyn fmt::Write, module: &str) -> fmt::Result {
+    if crate::arch::x86::tsc_clock_ready() {
+        write!(out, "[{}ms] ", crate::arch::x86::tsc_ms())?;
+    }
+    write!(out, "[{}] ", module)
+}
+
+pub fn write_line(module: &str, args: fmt::Arguments) {
+    use fmt::Write;
+    let _ = write_prefix(&mut Console, module);
+    let _ = writeln!(Console, "{}", args);
+}
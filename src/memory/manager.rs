@@ -1,7 +1,8 @@
 use crate::memory::mem;
 use core::cell::UnsafeCell;
-use core::ptr::null_mut;
 use spin::Mutex;
+#[cfg(feature = "uefi")]
+use uefi::table::boot::{MemoryDescriptor, MemoryType};
 
 // Memory constants for bootloader environment
 const MEMORY_START: usize = 0x100000; // 1MB - above conventional memory
@@ -9,6 +10,9 @@ const MEMORY_END: usize = 0x800000; // 8MB - safe upper limit for bootloader
 const PAGE_SIZE: usize = 4096;
 const MAX_REGIONS: usize = 32;
 
+/// Buddy orders 0..MAX_ORDER: blocks of 4 KiB up to 2 GiB
+pub const MAX_ORDER: usize = 20;
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum MemoryRegionType {
     Available,
@@ -20,6 +24,24 @@ pub enum MemoryRegionType {
     Kernel,
 }
 
+/// Category of a firmware memory type. Boot services and loader memory is
+/// `Bootloader`: free to reclaim once boot services have exited.
+#[cfg(feature = "uefi")]
+pub fn from_uefi_type(ty: MemoryType) -> MemoryRegionType {
+    match ty {
+        MemoryType::CONVENTIONAL => MemoryRegionType::Available,
+        MemoryType::LOADER_CODE
+        | MemoryType::LOADER_DATA
+        | MemoryType::BOOT_SERVICES_CODE
+        | MemoryType::BOOT_SERVICES_DATA => MemoryRegionType::Bootloader,
+        MemoryType::RUNTIME_SERVICES_CODE | MemoryType::RUNTIME_SERVICES_DATA => MemoryRegionType::Reserved,
+        MemoryType::ACPI_RECLAIM => MemoryRegionType::AcpiReclaim,
+        MemoryType::ACPI_NON_VOLATILE => MemoryRegionType::AcpiNvs,
+        MemoryType::UNUSABLE => MemoryRegionType::BadMemory,
+        _ => MemoryRegionType::Reserved,
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct MemoryRegion {
     pub start: usize,
@@ -27,13 +49,34 @@ pub struct MemoryRegion {
     pub region_type: MemoryRegionType,
 }
 
+/// Free list node, stored in the first bytes of the free block itself
+struct FreeBlock {
+    next: Option<*mut FreeBlock>,
+}
+
+/// Header bytes of a free block that belong to the allocator
+pub const FREE_BLOCK_HEADER: usize = core::mem::size_of::<FreeBlock>();
+
+/// Smallest order whose block holds `pages` pages.
+pub fn order_for_pages(pages: usize) -> usize {
+    pages.max(1).next_power_of_two().trailing_zeros() as usize
+}
+
+fn order_size(order: usize) -> usize {
+    PAGE_SIZE << order
+}
+
 pub struct MemoryManager {
     regions: [Option<MemoryRegion>; MAX_REGIONS],
     region_count: usize,
     heap_start: usize,
-    heap_current: usize,
     heap_end: usize,
     allocated_bytes: usize,
+    /// Buddy free lists, one per order. Blocks are aligned to their size.
+    free_lists: [Option<*mut FreeBlock>; MAX_ORDER],
+    /// Free lists are built on first use: on UEFI the heap must not be
+    /// touched before ExitBootServices
+    buddy_ready: bool,
 }
 
 impl MemoryManager {
@@ -42,9 +85,10 @@ impl MemoryManager {
             regions: [None; MAX_REGIONS],
             region_count: 0,
             heap_start: MEMORY_START,
-            heap_current: MEMORY_START,
             heap_end: MEMORY_END,
             allocated_bytes: 0,
+            free_lists: [None; MAX_ORDER],
+            buddy_ready: false,
         };
 
         // Initialize with basic memory layout
@@ -72,6 +116,52 @@ impl MemoryManager {
         });
     }
 
+    /// Build the region table from a UEFI memory map. Adjacent descriptors of
+    /// the same category are merged so typical maps fit in `MAX_REGIONS`; the
+    /// heap is the largest `Available` region. Only allocate from it after
+    /// ExitBootServices, before that the firmware may still hand it out.
+    #[cfg(feature = "uefi")]
+    pub fn from_uefi_map<'a>(map: impl Iterator<Item = &'a MemoryDescriptor>) -> Self {
+        let mut manager = MemoryManager {
+            regions: [None; MAX_REGIONS],
+            region_count: 0,
+            heap_start: 0,
+            heap_end: 0,
+            allocated_bytes: 0,
+            free_lists: [None; MAX_ORDER],
+            buddy_ready: false,
+        };
+
+        for desc in map {
+            let region = MemoryRegion {
+                start: desc.phys_start as usize,
+                size: desc.page_count as usize * PAGE_SIZE,
+                region_type: from_uefi_type(desc.ty),
+            };
+            let last = manager.region_count.checked_sub(1).and_then(|i| manager.regions[i].as_mut());
+            if let Some(last) = last {
+                if last.region_type == region.region_type && last.start + last.size == region.start {
+                    last.size += region.size;
+                    continue;
+                }
+            }
+            manager.add_region(region);
+        }
+
+        let largest = manager
+            .regions
+            .iter()
+            .flatten()
+            .filter(|r| r.region_type == MemoryRegionType::Available)
+            .max_by_key(|r| r.size)
+            .copied();
+        if let Some(r) = largest {
+            manager.heap_start = r.start;
+            manager.heap_end = r.start + r.size;
+        }
+        manager
+    }
+
     fn add_region(&mut self, region: MemoryRegion) {
         if self.region_count < MAX_REGIONS {
             self.regions[self.region_count] = Some(region);
@@ -79,88 +169,200 @@ impl MemoryManager {
         }
     }
 
-    /// Simple bump allocator for bootloader use
-    pub fn allocate(&mut self, size: usize) -> Option<*mut u8> {
-        if size == 0 {
-            return None;
+    // ===== Buddy allocator =====
+
+    /// Carve the heap into the largest naturally aligned blocks that fit.
+    fn ensure_buddy_ready(&mut self) {
+        if self.buddy_ready {
+            return;
+        }
+        self.buddy_ready = true;
+        self.free_lists = [None; MAX_ORDER];
+
+        let mut addr = (self.heap_start + PAGE_SIZE - 1) & !(PAGE_SIZE - 1);
+        let end = self.heap_end & !(PAGE_SIZE - 1);
+        while addr < end {
+            let mut order = MAX_ORDER - 1;
+            while addr % order_size(order) != 0 || addr + order_size(order) > end {
+                order -= 1;
+            }
+            self.push_free(addr, order);
+            addr += order_size(order);
         }
+    }
+
+    fn push_free(&mut self, addr: usize, order: usize) {
+        let block = addr as *mut FreeBlock;
+        unsafe {
+            block.write(FreeBlock { next: self.free_lists[order] });
+        }
+        self.free_lists[order] = Some(block);
+    }
 
-        // Align to 8-byte boundary
-        let aligned_size = (size + 7) & !7;
+    fn pop_free(&mut self, order: usize) -> Option<usize> {
+        let block = self.free_lists[order]?;
+        self.free_lists[order] = unsafe { (*block).next };
+        Some(block as usize)
+    }
 
-        // Check if we have enough space
-        if self.heap_current + aligned_size > self.heap_end {
+    /// Unlink the free block at `addr` from the `order` list, if it is there.
+    fn take_free(&mut self, addr: usize, order: usize) -> bool {
+        let mut prev: Option<*mut FreeBlock> = None;
+        let mut cur = self.free_lists[order];
+        while let Some(block) = cur {
+            let next = unsafe { (*block).next };
+            if block as usize == addr {
+                match prev {
+                    Some(p) => unsafe { (*p).next = next },
+                    None => self.free_lists[order] = next,
+                }
+                return true;
+            }
+            prev = Some(block);
+            cur = next;
+        }
+        false
+    }
+
+    /// Allocate a block of `PAGE_SIZE << order` bytes, splitting a larger
+    /// block when the list for `order` is empty. Not zeroed.
+    pub fn allocate_pages(&mut self, order: usize) -> Option<*mut u8> {
+        if order >= MAX_ORDER {
             return None;
         }
+        self.ensure_buddy_ready();
 
-        let ptr = self.heap_current as *mut u8;
-        self.heap_current += aligned_size;
-        self.allocated_bytes += aligned_size;
+        let from = (order..MAX_ORDER).find(|&o| self.free_lists[o].is_some())?;
+        let addr = self.pop_free(from)?;
+        // Keep the lower half, free the upper halves down to `order`
+        for o in (order..from).rev() {
+            self.push_free(addr + order_size(o), o);
+        }
 
-        // Zero the allocated memory
-        unsafe {
-            mem::memset(ptr, 0, aligned_size);
+        self.allocated_bytes += order_size(order);
+        Some(addr as *mut u8)
+    }
+
+    /// Return a block from `allocate_pages(order)`, merging it with its buddy
+    /// for as long as the buddy is free too.
+    pub fn free_pages(&mut self, ptr: *mut u8, order: usize) {
+        let mut addr = ptr as usize;
+        if order >= MAX_ORDER
+            || addr % order_size(order) != 0
+            || addr < self.heap_start
+            || addr + order_size(order) > self.heap_end
+        {
+            debug_log!("memory", "free_pages: bad block 0x{:x} order {}", addr, order);
+            return;
         }
+        self.allocated_bytes = self.allocated_bytes.saturating_sub(order_size(order));
 
-        Some(ptr)
+        let mut order = order;
+        while order + 1 < MAX_ORDER {
+            let buddy = addr ^ order_size(order);
+            if !self.take_free(buddy, order) {
+                break;
+            }
+            addr = addr.min(buddy);
+            order += 1;
+        }
+        self.push_free(addr, order);
     }
 
-    /// Allocate aligned memory (useful for page-aligned allocations)
-    pub fn allocate_aligned(&mut self, size: usize, alignment: usize) -> Option<*mut u8> {
-        if size == 0 || alignment == 0 || !alignment.is_power_of_two() {
-            return None;
+    /// Remove `[start, end)` from the free lists. Returns the bytes that were
+    /// free; the rest of the range was already allocated (or outside the heap).
+    fn claim_range(&mut self, start: usize, end: usize) -> usize {
+        let mut claimed = 0;
+        for order in (0..MAX_ORDER).rev() {
+            let size = order_size(order);
+            // Splitting only pushes onto lower lists, so rescanning this one ends
+            loop {
+                let mut hit = None;
+                let mut cur = self.free_lists[order];
+                while let Some(block) = cur {
+                    let b = block as usize;
+                    if b < end && b + size > start {
+                        hit = Some(b);
+                        break;
+                    }
+                    cur = unsafe { (*block).next };
+                }
+                let b = match hit {
+                    Some(b) => b,
+                    None => break,
+                };
+                self.take_free(b, order);
+                if (b >= start && b + size <= end) || order == 0 {
+                    claimed += size;
+                } else {
+                    self.push_free(b, order - 1);
+                    self.push_free(b + size / 2, order - 1);
+                }
+            }
         }
+        claimed
+    }
 
-        // Calculate aligned start address
-        let aligned_current = (self.heap_current + alignment - 1) & !(alignment - 1);
+    /// Call `f(start, len)` for the bytes of every free block past its list
+    /// node (e.g. for the RAM test, which must not clobber the links).
+    pub fn for_each_free_range(&mut self, mut f: impl FnMut(usize, usize)) {
+        self.ensure_buddy_ready();
+        for order in 0..MAX_ORDER {
+            let mut cur = self.free_lists[order];
+            while let Some(block) = cur {
+                f(block as usize + FREE_BLOCK_HEADER, order_size(order) - FREE_BLOCK_HEADER);
+                cur = unsafe { (*block).next };
+            }
+        }
+    }
 
-        // Check if we have enough space
-        if aligned_current + size > self.heap_end {
+    /// Allocate `size` bytes, zeroed, in whole pages
+    pub fn allocate(&mut self, size: usize) -> Option<*mut u8> {
+        self.allocate_aligned(size, 8)
+    }
+
+    /// Allocate zeroed memory aligned to `alignment`. Buddy blocks are
+    /// aligned to their size, so the order is also raised to the alignment.
+    pub fn allocate_aligned(&mut self, size: usize, alignment: usize) -> Option<*mut u8> {
+        if size == 0 || alignment == 0 || !alignment.is_power_of_two() {
             return None;
         }
 
-        // Update heap pointer to aligned position
-        self.heap_current = aligned_current + size;
-        self.allocated_bytes += size;
-
-        let ptr = aligned_current as *mut u8;
+        let pages = size.max(alignment).div_ceil(PAGE_SIZE);
+        let order = order_for_pages(pages);
+        let ptr = self.allocate_pages(order)?;
 
         // Zero the allocated memory
         unsafe {
-            mem::memset(ptr, 0, size);
+            mem::memset(ptr, 0, order_size(order));
         }
 
         Some(ptr)
     }
 
-    /// Allocate page-aligned memory
-    pub fn allocate_pages(&mut self, page_count: usize) -> Option<*mut u8> {
-        let size = page_count * PAGE_SIZE;
-        self.allocate_aligned(size, PAGE_SIZE)
-    }
-
     /// Get memory statistics
     pub fn get_stats(&self) -> MemoryStats {
         MemoryStats {
             total_memory: self.heap_end - self.heap_start,
             used_memory: self.allocated_bytes,
-            free_memory: self.heap_end - self.heap_current,
+            free_memory: self.available_memory(),
             heap_start: self.heap_start,
-            heap_current: self.heap_current,
             heap_end: self.heap_end,
         }
     }
 
     /// Reserve memory region (useful for kernel loading)
     pub fn reserve_region(&mut self, start: usize, size: usize) -> Result<(), &'static str> {
-        // Check if the region conflicts with our heap
-        if start < self.heap_current && start + size > self.heap_start {
-            return Err("Cannot reserve region that conflicts with allocated memory");
-        }
-
-        // If the region is at the end of our heap, reduce available space
-        if start >= self.heap_current && start < self.heap_end {
-            self.heap_end = start;
+        // Take the heap part of the region out of the free lists
+        let lo = start.max(self.heap_start) & !(PAGE_SIZE - 1);
+        let hi = ((start + size).min(self.heap_end) + PAGE_SIZE - 1) & !(PAGE_SIZE - 1);
+        if lo < hi {
+            self.ensure_buddy_ready();
+            let claimed = self.claim_range(lo, hi);
+            self.allocated_bytes += claimed;
+            if claimed < hi - lo {
+                return Err("Cannot reserve region that conflicts with allocated memory");
+            }
         }
 
         self.add_region(MemoryRegion {
@@ -211,16 +413,14 @@ impl MemoryManager {
         &self.regions[..self.region_count]
     }
 
-    /// Simple deallocation (bootloader typically doesn't need this)
-    #[allow(unused_variables)]
+    /// Free memory from `allocate`/`allocate_aligned` of `size` bytes
     pub fn deallocate(&mut self, ptr: *mut u8, size: usize) {
-        // In a bootloader, we typically don't deallocate memory
-        // This is a placeholder for future implementation
+        self.free_pages(ptr, order_for_pages(size.div_ceil(PAGE_SIZE)));
     }
 
     /// Reset allocator to initial state (useful for cleanup)
     pub fn reset(&mut self) {
-        self.heap_current = self.heap_start;
+        self.buddy_ready = false;
         self.allocated_bytes = 0;
     }
 }
@@ -231,7 +431,6 @@ pub struct MemoryStats {
     pub used_memory: usize,
     pub free_memory: usize,
     pub heap_start: usize,
-    pub heap_current: usize,
     pub heap_end: usize,
 }
 
@@ -245,6 +444,15 @@ pub fn init_global_manager() {
     }
 }
 
+/// Replace the global manager with one built from the firmware memory map.
+#[cfg(feature = "uefi")]
+pub fn init_from_uefi_map<'a>(map: impl Iterator<Item = &'a MemoryDescriptor>) {
+    let mut guard = MEMORY_MANAGER.lock();
+    unsafe {
+        *guard.get() = Some(MemoryManager::from_uefi_map(map));
+    }
+}
+
 pub fn get_global_manager() -> Option<&'static mut MemoryManager> {
     let mut guard = MEMORY_MANAGER.lock();
     unsafe { (*guard.get()).as_mut() }
@@ -255,9 +463,18 @@ pub fn global_allocate(size: usize) -> Option<*mut u8> {
     unsafe { (*guard.get()).as_mut()?.allocate(size) }
 }
 
+/// Allocate at least `count` pages (rounded up to a power of two).
 pub fn global_allocate_pages(count: usize) -> Option<*mut u8> {
     let mut guard = MEMORY_MANAGER.lock();
-    unsafe { (*guard.get()).as_mut()?.allocate_pages(count) }
+    unsafe { (*guard.get()).as_mut()?.allocate_pages(order_for_pages(count)) }
+}
+
+/// Free pages from `global_allocate_pages(count)`.
+pub fn global_free_pages(ptr: *mut u8, count: usize) {
+    let mut guard = MEMORY_MANAGER.lock();
+    if let Some(manager) = unsafe { (*guard.get()).as_mut() } {
+        manager.free_pages(ptr, order_for_pages(count));
+    }
 }
 
 // Helper functions for common operations
@@ -275,6 +492,6 @@ impl MemoryManager {
 
     /// Get available memory in bytes
     pub fn available_memory(&self) -> usize {
-        self.heap_end - self.heap_current
+        (self.heap_end - self.heap_start).saturating_sub(self.allocated_bytes)
     }
 }
@@ -1,4 +1,6 @@
-#[unsafe(no_mangle)]
+// Exported as the C symbols only on the BIOS build, where build-std does not
+// provide them; the UEFI target already links its own.
+#[cfg_attr(feature = "bios", unsafe(no_mangle))]
 pub extern "C" fn memcpy(dest: *mut u8, src: *const u8, n: usize) -> *mut u8 {
     let mut i = 0;
     unsafe {
@@ -10,7 +12,7 @@ pub extern "C" fn memcpy(dest: *mut u8, src: *const u8, n: usize) -> *mut u8 {
     dest
 }
 
-#[unsafe(no_mangle)]
+#[cfg_attr(feature = "bios", unsafe(no_mangle))]
 pub extern "C" fn memset(s: *mut u8, c: i32, n: usize) -> *mut u8 {
     let mut i = 0;
     unsafe {
@@ -22,7 +24,7 @@ pub extern "C" fn memset(s: *mut u8, c: i32, n: usize) -> *mut u8 {
     s
 }
 
-#[unsafe(no_mangle)]
+#[cfg_attr(feature = "bios", unsafe(no_mangle))]
 pub extern "C" fn memcmp(s1: *const u8, s2: *const u8, n: usize) -> i32 {
     for i in 0..n {
         let a = unsafe { *s1.add(i) };
@@ -33,3 +35,16 @@ pub extern "C" fn memcmp(s1: *const u8, s2: *const u8, n: usize) -> i32 {
     }
     0
 }
+
+/// `memcpy` that refuses to write past `dest_cap` bytes or to copy between
+/// overlapping ranges.
+pub fn safe_copy(dest: *mut u8, dest_cap: usize, src: *const u8, n: usize) -> Result<(), &'static str> {
+    if n > dest_cap {
+        return Err("copy: n > dest_cap");
+    }
+    if (dest as usize).abs_diff(src as usize) < n {
+        return Err("copy: overlapping ranges");
+    }
+    memcpy(dest, src, n);
+    Ok(())
+}
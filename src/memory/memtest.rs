@@ -0,0 +1,67 @@
+//! Walking-ones RAM test (`feature = "memtest"`).
+//!
+//! Catches stuck or shorted data bits before a flaky DIMM turns into random
+//! kernel crashes. Destructive: only run it on memory nothing lives in yet.
+
+use core::ptr::{read_volatile, write_volatile};
+
+use super::manager::get_global_manager;
+use crate::drivers::vga;
+
+/// Write each of the 32 single-bit patterns to every aligned dword of
+/// `[start, start + size)` and read it back. `Err` holds the first bad address.
+pub fn test_memory_region(start: usize, size: usize) -> Result<(), usize> {
+    let first = (start + 3) & !3;
+    let end = start.saturating_add(size) & !3;
+
+    let mut addr = first;
+    while addr < end {
+        let p = addr as *mut u32;
+        for bit in 0..32 {
+            let pattern = 1u32 << bit;
+            unsafe {
+                write_volatile(p, pattern);
+                if read_volatile(p) != pattern {
+                    return Err(addr);
+                }
+            }
+        }
+        unsafe { write_volatile(p, 0) };
+        addr += 4;
+    }
+    Ok(())
+}
+
+/// Test the free blocks of the page allocator below `limit` (the kernel load
+/// address). Allocated blocks and the free list links are left alone.
+pub fn run(limit: usize) -> Result<(), usize> {
+    let manager = match get_global_manager() {
+        Some(m) => m,
+        None => return Ok(()),
+    };
+
+    let mut result = Ok(());
+    manager.for_each_free_range(|start, len| {
+        let end = (start + len).min(limit);
+        if result.is_ok() && start < end {
+            result = test_memory_region(start, end - start);
+        }
+    });
+    result
+}
+
+/// Run the test and print `[memtest] OK` or `[memtest] FAIL at 0x...`.
+pub fn run_and_report(limit: usize) -> bool {
+    match run(limit) {
+        Ok(()) => {
+            vga::print_string("[memtest] OK\n");
+            true
+        }
+        Err(addr) => {
+            vga::print_string("[memtest] FAIL at ");
+            vga::print_hex32(addr as u32);
+            vga::print_string("\n");
+            false
+        }
+    }
+}
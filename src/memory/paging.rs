@@ -0,0 +1,238 @@
+//! Four-level (x86_64) page tables for the kernel hand-off.
+//!
+//! Tables are built while the loader still runs identity-mapped, so table
+//! addresses are used directly as pointers. On the BIOS path they come from
+//! `memory::allocate_pages`; on the UEFI path from LOADER_DATA pages of boot
+//! services, since the memory manager's heap is only usable after
+//! ExitBootServices and the tables have to be ready before it. They are kept
+//! below 4 GiB there, because the SMP trampoline loads CR3 from 32-bit code.
+//! Ranges are
+//! mapped with 2 MiB pages where alignment allows.
+//!
+//! `EXECUTE_DISABLE` is only written to entries when CPUID reports NX; on
+//! older CPUs it is dropped, since the bit is reserved there.
+
+use core::ops::BitOr;
+
+#[cfg(feature = "uefi")]
+use uefi::table::boot::{AllocateType, MemoryType};
+
+use crate::arch::cpuid::CpuInfo;
+
+const PAGE_SIZE: u64 = 0x1000;
+pub const LARGE_PAGE_SIZE: u64 = 0x20_0000;
+const ENTRIES: usize = 512;
+const ADDR_MASK: u64 = 0x000F_FFFF_FFFF_F000;
+
+/// Identity-mapped at least: the RAM a 32-bit loader can reach and the
+/// usual platform MMIO (LAPIC, I/O APIC, HPET)
+pub const LOW_IDENTITY_LIMIT: u64 = 0x1_0000_0000;
+
+const MSR_EFER: u32 = 0xC000_0080;
+const EFER_NXE: u32 = 1 << 11;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PageFlags(u64);
+
+impl PageFlags {
+    pub const PRESENT: Self = Self(1 << 0);
+    pub const WRITABLE: Self = Self(1 << 1);
+    pub const USER_ACCESSIBLE: Self = Self(1 << 2);
+    pub const WRITE_THROUGH: Self = Self(1 << 3);
+    pub const NO_CACHE: Self = Self(1 << 4);
+    /// PS bit in a PD entry: maps a 2 MiB page
+    const HUGE: Self = Self(1 << 7);
+    pub const GLOBAL: Self = Self(1 << 8);
+    /// Reserved (and faults) unless EFER.NXE is set; see `enable_nx`
+    pub const EXECUTE_DISABLE: Self = Self(1 << 63);
+
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    pub const fn bits(self) -> u64 {
+        self.0
+    }
+
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    const fn without(self, other: Self) -> Self {
+        Self(self.0 & !other.0)
+    }
+}
+
+impl BitOr for PageFlags {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+#[repr(C, align(4096))]
+struct PageTable([u64; ENTRIES]);
+
+/// Allocate a zeroed table.
+fn new_table() -> Result<*mut PageTable, &'static str> {
+    #[cfg(feature = "bios")]
+    let page = super::allocate_pages(1).map_err(|_| "paging: out of memory for page tables")?;
+    #[cfg(feature = "uefi")]
+    let page = unsafe { uefi_services::system_table().as_ref() }
+        .boot_services()
+        .allocate_pages(AllocateType::MaxAddress(LOW_IDENTITY_LIMIT - 1), MemoryType::LOADER_DATA, 1)
+        .map_err(|_| "paging: out of memory for page tables")? as *mut u8;
+    let table = page as *mut PageTable;
+    unsafe {
+        table.write(PageTable([0; ENTRIES]));
+    }
+    Ok(table)
+}
+
+#[inline]
+fn index(virt: u64, level: u32) -> usize {
+    ((virt >> (12 + 9 * level)) & 0x1FF) as usize
+}
+
+/// Bits 63:48 must copy bit 47.
+fn is_canonical(virt: u64) -> bool {
+    let upper = virt >> 47;
+    upper == 0 || upper == 0x1_FFFF
+}
+
+/// Set EFER.NXE if the CPU supports it, so `EXECUTE_DISABLE` entries are
+/// valid. Works from 32-bit protected mode too, before the long mode switch.
+/// Returns whether NX is now enabled.
+pub unsafe fn enable_nx() -> bool {
+    if !CpuInfo::read().has_nx {
+        return false;
+    }
+    unsafe {
+        core::arch::asm!(
+            "rdmsr",
+            "or eax, {nxe}",
+            "wrmsr",
+            nxe = const EFER_NXE,
+            in("ecx") MSR_EFER,
+            out("eax") _,
+            out("edx") _,
+            options(nostack, preserves_flags)
+        );
+    }
+    true
+}
+
+pub struct PageTableSet {
+    pml4: *mut PageTable,
+    /// `EXECUTE_DISABLE` is kept in entries only when the CPU has NX
+    nx: bool,
+}
+
+impl PageTableSet {
+    pub fn new() -> Result<Self, &'static str> {
+        Ok(Self { pml4: new_table()?, nx: CpuInfo::read().has_nx })
+    }
+
+    /// Physical address of the PML4, for CR3 or `enter_long_mode`.
+    pub fn pml4_address(&self) -> u64 {
+        self.pml4 as u64
+    }
+
+    /// Map `[phys, phys + size)` at the same virtual address.
+    pub fn identity_map_range(&mut self, phys: usize, size: usize, flags: PageFlags) -> Result<(), &'static str> {
+        self.map_range(phys as u64, phys as u64, size as u64, flags)
+    }
+
+    /// Map the kernel image (read/write/execute) at a higher-half address.
+    /// `virt_base` is a `u64` so the 32-bit BIOS path can name it too.
+    pub fn map_kernel(&mut self, virt_base: u64, phys_base: usize, size: usize) -> Result<(), &'static str> {
+        self.map_range(virt_base, phys_base as u64, size as u64, PageFlags::WRITABLE | PageFlags::GLOBAL)
+    }
+
+    /// Map `size` bytes (rounded out to pages) from `virt` to `phys`.
+    pub fn map_range(&mut self, virt: u64, phys: u64, size: u64, flags: PageFlags) -> Result<(), &'static str> {
+        if (virt ^ phys) & (PAGE_SIZE - 1) != 0 {
+            return Err("paging: virt and phys differ in page offset");
+        }
+        let flags = if self.nx { flags } else { flags.without(PageFlags::EXECUTE_DISABLE) };
+        let mut v = virt & !(PAGE_SIZE - 1);
+        let mut p = phys & !(PAGE_SIZE - 1);
+        let end = virt.checked_add(size).ok_or("paging: range overflows")?;
+        if !is_canonical(v) || !is_canonical(end.saturating_sub(1)) {
+            return Err("paging: non-canonical address");
+        }
+
+        while v < end {
+            let large = v % LARGE_PAGE_SIZE == 0 && p % LARGE_PAGE_SIZE == 0 && end - v >= LARGE_PAGE_SIZE;
+            if large && self.map_large(v, p, flags)? {
+                v += LARGE_PAGE_SIZE;
+                p += LARGE_PAGE_SIZE;
+            } else {
+                self.map_page(v, p, flags)?;
+                v += PAGE_SIZE;
+                p += PAGE_SIZE;
+            }
+        }
+        Ok(())
+    }
+
+    /// Load CR3 with this PML4 (enabling NX first when the tables use it).
+    /// Only valid with PAE paging (long mode) and with the loader's own code
+    /// and stack mapped.
+    pub unsafe fn load(&self) {
+        unsafe {
+            if self.nx {
+                enable_nx();
+            }
+            core::arch::asm!("mov cr3, {}", in(reg) self.pml4 as usize, options(nostack, preserves_flags));
+        }
+    }
+
+    /// Walk to the table `level` (2 = PDPT .. 0 = PT) for `virt`, creating
+    /// missing levels. `None` when a 2 MiB page already covers it.
+    fn walk(&mut self, virt: u64, flags: PageFlags, level: u32) -> Result<Option<*mut PageTable>, &'static str> {
+        // Intermediate entries are as permissive as the leaf; the leaf decides
+        let parent_flags = PageFlags::PRESENT
+            | PageFlags::WRITABLE
+            | if flags.contains(PageFlags::USER_ACCESSIBLE) { PageFlags::USER_ACCESSIBLE } else { PageFlags::empty() };
+
+        let mut table = self.pml4;
+        for l in (level + 1..=3).rev() {
+            let entry = unsafe { &mut (*table).0[index(virt, l)] };
+            if *entry & PageFlags::PRESENT.bits() == 0 {
+                let next = new_table()?;
+                *entry = next as u64 | parent_flags.bits();
+            } else if *entry & PageFlags::HUGE.bits() != 0 {
+                return Ok(None);
+            } else {
+                *entry |= parent_flags.bits();
+            }
+            table = (*entry & ADDR_MASK) as usize as *mut PageTable;
+        }
+        Ok(Some(table))
+    }
+
+    fn map_page(&mut self, virt: u64, phys: u64, flags: PageFlags) -> Result<(), &'static str> {
+        let pt = self.walk(virt, flags, 0)?.ok_or("paging: range already mapped by a large page")?;
+        unsafe {
+            (*pt).0[index(virt, 0)] = phys | (flags | PageFlags::PRESENT).bits();
+        }
+        Ok(())
+    }
+
+    /// `false` if the PD slot already holds a 4 KiB table; the caller then
+    /// maps this stretch page by page.
+    fn map_large(&mut self, virt: u64, phys: u64, flags: PageFlags) -> Result<bool, &'static str> {
+        let pd = match self.walk(virt, flags, 1)? {
+            Some(pd) => pd,
+            None => return Err("paging: range already mapped by a large page"),
+        };
+        let entry = unsafe { &mut (*pd).0[index(virt, 1)] };
+        if *entry & PageFlags::PRESENT.bits() != 0 && *entry & PageFlags::HUGE.bits() == 0 {
+            return Ok(false);
+        }
+        *entry = phys | (flags | PageFlags::PRESENT | PageFlags::HUGE).bits();
+        Ok(true)
+    }
+}
@@ -0,0 +1,158 @@
+//! SMBIOS (DMI) tables: firmware, board and memory slot information.
+//!
+//! Like ACPI, the tables are read in place through their physical addresses.
+//! Both the 32-bit (`_SM_`) and the 64-bit (`_SM3_`) entry points are
+//! understood; structures are walked the same way for both.
+
+use crate::acpi::{checksum_ok, read_u32, read_u64, read_u8};
+
+const SM2_ANCHOR: &[u8; 4] = b"_SM_";
+const SM3_ANCHOR: &[u8; 5] = b"_SM3_";
+const DMI_ANCHOR: &[u8; 5] = b"_DMI_";
+const SCAN_START: usize = 0xF0000;
+const SCAN_END: usize = 0x100000;
+/// Offset and size of the intermediate `_DMI_` anchor in a 2.x entry point
+const SM2_DMI_OFFSET: usize = 0x10;
+const SM2_DMI_LEN: usize = 15;
+const SM3_MIN_LEN: usize = 24;
+
+/// Structure types
+pub const TYPE_BIOS_INFO: u8 = 0;
+pub const TYPE_SYSTEM_INFO: u8 = 1;
+pub const TYPE_BASEBOARD: u8 = 2;
+pub const TYPE_PROCESSOR: u8 = 4;
+pub const TYPE_MEMORY_DEVICE: u8 = 17;
+pub const TYPE_END_OF_TABLE: u8 = 127;
+
+/// Type 0 offset of the BIOS version string number
+const BIOS_INFO_VERSION: usize = 0x05;
+/// Every structure starts with type, length and a 16-bit handle
+const STRUCTURE_HEADER_LEN: usize = 4;
+
+fn signature_at(addr: usize, sig: &[u8]) -> bool {
+    sig.iter().enumerate().all(|(i, &b)| unsafe { read_u8(addr + i) } == b)
+}
+
+/// 2.x entry point: its own checksum plus the one over the `_DMI_` part.
+fn sm2_valid(addr: usize) -> bool {
+    if !signature_at(addr, SM2_ANCHOR) {
+        return false;
+    }
+    let len = unsafe { read_u8(addr + 5) } as usize;
+    (0x1F..=0x20).contains(&len)
+        && checksum_ok(addr, len)
+        && signature_at(addr + SM2_DMI_OFFSET, DMI_ANCHOR)
+        && checksum_ok(addr + SM2_DMI_OFFSET, SM2_DMI_LEN)
+}
+
+fn sm3_valid(addr: usize) -> bool {
+    if !signature_at(addr, SM3_ANCHOR) {
+        return false;
+    }
+    let len = unsafe { read_u8(addr + 6) } as usize;
+    len >= SM3_MIN_LEN && checksum_ok(addr, len)
+}
+
+fn anchor_valid(addr: usize) -> bool {
+    sm3_valid(addr) || sm2_valid(addr)
+}
+
+/// Entry point on a 16-byte boundary in the BIOS area, 64-bit one first.
+fn find_anchor_legacy() -> Option<usize> {
+    let scan = |valid: fn(usize) -> bool| (SCAN_START..SCAN_END).step_by(16).find(|&addr| valid(addr));
+    scan(sm3_valid).or_else(|| scan(sm2_valid))
+}
+
+/// Entry point from the EFI configuration table, preferring SMBIOS 3.
+#[cfg(feature = "uefi")]
+fn find_anchor_uefi() -> Option<usize> {
+    use uefi::table::cfg::{SMBIOS3_GUID, SMBIOS_GUID};
+
+    let st = unsafe { uefi_services::system_table().as_ref() };
+    let tables = st.config_table();
+    [SMBIOS3_GUID, SMBIOS_GUID].iter().find_map(|guid| {
+        tables
+            .iter()
+            .find(|t| t.guid == *guid)
+            .map(|t| t.address as usize)
+            .filter(|&addr| anchor_valid(addr))
+    })
+}
+
+/// Physical address of a valid SMBIOS entry point. On UEFI the configuration
+/// table is asked first; the 0xF0000-0xFFFFF scan is the fallback.
+pub fn find_anchor() -> Option<u64> {
+    #[cfg(feature = "uefi")]
+    if let Some(anchor) = find_anchor_uefi() {
+        return Some(anchor as u64);
+    }
+    find_anchor_legacy().map(|addr| addr as u64)
+}
+
+/// Structure table `(address, length)` described by the entry point. For
+/// SMBIOS 3 the length is only an upper bound; the end-of-table structure
+/// marks the real end.
+fn structure_table(anchor: usize) -> Option<(usize, usize)> {
+    let (addr, len) = if sm3_valid(anchor) {
+        unsafe { (read_u64(anchor + 0x10) as usize, read_u32(anchor + 0x0C) as usize) }
+    } else if sm2_valid(anchor) {
+        unsafe { (read_u32(anchor + 0x18) as usize, core::ptr::read_unaligned((anchor + 0x16) as *const u16) as usize) }
+    } else {
+        return None;
+    };
+    if addr == 0 || len == 0 { None } else { Some((addr, len)) }
+}
+
+/// First structure of `type_id`: the formatted area followed by its string
+/// set, up to and including the closing double NUL.
+pub fn get_structure(type_id: u8) -> Option<&'static [u8]> {
+    let (table, table_len) = structure_table(find_anchor()? as usize)?;
+    let end = table + table_len;
+
+    let mut addr = table;
+    while addr + STRUCTURE_HEADER_LEN <= end {
+        let ty = unsafe { read_u8(addr) };
+        let len = unsafe { read_u8(addr + 1) } as usize;
+        if len < STRUCTURE_HEADER_LEN {
+            return None;
+        }
+        // String set: NUL-terminated strings, ended by an extra NUL
+        let mut next = addr + len;
+        while next + 1 < end && unsafe { read_u8(next) != 0 || read_u8(next + 1) != 0 } {
+            next += 1;
+        }
+        next += 2;
+        if next > end {
+            return None;
+        }
+        if ty == type_id {
+            return Some(unsafe { core::slice::from_raw_parts(addr as *const u8, next - addr) });
+        }
+        if ty == TYPE_END_OF_TABLE {
+            return None;
+        }
+        addr = next;
+    }
+    None
+}
+
+/// String number `index` (1-based, as stored in the formatted area) of a
+/// structure returned by `get_structure`. 0 means "no string".
+pub fn structure_string(structure: &'static [u8], index: u8) -> Option<&'static str> {
+    if index == 0 {
+        return None;
+    }
+    let len = *structure.get(1)? as usize;
+    let strings = structure.get(len..)?;
+    let s = strings.split(|&b| b == 0).nth(index as usize - 1)?;
+    if s.is_empty() {
+        return None;
+    }
+    core::str::from_utf8(s).ok()
+}
+
+/// BIOS version string from the type 0 structure.
+pub fn bios_version() -> Option<&'static str> {
+    let bios = get_structure(TYPE_BIOS_INFO)?;
+    structure_string(bios, *bios.get(BIOS_INFO_VERSION)?)
+}
@@ -0,0 +1,167 @@
+//! Application processor start-up trampoline.
+//!
+//! A STARTUP IPI starts an AP in real mode at `vector << 12`, so the stub has
+//! to live in a 4 KiB page below 1 MiB. It goes real mode -> protected mode ->
+//! long mode with the bootloader's CR3 and then calls the kernel's entry on
+//! the stack from `tramp_stack`. The kernel writes a fresh stack pointer
+//! there (see `stack_offset`) before each SIPI.
+//!
+//! Written in AT&T syntax for the explicit `lgdtl`/`ljmpl` operand sizes in
+//! 16-bit code.
+
+use core::ptr;
+
+core::arch::global_asm!(
+    ".section .rodata.smp_trampoline, \"a\"",
+    ".global smp_trampoline_start",
+    ".global smp_trampoline_end",
+    ".global tramp_pm",
+    ".global tramp_lm",
+    ".global tramp_gdt",
+    ".global tramp_gdtr_base",
+    ".global tramp_pm_ptr",
+    ".global tramp_lm_ptr",
+    ".global tramp_cr3",
+    ".global tramp_stack",
+    ".global tramp_entry",
+    ".code16",
+    "smp_trampoline_start:",
+    "    cli",
+    "    cld",
+    "    mov %cs, %ax",
+    "    mov %ax, %ds",
+    // Physical base of the copy, for the data fields below. Kept in EBP
+    // through the mode switches: there is no stack until `tramp_stack`.
+    "    movzwl %ax, %ebp",
+    "    shl $4, %ebp",
+    "    lgdtl (tramp_gdtr - smp_trampoline_start)",
+    "    mov %cr0, %eax",
+    "    or $1, %eax",
+    "    mov %eax, %cr0",
+    "    ljmpl *(tramp_pm_ptr - smp_trampoline_start)",
+    ".code32",
+    "tramp_pm:",
+    "    mov $0x10, %ax",
+    "    mov %ax, %ds",
+    "    mov %ax, %es",
+    "    mov %ax, %ss",
+    "    mov %cr4, %eax",
+    "    or $(1 << 5), %eax",
+    "    mov %eax, %cr4",
+    "    mov (tramp_cr3 - smp_trampoline_start)(%ebp), %eax",
+    "    mov %eax, %cr3",
+    "    mov $0xC0000080, %ecx",
+    "    rdmsr",
+    "    or $(1 << 8), %eax",
+    "    wrmsr",
+    "    mov %cr0, %eax",
+    "    or $0x80000000, %eax",
+    "    mov %eax, %cr0",
+    "    ljmpl *(tramp_lm_ptr - smp_trampoline_start)(%ebp)",
+    ".code64",
+    "tramp_lm:",
+    // Zero-extend the base into rbp
+    "    mov %ebp, %ebp",
+    "    mov (tramp_stack - smp_trampoline_start)(%rbp), %rsp",
+    "    and $-16, %rsp",
+    "    mov (tramp_entry - smp_trampoline_start)(%rbp), %rax",
+    "    call *%rax",
+    "2:  hlt",
+    "    jmp 2b",
+    ".p2align 3",
+    "tramp_gdt:",
+    "    .quad 0",
+    "    .quad 0x00CF9A000000FFFF", // 0x08 code32
+    "    .quad 0x00CF92000000FFFF", // 0x10 data
+    "    .quad 0x00AF9A000000FFFF", // 0x18 code64
+    "tramp_gdtr:",
+    "    .word 4 * 8 - 1",
+    "tramp_gdtr_base:",
+    "    .long 0",
+    "tramp_pm_ptr:",
+    "    .long 0",
+    "    .word 0x08",
+    "tramp_lm_ptr:",
+    "    .long 0",
+    "    .word 0x18",
+    ".p2align 3",
+    "tramp_cr3:",
+    "    .quad 0",
+    "tramp_stack:",
+    "    .quad 0",
+    "tramp_entry:",
+    "    .quad 0",
+    "smp_trampoline_end:",
+    ".code32",
+    ".text",
+    options(att_syntax)
+);
+
+unsafe extern "C" {
+    static smp_trampoline_start: u8;
+    static smp_trampoline_end: u8;
+    static tramp_pm: u8;
+    static tramp_lm: u8;
+    static tramp_gdt: u8;
+    static tramp_gdtr_base: u8;
+    static tramp_pm_ptr: u8;
+    static tramp_lm_ptr: u8;
+    static tramp_cr3: u8;
+    static tramp_stack: u8;
+    static tramp_entry: u8;
+}
+
+/// The stub's page must end below this (the reach of a SIPI vector)
+pub const REAL_MODE_LIMIT: usize = 0x10_0000;
+const PAGE_SIZE: usize = 0x1000;
+
+static mut TRAMPOLINE_ADDR: usize = 0;
+
+/// Offset of a stub label from `smp_trampoline_start`.
+fn offset_of(sym: *const u8) -> usize {
+    sym as usize - ptr::addr_of!(smp_trampoline_start) as usize
+}
+
+/// Byte offset of the 64-bit AP stack pointer inside the installed stub.
+pub fn stack_offset() -> usize {
+    offset_of(ptr::addr_of!(tramp_stack))
+}
+
+/// Physical address of the installed trampoline, 0 if none.
+pub fn trampoline_address() -> usize {
+    unsafe { TRAMPOLINE_ADDR }
+}
+
+/// Copy the AP stub to `phys_below_1mb` (page aligned, below 1 MiB), point it
+/// at `long_mode_entry` and the current CR3, and return the SIPI vector. The
+/// stub loads CR3 in protected mode, so the current PML4 must sit below 4 GiB.
+///
+/// # Panics
+/// If the address is not usable as a SIPI target.
+pub fn install_trampoline(phys_below_1mb: usize, long_mode_entry: u64) -> u8 {
+    let start = ptr::addr_of!(smp_trampoline_start) as usize;
+    let size = ptr::addr_of!(smp_trampoline_end) as usize - start;
+    assert!(phys_below_1mb % PAGE_SIZE == 0, "SMP trampoline must be page aligned");
+    assert!(phys_below_1mb + size <= REAL_MODE_LIMIT, "SMP trampoline must sit below 1 MiB");
+
+    let cr3: usize;
+    unsafe {
+        core::arch::asm!("mov {}, cr3", out(reg) cr3, options(nomem, nostack, preserves_flags));
+    }
+
+    let base = phys_below_1mb;
+    unsafe {
+        ptr::copy_nonoverlapping(start as *const u8, base as *mut u8, size);
+        let patch32 = |sym: *const u8, v: u32| ptr::write_unaligned((base + offset_of(sym)) as *mut u32, v);
+        let patch64 = |sym: *const u8, v: u64| ptr::write_unaligned((base + offset_of(sym)) as *mut u64, v);
+
+        patch32(ptr::addr_of!(tramp_gdtr_base), (base + offset_of(ptr::addr_of!(tramp_gdt))) as u32);
+        patch32(ptr::addr_of!(tramp_pm_ptr), (base + offset_of(ptr::addr_of!(tramp_pm))) as u32);
+        patch32(ptr::addr_of!(tramp_lm_ptr), (base + offset_of(ptr::addr_of!(tramp_lm))) as u32);
+        patch64(ptr::addr_of!(tramp_cr3), cr3 as u64);
+        patch64(ptr::addr_of!(tramp_entry), long_mode_entry);
+        TRAMPOLINE_ADDR = base;
+    }
+
+    (base >> 12) as u8
+}
@@ -0,0 +1,110 @@
+//! Keyboard input through `SimpleTextInputProtocol`.
+//!
+//! PS/2 ports are not available under UEFI; the firmware console is the only
+//! portable source of key presses.
+
+use uefi::prelude::*;
+use uefi::proto::console::text::{Key, ScanCode};
+use uefi::table::boot::{EventType, TimerTrigger, Tpl};
+use uefi::Event;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MenuAction {
+    Up,
+    Down,
+    Select,
+    Cancel,
+}
+
+/// Non-blocking read of one key from the console, `None` if nothing is queued.
+pub fn read_key_uefi(st: &SystemTable<Boot>) -> Option<Key> {
+    st.stdin().read_key().ok().flatten()
+}
+
+/// Translate a key into a menu action. Other keys are ignored.
+pub fn key_to_action(key: &Key) -> Option<MenuAction> {
+    match key {
+        Key::Special(ScanCode::UP) => Some(MenuAction::Up),     // 0x01
+        Key::Special(ScanCode::DOWN) => Some(MenuAction::Down), // 0x02
+        Key::Special(ScanCode::ESCAPE) => Some(MenuAction::Cancel), // 0x17
+        Key::Printable(c) if u16::from(*c) == b'\r' as u16 => Some(MenuAction::Select),
+        _ => None,
+    }
+}
+
+/// Timer period is in 100 ns units
+const ONE_SECOND: u64 = 10_000_000;
+
+/// What ended one `wait_for_key_or_timer`.
+enum Wakeup {
+    Action(MenuAction),
+    Timer,
+}
+
+/// Sleep in `WaitForEvent` until a menu key is pressed or `timer` (if any)
+/// signals. Other keys are swallowed. `None` if the console has no key event
+/// or the wait fails.
+fn wait_for_key_or_timer(st: &SystemTable<Boot>, timer: Option<&Event>) -> Option<Wakeup> {
+    let bs = st.boot_services();
+    loop {
+        let keyboard_event = st.stdin().wait_for_key_event()?;
+        let index = match timer {
+            Some(timer) => bs.wait_for_event(&mut [keyboard_event, unsafe { timer.unsafe_clone() }]),
+            None => bs.wait_for_event(&mut [keyboard_event]),
+        }
+        .ok()?;
+        if index != 0 {
+            return Some(Wakeup::Timer);
+        }
+        if let Some(action) = read_key_uefi(st).as_ref().and_then(key_to_action) {
+            return Some(Wakeup::Action(action));
+        }
+    }
+}
+
+/// Block until a menu key is pressed. `None` if the console cannot wait for
+/// keys.
+pub fn wait_for_menu_action(st: &SystemTable<Boot>) -> Option<MenuAction> {
+    match wait_for_key_or_timer(st, None)? {
+        Wakeup::Action(action) => Some(action),
+        Wakeup::Timer => None,
+    }
+}
+
+/// Menu countdown: waits on the key and a 1 s periodic timer event, calling
+/// `on_tick` with the seconds left after each tick. Returns the first menu
+/// action, or `None` once the countdown reaches zero.
+///
+/// The timer is a plain `TIMER` event: `WaitForEvent` rejects notify-signal
+/// events, and a notify-wait event would need a notification function.
+pub fn countdown_menu(
+    st: &SystemTable<Boot>,
+    seconds: u32,
+    mut on_tick: impl FnMut(u32),
+) -> Option<MenuAction> {
+    let bs = st.boot_services();
+    let timer_event = unsafe { bs.create_event(EventType::TIMER, Tpl::CALLBACK, None, None) }.ok()?;
+    if bs.set_timer(&timer_event, TimerTrigger::Periodic(ONE_SECOND)).is_err() {
+        let _ = bs.close_event(timer_event);
+        return None;
+    }
+
+    let mut remaining = seconds;
+    let action = loop {
+        if remaining == 0 {
+            break None;
+        }
+        match wait_for_key_or_timer(st, Some(&timer_event)) {
+            Some(Wakeup::Action(action)) => break Some(action),
+            Some(Wakeup::Timer) => {
+                remaining -= 1;
+                on_tick(remaining);
+            }
+            None => break None,
+        }
+    };
+
+    let _ = bs.set_timer(&timer_event, TimerTrigger::Cancel);
+    let _ = bs.close_event(timer_event);
+    action
+}
@@ -0,0 +1,6 @@
+//! Helpers built on UEFI boot services (`feature = "uefi"`).
+
+pub mod input;
+pub mod path;
+pub mod pool;
+pub mod protocol;
@@ -0,0 +1,59 @@
+//! Path conversion for SimpleFileSystem.
+//!
+//! `EFI_FILE_PROTOCOL.Open` takes UCS-2 paths with `\` separators relative
+//! to the volume root. The loader's paths (`KERNEL_PATHS`, the command line)
+//! are written POSIX style, and strict firmware rejects those as is.
+
+use uefi::CStr16;
+
+/// Path buffer length in UTF-16 units, NUL included
+pub const UEFI_PATH_MAX: usize = 260;
+
+const SEPARATOR: u16 = b'\\' as u16;
+
+/// Convert `path` into `buf` for `Directory::open`: `/` (and `\`) separated
+/// components are joined with `\`, the leading separator dropped, `.` and
+/// empty components skipped, and `..` backs up one component (staying at
+/// the root). Fails if the result does not fit or has a character outside
+/// UCS-2.
+pub fn normalize_uefi_path<'a>(path: &str, buf: &'a mut [u16; UEFI_PATH_MAX]) -> Result<&'a CStr16, ()> {
+    // Always leaves room for the NUL
+    fn push(buf: &mut [u16; UEFI_PATH_MAX], len: &mut usize, c: u16) -> Result<(), ()> {
+        if *len + 1 >= UEFI_PATH_MAX {
+            return Err(());
+        }
+        buf[*len] = c;
+        *len += 1;
+        Ok(())
+    }
+
+    let mut len = 0;
+    for component in path.split(['/', '\\']) {
+        match component {
+            "" | "." => continue,
+            ".." => {
+                while len > 0 {
+                    len -= 1;
+                    if buf[len] == SEPARATOR {
+                        break;
+                    }
+                }
+                continue;
+            }
+            _ => {}
+        }
+        if len > 0 {
+            push(buf, &mut len, SEPARATOR)?;
+        }
+        for ch in component.chars() {
+            let c = u16::try_from(ch as u32).map_err(|_| ())?;
+            if c == 0 {
+                return Err(());
+            }
+            push(buf, &mut len, c)?;
+        }
+    }
+
+    buf[len] = 0;
+    CStr16::from_u16_with_nul(&buf[..=len]).map_err(|_| ())
+}
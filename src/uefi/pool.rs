@@ -0,0 +1,67 @@
+//! Boot services pool allocations.
+//!
+//! There is no global allocator in this crate, so temporary buffers (file
+//! contents, memory maps) come straight from `AllocatePool`. They are only
+//! valid until `ExitBootServices`.
+
+use core::ptr;
+
+use uefi::table::boot::{BootServices, MemoryType};
+
+pub fn uefi_alloc(bs: &BootServices, size: usize) -> Result<*mut u8, &'static str> {
+    bs.allocate_pool(MemoryType::LOADER_DATA, size)
+        .map_err(|_| "AllocatePool failed")
+}
+
+pub fn uefi_free(bs: &BootServices, ptr: *mut u8) {
+    let _ = bs.free_pool(ptr);
+}
+
+/// Owned pool allocation of `len` values of `T`, freed on drop.
+pub struct UefiBox<T> {
+    ptr: *mut T,
+    len: usize,
+    bs: &'static BootServices,
+}
+
+impl<T: Copy> UefiBox<T> {
+    /// Allocate `len` elements, each initialised to `fill`.
+    pub fn new_filled(bs: &'static BootServices, len: usize, fill: T) -> Result<Self, &'static str> {
+        let size = len
+            .checked_mul(core::mem::size_of::<T>())
+            .ok_or("UefiBox: size overflow")?;
+        // Pool memory is 8-byte aligned
+        if core::mem::align_of::<T>() > 8 {
+            return Err("UefiBox: alignment not supported");
+        }
+        let ptr = uefi_alloc(bs, size.max(1))? as *mut T;
+        for i in 0..len {
+            unsafe { ptr::write(ptr.add(i), fill) };
+        }
+        Ok(Self { ptr, len, bs })
+    }
+}
+
+impl<T> UefiBox<T> {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn as_slice(&self) -> &[T] {
+        unsafe { core::slice::from_raw_parts(self.ptr, self.len) }
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        unsafe { core::slice::from_raw_parts_mut(self.ptr, self.len) }
+    }
+}
+
+impl<T> Drop for UefiBox<T> {
+    fn drop(&mut self) {
+        uefi_free(self.bs, self.ptr as *mut u8);
+    }
+}
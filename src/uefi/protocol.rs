@@ -0,0 +1,48 @@
+//! Checked protocol lookups.
+
+use core::fmt;
+
+use uefi::prelude::*;
+use uefi::proto::ProtocolPointer;
+use uefi::table::boot::BootServices;
+
+#[derive(Copy, Clone, Debug)]
+pub enum BootError {
+    /// A protocol required to continue is not installed on the handle
+    UefiProtocol(&'static str),
+}
+
+impl BootError {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BootError::UefiProtocol(_) => "required UEFI protocol not available",
+        }
+    }
+}
+
+impl fmt::Display for BootError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BootError::UefiProtocol(name) => write!(f, "missing UEFI protocol: {}", name),
+        }
+    }
+}
+
+impl From<BootError> for &'static str {
+    fn from(e: BootError) -> Self {
+        e.as_str()
+    }
+}
+
+/// Look up protocol `P` on `handle`. The error names the protocol; callers
+/// that cannot do without it log it (its `Display`) before giving up.
+pub fn require_protocol<P: ProtocolPointer + ?Sized>(
+    bs: &BootServices,
+    handle: Handle,
+) -> Result<&'static mut P, BootError> {
+    match bs.handle_protocol::<P>(handle) {
+        // SAFETY: the interface stays installed until ExitBootServices
+        Ok(cell) => Ok(unsafe { &mut *cell.get() }),
+        Err(_) => Err(BootError::UefiProtocol(core::any::type_name::<P>())),
+    }
+}
@@ -0,0 +1,274 @@
+//! UEFI entry point (`feature = "uefi"`).
+
+use core::fmt::Write;
+use core::panic::PanicInfo;
+
+use uefi::prelude::*;
+use uefi::proto::media::file::{Directory, FileSystemInfo};
+use uefi::proto::media::fs::SimpleFileSystem;
+
+use crate::boot::bootinfo::VOLUME_LABEL_MAX;
+use crate::uefi::protocol::require_protocol;
+
+use crate::kernel::loader::{self, BOOT_DISK_UEFI_BLOCK_IO, BOOT_FS_FAT32, boot_status_word, jump_to_kernel};
+use crate::config::ini::BootConfig;
+use crate::drivers::uefi_disk;
+use crate::ui::uefi_menu::{self, BootEntry};
+
+const MAX_MENU_ENTRIES: usize = crate::config::ini::MAX_ENTRIES;
+
+#[entry]
+fn efi_main(image_handle: Handle, mut st: SystemTable<Boot>) -> Status {
+    // Initialize UEFI services (logger + allocator helpers)
+    if uefi_services::init(&mut st).is_err() {
+        // If UEFI services init fails, try to write minimal message
+        let _ = st.stdout().write_str("UEFI service init failed\n");
+        return Status::ABORTED;
+    }
+
+    let stdout = st.stdout();
+
+    writeln!(stdout, "RustyBoot (UEFI) starting...").ok();
+    // Mirror the log to COM1 where there is one; Hyper-V Gen2 has no legacy UART
+    if !crate::arch::cpuid::legacy_devices_unavailable() {
+        let _ = crate::drivers::serial::init(crate::drivers::serial::COM1, 115_200);
+    }
+    if crate::arch::cpuid::legacy_devices_unavailable() {
+        writeln!(stdout, "Hyper-V Gen2: using UEFI-only driver path").ok();
+    }
+
+    // Print firmware vendor and version
+    writeln!(
+        stdout,
+        "firmware: {}",
+        st.firmware_vendor().to_string_lossy()
+    ).ok();
+
+    // Dump a compact memory map
+    #[cfg(debug_assertions)]
+    {
+        debug_log!("uefi", "Memory Map:");
+        if let Err(e) = dump_memory_map(&st) {
+            debug_log!("uefi", "Failed to dump memory map: {:?}", e);
+        }
+    }
+
+    // Hand-off structure for the kernel, filled in as loading goes on
+    let boot_info = match crate::boot::bootinfo::allocate(st.boot_services()) {
+        Ok(info) => info,
+        Err(e) => {
+            writeln!(stdout, "[uefi] {}", e).ok();
+            return Status::OUT_OF_RESOURCES;
+        }
+    };
+
+    // Serial-only firmware has no GOP; the descriptor then stays zeroed
+    match crate::drivers::framebuffer::Framebuffer::locate(st.boot_services()) {
+        Some(fb) => {
+            writeln!(stdout, "[uefi] GOP: {}x{} at 0x{:x}", fb.width, fb.height, fb.base).ok();
+            boot_info.framebuffer = fb.descriptor();
+        }
+        None => log_info!("uefi", "No GOP framebuffer"),
+    }
+
+    // Region table for the kernel hand-off; the map is re-read before ExitBootServices
+    {
+        let mut map_buf = [0u8; 4096 * 4];
+        match st.boot_services().memory_map(&mut map_buf) {
+            Ok((_key, desc_iter)) => crate::memory::manager::init_from_uefi_map(desc_iter),
+            Err(_) => log_info!("uefi", "Failed to read memory map for the region table"),
+        }
+    }
+
+    // ATA PIO is BIOS-only; here the firmware's BlockIo is the disk backend
+    // and `uefi_disk::read_sectors` reads through it
+    match uefi_disk::UefiDisk::boot_disk(&st, image_handle).and_then(uefi_disk::set_boot_disk) {
+        Ok(()) => {
+            if let Some(disk) = uefi_disk::boot_disk() {
+                log_info!("uefi", "Boot disk: {} blocks of {} bytes", disk.last_block + 1, disk.block_size);
+            }
+        }
+        Err(e) => log_info!("uefi", "Boot disk: {}", e),
+    }
+
+    // Partition table of the boot disk, from the backup copy if need be
+    if let Some(disk) = uefi_disk::boot_disk() {
+        match crate::boot::gpt::probe_with_backup_recovery(st.boot_services(), disk.handle) {
+            Ok(gpt) => log_info!(
+                "gpt",
+                "{} partitions{}",
+                gpt.partitions.iter().flatten().count(),
+                if gpt.from_backup { " (from backup header)" } else { "" }
+            ),
+            Err(e) => log_info!("gpt", "{}", e),
+        }
+    }
+
+    // Try to find a simple FS for loaded image
+    match require_protocol::<SimpleFileSystem>(st.boot_services(), image_handle) {
+        Ok(mut sfs) => {
+            match sfs.open_volume() {
+                Ok(mut root_dir) => {
+                    print_volume_label_uefi(&mut root_dir);
+
+                    let config = match crate::config::ini::load(&mut root_dir) {
+                        Ok(config) => config,
+                        Err(e) => {
+                            log_info!("config", "{}, using defaults", e);
+                            BootConfig::empty()
+                        }
+                    };
+                    let choice = choose_kernel(&st, &config);
+                    // The EFI variable still takes precedence over the config
+                    boot_info.set_cmdline(crate::boot::cmdline::resolve(&st, choice.cmdline));
+
+                    debug_log!("uefi", "Found Simple File System. Searching kernel...");
+
+                    match loader::find_and_load_kernel(
+                        &st,
+                        image_handle,
+                        &mut root_dir,
+                        choice.kernel,
+                        choice.initrd,
+                        boot_info,
+                    ) {
+                        Ok(entry) => {
+                            let kaslr = crate::boot::cmdline::CmdLine::new(boot_info.cmdline_str()).has("kaslr");
+                            let status = boot_status_word(BOOT_FS_FAT32, BOOT_DISK_UEFI_BLOCK_IO, kaslr);
+                            jump_to_kernel(&st, image_handle, entry, status, boot_info);
+                        }
+                        Err(e) => {
+                            writeln!(stdout, "[uefi][fs] {}", e).ok();
+                        }
+                    }
+                }
+                Err(e) => {
+                    writeln!(stdout, "[uefi][fs] Failed to open {:?}", e).ok();
+                }
+            }
+        }
+        Err(e) => {
+            writeln!(stdout, "[uefi][fs] No simple File System bound to image handle ({})", e).ok();
+        }
+    }
+    writeln!(stdout, "\n[uefi] No kernel could be booted - returning to firmware.").ok();
+    Status::LOAD_ERROR
+}
+
+/// What the menu, or the config on its own, picked to boot.
+struct BootChoice<'a> {
+    kernel: Option<&'a str>,
+    initrd: Option<&'a str>,
+    cmdline: &'a str,
+}
+
+fn non_empty(s: &str) -> Option<&str> {
+    Some(s).filter(|s| !s.is_empty())
+}
+
+/// Let the user pick a `[boot.N]` entry on the firmware console, starting
+/// on `default` and booting it after `timeout` seconds. Entries inherit the
+/// `[boot]` initrd and cmdline they leave unset. Without entries, the
+/// `[boot]` kernel (if any) and the built-in paths are offered instead.
+fn choose_kernel<'a>(st: &SystemTable<Boot>, config: &'a BootConfig) -> BootChoice<'a> {
+    let mut entries = [BootEntry { title: "", path: "" }; MAX_MENU_ENTRIES];
+    let mut sections = [0usize; MAX_MENU_ENTRIES];
+    let mut count = 0;
+    let mut default = 0;
+    for (index, entry) in config.entries().take(MAX_MENU_ENTRIES) {
+        if index == config.default as usize {
+            default = count;
+        }
+        entries[count] = BootEntry { title: entry.title_str(), path: entry.kernel_str() };
+        sections[count] = index;
+        count += 1;
+    }
+    if count > 0 {
+        let choice = uefi_menu::show(st, &entries[..count], default, config.timeout);
+        let entry = &config.entries[sections[choice]];
+        return BootChoice {
+            kernel: Some(entry.kernel_str()),
+            initrd: non_empty(entry.initrd_str()).or(non_empty(config.initrd_str())),
+            cmdline: non_empty(entry.cmdline_str()).unwrap_or(config.cmdline_str()),
+        };
+    }
+
+    let paths = non_empty(config.kernel_str()).into_iter().chain(loader::KERNEL_PATHS.iter().copied());
+    for path in paths.take(MAX_MENU_ENTRIES) {
+        entries[count] = BootEntry { title: path, path };
+        count += 1;
+    }
+    let choice = uefi_menu::show(st, &entries[..count], 0, config.timeout);
+    BootChoice { kernel: Some(entries[choice].path), initrd: non_empty(config.initrd_str()), cmdline: config.cmdline_str() }
+}
+
+static mut VOLUME_LABEL: [u8; VOLUME_LABEL_MAX] = [0; VOLUME_LABEL_MAX];
+static mut VOLUME_LABEL_LEN: usize = 0;
+
+/// Label of the volume the loader booted from, empty if unknown.
+pub fn volume_label() -> &'static str {
+    unsafe {
+        let label = &*core::ptr::addr_of!(VOLUME_LABEL);
+        core::str::from_utf8(&label[..VOLUME_LABEL_LEN]).unwrap_or("")
+    }
+}
+
+/// Print `[uefi][fs] Volume: <label>` and remember the label for the kernel.
+fn print_volume_label_uefi(root: &mut Directory) {
+    let mut info_buf = [0u8; 256];
+    let info = match root.get_info::<FileSystemInfo>(&mut info_buf) {
+        Ok(info) => info,
+        Err(_) => return,
+    };
+
+    // UTF-16 -> UTF-8, cut at a character boundary
+    let label = unsafe { &mut *core::ptr::addr_of_mut!(VOLUME_LABEL) };
+    let mut len = 0usize;
+    for c in info.volume_label().iter() {
+        let ch = char::from(*c);
+        if len + ch.len_utf8() > VOLUME_LABEL_MAX {
+            break;
+        }
+        ch.encode_utf8(&mut label[len..]);
+        len += ch.len_utf8();
+    }
+    unsafe { VOLUME_LABEL_LEN = len };
+
+    let st = unsafe { uefi_services::system_table().as_mut() };
+    writeln!(st.stdout(), "[uefi][fs] Volume: {}", volume_label()).ok();
+}
+
+///Dump memory map using BootServices::memory_map
+fn dump_memory_map(st: &SystemTable<Boot>) -> Result<(), Status> {
+    let bs = st.boot_services();
+
+    // Choose a reasonably large buffer for memory map
+    // Using 4096 * 4 here; if too small, memory map will return BufferTooSmall
+
+    let mut buffer = [0u8; 4096 * 4];
+
+    // `memory_map` returns (memory_map, desc_size)
+    match bs.memory_map(&mut buffer) {
+        Ok((_key, desc_iter)) => {
+            let stdout = st.stdout();
+            for desc in desc_iter {
+                // Print basic fields: ty, phys_start, pages
+                let ty = desc.ty;
+                let phys = desc.phys_start;
+                let pages = desc.page_count;
+                let size_bytes = (pages as usize) * 4096usize;
+                writeln!(stdout, "Type={:?}, phys=0x{:x}, pages={}, size={}, bytes", ty, phys, pages, size_bytes).ok();
+            }
+            Ok(())
+        }
+        Err(err) => Err(err.status()),
+    }
+}
+
+#[panic_handler]
+fn panic(_info: &PanicInfo) -> ! {
+    // Try to print panic info if possible
+    uefi_services::println!("Panic: {}", _info);
+    unsafe { uefi_services::system_table().as_ref() }.boot_services().stall(5_000_000);
+    crate::acpi::fadt::acpi_reset()
+}
\ No newline at end of file
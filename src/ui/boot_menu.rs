@@ -0,0 +1,182 @@
+//! Text-mode boot menu on VGA with a countdown.
+//!
+//! Polls the PS/2 keyboard every `POLL_INTERVAL_US`; the countdown runs on
+//! TSC busy-waits (`arch::time`). Up/Down move the selection, Enter boots it,
+//! and any other key stops the countdown.
+
+use crate::arch::time::busy_wait_us;
+use crate::drivers::kbd::{self, Key};
+use crate::drivers::vga::{self, VgaColor, attr};
+
+// ===== Layout =====
+const SCREEN_COLS: usize = 80;
+const BOX_WIDTH: usize = 64;
+const BOX_LEFT: usize = (SCREEN_COLS - BOX_WIDTH) / 2;
+const BOX_TOP: usize = 3;
+/// Entries beyond this are not shown
+const MAX_VISIBLE: usize = 16;
+/// `YYYY-MM-DD HH:MM:SS`, right of each title
+const MTIME_WIDTH: usize = 19;
+const TITLE: &str = " RustyBoot ";
+
+// CP437 double-line box drawing
+const BOX_TOP_LEFT: u8 = 0xC9;
+const BOX_TOP_RIGHT: u8 = 0xBB;
+const BOX_BOTTOM_LEFT: u8 = 0xC8;
+const BOX_BOTTOM_RIGHT: u8 = 0xBC;
+const BOX_HORIZONTAL: u8 = 0xCD;
+const BOX_VERTICAL: u8 = 0xBA;
+
+const NORMAL_ATTR: u8 = attr(VgaColor::LightGrey, VgaColor::Black);
+const SELECTED_ATTR: u8 = attr(VgaColor::Black, VgaColor::LightGrey);
+const BORDER_ATTR: u8 = attr(VgaColor::LightCyan, VgaColor::Black);
+const STATUS_ATTR: u8 = attr(VgaColor::Yellow, VgaColor::Black);
+
+const POLL_INTERVAL_US: u64 = 10_000;
+const POLLS_PER_SEC: u32 = 100;
+
+#[derive(Copy, Clone, Debug)]
+pub struct BootEntry<'a> {
+    /// Shown in the menu
+    pub title: &'a str,
+    /// Kernel to load when chosen
+    pub path: &'a str,
+    /// Modification time of the kernel file, `""` when unknown
+    pub mtime: &'a str,
+}
+
+/// Show the menu and return the chosen index. Entry 0 is the default and is
+/// returned when `timeout_secs` runs out (or immediately for a 0 timeout or
+/// fewer than two entries). Leaves the screen cleared.
+pub fn show(entries: &[BootEntry], timeout_secs: u32) -> usize {
+    if entries.len() < 2 || timeout_secs == 0 {
+        return 0;
+    }
+    let visible = entries.len().min(MAX_VISIBLE);
+
+    kbd::init();
+    vga::clear_screen();
+    draw_box(visible);
+
+    let mut selected = 0usize;
+    let mut remaining = Some(timeout_secs);
+    let mut polls = 0u32;
+    draw_entries(&entries[..visible], selected);
+    draw_status(&entries[selected], remaining, visible);
+
+    loop {
+        if let Some(event) = kbd::read_key() {
+            if event.pressed {
+                match event.key {
+                    Key::Enter => break,
+                    Key::Up => selected = selected.saturating_sub(1),
+                    Key::Down => selected = (selected + 1).min(visible - 1),
+                    _ => {}
+                }
+                // Any key press ends the countdown
+                remaining = None;
+                draw_entries(&entries[..visible], selected);
+                draw_status(&entries[selected], remaining, visible);
+            }
+        }
+
+        busy_wait_us(POLL_INTERVAL_US);
+        if let Some(secs) = remaining {
+            polls += 1;
+            if polls == POLLS_PER_SEC {
+                polls = 0;
+                if secs <= 1 {
+                    break;
+                }
+                remaining = Some(secs - 1);
+                draw_status(&entries[selected], remaining, visible);
+            }
+        }
+    }
+
+    vga::clear_screen();
+    selected
+}
+
+fn draw_box(rows: usize) {
+    let right = BOX_LEFT + BOX_WIDTH - 1;
+    let bottom = BOX_TOP + rows + 1;
+    for col in BOX_LEFT + 1..right {
+        vga::write_char_attr(col, BOX_TOP, BOX_HORIZONTAL, BORDER_ATTR);
+        vga::write_char_attr(col, bottom, BOX_HORIZONTAL, BORDER_ATTR);
+    }
+    for row in BOX_TOP + 1..bottom {
+        vga::write_char_attr(BOX_LEFT, row, BOX_VERTICAL, BORDER_ATTR);
+        vga::write_char_attr(right, row, BOX_VERTICAL, BORDER_ATTR);
+    }
+    vga::write_char_attr(BOX_LEFT, BOX_TOP, BOX_TOP_LEFT, BORDER_ATTR);
+    vga::write_char_attr(right, BOX_TOP, BOX_TOP_RIGHT, BORDER_ATTR);
+    vga::write_char_attr(BOX_LEFT, bottom, BOX_BOTTOM_LEFT, BORDER_ATTR);
+    vga::write_char_attr(right, bottom, BOX_BOTTOM_RIGHT, BORDER_ATTR);
+
+    let title_col = BOX_LEFT + (BOX_WIDTH - TITLE.len()) / 2;
+    write_text(title_col, BOX_TOP, TITLE.as_bytes(), TITLE.len(), BORDER_ATTR);
+}
+
+fn draw_entries(entries: &[BootEntry], selected: usize) {
+    let inner = BOX_WIDTH - 2;
+    // Leading space, title, gap, then the date and a trailing space
+    let title_width = inner - MTIME_WIDTH - 3;
+    for (i, entry) in entries.iter().enumerate() {
+        let cell_attr = if i == selected { SELECTED_ATTR } else { NORMAL_ATTR };
+        let row = BOX_TOP + 1 + i;
+        vga::write_char_attr(BOX_LEFT + 1, row, b' ', cell_attr);
+        write_text(BOX_LEFT + 2, row, entry.title.as_bytes(), title_width, cell_attr);
+        vga::write_char_attr(BOX_LEFT + 2 + title_width, row, b' ', cell_attr);
+        write_text(BOX_LEFT + 3 + title_width, row, entry.mtime.as_bytes(), MTIME_WIDTH + 1, cell_attr);
+    }
+}
+
+/// Status line two rows below the box.
+fn draw_status(entry: &BootEntry, remaining: Option<u32>, visible: usize) {
+    let row = BOX_TOP + visible + 3;
+    let mut line = [b' '; SCREEN_COLS];
+    let mut len = 0;
+    let mut push = |s: &[u8]| {
+        for &b in s {
+            if len < line.len() {
+                line[len] = b;
+                len += 1;
+            }
+        }
+    };
+
+    match remaining {
+        Some(secs) => {
+            push(b"Booting '");
+            push(entry.title.as_bytes());
+            push(b"' in ");
+            let mut digits = [0u8; 10];
+            push(dec(secs, &mut digits));
+            push(b"s. Up/Down to select, Enter to boot.");
+        }
+        None => push(b"Up/Down to select, Enter to boot."),
+    }
+    write_text(0, row, &line, SCREEN_COLS, STATUS_ATTR);
+}
+
+/// Write `text` at (`col`, `row`), padded with spaces or cut to `width` cells.
+fn write_text(col: usize, row: usize, text: &[u8], width: usize, cell_attr: u8) {
+    let mut bytes = text.iter().copied();
+    for i in 0..width {
+        let ch = bytes.next().unwrap_or(b' ');
+        vga::write_char_attr(col + i, row, ch, cell_attr);
+    }
+}
+
+fn dec(mut v: u32, buf: &mut [u8; 10]) -> &[u8] {
+    let mut i = buf.len();
+    loop {
+        i -= 1;
+        buf[i] = b'0' + (v % 10) as u8;
+        v /= 10;
+        if v == 0 {
+            return &buf[i..];
+        }
+    }
+}
@@ -0,0 +1,4 @@
+#[cfg(feature = "bios")]
+pub mod boot_menu;
+#[cfg(feature = "uefi")]
+pub mod uefi_menu;
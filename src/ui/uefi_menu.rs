@@ -0,0 +1,87 @@
+//! Boot menu on the UEFI text console with a countdown.
+//!
+//! Keys come from `SimpleTextInputProtocol` (see `uefi::input`), and the
+//! console is cleared and redrawn on every change. Up/Down move the
+//! selection, Enter boots it, Esc boots the default, and any other menu key
+//! stops the countdown.
+
+use core::fmt::Write;
+
+use uefi::prelude::*;
+
+use crate::uefi::input::{self, MenuAction};
+
+/// Entries beyond this are not shown
+const MAX_VISIBLE: usize = 16;
+
+#[derive(Copy, Clone, Debug)]
+pub struct BootEntry<'a> {
+    /// Shown in the menu
+    pub title: &'a str,
+    /// Kernel to load when chosen
+    pub path: &'a str,
+}
+
+/// Show the menu and return the chosen index. `default` is preselected and
+/// returned when `timeout_secs` runs out (or immediately for a 0 timeout or
+/// fewer than two entries). Leaves the console cleared.
+pub fn show(st: &SystemTable<Boot>, entries: &[BootEntry], default: usize, timeout_secs: u32) -> usize {
+    let default = default.min(entries.len().saturating_sub(1));
+    if entries.len() < 2 || timeout_secs == 0 {
+        return default;
+    }
+    let visible = entries.len().min(MAX_VISIBLE);
+    let default = default.min(visible - 1);
+
+    let mut selected = default;
+    draw(st, &entries[..visible], selected, Some(timeout_secs));
+    let first = input::countdown_menu(st, timeout_secs, |secs| draw(st, &entries[..visible], default, Some(secs)));
+    let mut action = match first {
+        Some(action) => action,
+        None => {
+            let _ = st.stdout().clear();
+            return default;
+        }
+    };
+
+    loop {
+        match action {
+            MenuAction::Select => break,
+            MenuAction::Cancel => {
+                selected = default;
+                break;
+            }
+            MenuAction::Up => selected = selected.saturating_sub(1),
+            MenuAction::Down => selected = (selected + 1).min(visible - 1),
+        }
+        draw(st, &entries[..visible], selected, None);
+        action = match input::wait_for_menu_action(st) {
+            Some(action) => action,
+            None => break,
+        };
+    }
+
+    let _ = st.stdout().clear();
+    selected
+}
+
+/// Entry list, then a status line with the countdown while it runs.
+fn draw(st: &SystemTable<Boot>, entries: &[BootEntry], selected: usize, remaining: Option<u32>) {
+    let stdout = st.stdout();
+    let _ = stdout.clear();
+    writeln!(stdout, " RustyBoot\n").ok();
+    for (i, entry) in entries.iter().enumerate() {
+        let marker = if i == selected { '>' } else { ' ' };
+        writeln!(stdout, " {} {}", marker, entry.title).ok();
+    }
+    writeln!(stdout).ok();
+    match remaining {
+        Some(secs) => writeln!(
+            stdout,
+            "Booting '{}' in {}s. Up/Down to select, Enter to boot.",
+            entries[selected].title, secs
+        ),
+        None => writeln!(stdout, "Up/Down to select, Enter to boot, Esc for the default."),
+    }
+    .ok();
+}
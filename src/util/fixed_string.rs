@@ -0,0 +1,65 @@
+//! Fixed-capacity UTF-8 string for paths and names read from disk.
+
+#[derive(Copy, Clone)]
+pub struct FixedString<const N: usize> {
+    buf: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> FixedString<N> {
+    pub const fn new() -> Self {
+        Self { buf: [0; N], len: 0 }
+    }
+
+    /// Append `s`, failing without modification if it does not fit.
+    pub fn push_str(&mut self, s: &str) -> Result<(), &'static str> {
+        self.push_bytes(s.as_bytes())
+    }
+
+    /// Append raw bytes; they must keep the contents valid UTF-8.
+    pub fn push_bytes(&mut self, bytes: &[u8]) -> Result<(), &'static str> {
+        let end = self.len + bytes.len();
+        if end > N {
+            return Err("FixedString capacity exceeded");
+        }
+        if core::str::from_utf8(bytes).is_err() {
+            return Err("FixedString: invalid UTF-8");
+        }
+        self.buf[self.len..end].copy_from_slice(bytes);
+        self.len = end;
+        Ok(())
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+
+    pub fn as_str(&self) -> &str {
+        // SAFETY: every push checks the appended bytes are UTF-8
+        unsafe { core::str::from_utf8_unchecked(self.as_bytes()) }
+    }
+}
+
+impl<const N: usize> core::fmt::Debug for FixedString<N> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        core::fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl<const N: usize> core::fmt::Display for FixedString<N> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
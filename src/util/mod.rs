@@ -0,0 +1,3 @@
+pub mod fixed_string;
+pub mod once;
+pub mod static_vec;
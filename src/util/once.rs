@@ -0,0 +1,38 @@
+//! Write-once global cell.
+//!
+//! The bootloader is single-threaded until the kernel jump, so globals that
+//! are set once during init (mounted filesystem parameters and the like) do
+//! not need a lock, only a guard against being set twice.
+
+use core::cell::UnsafeCell;
+
+pub struct OnceCell<T>(UnsafeCell<Option<T>>);
+
+// SAFETY: there is only one thread of execution in the bootloader
+unsafe impl<T> Sync for OnceCell<T> {}
+
+impl<T> OnceCell<T> {
+    pub const fn new() -> Self {
+        Self(UnsafeCell::new(None))
+    }
+
+    /// Store `value`; hands it back if the cell was already set.
+    pub fn set(&self, value: T) -> Result<(), T> {
+        let slot = unsafe { &mut *self.0.get() };
+        if slot.is_some() {
+            return Err(value);
+        }
+        *slot = Some(value);
+        Ok(())
+    }
+
+    pub fn get(&self) -> Option<&T> {
+        unsafe { (*self.0.get()).as_ref() }
+    }
+
+    /// Clear the cell so state can be set up again between tests.
+    #[cfg(test)]
+    pub fn reset(&self) {
+        unsafe { *self.0.get() = None };
+    }
+}
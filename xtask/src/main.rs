@@ -0,0 +1,383 @@
+//! RustyBoot build pipeline: `cargo xtask <build|image|run> [options]`
+//!
+//! - `build`  compile the EFI application or the BIOS flat binary
+//! - `image`  build, then create a bootable disk image with a test kernel
+//! - `run`    build the image and boot it in QEMU
+//!
+//! `image` and `run` are UEFI only: the BIOS binary has no stage-1 boot
+//! sector to start it from a disk.
+//!
+//! Options: `--arch x86_64|i686` (default x86_64), `--firmware uefi|bios`
+//! (default uefi), `--kernel PATH` (use a real kernel instead of the
+//! generated test kernel), `--ovmf PATH` (UEFI firmware for `run`; found
+//! automatically otherwise), `--kvm` (hardware acceleration for `run`).
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+
+// The loader's ELF helpers only need `core`, so their unit tests run here
+#[cfg(test)]
+#[allow(dead_code)]
+#[path = "../../src/kernel/elf.rs"]
+mod elf;
+
+type Result<T> = std::result::Result<T, String>;
+
+const IMAGE_NAME: &str = "disk.img";
+const UEFI_IMAGE_MB: u64 = 64; // smallest size mformat will lay out as FAT32
+const BIOS_JSON_TARGET: &str = "i686-bootloader.json";
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Arch {
+    X86_64,
+    I686,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Firmware {
+    Uefi,
+    Bios,
+}
+
+struct Options {
+    arch: Arch,
+    firmware: Firmware,
+    kernel: Option<PathBuf>,
+    ovmf: Option<PathBuf>,
+    kvm: bool,
+}
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let cmd = args.next().unwrap_or_default();
+    let rest: Vec<String> = args.collect();
+
+    let result = parse_options(&rest).and_then(|opts| match cmd.as_str() {
+        "build" => build(&opts).map(|_| ()),
+        "image" => image(&opts).map(|_| ()),
+        "run" => run(&opts),
+        _ => Err(usage()),
+    });
+
+    if let Err(e) = result {
+        eprintln!("xtask: {}", e);
+        process::exit(1);
+    }
+}
+
+fn usage() -> String {
+    "usage: cargo xtask <build|image|run> [--arch x86_64|i686] [--firmware uefi|bios] \
+     [--kernel PATH] [--ovmf PATH] [--kvm]"
+        .to_string()
+}
+
+fn parse_options(args: &[String]) -> Result<Options> {
+    let mut opts = Options {
+        arch: Arch::X86_64,
+        firmware: Firmware::Uefi,
+        kernel: None,
+        ovmf: None,
+        kvm: false,
+    };
+
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        let mut value = || it.next().cloned().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--arch" => {
+                opts.arch = match value()?.as_str() {
+                    "x86_64" => Arch::X86_64,
+                    "i686" => Arch::I686,
+                    other => return Err(format!("unknown arch `{}`", other)),
+                }
+            }
+            "--firmware" => {
+                opts.firmware = match value()?.as_str() {
+                    "uefi" => Firmware::Uefi,
+                    "bios" => Firmware::Bios,
+                    other => return Err(format!("unknown firmware `{}`", other)),
+                }
+            }
+            "--kernel" => opts.kernel = Some(PathBuf::from(value()?)),
+            "--ovmf" => opts.ovmf = Some(PathBuf::from(value()?)),
+            "--kvm" => opts.kvm = true,
+            other => return Err(format!("unknown option `{}`\n{}", other, usage())),
+        }
+    }
+
+    if opts.firmware == Firmware::Bios && opts.arch != Arch::I686 {
+        return Err("BIOS builds are 32-bit only; use --arch i686".to_string());
+    }
+    Ok(opts)
+}
+
+// ===== Helpers =====
+
+fn project_root() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .parent()
+        .expect("xtask lives in a subdirectory of the project")
+        .to_path_buf()
+}
+
+fn run_cmd(cmd: &mut Command) -> Result<()> {
+    let status = cmd
+        .status()
+        .map_err(|e| format!("failed to spawn {:?}: {}", cmd.get_program(), e))?;
+    if !status.success() {
+        return Err(format!("{:?} exited with {}", cmd.get_program(), status));
+    }
+    Ok(())
+}
+
+fn have_tool(name: &str) -> bool {
+    Command::new(name)
+        .arg("--version")
+        .output()
+        .map(|o| o.status.success())
+        .unwrap_or(false)
+}
+
+fn target_dir() -> PathBuf {
+    project_root().join("target")
+}
+
+// ===== build =====
+
+/// Compile the bootloader; returns the path of the artifact to install.
+fn build(opts: &Options) -> Result<PathBuf> {
+    let root = project_root();
+    let cargo = env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+    let mut cmd = Command::new(cargo);
+    cmd.current_dir(&root)
+        .args(["build", "--release", "--package", "RustyBoot"]);
+
+    match opts.firmware {
+        Firmware::Uefi => {
+            let triple = match opts.arch {
+                Arch::X86_64 => "x86_64-unknown-uefi",
+                Arch::I686 => "i686-unknown-uefi",
+            };
+            cmd.args(["--target", triple]);
+            run_cmd(&mut cmd)?;
+            Ok(target_dir().join(triple).join("release").join("RustyBoot.efi"))
+        }
+        Firmware::Bios => {
+            cmd.args([
+                "--target",
+                BIOS_JSON_TARGET,
+                "-Zjson-target-spec",
+                "-Zbuild-std=core,compiler_builtins",
+                "-Zbuild-std-features=compiler-builtins-mem",
+                "--no-default-features",
+                "--features",
+                "bios",
+            ]);
+            run_cmd(&mut cmd)?;
+
+            let out_dir = target_dir().join("i686-bootloader").join("release");
+            let elf = out_dir.join("RustyBoot");
+            let bin = out_dir.join("bootloader.bin");
+            run_cmd(Command::new("objcopy").arg("-O").arg("binary").arg(&elf).arg(&bin))?;
+            Ok(bin)
+        }
+    }
+}
+
+// ===== image =====
+
+/// Build and write `target/disk.img`; returns its path.
+fn image(opts: &Options) -> Result<PathBuf> {
+    if opts.firmware == Firmware::Bios {
+        return Err("BIOS disk images are not supported (no stage-1 boot sector); \
+                    use `build --firmware bios` for the flat binary"
+            .to_string());
+    }
+    let artifact = build(opts)?;
+    let img = target_dir().join(IMAGE_NAME);
+
+    let kernel = match &opts.kernel {
+        Some(k) => k.clone(),
+        None => {
+            let k = target_dir().join("test_kernel.elf");
+            fs::write(&k, test_kernel_elf64()).map_err(|e| format!("writing test kernel: {}", e))?;
+            k
+        }
+    };
+
+    uefi_image(opts.arch, &artifact, &kernel, &img)?;
+    println!("xtask: wrote {}", img.display());
+    Ok(img)
+}
+
+/// FAT32 "superfloppy" ESP with the loader at the removable-media path.
+fn uefi_image(arch: Arch, efi: &Path, kernel: &Path, img: &Path) -> Result<()> {
+    if !have_tool("mformat") {
+        return Err("mtools not found (needed for FAT32 images); install `mtools`".to_string());
+    }
+
+    let file = fs::File::create(img).map_err(|e| format!("creating {}: {}", img.display(), e))?;
+    file.set_len(UEFI_IMAGE_MB * 1024 * 1024)
+        .map_err(|e| format!("sizing {}: {}", img.display(), e))?;
+    drop(file);
+
+    let boot_name = match arch {
+        Arch::X86_64 => "::/EFI/BOOT/BOOTX64.EFI",
+        Arch::I686 => "::/EFI/BOOT/BOOTIA32.EFI",
+    };
+
+    run_cmd(Command::new("mformat").arg("-i").arg(img).args(["-F", "-v", "RUSTYBOOT", "::"]))?;
+    run_cmd(Command::new("mmd").arg("-i").arg(img).args(["::/EFI", "::/EFI/BOOT"]))?;
+    run_cmd(Command::new("mcopy").arg("-i").arg(img).arg(efi).arg(boot_name))?;
+    run_cmd(Command::new("mcopy").arg("-i").arg(img).arg(kernel).arg("::/kernel.elf"))?;
+    Ok(())
+}
+
+/// Smallest useful ELF64 kernel: one PT_LOAD at 2 MiB running `hlt; jmp $-1`.
+fn test_kernel_elf64() -> Vec<u8> {
+    const BASE: u64 = 0x20_0000;
+    const EHDR: u64 = 64;
+    const PHDR: u64 = 56;
+    let code: [u8; 3] = [0xF4, 0xEB, 0xFD];
+    let entry = BASE + EHDR + PHDR;
+    let total = EHDR + PHDR + code.len() as u64;
+
+    let mut f = Vec::with_capacity(total as usize);
+    // e_ident: magic, ELFCLASS64, little-endian, version 1, SysV ABI
+    f.extend_from_slice(b"\x7fELF\x02\x01\x01\x00");
+    f.extend_from_slice(&[0u8; 8]);
+    f.extend_from_slice(&2u16.to_le_bytes()); // e_type = EXEC
+    f.extend_from_slice(&0x3Eu16.to_le_bytes()); // e_machine = x86_64
+    f.extend_from_slice(&1u32.to_le_bytes()); // e_version
+    f.extend_from_slice(&entry.to_le_bytes()); // e_entry
+    f.extend_from_slice(&EHDR.to_le_bytes()); // e_phoff
+    f.extend_from_slice(&0u64.to_le_bytes()); // e_shoff
+    f.extend_from_slice(&0u32.to_le_bytes()); // e_flags
+    f.extend_from_slice(&(EHDR as u16).to_le_bytes()); // e_ehsize
+    f.extend_from_slice(&(PHDR as u16).to_le_bytes()); // e_phentsize
+    f.extend_from_slice(&1u16.to_le_bytes()); // e_phnum
+    f.extend_from_slice(&0u16.to_le_bytes()); // e_shentsize
+    f.extend_from_slice(&0u16.to_le_bytes()); // e_shnum
+    f.extend_from_slice(&0u16.to_le_bytes()); // e_shstrndx
+
+    // PT_LOAD covering the whole file, R+X
+    f.extend_from_slice(&1u32.to_le_bytes()); // p_type
+    f.extend_from_slice(&5u32.to_le_bytes()); // p_flags
+    f.extend_from_slice(&0u64.to_le_bytes()); // p_offset
+    f.extend_from_slice(&BASE.to_le_bytes()); // p_vaddr
+    f.extend_from_slice(&BASE.to_le_bytes()); // p_paddr
+    f.extend_from_slice(&total.to_le_bytes()); // p_filesz
+    f.extend_from_slice(&total.to_le_bytes()); // p_memsz
+    f.extend_from_slice(&0x1000u64.to_le_bytes()); // p_align
+
+    f.extend_from_slice(&code);
+    f
+}
+
+// ===== run =====
+
+/// OVMF firmware: read-only code plus a vars store, or one combined image.
+struct Ovmf {
+    code: PathBuf,
+    vars: Option<PathBuf>,
+}
+
+/// (code, vars) locations used by common distributions; `None` vars means
+/// a combined CODE+VARS image.
+const OVMF_CANDIDATES: &[(&str, Option<&str>)] = &[
+    // Debian / Ubuntu
+    ("/usr/share/OVMF/OVMF_CODE.fd", Some("/usr/share/OVMF/OVMF_VARS.fd")),
+    ("/usr/share/OVMF/OVMF_CODE_4M.fd", Some("/usr/share/OVMF/OVMF_VARS_4M.fd")),
+    // Fedora
+    ("/usr/share/edk2/ovmf/OVMF_CODE.fd", Some("/usr/share/edk2/ovmf/OVMF_VARS.fd")),
+    // Arch
+    ("/usr/share/edk2/x64/OVMF_CODE.4m.fd", Some("/usr/share/edk2/x64/OVMF_VARS.4m.fd")),
+    ("/usr/share/edk2/x64/OVMF.fd", None),
+    // openSUSE
+    ("/usr/share/qemu/ovmf-x86_64-code.bin", Some("/usr/share/qemu/ovmf-x86_64-vars.bin")),
+];
+
+fn find_ovmf(opts: &Options) -> Result<Ovmf> {
+    if let Some(p) = opts.ovmf.clone().or_else(|| env::var_os("OVMF_PATH").map(PathBuf::from)) {
+        return Ok(Ovmf { code: p, vars: None });
+    }
+    if opts.arch != Arch::X86_64 {
+        return Err("no known OVMF location for i686; pass --ovmf PATH".to_string());
+    }
+
+    let bundled = project_root().join("firmware");
+    let bundled_code = bundled.join("OVMF_CODE.fd");
+    let bundled_vars = bundled.join("OVMF_VARS.fd");
+    let bundled_combined = bundled.join("OVMF.fd");
+
+    let mut candidates: Vec<(PathBuf, Option<PathBuf>)> = OVMF_CANDIDATES
+        .iter()
+        .map(|(c, v)| (PathBuf::from(c), v.map(PathBuf::from)))
+        .collect();
+    candidates.push((bundled_code, Some(bundled_vars)));
+    candidates.push((bundled_combined, None));
+
+    for (code, vars) in candidates {
+        if !code.is_file() || vars.as_ref().is_some_and(|v| !v.is_file()) {
+            continue;
+        }
+        return Ok(Ovmf { code, vars });
+    }
+    Err("OVMF firmware not found; install ovmf/edk2-ovmf, put it in firmware/, or pass --ovmf PATH"
+        .to_string())
+}
+
+/// Copy the firmware's NVRAM image so QEMU can write to it without
+/// touching the system-wide file.
+fn writable_copy(src: &Path, name: &str) -> Result<PathBuf> {
+    let dst = env::temp_dir().join(name);
+    fs::copy(src, &dst).map_err(|e| format!("copying {}: {}", src.display(), e))?;
+    Ok(dst)
+}
+
+fn print_cmd(cmd: &Command) {
+    let mut line = cmd.get_program().to_string_lossy().into_owned();
+    for arg in cmd.get_args() {
+        line.push(' ');
+        line.push_str(&arg.to_string_lossy());
+    }
+    println!("xtask: {}", line);
+}
+
+fn run(opts: &Options) -> Result<()> {
+    let img = image(opts)?;
+
+    let qemu = match opts.arch {
+        Arch::X86_64 => "qemu-system-x86_64",
+        Arch::I686 => "qemu-system-i386",
+    };
+    let mut cmd = Command::new(qemu);
+
+    let ovmf = find_ovmf(opts)?;
+    match &ovmf.vars {
+        Some(vars) => {
+            let vars = writable_copy(vars, "rustyboot-OVMF_VARS.fd")?;
+            cmd.arg("-drive")
+                .arg(format!("if=pflash,format=raw,readonly=on,file={}", ovmf.code.display()))
+                .arg("-drive")
+                .arg(format!("if=pflash,format=raw,file={}", vars.display()));
+        }
+        None => {
+            let combined = writable_copy(&ovmf.code, "rustyboot-OVMF.fd")?;
+            cmd.arg("-drive")
+                .arg(format!("if=pflash,format=raw,file={}", combined.display()));
+        }
+    }
+
+    cmd.arg("-drive")
+        .arg(format!("format=raw,file={}", img.display()))
+        .args(["-serial", "stdio"]);
+    if opts.kvm {
+        cmd.args(["-enable-kvm", "-cpu", "host"]);
+    }
+
+    print_cmd(&cmd);
+    run_cmd(&mut cmd)
+}